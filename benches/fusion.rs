@@ -0,0 +1,49 @@
+// cargo bench --bench fusion
+//
+// runs a layered circuit, a layer of single-qubit gates on every qubit between layers of CX,
+// with fused single-qubit gates (the default) and gate by gate.
+use criterion::{criterion_group, criterion_main, Criterion};
+use rand::{Rng, SeedableRng};
+use yass::circuit::Circuit;
+use yass::gates::Gate;
+use yass::qubit::Qubit;
+use yass::stabilizer_simulator::StabilizerSimulator;
+
+fn layered_circuit(num_qubits: u32, depth: usize) -> Circuit {
+    let mut rng = rand::rngs::StdRng::seed_from_u64(1);
+    let mut circuit = Circuit::new(num_qubits as usize);
+    for _ in 0..depth {
+        for qubit in Qubit::range(num_qubits as usize) {
+            for _ in 0..3 {
+                circuit.gate(match rng.gen_range(0..3) {
+                    0 => Gate::H(qubit),
+                    1 => Gate::S(qubit),
+                    _ => Gate::X(qubit),
+                });
+            }
+        }
+        for a in (0..num_qubits - 1).step_by(2) {
+            circuit.gate(Gate::Cx(Qubit(a), Qubit(a + 1)));
+        }
+    }
+    circuit
+}
+
+fn fusion(c: &mut Criterion) {
+    let circuit = layered_circuit(64, 20);
+    let mut group = c.benchmark_group("single-qubit layers, 64 qubits");
+    group.bench_function("fused", |b| {
+        b.iter(|| StabilizerSimulator::new(64, 0).run(&circuit).unwrap())
+    });
+    group.bench_function("gate by gate", |b| {
+        b.iter(|| {
+            let mut simulator = StabilizerSimulator::new(64, 0);
+            simulator.disable_fusion();
+            simulator.run(&circuit).unwrap()
+        })
+    });
+    group.finish();
+}
+
+criterion_group!(benches, fusion);
+criterion_main!(benches);
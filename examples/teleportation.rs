@@ -0,0 +1,23 @@
+// cargo run --example teleportation
+use yass::circuit::Circuit;
+use yass::gates::Gate;
+use yass::qubit::Qubit;
+use yass::stabilizer_simulator::StabilizerSimulator;
+
+fn main() {
+    // teleport |1> from qubit 0 to qubit 2, using qubit 1 as the other half of the bell pair.
+    for seed in 0..4 {
+        let mut simulator = StabilizerSimulator::new(3, seed);
+        simulator.apply_gate(&Gate::X(Qubit(0)));
+        let bell_measurement = simulator
+            .run(&Circuit::teleportation(Qubit(0), Qubit(1), Qubit(2)))
+            .expect("teleportation circuit should run");
+        let teleported = simulator
+            .measure(Qubit(2))
+            .expect("measurement should succeed");
+        println!(
+            "bell measurement {:?} -> target measured {}",
+            bell_measurement, teleported as u8
+        );
+    }
+}
@@ -0,0 +1,17 @@
+// cargo run --example tui --features tui
+use yass::circuit::Circuit;
+use yass::gates::Gate;
+use yass::qubit::Qubit;
+
+fn main() -> std::io::Result<()> {
+    // a three qubit GHZ state, then measure everything.
+    let mut circuit = Circuit::new(3);
+    circuit
+        .gate(Gate::H(Qubit(0)))
+        .gate(Gate::Cx(Qubit(0), Qubit(1)))
+        .gate(Gate::Cx(Qubit(1), Qubit(2)))
+        .measure(Qubit(0))
+        .measure(Qubit(1))
+        .measure(Qubit(2));
+    yass::tui::run(&circuit, 0)
+}
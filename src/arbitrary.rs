@@ -0,0 +1,120 @@
+use crate::circuit::{Circuit, Instruction};
+use crate::gates::Gate;
+use crate::qubit::Qubit;
+use crate::stabilizer_simulator::StabilizerSimulator;
+use proptest::prelude::*;
+
+// proptest strategies, so gate identities can be property tested over lots of
+// random circuits and states instead of a handful of hand picked ones.
+
+pub fn gate(num_qubits: u32) -> BoxedStrategy<Gate> {
+    // any gate acting on qubits below num_qubits. Two-qubit gates need num_qubits >= 2.
+    let qubit = (0..num_qubits).prop_map(Qubit);
+    let single = prop_oneof![
+        qubit.clone().prop_map(Gate::H),
+        qubit.clone().prop_map(Gate::S),
+        qubit.clone().prop_map(Gate::X),
+        qubit.clone().prop_map(Gate::Y),
+        qubit.prop_map(Gate::Z),
+    ];
+    if num_qubits < 2 {
+        return single.boxed();
+    }
+    let pair = (0..num_qubits, 1..num_qubits)
+        .prop_map(move |(a, offset)| (Qubit(a), Qubit((a + offset) % num_qubits)));
+    prop_oneof![
+        single,
+        pair.clone().prop_map(|(a, b)| Gate::Cx(a, b)),
+        pair.clone().prop_map(|(a, b)| Gate::Cz(a, b)),
+        pair.prop_map(|(a, b)| Gate::Swap(a, b)),
+    ]
+    .boxed()
+}
+
+pub fn unitary_circuit(num_qubits: u32, max_gates: usize) -> BoxedStrategy<Circuit> {
+    // gates only, no measurements -- handy for comparing states.
+    prop::collection::vec(gate(num_qubits), 0..=max_gates)
+        .prop_map(move |gates| {
+            let mut circuit = Circuit::new(num_qubits as usize);
+            for gate in gates {
+                circuit.gate(gate);
+            }
+            circuit
+        })
+        .boxed()
+}
+
+pub fn circuit(num_qubits: u32, max_instructions: usize) -> BoxedStrategy<Circuit> {
+    let instruction = prop_oneof![
+        4 => gate(num_qubits).prop_map(Instruction::Gate),
+        1 => (0..num_qubits).prop_map(|q| Instruction::Measure(Qubit(q))),
+        1 => (0..num_qubits).prop_map(|q| Instruction::PrepX(Qubit(q))),
+        1 => Just(Instruction::Tick),
+    ];
+    prop::collection::vec(instruction, 0..=max_instructions)
+        .prop_map(move |instructions| {
+            let mut circuit = Circuit::new(num_qubits as usize);
+            for instruction in instructions {
+                circuit.push(instruction);
+            }
+            circuit
+        })
+        .boxed()
+}
+
+pub fn stabilizer_state(num_qubits: u32) -> BoxedStrategy<StabilizerSimulator> {
+    // a simulator in a random stabilizer state, reached by running a random circuit on |0...0>.
+    // 2n^2 gates is enough to scramble small registers thoroughly.
+    let max_gates = (2 * num_qubits * num_qubits).max(1) as usize;
+    (unitary_circuit(num_qubits, max_gates), any::<u64>())
+        .prop_map(move |(circuit, seed)| {
+            let mut simulator = StabilizerSimulator::new(num_qubits as usize, seed);
+            simulator
+                .run(&circuit)
+                .expect("unitary circuits can't fail to run");
+            simulator
+        })
+        .boxed()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn generators(simulator: &StabilizerSimulator) -> Vec<String> {
+        simulator
+            .stabilizers()
+            .iter()
+            .chain(simulator.destabilizers().iter())
+            .map(|generator| generator.to_string())
+            .collect()
+    }
+
+    proptest! {
+        #[test]
+        fn test_self_inverse_gates(mut state in stabilizer_state(4), gate in gate(4)) {
+            prop_assume!(!matches!(gate, Gate::S(_)));
+            let before = generators(&state);
+            state.apply_gate(&gate);
+            state.apply_gate(&gate);
+            prop_assert_eq!(generators(&state), before);
+        }
+
+        #[test]
+        fn test_s_has_order_four(mut state in stabilizer_state(3), qubit in (0..3u32).prop_map(Qubit)) {
+            let before = generators(&state);
+            for _ in 0..4 {
+                state.apply_gate(&Gate::S(qubit));
+            }
+            prop_assert_eq!(generators(&state), before);
+        }
+
+        #[test]
+        fn test_random_circuits_keep_invariants(circuit in circuit(5, 40), seed in any::<u64>()) {
+            let mut simulator = StabilizerSimulator::new(5, seed);
+            simulator.enable_invariant_checks();
+            simulator.run(&circuit).unwrap();
+            prop_assert_eq!(simulator.verify_invariants(), Ok(()));
+        }
+    }
+}
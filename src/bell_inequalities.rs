@@ -0,0 +1,107 @@
+use crate::circuit::Circuit;
+use crate::mbqc::MeasurementBasis;
+use crate::qubit::Qubit;
+use crate::stabilizer_simulator::StabilizerSimulator;
+use rand::{Rng, SeedableRng};
+
+// CHSH and Mermin inequality checks. Any local hidden variable model keeps both values at
+// or below 2, while quantum mechanics reaches 2*sqrt(2) for CHSH and 4 for Mermin. They make
+// a good end to end check on measurements in every basis, since a sign or basis mistake
+// anywhere drags the value back down.
+
+pub const CHSH_CLASSICAL_BOUND: f64 = 2.0;
+pub const MERMIN_CLASSICAL_BOUND: f64 = 2.0;
+
+pub fn correlator(
+    preparation: &Circuit,
+    bases: &[(Qubit, MeasurementBasis)],
+    shots: usize,
+    seed: u64,
+) -> Result<f64, &'static str> {
+    // the average over shots of the product of the +1/-1 outcomes of measuring each listed
+    // qubit in its basis, after running the preparation. I.e. the expectation of that pauli.
+    if shots == 0 {
+        return Err("Need at least one shot to estimate a correlator.");
+    }
+    let mut rng = rand::rngs::StdRng::seed_from_u64(seed);
+    let mut total = 0i64;
+    for _ in 0..shots {
+        let mut simulator = StabilizerSimulator::new(preparation.num_qubits(), rng.gen());
+        simulator.run(preparation)?;
+        let mut parity = false;
+        for &(qubit, basis) in bases {
+            simulator.apply_gates(basis.rotation_to_z(qubit));
+            parity ^= simulator.measure(qubit)?;
+        }
+        total += if parity { -1 } else { 1 };
+    }
+    Ok(total as f64 / shots as f64)
+}
+
+pub fn chsh(shots: usize, seed: u64) -> Result<f64, &'static str> {
+    // Alice measures A0 = Z or A1 = X, Bob measures B0 = (Z + X)/sqrt(2) or B1 = (Z - X)/sqrt(2),
+    // all on a Bell pair, and S = <A0 B0> + <A0 B1> + <A1 B0> - <A1 B1>.
+    // Bob's observables aren't paulis, but each correlator is linear in them, so it's the
+    // (scaled) sum of two pauli correlators we can measure directly.
+    use MeasurementBasis::{X, Z};
+    let bell_pair = Circuit::bell_pair(Qubit(0), Qubit(1));
+    let e = |a, b, seed| correlator(&bell_pair, &[(Qubit(0), a), (Qubit(1), b)], shots, seed);
+    let zz = e(Z, Z, seed)?;
+    let zx = e(Z, X, seed.wrapping_add(1))?;
+    let xz = e(X, Z, seed.wrapping_add(2))?;
+    let xx = e(X, X, seed.wrapping_add(3))?;
+    let a0_b0 = (zz + zx) / 2f64.sqrt();
+    let a0_b1 = (zz - zx) / 2f64.sqrt();
+    let a1_b0 = (xz + xx) / 2f64.sqrt();
+    let a1_b1 = (xz - xx) / 2f64.sqrt();
+    Ok(a0_b0 + a0_b1 + a1_b0 - a1_b1)
+}
+
+pub fn mermin(shots: usize, seed: u64) -> Result<f64, &'static str> {
+    // M = <XXX> - <XYY> - <YXY> - <YYX> on a three qubit GHZ state.
+    use MeasurementBasis::{X, Y};
+    let ghz = Circuit::ghz(&[Qubit(0), Qubit(1), Qubit(2)]);
+    let mut value = 0.0;
+    for (i, (bases, sign)) in [
+        ([X, X, X], 1.0),
+        ([X, Y, Y], -1.0),
+        ([Y, X, Y], -1.0),
+        ([Y, Y, X], -1.0),
+    ]
+    .into_iter()
+    .enumerate()
+    {
+        let bases: Vec<(Qubit, MeasurementBasis)> = Qubit::range(3).zip(bases).collect();
+        value += sign * correlator(&ghz, &bases, shots, seed.wrapping_add(i as u64))?;
+    }
+    Ok(value)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_chsh_violates_classical_bound() {
+        let value = chsh(2000, 0).unwrap();
+        assert!(value > CHSH_CLASSICAL_BOUND + 0.5, "{value}");
+        assert!((value - 2.0 * 2f64.sqrt()).abs() < 0.15, "{value}");
+    }
+
+    #[test]
+    fn test_mermin_is_maximally_violated() {
+        // every term is deterministic on a GHZ state, so there's no statistical noise.
+        assert_eq!(mermin(50, 0).unwrap(), 4.0);
+        assert!(mermin(50, 0).unwrap() > MERMIN_CLASSICAL_BOUND);
+    }
+
+    #[test]
+    fn test_correlator_of_product_state() {
+        let mut plus = Circuit::new(1);
+        plus.gate(crate::gates::Gate::H(Qubit(0)));
+        assert_eq!(
+            correlator(&plus, &[(Qubit(0), MeasurementBasis::X)], 20, 0).unwrap(),
+            1.0
+        );
+    }
+}
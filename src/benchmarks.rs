@@ -0,0 +1,176 @@
+use crate::circuit::Circuit;
+use crate::code::surface_code_memory;
+use crate::gates::Gate;
+use crate::qubit::Qubit;
+use rand::seq::SliceRandom;
+use rand::{Rng, SeedableRng};
+
+// standard workloads for timing the simulator, each with something a correct run has to get
+// right, so a faster build can be checked against the same numbers it's timed on. They're
+// all noiseless and generated from their parameters (and a seed), so anyone running the
+// suite gets exactly the same circuits.
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Expectation {
+    // every measurement in a shot agrees, and each value turns up half the time.
+    AllEqual,
+    // every measurement comes out 0.
+    AllZero,
+    // no detector ever fires.
+    NoDetectionEvents,
+}
+
+#[derive(Debug, Clone)]
+pub struct Workload {
+    pub name: String,
+    pub circuit: Circuit,
+    pub expectation: Expectation,
+}
+
+impl Workload {
+    pub fn check_shot(&self, shot: &[bool]) -> Result<(), &'static str> {
+        match self.expectation {
+            Expectation::AllEqual if shot.iter().any(|&bit| bit != shot[0]) => {
+                Err("Measurements in a shot should all agree.")
+            }
+            Expectation::AllZero if shot.iter().any(|&bit| bit) => {
+                Err("Every measurement should come out 0.")
+            }
+            Expectation::NoDetectionEvents
+                if self
+                    .circuit
+                    .detector_values(shot)
+                    .into_iter()
+                    .any(|fired| fired) =>
+            {
+                Err("No detector should fire.")
+            }
+            _ => Ok(()),
+        }
+    }
+
+    pub fn check(&self, shots: &[Vec<bool>]) -> Result<(), &'static str> {
+        // every shot passes check_shot, and for AllEqual, the shots that came out all 1 are
+        // within 5 standard deviations of half.
+        for shot in shots {
+            self.check_shot(shot)?;
+        }
+        if self.expectation == Expectation::AllEqual && !shots.is_empty() {
+            let ones = shots
+                .iter()
+                .filter(|shot| shot.first() == Some(&true))
+                .count() as f64;
+            let n = shots.len() as f64;
+            if (ones - n / 2.0).abs() > 5.0 * (n / 4.0).sqrt() {
+                return Err("Outcomes should be 0 and 1 about equally often.");
+            }
+        }
+        Ok(())
+    }
+}
+
+pub fn ghz_chain(num_qubits: usize) -> Workload {
+    // a GHZ state down a line of CXs, then every qubit measured.
+    let qubits: Vec<Qubit> = Qubit::range(num_qubits).collect();
+    let mut circuit = Circuit::ghz(&qubits);
+    for &qubit in &qubits {
+        circuit.measure(qubit);
+    }
+    Workload {
+        name: format!("ghz chain, {num_qubits} qubits"),
+        circuit,
+        expectation: Expectation::AllEqual,
+    }
+}
+
+pub fn random_clifford_mirror(num_qubits: usize, depth: usize, seed: u64) -> Workload {
+    // `depth` layers of a random single-qubit gate on every qubit then CXs on random disjoint
+    // pairs, followed by the same layers undone in reverse, so every qubit ends up back in
+    // |0> however scrambled it got in the middle.
+    let mut rng = rand::rngs::StdRng::seed_from_u64(seed);
+    let mut qubits: Vec<Qubit> = Qubit::range(num_qubits).collect();
+    let mut gates = vec![];
+    for _ in 0..depth {
+        for &qubit in &qubits {
+            gates.push(match rng.gen_range(0..5) {
+                0 => Gate::H(qubit),
+                1 => Gate::S(qubit),
+                2 => Gate::X(qubit),
+                3 => Gate::Y(qubit),
+                _ => Gate::Z(qubit),
+            });
+        }
+        qubits.shuffle(&mut rng);
+        for pair in qubits.chunks_exact(2) {
+            gates.push(Gate::Cx(pair[0], pair[1]));
+        }
+    }
+    let mut circuit = Circuit::new(num_qubits);
+    for &gate in &gates {
+        circuit.gate(gate);
+    }
+    circuit.tick();
+    for gate in gates.iter().rev().flat_map(Gate::inverse) {
+        circuit.gate(gate);
+    }
+    for qubit in Qubit::range(num_qubits) {
+        circuit.measure(qubit);
+    }
+    Workload {
+        name: format!("random clifford mirror, {num_qubits} qubits, depth {depth}"),
+        circuit,
+        expectation: Expectation::AllZero,
+    }
+}
+
+pub fn surface_code_workload(distance: usize, rounds: usize) -> Result<Workload, &'static str> {
+    // the memory experiment from code::surface_code_memory, readout and observable included.
+    Ok(Workload {
+        name: format!("surface code memory, d={distance}, {rounds} rounds"),
+        circuit: surface_code_memory(distance, rounds)?,
+        expectation: Expectation::NoDetectionEvents,
+    })
+}
+
+pub fn standard_suite() -> Vec<Workload> {
+    // the same list every time: GHZ chains and mirror circuits at a few sizes, and surface
+    // code memories at every odd distance from 3 to 25, for as many rounds as the distance.
+    let mut suite = vec![];
+    for num_qubits in [10, 100, 1000] {
+        suite.push(ghz_chain(num_qubits));
+    }
+    for (num_qubits, depth) in [(10, 10), (50, 20), (200, 50)] {
+        suite.push(random_clifford_mirror(num_qubits, depth, 0));
+    }
+    for distance in (3..=25).step_by(2) {
+        suite.push(
+            surface_code_workload(distance, distance)
+                .expect("odd distances of at least 3 are valid surface codes"),
+        );
+    }
+    suite
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::sampler::Sampler;
+
+    #[test]
+    fn test_small_workloads_meet_their_expectations() {
+        let workloads = [
+            ghz_chain(6),
+            random_clifford_mirror(6, 6, 4),
+            surface_code_workload(3, 2).unwrap(),
+        ];
+        for workload in &workloads {
+            let shots = Sampler::new(&workload.circuit, 1).sample(200).unwrap();
+            assert_eq!(workload.check(&shots), Ok(()), "{}", workload.name);
+        }
+        assert!(workloads[1].check_shot(&[false, true]).is_err());
+        assert!(workloads[0].check(&vec![vec![false; 6]; 200]).is_err());
+        // d^2 data qubits and d^2 - 1 ancillas.
+        assert_eq!(workloads[2].circuit.num_qubits(), 2 * 3 * 3 - 1);
+        assert!(surface_code_workload(4, 1).is_err());
+    }
+}
@@ -0,0 +1,148 @@
+use crate::{load_circuit, Args};
+use std::collections::BTreeMap;
+use yass::circuit::{Circuit, Instruction};
+use yass::determinism::{analyze_determinism, MeasurementValue};
+use yass::noise::NoiseModel;
+
+// yass analyze: a summary of a circuit without running it. Gate counts are by name, with
+// classically controlled gates counted apart from plain ones. Measurement values come from
+// the determinism analysis of the noiseless circuit, so a deterministic one shows as the
+// constant it always is, or as the parity of earlier measurements it always equals.
+
+fn operation_name(instruction: &Instruction) -> Option<String> {
+    match instruction {
+        Instruction::Gate(gate) => Some(gate.name().to_string()),
+        Instruction::Conditional { gate, .. }
+        | Instruction::ConditionalOnBit { gate, .. }
+        | Instruction::SweepConditional { gate, .. } => {
+            Some(format!("{} (classically controlled)", gate.name()))
+        }
+        Instruction::Measure(_) | Instruction::MeasureToBit { .. } => Some("M".to_string()),
+        Instruction::Reset(_) => Some("R".to_string()),
+        Instruction::PrepX(_) => Some("RX".to_string()),
+        Instruction::PrepY(_) => Some("RY".to_string()),
+        Instruction::Tick => None,
+    }
+}
+
+fn describe(value: &MeasurementValue) -> String {
+    match value {
+        MeasurementValue::Random => "random".to_string(),
+        MeasurementValue::Determined { parity_of, flipped } if parity_of.is_empty() => {
+            format!("always {}", *flipped as u8)
+        }
+        MeasurementValue::Determined { parity_of, flipped } => {
+            let mut terms: Vec<String> = parity_of.iter().map(|m| format!("m{m}")).collect();
+            if *flipped {
+                terms.push("1".to_string());
+            }
+            format!("= {}", terms.join(" ^ "))
+        }
+    }
+}
+
+pub fn report(circuit: &Circuit, noise: Option<(&str, &NoiseModel)>) -> String {
+    let mut lines = vec![format!("qubits: {}", circuit.num_qubits())];
+
+    let mut counts: BTreeMap<String, usize> = BTreeMap::new();
+    for name in circuit.instructions().iter().filter_map(operation_name) {
+        *counts.entry(name).or_insert(0) += 1;
+    }
+    lines.push(format!("operations: {}", counts.values().sum::<usize>()));
+    for (name, count) in &counts {
+        lines.push(format!("  {name}: {count}"));
+    }
+    lines.push(format!(
+        "depth: {} ({} with two-qubit gates)",
+        circuit.depth(),
+        circuit.two_qubit_depth()
+    ));
+
+    match analyze_determinism(circuit) {
+        Ok(values) => {
+            let deterministic = values.iter().filter(|v| v.is_deterministic()).count();
+            lines.push(format!(
+                "measurements: {} ({deterministic} deterministic, {} random)",
+                values.len(),
+                values.len() - deterministic
+            ));
+            for (m, value) in values.iter().enumerate() {
+                lines.push(format!("  m{m}: {}", describe(value)));
+            }
+        }
+        Err(reason) => {
+            lines.push(format!("measurements: {}", circuit.num_measurements()));
+            lines.push(format!("  can't tell which are deterministic: {reason}"));
+        }
+    }
+    lines.push(format!(
+        "detectors: {}, observables: {}",
+        circuit.detectors().len(),
+        circuit.observables().len()
+    ));
+
+    match noise {
+        Some((spec, noise)) => {
+            let totals = noise.totals(circuit);
+            lines.push(format!("noise ({spec}):"));
+            lines.push(format!("  gate channels: {}", totals.gate_channels));
+            lines.push(format!(
+                "  crosstalk channels: {}",
+                totals.crosstalk_channels
+            ));
+            lines.push(format!("  measurement flips: {}", totals.measurement_flips));
+            lines.push(format!("  idle channels: {}", totals.idle_channels));
+            lines.push(format!(
+                "  expected errors per shot: {}",
+                totals.expected_errors
+            ));
+        }
+        None => lines.push("noise: none (pass --noise to apply a model)".to_string()),
+    }
+    lines.join("\n")
+}
+
+pub fn run(args: &[String]) -> Result<(), String> {
+    let args = Args::parse(args, &["noise"])?;
+    let loaded = load_circuit(args.single_path()?, args.option("noise"))?;
+    let noise = loaded
+        .noise
+        .as_ref()
+        .map(|(spec, noise)| (spec.as_str(), noise));
+    println!("{}", report(&loaded.circuit, noise));
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::parse_noise;
+    use yass::parser::parse;
+
+    #[test]
+    fn test_report() {
+        let circuit = parse("H 0\nCX 0 1\nTICK\nM 0 1\nR 0\nM 0\nCX rec[-1] 1").unwrap();
+        let noise = parse_noise("depolarizing:0.25").unwrap();
+        let report = report(&circuit, Some(("depolarizing:0.25", &noise)));
+        let expected = "qubits: 2
+operations: 7
+  CX: 1
+  H: 1
+  M: 3
+  R: 1
+  X (classically controlled): 1
+depth: 2 (1 with two-qubit gates)
+measurements: 3 (2 deterministic, 1 random)
+  m0: random
+  m1: = m0
+  m2: always 0
+detectors: 0, observables: 0
+noise (depolarizing:0.25):
+  gate channels: 2
+  crosstalk channels: 0
+  measurement flips: 3
+  idle channels: 0
+  expected errors per shot: 1.25";
+        assert_eq!(report, expected);
+    }
+}
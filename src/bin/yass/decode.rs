@@ -0,0 +1,154 @@
+use crate::{read_input, Args};
+use std::fs::File;
+use yass::decoding::{Decoder, RepetitionDecoder, UnionFindDecoder};
+use yass::dem::DetectorErrorModel;
+use yass::shot_format::{read_shots, ShotFormat};
+
+// yass decode: runs a decoder over a file of detection events, one shot per record with a bit
+// per detector of the model, and counts the observable flips it predicts. Given the flips
+// that really happened (--obs-in, a bit per observable per shot), it counts the shots the
+// decoder got wrong, which is the logical error count.
+
+pub struct Decoded {
+    pub shots: usize,
+    // per observable, the shots predicted to flip it, and the shots the prediction was wrong.
+    pub predicted: Vec<usize>,
+    pub wrong: Option<Vec<usize>>,
+    // shots with any observable wrong.
+    pub logical_errors: Option<usize>,
+}
+
+pub fn decode_shots(
+    decoder: &dyn Decoder,
+    num_observables: usize,
+    events: &[Vec<bool>],
+    actual: Option<&[Vec<bool>]>,
+) -> Result<Decoded, String> {
+    if actual.is_some_and(|actual| actual.len() != events.len()) {
+        return Err("the observable file has a different number of shots".to_string());
+    }
+    let mut decoded = Decoded {
+        shots: events.len(),
+        predicted: vec![0; num_observables],
+        wrong: actual.map(|_| vec![0; num_observables]),
+        logical_errors: actual.map(|_| 0),
+    };
+    for (shot, events) in events.iter().enumerate() {
+        let prediction = decoder
+            .decode(events)
+            .map_err(|error| format!("shot {shot}: {error}"))?;
+        for (count, &flip) in decoded.predicted.iter_mut().zip(&prediction) {
+            *count += flip as usize;
+        }
+        if let (Some(actual), Some(wrong), Some(errors)) = (
+            actual,
+            decoded.wrong.as_mut(),
+            decoded.logical_errors.as_mut(),
+        ) {
+            let mut any = false;
+            for ((count, &flip), &happened) in wrong.iter_mut().zip(&prediction).zip(&actual[shot])
+            {
+                *count += (flip != happened) as usize;
+                any |= flip != happened;
+            }
+            *errors += any as usize;
+        }
+    }
+    Ok(decoded)
+}
+
+pub fn report(decoded: &Decoded) -> String {
+    let mut lines = vec![format!("shots: {}", decoded.shots)];
+    lines.push("predicted flips:".to_string());
+    for (observable, count) in decoded.predicted.iter().enumerate() {
+        lines.push(format!("  L{observable}: {count}"));
+    }
+    if let (Some(wrong), Some(errors)) = (&decoded.wrong, decoded.logical_errors) {
+        let rate = errors as f64 / decoded.shots.max(1) as f64;
+        lines.push(format!("logical errors: {errors} ({rate})"));
+        for (observable, count) in wrong.iter().enumerate() {
+            lines.push(format!("  L{observable}: {count}"));
+        }
+    }
+    lines.join("\n")
+}
+
+fn read_bits(path: &str, format: ShotFormat, bits: usize) -> Result<Vec<Vec<bool>>, String> {
+    let file = File::open(path).map_err(|error| format!("reading {path}: {error}"))?;
+    read_shots(file, format, bits).map_err(|error| format!("reading {path}: {error}"))
+}
+
+pub fn run(args: &[String]) -> Result<(), String> {
+    let args = Args::parse(
+        args,
+        &[
+            "dem",
+            "in",
+            "in-format",
+            "obs-in",
+            "obs-in-format",
+            "decoder",
+        ],
+    )?;
+    if !args.positional.is_empty() {
+        return Err("decode takes its files as --dem and --in".to_string());
+    }
+    let dem_path = args.option("dem").ok_or("--dem is required")?;
+    let model = DetectorErrorModel::parse(&read_input(dem_path)?)
+        .map_err(|error| format!("{dem_path}: {error}"))?;
+    let decoder: Box<dyn Decoder> = match args.option("decoder").unwrap_or("union-find") {
+        "union-find" => Box::new(UnionFindDecoder::new(&model)?),
+        "repetition" => Box::new(RepetitionDecoder::new(&model)?),
+        name => {
+            return Err(format!(
+                "unknown decoder {name}, expected union-find or repetition"
+            ))
+        }
+    };
+    let format = |option| args.option(option).unwrap_or("b8").parse::<ShotFormat>();
+    let events_path = args.option("in").ok_or("--in is required")?;
+    let events = read_bits(events_path, format("in-format")?, model.num_detectors())?;
+    let actual = match args.option("obs-in") {
+        Some(path) => Some(read_bits(
+            path,
+            format("obs-in-format")?,
+            model.num_observables(),
+        )?),
+        None => None,
+    };
+    let decoded = decode_shots(
+        decoder.as_ref(),
+        model.num_observables(),
+        &events,
+        actual.as_deref(),
+    )?;
+    println!("{}", report(&decoded));
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_decode_shots() {
+        // a distance 3 repetition code read out once: D0 checks data 0 and 1, D1 data 1 and 2.
+        let model = DetectorErrorModel::parse(
+            "error(0.1) D0 L0
+             error(0.1) D0 D1
+             error(0.1) D1",
+        )
+        .unwrap();
+        let decoder = UnionFindDecoder::new(&model).unwrap();
+        let events = [[false, false], [true, false], [true, true], [false, true]].map(Vec::from);
+        let actual = [[false], [true], [false], [true]].map(Vec::from);
+        let decoded = decode_shots(&decoder, 1, &events, Some(&actual)).unwrap();
+        assert_eq!(decoded.predicted, vec![1]);
+        assert_eq!(decoded.logical_errors, Some(1));
+        assert_eq!(
+            report(&decoded),
+            "shots: 4\npredicted flips:\n  L0: 1\nlogical errors: 1 (0.25)\n  L0: 1"
+        );
+        assert!(decode_shots(&decoder, 1, &events, Some(&actual[..2])).is_err());
+    }
+}
@@ -0,0 +1,56 @@
+use crate::{load_circuit, Args, Loaded};
+use yass::dem::DetectorErrorModel;
+
+// yass dem: the detector error model of a noisy circuit, in stim's text format, on stdout.
+// Without noise every fault has probability zero and the model would have no errors at all,
+// which is never what's wanted, so a noise model is required.
+
+pub fn detector_error_model(loaded: &Loaded) -> Result<DetectorErrorModel, String> {
+    let Some((_, noise)) = &loaded.noise else {
+        return Err("no noise model, pass --noise or add a \"# noise: <spec>\" line".to_string());
+    };
+    DetectorErrorModel::from_circuit(&loaded.circuit, noise).map_err(str::to_string)
+}
+
+pub fn run(args: &[String]) -> Result<(), String> {
+    let args = Args::parse(args, &["noise"])?;
+    let loaded = load_circuit(args.single_path()?, args.option("noise"))?;
+    print!("{}", detector_error_model(&loaded)?);
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::parse_noise;
+    use yass::parser::parse;
+
+    #[test]
+    fn test_detector_error_model() {
+        // a distance 2 repetition code: one check, read out once and compared with the data.
+        let circuit = parse(
+            "CX 0 1 2 1
+             M 1
+             DETECTOR(0, 0) rec[-1]
+             M 0 2
+             DETECTOR(0, 1) rec[-1] rec[-2] rec[-3]
+             OBSERVABLE_INCLUDE(0) rec[-2]",
+        )
+        .unwrap();
+        let mut loaded = Loaded {
+            circuit,
+            noise: None,
+        };
+        assert!(detector_error_model(&loaded).is_err());
+        loaded.noise = Some((String::new(), parse_noise("measurement:0.125").unwrap()));
+        let model = detector_error_model(&loaded).unwrap();
+        assert_eq!(model.num_detectors(), 2);
+        assert_eq!(model.num_observables(), 1);
+        // a flipped check, or a flipped data readout on either side.
+        assert_eq!(model.errors().len(), 3);
+        assert_eq!(
+            DetectorErrorModel::parse(&model.to_string()).unwrap(),
+            model
+        );
+    }
+}
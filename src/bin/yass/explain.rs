@@ -0,0 +1,111 @@
+use crate::{load_circuit, Args};
+use yass::circuit::{Circuit, Instruction};
+use yass::debugger::Debugger;
+use yass::pauli::PauliString;
+
+// yass explain: runs a circuit one instruction at a time and says what each one did to the
+// stabilizer generators, for working through the formalism by hand. Gates come with the
+// simulator's trace explanation of the rule they follow; measurements and resets with the
+// outcome and the generators they rewrote. Any generator whose sign changed is flagged, and
+// the stabilizers are listed after every step that changed them. Random outcomes are drawn
+// with --seed.
+
+fn changes(before: &[PauliString], after: &[PauliString]) -> Vec<String> {
+    // like the trace's, for the steps that aren't gates. A measurement replaces a generator
+    // outright, which only counts as a sign flip when it's the same pauli string back.
+    before
+        .iter()
+        .zip(after)
+        .enumerate()
+        .filter(|(_, (before, after))| before != after)
+        .map(|(i, (before, after))| {
+            let same_paulis = before.x_bits == after.x_bits && before.z_bits == after.z_bits;
+            let flipped = match same_paulis && before.phase_is_negated != after.phase_is_negated {
+                true => " (sign flipped)",
+                false => "",
+            };
+            format!("stabilizer {i}: {before} -> {after}{flipped}")
+        })
+        .collect()
+}
+
+pub fn explain(circuit: &Circuit, seed: u64) -> Result<String, String> {
+    let mut debugger = Debugger::new(circuit, seed);
+    debugger.enable_trace();
+    let mut lines = vec![format!(
+        "start: every qubit in |0>, stabilized by Z on each of the {} qubits",
+        circuit.num_qubits()
+    )];
+    let list = |lines: &mut Vec<String>, stabilizers: &[PauliString]| {
+        lines.push("  stabilizers:".to_string());
+        for stabilizer in stabilizers {
+            lines.push(format!("    {stabilizer}"));
+        }
+    };
+    list(&mut lines, &debugger.stabilizers());
+    let mut sign_flips = 0;
+    while !debugger.is_finished() {
+        let before = debugger.stabilizers();
+        let num_measured = debugger.measurement_record().len();
+        let step = debugger.position() + 1;
+        let instruction = debugger.step()?.expect("the circuit isn't finished");
+        lines.push(format!("{step}. {instruction}"));
+
+        let mut explained = vec![];
+        for entry in debugger.take_trace_log() {
+            explained.extend(entry.lines().map(|line| line.trim().to_string()));
+        }
+        if !matches!(instruction, Instruction::Gate(_)) {
+            explained.extend(changes(&before, &debugger.stabilizers()));
+        }
+        if let Some(&outcome) = debugger.measurement_record().get(num_measured) {
+            explained.push(format!("measured {}", outcome as u8));
+        }
+        for line in explained {
+            if line.starts_with("stabilizer") && line.ends_with("(sign flipped)") {
+                sign_flips += 1;
+            }
+            lines.push(format!("  {line}"));
+        }
+        let after = debugger.stabilizers();
+        if after != before {
+            list(&mut lines, &after);
+        }
+    }
+    lines.push(format!(
+        "done: {} steps, {sign_flips} stabilizer sign flips",
+        circuit.instructions().len()
+    ));
+    Ok(lines.join("\n"))
+}
+
+pub fn run(args: &[String]) -> Result<(), String> {
+    let args = Args::parse(args, &["seed"])?;
+    let seed = args.number("seed", 0)?;
+    let loaded = load_circuit(args.single_path()?, None)?;
+    println!("{}", explain(&loaded.circuit, seed)?);
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use yass::parser::parse;
+
+    #[test]
+    fn test_explain() {
+        let circuit = parse("H 0\nCX 0 1\nZ 0\nM 0").unwrap();
+        let text = explain(&circuit, 0).unwrap();
+        let lines: Vec<&str> = text.lines().collect();
+        assert_eq!(
+            lines[0],
+            "start: every qubit in |0>, stabilized by Z on each of the 2 qubits"
+        );
+        assert_eq!(lines[2..4], ["    +ZI", "    +IZ"]);
+        assert!(lines.contains(&"3. Z 0"));
+        assert!(lines.contains(&"  stabilizer 0: +XX -> -XX (sign flipped)"));
+        assert!(lines.contains(&"    -XX"));
+        assert!(lines.contains(&"4. M 0"));
+        assert!(text.ends_with("done: 4 steps, 1 stabilizer sign flips"));
+    }
+}
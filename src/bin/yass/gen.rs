@@ -0,0 +1,74 @@
+use crate::{parse_noise, Args};
+use yass::code::{repetition_code_memory, surface_code_memory};
+use yass::parser;
+
+// yass gen: memory experiments from the built-in code generators, as circuit files with
+// their detectors and observable, ready for yass dem and sampling. A noise spec is checked
+// and written into the file as a "# noise:" line, which the other subcommands pick up.
+
+pub fn generate(
+    code: &str,
+    distance: usize,
+    rounds: usize,
+    noise: Option<&str>,
+) -> Result<String, String> {
+    let circuit = match code {
+        "surface-code" => surface_code_memory(distance, rounds)?,
+        "repetition-code" => repetition_code_memory(distance, rounds)?,
+        _ => {
+            return Err(format!(
+                "unknown code {code}, expected surface-code or repetition-code"
+            ))
+        }
+    };
+    let mut text = format!("# {code} memory, distance {distance}, {rounds} rounds\n");
+    if let Some(spec) = noise {
+        parse_noise(spec)?;
+        text.push_str(&format!("# noise: {spec}\n"));
+    }
+    text.push_str(&parser::to_text(&circuit));
+    Ok(text)
+}
+
+pub fn run(args: &[String]) -> Result<(), String> {
+    let args = Args::parse(args, &["distance", "rounds", "noise"])?;
+    let [code] = &args.positional[..] else {
+        return Err("expected a code, surface-code or repetition-code".to_string());
+    };
+    let distance = args.number("distance", 3)?;
+    let rounds = args.number("rounds", distance)?;
+    print!(
+        "{}",
+        generate(code, distance, rounds, args.option("noise"))?
+    );
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::dem::detector_error_model;
+    use crate::Loaded;
+
+    #[test]
+    fn test_generate() {
+        let text = generate("surface-code", 3, 2, Some("depolarizing:0.001")).unwrap();
+        assert!(text.starts_with(
+            "# surface-code memory, distance 3, 2 rounds\n# noise: depolarizing:0.001\n"
+        ));
+        let circuit = parser::parse(&text).unwrap();
+        assert_eq!(circuit.detectors().len(), 12);
+        let generated = surface_code_memory(3, 2).unwrap();
+        assert!(circuit.all_qubit_coords().eq(generated.all_qubit_coords()));
+        let loaded = Loaded {
+            circuit,
+            noise: Some((String::new(), parse_noise("depolarizing:0.001").unwrap())),
+        };
+        assert!(!detector_error_model(&loaded).unwrap().errors().is_empty());
+
+        assert!(generate("repetition-code", 3, 1, None).is_ok());
+        assert!(generate("surface-code", 4, 1, None).is_err());
+        assert!(generate("color-code", 3, 1, None).is_err());
+        assert!(generate("surface-code", 3, 1, Some("loud:0.1")).is_err());
+    }
+}
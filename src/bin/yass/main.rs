@@ -0,0 +1,220 @@
+use std::collections::HashMap;
+use std::fs;
+use std::io::{self, Read};
+use std::process::ExitCode;
+use std::str::FromStr;
+use yass::circuit::Circuit;
+use yass::noise::NoiseModel;
+use yass::parser;
+
+mod analyze;
+mod decode;
+mod dem;
+mod explain;
+mod gen;
+mod sample;
+#[cfg(feature = "server")]
+mod serve;
+
+// the yass command line, for working with circuit files (in the parser module's text format)
+// from the shell. Each subcommand lives in a module of its own, with a run function taking
+// the arguments after its name.
+//
+// The text format has no noise instructions, so noise comes from a --noise spec, or failing
+// that from a "# noise: <spec>" comment in the circuit file. A spec is a comma separated list
+// of kind:probability, where the kind is one of
+//
+//   depolarizing   every gate, and measurement flips at the same rate
+//   measurement    measurement flips only
+//   idle           qubits a TICK finds idle
+//
+// or "none".
+
+const USAGE: &str = "usage: yass <command> [arguments]
+
+commands:
+  analyze <circuit> [--noise <spec>]   qubits, gate counts, depth, measurements and noise
+  dem <circuit> [--noise <spec>]       the circuit's detector error model, in stim's format
+  sample <circuit> [--shots 1] [--out-format 01|b8|hits] [--seed 0] [--noise <spec>]
+                                       measurement outcomes, one shot after another
+  decode --dem <model> --in <events> [--in-format b8] [--obs-in <flips>]
+         [--obs-in-format b8] [--decoder union-find|repetition]
+                                       decodes detection events, counting logical errors
+  gen surface-code|repetition-code [--distance 3] [--rounds <distance>] [--noise <spec>]
+                                       a memory experiment circuit with its detectors
+  explain <circuit> [--seed 0]         steps through the circuit, showing the stabilizers
+  serve [--addr 127.0.0.1:8080] [--max-qubits 10000] [--max-shots 1000000]
+        [--max-circuits 1000] [--max-work 100000000000] [--max-connections 16]
+        [--timeout 30]
+                                       a REST server for sampling and detector error models
+                                       (needs the server feature)
+
+Circuit files are read from stdin when the path is -.
+Noise specs look like depolarizing:0.001 or depolarizing:0.001,idle:0.0005.";
+
+// a subcommand's arguments: the positional ones in order, and --name value options.
+pub struct Args {
+    pub positional: Vec<String>,
+    options: HashMap<String, String>,
+}
+
+impl Args {
+    pub fn parse(args: &[String], known: &[&str]) -> Result<Args, String> {
+        let mut parsed = Args {
+            positional: vec![],
+            options: HashMap::new(),
+        };
+        let mut args = args.iter();
+        while let Some(arg) = args.next() {
+            match arg.strip_prefix("--") {
+                Some(name) if known.contains(&name) => {
+                    let value = args
+                        .next()
+                        .ok_or_else(|| format!("--{name} needs a value"))?;
+                    parsed.options.insert(name.to_string(), value.clone());
+                }
+                Some(name) => return Err(format!("unknown option --{name}")),
+                None => parsed.positional.push(arg.clone()),
+            }
+        }
+        Ok(parsed)
+    }
+
+    pub fn option(&self, name: &str) -> Option<&str> {
+        self.options.get(name).map(String::as_str)
+    }
+
+    pub fn number<T: FromStr>(&self, name: &str, default: T) -> Result<T, String> {
+        match self.option(name) {
+            Some(value) => value
+                .parse()
+                .map_err(|_| format!("--{name} should be a whole number, not {value}")),
+            None => Ok(default),
+        }
+    }
+
+    pub fn single_path(&self) -> Result<&str, String> {
+        match &self.positional[..] {
+            [path] => Ok(path),
+            [] => Err("expected a circuit file".to_string()),
+            _ => Err("expected a single circuit file".to_string()),
+        }
+    }
+}
+
+pub fn read_input(path: &str) -> Result<String, String> {
+    if path == "-" {
+        let mut text = String::new();
+        io::stdin()
+            .read_to_string(&mut text)
+            .map_err(|error| format!("reading stdin: {error}"))?;
+        return Ok(text);
+    }
+    fs::read_to_string(path).map_err(|error| format!("reading {path}: {error}"))
+}
+
+pub fn parse_noise(spec: &str) -> Result<NoiseModel, String> {
+    let mut noise = NoiseModel::noiseless();
+    if spec.trim() == "none" {
+        return Ok(noise);
+    }
+    for part in spec.split(',') {
+        let invalid = || format!("bad noise spec {part:?}, expected kind:probability");
+        let (kind, p) = part.trim().split_once(':').ok_or_else(invalid)?;
+        let p: f64 = p.parse().map_err(|_| invalid())?;
+        if !(0.0..=1.0).contains(&p) {
+            return Err(format!("noise probability {p} isn't between 0 and 1"));
+        }
+        match kind {
+            "depolarizing" => {
+                noise.single_qubit_depolarization = p;
+                noise.two_qubit_depolarization = p;
+                noise.measurement_flip = p;
+            }
+            "measurement" => noise.measurement_flip = p,
+            "idle" => noise.idle_depolarization = p,
+            _ => return Err(format!("unknown noise kind {kind}")),
+        }
+    }
+    Ok(noise)
+}
+
+pub fn noise_comment(text: &str) -> Option<&str> {
+    // the spec in a "# noise: <spec>" line, if the file has one.
+    text.lines()
+        .filter_map(|line| line.trim().strip_prefix('#'))
+        .find_map(|comment| comment.trim().strip_prefix("noise:"))
+        .map(str::trim)
+}
+
+// a circuit file, with the noise it should run under and the spec that came from.
+pub struct Loaded {
+    pub circuit: Circuit,
+    pub noise: Option<(String, NoiseModel)>,
+}
+
+pub fn load_circuit(path: &str, noise_option: Option<&str>) -> Result<Loaded, String> {
+    let text = read_input(path)?;
+    let circuit = parser::parse(&text).map_err(|error| format!("{path}: {error}"))?;
+    let noise = match noise_option.or(noise_comment(&text)) {
+        Some(spec) => Some((spec.to_string(), parse_noise(spec)?)),
+        None => None,
+    };
+    Ok(Loaded { circuit, noise })
+}
+
+fn main() -> ExitCode {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let result = match args.first().map(String::as_str) {
+        Some("analyze") => analyze::run(&args[1..]),
+        Some("decode") => decode::run(&args[1..]),
+        Some("dem") => dem::run(&args[1..]),
+        Some("explain") => explain::run(&args[1..]),
+        Some("gen") => gen::run(&args[1..]),
+        Some("sample") => sample::run(&args[1..]),
+        #[cfg(feature = "server")]
+        Some("serve") => serve::run(&args[1..]),
+        #[cfg(not(feature = "server"))]
+        Some("serve") => Err("yass was built without the server feature".to_string()),
+        Some("help" | "-h" | "--help") | None => {
+            println!("{USAGE}");
+            Ok(())
+        }
+        Some(command) => Err(format!("unknown command {command}\n\n{USAGE}")),
+    };
+    match result {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(error) => {
+            eprintln!("yass: {error}");
+            ExitCode::FAILURE
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_noise_specs() {
+        let noise = parse_noise("depolarizing:0.001, idle:0.01").unwrap();
+        assert_eq!(noise.two_qubit_depolarization, 0.001);
+        assert_eq!(noise.measurement_flip, 0.001);
+        assert_eq!(noise.idle_depolarization, 0.01);
+        assert_eq!(parse_noise("none").unwrap(), NoiseModel::noiseless());
+        assert!(parse_noise("depolarizing").is_err());
+        assert!(parse_noise("bitflip:0.1").is_err());
+        assert!(parse_noise("depolarizing:2").is_err());
+        assert_eq!(
+            noise_comment("H 0\n# noise: measurement:0.2\nM 0"),
+            Some("measurement:0.2")
+        );
+        assert_eq!(noise_comment("# just a comment\nH 0"), None);
+
+        let args: Vec<String> = ["a.stim", "--noise", "none"].map(String::from).into();
+        let parsed = Args::parse(&args, &["noise"]).unwrap();
+        assert_eq!(parsed.single_path(), Ok("a.stim"));
+        assert_eq!(parsed.option("noise"), Some("none"));
+        assert!(Args::parse(&args, &[]).is_err());
+    }
+}
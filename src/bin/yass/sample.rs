@@ -0,0 +1,78 @@
+use crate::{load_circuit, Args, Loaded};
+use std::io::{self, BufWriter, Write};
+use yass::sampler::Sampler;
+use yass::shot_format::ShotFormat;
+
+// yass sample: measurement outcomes for a number of shots, on stdout in one of stim's shot
+// formats (01 by default, see shot_format), so they can be piped straight into other tools.
+// Shots are streamed out as they're sampled rather than collected first. Noise is optional
+// here: without it every shot is an ideal run of the circuit.
+
+pub fn sample(
+    loaded: &Loaded,
+    shots: usize,
+    format: ShotFormat,
+    seed: u64,
+    writer: &mut impl Write,
+) -> Result<(), String> {
+    let mut sampler = Sampler::new(&loaded.circuit, seed);
+    if let Some((_, noise)) = &loaded.noise {
+        sampler = sampler.with_noise(noise.clone());
+    }
+    sampler
+        .write(shots, format, writer)
+        .and_then(|()| writer.flush())
+        .map_err(|error| format!("writing shots: {error}"))
+}
+
+pub fn run(args: &[String]) -> Result<(), String> {
+    let args = Args::parse(args, &["shots", "out-format", "seed", "noise"])?;
+    let shots = args.number("shots", 1)?;
+    let format: ShotFormat = args.option("out-format").unwrap_or("01").parse()?;
+    let seed = args.number("seed", 0)?;
+    let loaded = load_circuit(args.single_path()?, args.option("noise"))?;
+    sample(
+        &loaded,
+        shots,
+        format,
+        seed,
+        &mut BufWriter::new(io::stdout().lock()),
+    )
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::parse_noise;
+    use yass::parser::parse;
+    use yass::shot_format::read_shots;
+
+    #[test]
+    fn test_sample() {
+        // a bell pair: the two outcomes always agree, and the third qubit is flipped to 1.
+        let mut loaded = Loaded {
+            circuit: parse("H 0\nCX 0 1\nX 2\nM 0 1 2").unwrap(),
+            noise: None,
+        };
+        for format in [ShotFormat::Ascii01, ShotFormat::B8, ShotFormat::Hits] {
+            let mut written = vec![];
+            sample(&loaded, 50, format, 3, &mut written).unwrap();
+            let shots = read_shots(&written[..], format, 3).unwrap();
+            assert_eq!(shots.len(), 50);
+            assert!(shots.iter().all(|shot| shot[0] == shot[1] && shot[2]));
+            assert!(shots.iter().any(|shot| shot[0]) && shots.iter().any(|shot| !shot[0]));
+        }
+
+        // the same seed gives the same shots, and noise changes them.
+        let mut first = vec![];
+        sample(&loaded, 20, ShotFormat::Ascii01, 7, &mut first).unwrap();
+        let mut again = vec![];
+        sample(&loaded, 20, ShotFormat::Ascii01, 7, &mut again).unwrap();
+        assert_eq!(first, again);
+        loaded.noise = Some((String::new(), parse_noise("measurement:1").unwrap()));
+        let mut flipped = vec![];
+        sample(&loaded, 20, ShotFormat::Hits, 7, &mut flipped).unwrap();
+        let shots = read_shots(&flipped[..], ShotFormat::Hits, 3).unwrap();
+        assert!(shots.iter().all(|shot| shot[0] == shot[1] && !shot[2]));
+    }
+}
@@ -0,0 +1,39 @@
+use crate::Args;
+use std::time::Duration;
+use yass::server::{Server, ServerLimits};
+
+// yass serve: runs the REST server (see the server module) until it's killed.
+
+pub fn run(args: &[String]) -> Result<(), String> {
+    let args = Args::parse(
+        args,
+        &[
+            "addr",
+            "max-qubits",
+            "max-shots",
+            "max-circuits",
+            "max-work",
+            "max-connections",
+            "timeout",
+        ],
+    )?;
+    if !args.positional.is_empty() {
+        return Err("serve only takes options".to_string());
+    }
+    let defaults = ServerLimits::default();
+    let limits = ServerLimits {
+        max_qubits: args.number("max-qubits", defaults.max_qubits)?,
+        max_shots: args.number("max-shots", defaults.max_shots)?,
+        max_circuits: args.number("max-circuits", defaults.max_circuits)?,
+        max_work: args.number("max-work", defaults.max_work)?,
+        max_connections: args.number("max-connections", defaults.max_connections)?,
+        timeout: Duration::from_secs(args.number("timeout", defaults.timeout.as_secs())?),
+    };
+    let address = args.option("addr").unwrap_or("127.0.0.1:8080");
+    let server = Server::bind(address)
+        .map_err(|error| format!("binding {address}: {error}"))?
+        .with_limits(limits)?;
+    let address = server.local_addr().map_err(|error| error.to_string())?;
+    eprintln!("listening on http://{address}");
+    server.run().map_err(|error| error.to_string())
+}
@@ -0,0 +1,218 @@
+use crate::circuit::Circuit;
+use crate::debugger::Debugger;
+use crate::pauli::PauliString;
+use crate::stabilizer_simulator::StabilizerSimulator;
+
+// the tableau as a picture, one pixel per bit: a row per generator (destabilizers on top,
+// then stabilizers), with the X bits on the left, the Z bits in the middle and the sign in
+// the last column. Set X bits are red, set Z bits blue and negative signs black, so at a
+// few thousand qubits you can still see at a glance whether the tableau is block diagonal,
+// or how far entanglement has spread.
+//
+// Written as PNG (uncompressed, so nothing needs pulling in to write it) or as an SVG heatmap.
+
+const WHITE: [u8; 3] = [255, 255, 255];
+const X_SET: [u8; 3] = [214, 39, 40];
+const Z_SET: [u8; 3] = [31, 119, 180];
+const SIGN_COLUMN: [u8; 3] = [230, 230, 230];
+const NEGATIVE: [u8; 3] = [0, 0, 0];
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TableauBitmap {
+    // how many instructions had run when it was taken.
+    pub step: usize,
+    pub generators: Vec<PauliString>,
+}
+
+impl TableauBitmap {
+    pub fn new(simulator: &StabilizerSimulator, step: usize) -> TableauBitmap {
+        let mut generators = simulator.destabilizers();
+        generators.extend(simulator.stabilizers());
+        TableauBitmap { step, generators }
+    }
+
+    pub fn width(&self) -> usize {
+        // n X columns, n Z columns and the sign, with 2n generators.
+        self.generators.len() + 1
+    }
+
+    pub fn height(&self) -> usize {
+        self.generators.len()
+    }
+
+    pub fn pixel(&self, row: usize, column: usize) -> [u8; 3] {
+        let generator = &self.generators[row];
+        let n = generator.x_bits.len();
+        match column {
+            c if c < n && generator.x_bits[c] => X_SET,
+            c if c >= n && c < 2 * n && generator.z_bits[c - n] => Z_SET,
+            c if c == 2 * n && generator.phase_is_negated => NEGATIVE,
+            c if c == 2 * n => SIGN_COLUMN,
+            _ => WHITE,
+        }
+    }
+
+    pub fn to_svg(&self, scale: usize) -> String {
+        // a rect per colored pixel over a white background.
+        let (width, height) = (self.width() * scale, self.height() * scale);
+        let mut svg = format!(
+            "<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"{width}\" height=\"{height}\" \
+             viewBox=\"0 0 {width} {height}\" shape-rendering=\"crispEdges\">\n\
+             <rect width=\"100%\" height=\"100%\" fill=\"white\"/>\n"
+        );
+        for row in 0..self.height() {
+            for column in 0..self.width() {
+                let [r, g, b] = self.pixel(row, column);
+                if [r, g, b] != WHITE {
+                    svg.push_str(&format!(
+                        "<rect x=\"{}\" y=\"{}\" width=\"{scale}\" height=\"{scale}\" \
+                         fill=\"#{r:02x}{g:02x}{b:02x}\"/>\n",
+                        column * scale,
+                        row * scale
+                    ));
+                }
+            }
+        }
+        svg.push_str("</svg>\n");
+        svg
+    }
+
+    pub fn to_png(&self, scale: usize) -> Vec<u8> {
+        // 8 bit RGB, each pixel blown up to scale x scale.
+        let (width, height) = (self.width() * scale, self.height() * scale);
+        let mut raw = Vec::with_capacity(height * (3 * width + 1));
+        for row in 0..self.height() {
+            let mut line = vec![0u8]; // no filter
+            for column in 0..self.width() {
+                for _ in 0..scale {
+                    line.extend(self.pixel(row, column));
+                }
+            }
+            for _ in 0..scale {
+                raw.extend(&line);
+            }
+        }
+
+        let mut png = b"\x89PNG\r\n\x1a\n".to_vec();
+        let mut header = Vec::with_capacity(13);
+        header.extend((width as u32).to_be_bytes());
+        header.extend((height as u32).to_be_bytes());
+        header.extend([8, 2, 0, 0, 0]); // 8 bit, RGB, deflate, no filtering, not interlaced
+        chunk(&mut png, b"IHDR", &header);
+        chunk(&mut png, b"IDAT", &zlib_stored(&raw));
+        chunk(&mut png, b"IEND", &[]);
+        png
+    }
+}
+
+pub fn tableau_bitmaps(
+    circuit: &Circuit,
+    steps: &[usize],
+    seed: u64,
+) -> Result<Vec<TableauBitmap>, &'static str> {
+    // the tableau after each of the given numbers of instructions, in increasing order. A
+    // step of 0 is the initial state, and circuit.instructions().len() the final one.
+    let mut debugger = Debugger::new(circuit, seed);
+    steps
+        .iter()
+        .map(|&step| {
+            debugger.run_until(step)?;
+            Ok(TableauBitmap::new(debugger.simulator(), step))
+        })
+        .collect()
+}
+
+fn chunk(png: &mut Vec<u8>, kind: &[u8; 4], data: &[u8]) {
+    png.extend((data.len() as u32).to_be_bytes());
+    let start = png.len();
+    png.extend(kind);
+    png.extend(data);
+    let crc = crc32(&png[start..]);
+    png.extend(crc.to_be_bytes());
+}
+
+fn crc32(bytes: &[u8]) -> u32 {
+    let mut crc = !0u32;
+    for &byte in bytes {
+        crc ^= byte as u32;
+        for _ in 0..8 {
+            crc = if crc & 1 == 1 {
+                (crc >> 1) ^ 0xedb8_8320
+            } else {
+                crc >> 1
+            };
+        }
+    }
+    !crc
+}
+
+fn zlib_stored(data: &[u8]) -> Vec<u8> {
+    // a zlib stream of uncompressed deflate blocks, which can hold 65535 bytes each.
+    let mut out = vec![0x78, 0x01];
+    let blocks: Vec<&[u8]> = data.chunks(65535).collect();
+    for (i, block) in blocks.iter().enumerate() {
+        out.push((i + 1 == blocks.len()) as u8);
+        let len = block.len() as u16;
+        out.extend(len.to_le_bytes());
+        out.extend((!len).to_le_bytes());
+        out.extend(*block);
+    }
+    if blocks.is_empty() {
+        out.extend([1, 0, 0, 0xff, 0xff]);
+    }
+    let (mut a, mut b) = (1u32, 0u32);
+    for &byte in data {
+        a = (a + byte as u32) % 65521;
+        b = (b + a) % 65521;
+    }
+    out.extend(((b << 16) | a).to_be_bytes());
+    out
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::gates::Gate;
+    use crate::qubit::Qubit;
+
+    #[test]
+    fn test_tableau_bitmaps() {
+        let mut circuit = Circuit::bell_pair(Qubit(0), Qubit(1));
+        circuit.gate(Gate::Z(Qubit(0)));
+        let bitmaps = tableau_bitmaps(&circuit, &[0, 3], 1).unwrap();
+        let [initial, last] = &bitmaps[..] else {
+            panic!("expected two bitmaps");
+        };
+        assert_eq!((initial.width(), initial.height()), (5, 4));
+        // |00>: destabilizers X0 and X1, stabilizers Z0 and Z1.
+        assert_eq!(initial.pixel(0, 0), X_SET);
+        assert_eq!(initial.pixel(0, 1), WHITE);
+        assert_eq!(initial.pixel(3, 3), Z_SET);
+        assert_eq!(initial.pixel(3, 4), SIGN_COLUMN);
+        // after Z0, XX is negated.
+        let xx = (2..4)
+            .find(|&row| last.pixel(row, 0) == X_SET && last.pixel(row, 1) == X_SET)
+            .unwrap();
+        assert_eq!(last.pixel(xx, 4), NEGATIVE);
+        assert!(tableau_bitmaps(&circuit, &[2, 1], 1).is_err());
+
+        let svg = initial.to_svg(3);
+        assert!(svg.contains("width=\"15\" height=\"12\""));
+        assert_eq!(svg.matches("<rect").count(), 1 + 4 + 4);
+    }
+
+    #[test]
+    fn test_png() {
+        let bitmap = TableauBitmap::new(&StabilizerSimulator::seeded(3), 0);
+        let png = bitmap.to_png(2);
+        assert_eq!(&png[..8], b"\x89PNG\r\n\x1a\n");
+        assert_eq!(&png[12..16], b"IHDR");
+        assert_eq!(&png[16..24], &[0, 0, 0, 14, 0, 0, 0, 12]);
+        assert_eq!(&png[png.len() - 8..png.len() - 4], b"IEND");
+        // the check value every crc32 implementation is tested against.
+        assert_eq!(crc32(b"123456789"), 0xcbf4_3926);
+        // a raw row is a filter byte and 14 RGB pixels, and there are 12 of them.
+        let idat_length = u32::from_be_bytes(png[33..37].try_into().unwrap()) as usize;
+        assert_eq!(idat_length, 2 + 5 + 12 * (1 + 14 * 3) + 4);
+    }
+}
@@ -0,0 +1,212 @@
+use crate::circuit::{Circuit, Instruction};
+use crate::gates::Gate;
+use crate::qasm;
+use crate::qubit::Qubit;
+use serde_json::Value;
+use std::f64::consts::FRAC_PI_2;
+
+// reads the programs Amazon Braket takes, so circuits written for it can be checked here
+// before they're sent off. Both of Braket's IRs are understood: the JSON one (jaqcd),
+//
+//     {
+//       "braketSchemaHeader": {"name": "braket.ir.jaqcd.program", "version": "1"},
+//       "instructions": [{"type": "h", "target": 0}, {"type": "cnot", "control": 0, "target": 1}],
+//       "basis_rotation_instructions": [{"type": "h", "target": 1}]
+//     }
+//
+// and OpenQASM 3, as {"braketSchemaHeader": {"name": "braket.ir.openqasm.program", ...},
+// "source": "..."}, which goes through qasm::parse.
+//
+// Braket measures every qubit at the end of a program that doesn't measure anything itself
+// (and jaqcd never does), after the basis rotations, so the circuit does too. Only the
+// clifford gates are supported: h, s, si, v, vi, x, y, z, i, cnot, cy, cz and swap, plus rx, ry,
+// rz and phaseshift at multiples of pi/2 (which are cliffords up to a global phase). Noise
+// channels and the result types are ignored.
+
+pub fn parse(text: &str) -> Result<Circuit, &'static str> {
+    let program: Value = serde_json::from_str(text).map_err(|_| "Program isn't valid JSON.")?;
+    let schema = program
+        .pointer("/braketSchemaHeader/name")
+        .and_then(Value::as_str);
+    let mut circuit = match schema {
+        Some("braket.ir.openqasm.program") => {
+            let source = program
+                .get("source")
+                .and_then(Value::as_str)
+                .ok_or("OpenQASM program has no source.")?;
+            qasm::parse(source).map_err(|error| error.reason)?
+        }
+        Some("braket.ir.jaqcd.program") | None => {
+            let mut circuit = Circuit::new(0);
+            for key in ["instructions", "basis_rotation_instructions"] {
+                let instructions = match program.get(key) {
+                    None if key == "instructions" => return Err("Program has no instructions."),
+                    None => continue,
+                    Some(list) => list.as_array().ok_or("Expected a list of instructions.")?,
+                };
+                for instruction in instructions {
+                    for gate in gates(instruction)? {
+                        circuit.gate(gate);
+                    }
+                }
+            }
+            circuit
+        }
+        Some(_) => return Err("Unsupported Braket program type."),
+    };
+    if circuit.num_measurements() == 0 {
+        for q in 0..circuit.num_qubits() {
+            circuit.push(Instruction::Measure(Qubit(q as u32)));
+        }
+    }
+    Ok(circuit)
+}
+
+fn gates(instruction: &Value) -> Result<Vec<Gate>, &'static str> {
+    // the gates one jaqcd instruction stands for.
+    let qubit = |pointer: &str| {
+        instruction
+            .pointer(pointer)
+            .and_then(Value::as_u64)
+            .and_then(|q| u32::try_from(q).ok())
+            .map(Qubit)
+            .ok_or("Instruction is missing a qubit.")
+    };
+    let kind = instruction
+        .get("type")
+        .and_then(Value::as_str)
+        .ok_or("Instruction has no type.")?;
+    let quarter_turns = || {
+        // a rotation angle in radians, as a number of pi/2s.
+        let angle = instruction
+            .get("angle")
+            .and_then(Value::as_f64)
+            .ok_or("Rotation has no angle.")?;
+        let turns = angle / FRAC_PI_2;
+        if (turns - turns.round()).abs() > 1e-9 {
+            return Err("Rotation isn't a multiple of pi/2, so it isn't clifford.");
+        }
+        Ok((turns.round() as i64).rem_euclid(4) as usize)
+    };
+    let repeat = |gates: Vec<Gate>, times: usize| gates.repeat(times);
+    let sqrt_x = |q| vec![Gate::H(q), Gate::S(q), Gate::H(q)];
+    Ok(match kind {
+        "i" => vec![],
+        "h" => vec![Gate::H(qubit("/target")?)],
+        "x" => vec![Gate::X(qubit("/target")?)],
+        "y" => vec![Gate::Y(qubit("/target")?)],
+        "z" => vec![Gate::Z(qubit("/target")?)],
+        "s" => vec![Gate::S(qubit("/target")?)],
+        "si" => Gate::S(qubit("/target")?).inverse(),
+        "v" => sqrt_x(qubit("/target")?),
+        "vi" => repeat(sqrt_x(qubit("/target")?), 3),
+        "rz" | "phaseshift" => repeat(vec![Gate::S(qubit("/target")?)], quarter_turns()?),
+        "rx" => repeat(sqrt_x(qubit("/target")?), quarter_turns()?),
+        "ry" => {
+            // H then X takes Z to X and X to -Z, like a quarter turn about Y.
+            let q = qubit("/target")?;
+            repeat(vec![Gate::H(q), Gate::X(q)], quarter_turns()?)
+        }
+        "cnot" | "cy" | "cz" | "swap" => {
+            let (a, b) = match kind {
+                "swap" => (qubit("/targets/0")?, qubit("/targets/1")?),
+                _ => (qubit("/control")?, qubit("/target")?),
+            };
+            if a == b {
+                return Err("Two-qubit gates need two different qubits.");
+            }
+            match kind {
+                "cnot" => vec![Gate::Cx(a, b)],
+                "cz" => vec![Gate::Cz(a, b)],
+                "swap" => vec![Gate::Swap(a, b)],
+                _ => {
+                    let mut gates = Gate::S(b).inverse();
+                    gates.extend([Gate::Cx(a, b), Gate::S(b)]);
+                    gates
+                }
+            }
+        }
+        "bit_flip"
+        | "phase_flip"
+        | "pauli_channel"
+        | "depolarizing"
+        | "two_qubit_depolarizing"
+        | "two_qubit_dephasing"
+        | "amplitude_damping"
+        | "generalized_amplitude_damping"
+        | "phase_damping"
+        | "kraus" => vec![],
+        _ => return Err("Instruction isn't a clifford gate this can run."),
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::clifford::CliffordOperator;
+
+    #[test]
+    fn test_jaqcd_program() {
+        let circuit = parse(
+            r#"{
+                "braketSchemaHeader": {"name": "braket.ir.jaqcd.program", "version": "1"},
+                "instructions": [
+                    {"type": "h", "target": 0},
+                    {"type": "cnot", "control": 0, "target": 1},
+                    {"type": "swap", "targets": [1, 2]},
+                    {"type": "depolarizing", "target": 0, "probability": 0.01}
+                ],
+                "results": [{"type": "probability", "targets": [0, 1]}],
+                "basis_rotation_instructions": [{"type": "h", "target": 2}]
+            }"#,
+        )
+        .unwrap();
+        let q = Qubit;
+        assert_eq!(
+            circuit.instructions(),
+            &[
+                Instruction::Gate(Gate::H(q(0))),
+                Instruction::Gate(Gate::Cx(q(0), q(1))),
+                Instruction::Gate(Gate::Swap(q(1), q(2))),
+                Instruction::Gate(Gate::H(q(2))),
+                Instruction::Measure(q(0)),
+                Instruction::Measure(q(1)),
+                Instruction::Measure(q(2)),
+            ]
+        );
+        assert!(parse(r#"{"instructions": [{"type": "t", "target": 0}]}"#).is_err());
+        assert!(parse(r#"{"instructions": [{"type": "rz", "target": 0, "angle": 0.3}]}"#).is_err());
+        assert!(parse(r#"{"instructions": [{"type": "cz", "control": 1, "target": 1}]}"#).is_err());
+    }
+
+    #[test]
+    fn test_rotations_are_cliffords() {
+        // each rotation against the clifford it should be, through their images.
+        let rotation = |kind: &str, quarter_turns: f64| {
+            let instruction = format!(
+                r#"{{"type": "{kind}", "target": 0, "angle": {}}}"#,
+                quarter_turns * FRAC_PI_2
+            );
+            let gates = gates(&serde_json::from_str(&instruction).unwrap()).unwrap();
+            CliffordOperator::from_gates(1, &gates).unwrap()
+        };
+        let clifford = |gates: &[Gate]| CliffordOperator::from_gates(1, gates).unwrap();
+        let q = Qubit(0);
+        assert_eq!(rotation("rz", 1.0), clifford(&[Gate::S(q)]));
+        assert_eq!(rotation("rz", -1.0), clifford(&Gate::S(q).inverse()));
+        assert_eq!(rotation("rx", 2.0), clifford(&[Gate::X(q)]));
+        assert_eq!(rotation("ry", 2.0), clifford(&[Gate::Y(q)]));
+        assert_eq!(rotation("phaseshift", 4.0), clifford(&[]));
+    }
+
+    #[test]
+    fn test_openqasm_program() {
+        let program = serde_json::json!({
+            "braketSchemaHeader": {"name": "braket.ir.openqasm.program", "version": "1"},
+            "source": "OPENQASM 3.0;\nqubit[2] q;\nh q[0];\ncnot q[0], q[1];",
+        });
+        let circuit = parse(&program.to_string()).unwrap();
+        assert_eq!(circuit.instructions().len(), 4);
+        assert_eq!(circuit.num_measurements(), 2);
+    }
+}
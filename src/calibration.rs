@@ -0,0 +1,254 @@
+use crate::noise::{NoiseModel, PerQubitRates};
+use crate::qubit::Qubit;
+use std::collections::BTreeMap;
+
+// turns a device calibration into a pauli noise model. Gate fidelities become depolarizing
+// probabilities through the average gate fidelity of a depolarizing channel,
+//
+//     p = (1 - F) (d + 1) / d,    d = 2^k for a k-qubit gate,
+//
+// and a qubit's decoherence error (a T1/T2 proxy: the chance it decoheres over the span of
+// one gate) is folded into every gate on it by multiplying process fidelities,
+// 1 - p = (1 - p_gate) (1 - p_qubit) ... It's also the qubit's idle depolarization, for
+// moments it sits out. Readout errors become measurement flips.
+//
+// Qubits and pairs the calibration doesn't mention keep the base model's uniform rates, so a
+// calibration should list every coupled pair.
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct NoiseModelBuilder {
+    base: NoiseModel,
+    decoherence_error: BTreeMap<Qubit, f64>,
+    single_qubit_fidelity: BTreeMap<Qubit, f64>,
+    two_qubit_fidelity: BTreeMap<(Qubit, Qubit), f64>,
+    readout_error: BTreeMap<Qubit, f64>,
+}
+
+fn check_probability(p: f64) -> Result<f64, &'static str> {
+    if (0.0..=1.0).contains(&p) {
+        Ok(p)
+    } else {
+        Err("Calibration rates and fidelities must be between 0 and 1.")
+    }
+}
+
+impl Default for NoiseModelBuilder {
+    fn default() -> NoiseModelBuilder {
+        NoiseModelBuilder::new()
+    }
+}
+
+impl NoiseModelBuilder {
+    pub fn new() -> NoiseModelBuilder {
+        NoiseModelBuilder::on_top_of(NoiseModel::noiseless())
+    }
+
+    pub fn on_top_of(base: NoiseModel) -> NoiseModelBuilder {
+        NoiseModelBuilder {
+            base,
+            decoherence_error: BTreeMap::new(),
+            single_qubit_fidelity: BTreeMap::new(),
+            two_qubit_fidelity: BTreeMap::new(),
+            readout_error: BTreeMap::new(),
+        }
+    }
+
+    pub fn decoherence_error(mut self, qubit: Qubit, p: f64) -> NoiseModelBuilder {
+        self.decoherence_error.insert(qubit, p);
+        self
+    }
+
+    pub fn single_qubit_fidelity(mut self, qubit: Qubit, fidelity: f64) -> NoiseModelBuilder {
+        self.single_qubit_fidelity.insert(qubit, fidelity);
+        self
+    }
+
+    pub fn two_qubit_fidelity(mut self, a: Qubit, b: Qubit, fidelity: f64) -> NoiseModelBuilder {
+        self.two_qubit_fidelity
+            .insert((a.min(b), a.max(b)), fidelity);
+        self
+    }
+
+    pub fn readout_error(mut self, qubit: Qubit, p: f64) -> NoiseModelBuilder {
+        self.readout_error.insert(qubit, p);
+        self
+    }
+
+    pub fn build(&self) -> Result<NoiseModel, &'static str> {
+        let survival = |qubit: &Qubit| -> Result<f64, &'static str> {
+            let p = self.decoherence_error.get(qubit).copied().unwrap_or(0.0);
+            Ok(1.0 - check_probability(p)?)
+        };
+        let mut rates = PerQubitRates::default();
+        let single_qubits = self
+            .single_qubit_fidelity
+            .keys()
+            .chain(self.decoherence_error.keys());
+        for qubit in single_qubits {
+            let p_gate = match self.single_qubit_fidelity.get(qubit) {
+                Some(&fidelity) => (1.0 - check_probability(fidelity)?) * 1.5,
+                None => self.base.single_qubit_depolarization,
+            };
+            let p = 1.0 - (1.0 - p_gate) * survival(qubit)?;
+            rates
+                .single_qubit_depolarization
+                .insert(*qubit, check_probability(p)?);
+        }
+        for (&qubit, &p) in &self.decoherence_error {
+            rates
+                .idle_depolarization
+                .insert(qubit, check_probability(p)?);
+        }
+        for (&(a, b), &fidelity) in &self.two_qubit_fidelity {
+            let p_gate = (1.0 - check_probability(fidelity)?) * 1.25;
+            let p = 1.0 - (1.0 - p_gate) * survival(&a)? * survival(&b)?;
+            rates
+                .two_qubit_depolarization
+                .insert((a, b), check_probability(p)?);
+        }
+        for (&qubit, &p) in &self.readout_error {
+            rates.measurement_flip.insert(qubit, check_probability(p)?);
+        }
+        Ok(NoiseModel {
+            per_qubit: Some(rates),
+            ..self.base.clone()
+        })
+    }
+
+    #[cfg(feature = "calibration")]
+    pub fn from_json(text: &str) -> Result<NoiseModelBuilder, &'static str> {
+        // a calibration like
+        //
+        //     {
+        //       "qubits": [{"qubit": 0, "decoherence_error": 0.001,
+        //                   "single_qubit_fidelity": 0.9995, "readout_error": 0.02}, ...],
+        //       "pairs": [{"qubits": [0, 1], "fidelity": 0.99}, ...]
+        //     }
+        //
+        // where every per-qubit field is optional.
+        use serde_json::Value;
+
+        let calibration: Value =
+            serde_json::from_str(text).map_err(|_| "Calibration isn't valid JSON.")?;
+        let qubit = |value: &Value| -> Result<Qubit, &'static str> {
+            value
+                .as_u64()
+                .and_then(|qubit| u32::try_from(qubit).ok())
+                .map(Qubit)
+                .ok_or("Expected a qubit index.")
+        };
+        let number = |value: &Value| value.as_f64().ok_or("Expected a number.");
+        let list = |key: &str| match calibration.get(key) {
+            None => Ok(&[][..]),
+            Some(value) => value
+                .as_array()
+                .map(Vec::as_slice)
+                .ok_or("Expected a list of qubits or pairs."),
+        };
+
+        let mut builder = NoiseModelBuilder::new();
+        for entry in list("qubits")? {
+            let q = qubit(
+                entry
+                    .get("qubit")
+                    .ok_or("Qubit entry is missing its index.")?,
+            )?;
+            if let Some(value) = entry.get("decoherence_error") {
+                builder = builder.decoherence_error(q, number(value)?);
+            }
+            if let Some(value) = entry.get("single_qubit_fidelity") {
+                builder = builder.single_qubit_fidelity(q, number(value)?);
+            }
+            if let Some(value) = entry.get("readout_error") {
+                builder = builder.readout_error(q, number(value)?);
+            }
+        }
+        for entry in list("pairs")? {
+            let Some([a, b]) = entry
+                .get("qubits")
+                .and_then(Value::as_array)
+                .map(Vec::as_slice)
+            else {
+                return Err("Pair entry needs two qubits, like \"qubits\": [0, 1].");
+            };
+            let fidelity = number(
+                entry
+                    .get("fidelity")
+                    .ok_or("Pair entry is missing its fidelity.")?,
+            )?;
+            builder = builder.two_qubit_fidelity(qubit(a)?, qubit(b)?, fidelity);
+        }
+        Ok(builder)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::gates::Gate;
+
+    #[test]
+    fn test_calibration_rates() {
+        let noise = NoiseModelBuilder::on_top_of(NoiseModel::uniform_depolarizing(0.5))
+            .single_qubit_fidelity(Qubit(0), 0.998)
+            .decoherence_error(Qubit(0), 0.001)
+            .decoherence_error(Qubit(1), 0.002)
+            .two_qubit_fidelity(Qubit(1), Qubit(0), 0.992)
+            .readout_error(Qubit(1), 0.03)
+            .build()
+            .unwrap();
+        let close = |a: f64, b: f64| (a - b).abs() < 1e-12;
+        assert!(close(
+            noise.gate_error_probability(&Gate::H(Qubit(0))),
+            1.0 - 0.997 * 0.999
+        ));
+        // qubit 1 only has a decoherence error, on top of the base rate.
+        assert!(close(
+            noise.gate_error_probability(&Gate::S(Qubit(1))),
+            1.0 - 0.5 * 0.998
+        ));
+        assert!(close(
+            noise.gate_error_probability(&Gate::Cx(Qubit(0), Qubit(1))),
+            1.0 - 0.99 * 0.999 * 0.998
+        ));
+        assert_eq!(noise.measurement_flip_probability(Qubit(1)), 0.03);
+        assert_eq!(noise.idle_probability(Qubit(1)), 0.002);
+        // anything the calibration leaves out keeps the base rates.
+        assert_eq!(noise.gate_error_probability(&Gate::H(Qubit(2))), 0.5);
+        assert_eq!(
+            noise.gate_error_probability(&Gate::Cz(Qubit(1), Qubit(2))),
+            0.5
+        );
+        assert_eq!(noise.measurement_flip_probability(Qubit(0)), 0.5);
+
+        assert!(NoiseModelBuilder::new()
+            .readout_error(Qubit(0), 1.5)
+            .build()
+            .is_err());
+    }
+
+    #[cfg(feature = "calibration")]
+    #[test]
+    fn test_calibration_from_json() {
+        let builder = NoiseModelBuilder::from_json(
+            r#"{
+                "qubits": [
+                    {"qubit": 0, "single_qubit_fidelity": 0.998, "readout_error": 0.01},
+                    {"qubit": 1, "decoherence_error": 0.002}
+                ],
+                "pairs": [{"qubits": [0, 1], "fidelity": 0.992}]
+            }"#,
+        )
+        .unwrap();
+        assert_eq!(
+            builder,
+            NoiseModelBuilder::new()
+                .single_qubit_fidelity(Qubit(0), 0.998)
+                .readout_error(Qubit(0), 0.01)
+                .decoherence_error(Qubit(1), 0.002)
+                .two_qubit_fidelity(Qubit(0), Qubit(1), 0.992)
+        );
+        assert!(NoiseModelBuilder::from_json("{\"pairs\": [{\"qubits\": [0]}]}").is_err());
+        assert!(NoiseModelBuilder::from_json("not json").is_err());
+    }
+}
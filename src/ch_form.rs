@@ -0,0 +1,428 @@
+use crate::complex::Complex;
+use crate::gates::Gate;
+use crate::qubit::Qubit;
+use crate::simulator::Simulator;
+use rand::Rng;
+use std::f64::consts::FRAC_1_SQRT_2;
+
+// the CH-form of Bravyi et al., "Simulation of quantum circuits by low-rank stabilizer
+// decompositions" (2019). A stabilizer state is written as
+//
+//     omega * U_C * U_H * |s>
+//
+// where U_H is a layer of hadamards (on the qubits where v is set), s is a basis state, and
+// U_C is a Clifford that fixes |0...0>. U_C is never stored as gates, only by how it
+// conjugates paulis:
+//
+//     U_C^dagger Z_p U_C = Z(G[p])
+//     U_C^dagger X_p U_C = i^gamma[p] X(F[p]) Z(M[p])
+//
+// Unlike the tableau, this keeps track of the global phase, so single amplitudes <x|psi>
+// come out in O(n^2). That's what stabilizer-rank methods need, since they add up
+// amplitudes of many stabilizer states.
+
+fn i_pow(exponent: u8) -> Complex {
+    match exponent % 4 {
+        0 => Complex::ONE,
+        1 => Complex::I,
+        2 => Complex::ONE.scale(-1.0),
+        _ => Complex::I.scale(-1.0),
+    }
+}
+
+fn parity(bits: impl Iterator<Item = bool>) -> bool {
+    bits.fold(false, |parity, bit| parity ^ bit)
+}
+
+#[derive(Debug, Clone)]
+pub struct ChFormSimulator {
+    f: Vec<Vec<bool>>,
+    g: Vec<Vec<bool>>,
+    m: Vec<Vec<bool>>,
+    gamma: Vec<u8>,
+    v: Vec<bool>,
+    s: Vec<bool>,
+    omega: Complex,
+    rand: rand::rngs::StdRng,
+    measurement_record: Vec<bool>,
+    classical_bits: Vec<bool>,
+    sweep_bits: Vec<bool>,
+}
+
+impl ChFormSimulator {
+    pub fn new(num_qubits: usize, seed: u64) -> ChFormSimulator {
+        // |0...0>: U_C and U_H are both the identity.
+        let identity: Vec<Vec<bool>> = (0..num_qubits)
+            .map(|row| (0..num_qubits).map(|column| row == column).collect())
+            .collect();
+        ChFormSimulator {
+            f: identity.clone(),
+            g: identity,
+            m: vec![vec![false; num_qubits]; num_qubits],
+            gamma: vec![0; num_qubits],
+            v: vec![false; num_qubits],
+            s: vec![false; num_qubits],
+            omega: Complex::ONE,
+            rand: rand::SeedableRng::seed_from_u64(seed),
+            measurement_record: Vec::new(),
+            classical_bits: Vec::new(),
+            sweep_bits: Vec::new(),
+        }
+    }
+
+    pub fn set_sweep_bits(&mut self, sweep_bits: &[bool]) {
+        self.sweep_bits = sweep_bits.to_vec();
+    }
+
+    pub fn amplitude(&self, bits: &[bool]) -> Result<Complex, &'static str> {
+        // <bits|psi>, where bits[q] is the value of qubit q.
+        if bits.len() != self.s.len() {
+            return Err("Basis state must have one bit per qubit.");
+        }
+        // U_C^dagger |bits> = i^mu |u> for the u built up here, so the amplitude is
+        // i^mu <u| U_H |s>, which is only nonzero where u agrees with s off the hadamards.
+        let num_qubits = self.s.len();
+        let mut mu: u8 = 0;
+        let mut u = vec![false; num_qubits];
+        for p in (0..num_qubits).filter(|&p| bits[p]) {
+            mu = (mu + self.gamma[p]) % 4;
+            for (u, f) in u.iter_mut().zip(&self.f[p]) {
+                *u ^= f;
+            }
+            if parity((0..num_qubits).map(|j| self.m[p][j] && u[j])) {
+                mu = (mu + 2) % 4;
+            }
+        }
+        if (0..num_qubits).any(|j| !self.v[j] && u[j] != self.s[j]) {
+            return Ok(Complex::ZERO);
+        }
+        let num_hadamards = self.v.iter().filter(|&&v| v).count();
+        let sign = if parity((0..num_qubits).map(|j| self.v[j] && u[j] && self.s[j])) {
+            -1.0
+        } else {
+            1.0
+        };
+        Ok(self.omega * i_pow(mu).scale(sign * FRAC_1_SQRT_2.powi(num_hadamards as i32)))
+    }
+
+    // left multiplication, U_C <- gate * U_C.
+
+    fn left_s(&mut self, q: usize) {
+        for j in 0..self.s.len() {
+            self.m[q][j] ^= self.g[q][j];
+        }
+        self.gamma[q] = (self.gamma[q] + 3) % 4;
+    }
+
+    fn left_cz(&mut self, q: usize, r: usize) {
+        for j in 0..self.s.len() {
+            self.m[q][j] ^= self.g[r][j];
+            self.m[r][j] ^= self.g[q][j];
+        }
+    }
+
+    fn left_cx(&mut self, control: usize, target: usize) {
+        let num_qubits = self.s.len();
+        let overlap = parity((0..num_qubits).map(|j| self.m[control][j] && self.f[target][j]));
+        self.gamma[control] =
+            (self.gamma[control] + self.gamma[target] + if overlap { 2 } else { 0 }) % 4;
+        for j in 0..num_qubits {
+            self.g[target][j] ^= self.g[control][j];
+            self.f[control][j] ^= self.f[target][j];
+            self.m[control][j] ^= self.m[target][j];
+        }
+    }
+
+    // right multiplication, U_C <- U_C * gate. Only needed when folding a hadamard's
+    // superposition back into the form.
+
+    fn right_s(&mut self, q: usize) {
+        for i in 0..self.s.len() {
+            self.m[i][q] ^= self.f[i][q];
+            if self.f[i][q] {
+                self.gamma[i] = (self.gamma[i] + 3) % 4;
+            }
+        }
+    }
+
+    fn right_cz(&mut self, q: usize, r: usize) {
+        for i in 0..self.s.len() {
+            self.m[i][q] ^= self.f[i][r];
+            self.m[i][r] ^= self.f[i][q];
+            if self.f[i][q] && self.f[i][r] {
+                self.gamma[i] = (self.gamma[i] + 2) % 4;
+            }
+        }
+    }
+
+    fn right_cx(&mut self, control: usize, target: usize) {
+        for i in 0..self.s.len() {
+            self.g[i][control] ^= self.g[i][target];
+            self.f[i][target] ^= self.f[i][control];
+            self.m[i][control] ^= self.m[i][target];
+        }
+    }
+
+    fn left_h(&mut self, q: usize) {
+        // H_q U_C U_H |s> works out to a sum of two basis states under U_C U_H,
+        // (-1)^alpha (|t> + i^delta |u>) / sqrt(2), which update_sum folds back into CH-form.
+        let num_qubits = self.s.len();
+        let (f, g, m, v, s) = (&self.f[q], &self.g[q], &self.m[q], &self.v, &self.s);
+        let t: Vec<bool> = (0..num_qubits).map(|j| s[j] ^ (g[j] && v[j])).collect();
+        let u: Vec<bool> = (0..num_qubits)
+            .map(|j| s[j] ^ (f[j] && !v[j]) ^ (m[j] && v[j]))
+            .collect();
+        let alpha = parity((0..num_qubits).map(|j| g[j] && !v[j] && s[j]));
+        let beta = parity((0..num_qubits).map(|j| m[j] && !v[j] && s[j]))
+            ^ parity((0..num_qubits).map(|j| f[j] && v[j] && m[j]))
+            ^ parity((0..num_qubits).map(|j| f[j] && v[j] && s[j]));
+        let delta = (self.gamma[q] + if alpha ^ beta { 2 } else { 0 }) % 4;
+        self.update_sum(t, u, delta, alpha);
+    }
+
+    fn update_sum(&mut self, t: Vec<bool>, u: Vec<bool>, delta: u8, alpha: bool) {
+        // replaces the state with omega * (-1)^alpha * U_C U_H (|t> + i^delta |u>) / sqrt(2).
+        let sign = if alpha { -1.0 } else { 1.0 };
+        if t == u {
+            self.s = t;
+            self.omega = self.omega * (Complex::ONE + i_pow(delta)).scale(sign * FRAC_1_SQRT_2);
+            return;
+        }
+        // shuffle U_C (from the right) so t and u only differ on a single qubit q...
+        let num_qubits = self.s.len();
+        let differ_plain: Vec<usize> = (0..num_qubits)
+            .filter(|&j| !self.v[j] && t[j] != u[j])
+            .collect();
+        let differ_hadamard: Vec<usize> = (0..num_qubits)
+            .filter(|&j| self.v[j] && t[j] != u[j])
+            .collect();
+        let q = if let Some(&q) = differ_plain.first() {
+            for &j in &differ_plain[1..] {
+                self.right_cx(q, j);
+            }
+            for &j in &differ_hadamard {
+                self.right_cz(q, j);
+            }
+            q
+        } else {
+            let q = differ_hadamard[0];
+            for &j in &differ_hadamard[1..] {
+                self.right_cx(j, q);
+            }
+            q
+        };
+        // after the shuffle, the two basis states are y and y with qubit q flipped, where
+        // y[q] == t[q].
+        let y = if t[q] {
+            let mut y = u;
+            y[q] ^= true;
+            y
+        } else {
+            t
+        };
+        // ...then rewrite that one qubit's |y_q> + i^delta |z_q> (behind a hadamard or not)
+        // as omega' S^a H^b |c>.
+        let (phase, a, b, c) = if !self.v[q] {
+            let phase = if y[q] { i_pow(delta) } else { Complex::ONE };
+            let delta = if y[q] { (4 - delta) % 4 } else { delta };
+            (phase, delta & 1 == 1, true, delta >> 1 == 1)
+        } else if delta & 1 == 0 {
+            let c = delta >> 1 == 1;
+            let phase = if c && y[q] {
+                Complex::ONE.scale(-1.0)
+            } else {
+                Complex::ONE
+            };
+            (phase, false, false, c)
+        } else {
+            let phase = (Complex::ONE + i_pow(delta)).scale(FRAC_1_SQRT_2);
+            (phase, true, true, !((delta >> 1 == 1) ^ y[q]))
+        };
+        self.s = y;
+        self.s[q] = c;
+        self.omega = self.omega * phase.scale(sign);
+        if a {
+            self.right_s(q);
+        }
+        self.v[q] = b;
+    }
+
+    fn collapse(&mut self, qubit: Qubit) -> Result<bool, &'static str> {
+        // sample a basis state w of U_H |s>, and read off the outcome of Z(G[q]) on it, which
+        // is what Z_q on the full state turns into once U_C is moved out of the way.
+        let q = qubit.index();
+        if q >= self.s.len() {
+            return Err("Can't measure a qubit outside the simulator.");
+        }
+        let mut outcome = false;
+        for j in 0..self.s.len() {
+            let w = if self.v[j] {
+                self.rand.gen_bool(0.5)
+            } else {
+                self.s[j]
+            };
+            outcome ^= w && self.g[q][j];
+        }
+        self.project_z(q, outcome);
+        Ok(outcome)
+    }
+
+    fn project_z(&mut self, q: usize, outcome: bool) {
+        // replaces the state with its normalized projection onto Z_q = (-1)^outcome.
+        let num_qubits = self.s.len();
+        let t = self.s.clone();
+        let u: Vec<bool> = (0..num_qubits)
+            .map(|j| (self.g[q][j] && self.v[j]) ^ self.s[j])
+            .collect();
+        let flipped = parity((0..num_qubits).map(|j| self.g[q][j] && !self.v[j] && self.s[j]));
+        let delta = if flipped ^ outcome { 2 } else { 0 };
+        if t == u {
+            self.omega = self.omega.scale(FRAC_1_SQRT_2);
+        }
+        self.update_sum(t, u, delta, false);
+    }
+}
+
+impl Simulator for ChFormSimulator {
+    fn num_qubits(&self) -> usize {
+        self.s.len()
+    }
+
+    fn apply_gate(&mut self, gate: &Gate) {
+        // every gate is built out of S, CX, CZ and H, keeping track of the global phase.
+        match *gate {
+            Gate::H(q) => self.left_h(q.index()),
+            Gate::S(q) => self.left_s(q.index()),
+            Gate::Z(q) => {
+                self.left_s(q.index());
+                self.left_s(q.index());
+            }
+            Gate::X(q) => {
+                self.left_h(q.index());
+                self.apply_gate(&Gate::Z(q));
+                self.left_h(q.index());
+            }
+            Gate::Y(q) => {
+                // Y == iXZ
+                self.apply_gate(&Gate::Z(q));
+                self.apply_gate(&Gate::X(q));
+                self.omega = self.omega * Complex::I;
+            }
+            Gate::Cx(control, target) => self.left_cx(control.index(), target.index()),
+            Gate::Cz(a, b) => self.left_cz(a.index(), b.index()),
+            Gate::Swap(a, b) => {
+                self.left_cx(a.index(), b.index());
+                self.left_cx(b.index(), a.index());
+                self.left_cx(a.index(), b.index());
+            }
+        }
+    }
+
+    fn measure(&mut self, qubit: Qubit) -> Result<bool, &'static str> {
+        let outcome = self.collapse(qubit)?;
+        self.measurement_record.push(outcome);
+        Ok(outcome)
+    }
+
+    fn measurement_record(&self) -> &[bool] {
+        &self.measurement_record
+    }
+
+    fn classical_bits(&self) -> &[bool] {
+        &self.classical_bits
+    }
+
+    fn set_classical_bit(&mut self, bit: u32, value: bool) {
+        let bit = bit as usize;
+        if bit >= self.classical_bits.len() {
+            self.classical_bits.resize(bit + 1, false);
+        }
+        self.classical_bits[bit] = value;
+    }
+
+    fn sweep_bits(&self) -> &[bool] {
+        &self.sweep_bits
+    }
+
+    fn reset(&mut self, qubit: Qubit) -> Result<(), &'static str> {
+        if self.collapse(qubit)? {
+            self.apply_gate(&Gate::X(qubit));
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::circuit::{Circuit, Instruction};
+    use crate::dense::DenseSimulator;
+    use rand::SeedableRng;
+
+    fn assert_matches_dense(ch_form: &ChFormSimulator, dense: &DenseSimulator) {
+        let num_qubits = ch_form.num_qubits();
+        for index in 0..1usize << num_qubits {
+            let bits: Vec<bool> = (0..num_qubits).map(|q| index & (1 << q) != 0).collect();
+            let difference = ch_form.amplitude(&bits).unwrap() + dense.amplitude(index).scale(-1.0);
+            assert!(difference.norm_squared() < 1e-12, "amplitude {index}");
+        }
+    }
+
+    #[test]
+    fn test_bell_pair_amplitudes() {
+        let mut simulator = ChFormSimulator::new(2, 0);
+        simulator
+            .run(&Circuit::bell_pair(Qubit(0), Qubit(1)))
+            .unwrap();
+        let amplitude = |bits: &[bool]| simulator.amplitude(bits).unwrap();
+        assert!((amplitude(&[false, false]).re - FRAC_1_SQRT_2).abs() < 1e-12);
+        assert!((amplitude(&[true, true]).re - FRAC_1_SQRT_2).abs() < 1e-12);
+        assert_eq!(amplitude(&[true, false]), Complex::ZERO);
+    }
+
+    #[test]
+    fn test_random_circuits_match_dense_amplitudes() {
+        // global phase included. Measurements follow whichever branch the CH-form picks.
+        let mut rng = rand::rngs::StdRng::seed_from_u64(3);
+        for seed in 0..40 {
+            let num_qubits = rng.gen_range(1..=4u32);
+            let mut ch_form = ChFormSimulator::new(num_qubits as usize, seed);
+            let mut dense = DenseSimulator::new(num_qubits as usize);
+            for _ in 0..30 {
+                let a = rng.gen_range(0..num_qubits);
+                let b = Qubit((a + rng.gen_range(1..num_qubits.max(2))) % num_qubits);
+                let a = Qubit(a);
+                let instruction = match rng.gen_range(0..9) {
+                    0 | 1 => Instruction::Gate(Gate::H(a)),
+                    2 | 3 => Instruction::Gate(Gate::S(a)),
+                    4 if a != b => Instruction::Gate(Gate::Cx(a, b)),
+                    5 if a != b => Instruction::Gate(Gate::Cz(a, b)),
+                    6 if a != b => Instruction::Gate(Gate::Swap(a, b)),
+                    7 => {
+                        Instruction::Gate([Gate::X(a), Gate::Y(a), Gate::Z(a)][rng.gen_range(0..3)])
+                    }
+                    _ => Instruction::Measure(a),
+                };
+                match instruction {
+                    Instruction::Gate(gate) => {
+                        ch_form.apply_gate(&gate);
+                        dense.apply_gate(&gate);
+                    }
+                    Instruction::Measure(qubit) => {
+                        let outcome = ch_form.measure(qubit).unwrap();
+                        let probability = dense.probability_of_one(qubit);
+                        let expected = if outcome {
+                            probability
+                        } else {
+                            1.0 - probability
+                        };
+                        assert!(expected > 0.25, "measured an impossible outcome");
+                        dense.collapse(qubit, outcome);
+                    }
+                    _ => unreachable!(),
+                }
+                assert_matches_dense(&ch_form, &dense);
+            }
+        }
+    }
+}
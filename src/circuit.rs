@@ -0,0 +1,1456 @@
+use crate::gates::Gate;
+use crate::qubit::Qubit;
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt;
+use std::ops::{Bound, RangeBounds};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Instruction {
+    Gate(Gate),
+    // measure a qubit in the Z basis, appending the outcome
+    // to the measurement record.
+    Measure(Qubit),
+    // put a qubit in |0> (Reset), |+> (PrepX) or |+i> (PrepY), whatever state it was in.
+    // Unlike a measurement, nothing is added to the measurement record.
+    Reset(Qubit),
+    PrepX(Qubit),
+    PrepY(Qubit),
+    // classically controlled gate: applied only if the measurement `lookback` steps back in
+    // the record came out 1. A lookback of 1 is the most recent measurement, like stim's rec[-1].
+    Conditional { lookback: u32, gate: Gate },
+    // the same two, but through a classical register bit instead of the record position
+    // (see Circuit::add_classical_register). The measurement still goes in the record too.
+    MeasureToBit { qubit: Qubit, bit: u32 },
+    ConditionalOnBit { bit: u32, gate: Gate },
+    // gate applied only if sweep bit `bit` is 1 for this shot. Sweep bits are per-shot inputs
+    // (stim's sweep[k]), so one circuit can cover several variants of an experiment.
+    SweepConditional { bit: u32, gate: Gate },
+    // marks the end of a moment (a layer of instructions that happen at the same time).
+    // Doesn't do anything to the state, it only gives the circuit a time structure.
+    Tick,
+}
+
+impl Instruction {
+    pub fn qubits(&self) -> Vec<Qubit> {
+        match self {
+            Instruction::Gate(gate) => gate.qubits(),
+            Instruction::Measure(qubit)
+            | Instruction::Reset(qubit)
+            | Instruction::PrepX(qubit)
+            | Instruction::PrepY(qubit) => vec![*qubit],
+            Instruction::MeasureToBit { qubit, .. } => vec![*qubit],
+            Instruction::Conditional { gate, .. }
+            | Instruction::ConditionalOnBit { gate, .. }
+            | Instruction::SweepConditional { gate, .. } => gate.qubits(),
+            Instruction::Tick => vec![],
+        }
+    }
+
+    pub fn remap(&self, map: impl Fn(Qubit) -> Qubit) -> Instruction {
+        match self {
+            Instruction::Gate(gate) => Instruction::Gate(gate.remap(map)),
+            Instruction::Measure(qubit) => Instruction::Measure(map(*qubit)),
+            Instruction::Reset(qubit) => Instruction::Reset(map(*qubit)),
+            Instruction::PrepX(qubit) => Instruction::PrepX(map(*qubit)),
+            Instruction::PrepY(qubit) => Instruction::PrepY(map(*qubit)),
+            Instruction::Conditional { lookback, gate } => Instruction::Conditional {
+                lookback: *lookback,
+                gate: gate.remap(map),
+            },
+            Instruction::MeasureToBit { qubit, bit } => Instruction::MeasureToBit {
+                qubit: map(*qubit),
+                bit: *bit,
+            },
+            Instruction::ConditionalOnBit { bit, gate } => Instruction::ConditionalOnBit {
+                bit: *bit,
+                gate: gate.remap(map),
+            },
+            Instruction::SweepConditional { bit, gate } => Instruction::SweepConditional {
+                bit: *bit,
+                gate: gate.remap(map),
+            },
+            Instruction::Tick => Instruction::Tick,
+        }
+    }
+
+    pub fn is_two_qubit_gate(&self) -> bool {
+        match self {
+            Instruction::Gate(gate)
+            | Instruction::Conditional { gate, .. }
+            | Instruction::ConditionalOnBit { gate, .. }
+            | Instruction::SweepConditional { gate, .. } => gate.qubits().len() == 2,
+            _ => false,
+        }
+    }
+
+    pub(crate) fn with_gate(&self, gate: Gate) -> Instruction {
+        // the same instruction with its gate swapped out, keeping any classical control.
+        match *self {
+            Instruction::Conditional { lookback, .. } => {
+                Instruction::Conditional { lookback, gate }
+            }
+            Instruction::ConditionalOnBit { bit, .. } => {
+                Instruction::ConditionalOnBit { bit, gate }
+            }
+            Instruction::SweepConditional { bit, .. } => {
+                Instruction::SweepConditional { bit, gate }
+            }
+            _ => Instruction::Gate(gate),
+        }
+    }
+
+    pub(crate) fn preparation_gates(&self) -> Vec<Gate> {
+        // preparations are a Z-basis reset followed by these gates, which rotate |0> into
+        // the basis state we're after.
+        match *self {
+            Instruction::PrepX(qubit) => vec![Gate::H(qubit)],
+            Instruction::PrepY(qubit) => vec![Gate::H(qubit), Gate::S(qubit)],
+            _ => vec![],
+        }
+    }
+}
+
+impl fmt::Display for Instruction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        // one line of the text format (see the parser module), which parses back to the same
+        // instruction. Classically controlled paulis use stim's CX rec[-1] 2 spelling, and
+        // every other controlled gate goes through IF.
+        let controlled = |f: &mut fmt::Formatter<'_>, control: String, gate: &Gate| match *gate {
+            Gate::X(qubit) | Gate::Y(qubit) | Gate::Z(qubit) => {
+                write!(f, "C{} {control} {qubit}", gate.name())
+            }
+            _ => write!(f, "IF {control} {gate}"),
+        };
+        match self {
+            Instruction::Gate(gate) => write!(f, "{gate}"),
+            Instruction::Measure(qubit) => write!(f, "M {qubit}"),
+            Instruction::Reset(qubit) => write!(f, "R {qubit}"),
+            Instruction::PrepX(qubit) => write!(f, "RX {qubit}"),
+            Instruction::PrepY(qubit) => write!(f, "RY {qubit}"),
+            Instruction::Conditional { lookback, gate } => {
+                controlled(f, format!("rec[-{lookback}]"), gate)
+            }
+            Instruction::MeasureToBit { qubit, bit } => write!(f, "M bit[{bit}] {qubit}"),
+            Instruction::ConditionalOnBit { bit, gate } => {
+                controlled(f, format!("bit[{bit}]"), gate)
+            }
+            Instruction::SweepConditional { bit, gate } => {
+                controlled(f, format!("sweep[{bit}]"), gate)
+            }
+            Instruction::Tick => write!(f, "TICK"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LightconeTarget {
+    // an index into the measurement record.
+    Measurement(usize),
+    Detector(usize),
+}
+
+// the part of a circuit that can influence a target, from Circuit::lightcone_of, as a circuit of
+// its own on just the qubits involved.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Lightcone {
+    pub circuit: Circuit,
+    // qubits[new] is the original qubit that new qubit stands for.
+    pub qubits: Vec<Qubit>,
+    // where the kept instructions, and the kept measurements, were in the original circuit
+    // and its record.
+    pub instructions: Vec<usize>,
+    pub measurements: Vec<usize>,
+}
+
+// one difference between two circuits, found by Circuit::diff. The moment is the one the
+// instruction lands in on its own side (see assign_moments).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum InstructionDiff {
+    Removed {
+        moment: usize,
+        instruction: Instruction,
+    },
+    Added {
+        moment: usize,
+        instruction: Instruction,
+    },
+    // an instruction replaced by another on some of the same qubits, in the same moment.
+    Changed {
+        moment: usize,
+        before: Instruction,
+        after: Instruction,
+    },
+}
+
+impl InstructionDiff {
+    pub fn moment(&self) -> usize {
+        match *self {
+            InstructionDiff::Removed { moment, .. }
+            | InstructionDiff::Added { moment, .. }
+            | InstructionDiff::Changed { moment, .. } => moment,
+        }
+    }
+
+    fn first_qubit(&self) -> Option<Qubit> {
+        match self {
+            InstructionDiff::Removed { instruction, .. }
+            | InstructionDiff::Added { instruction, .. }
+            | InstructionDiff::Changed {
+                before: instruction,
+                ..
+            } => instruction.qubits().into_iter().min(),
+        }
+    }
+}
+
+impl fmt::Display for InstructionDiff {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        // like a line of a unified diff, with the moment in front.
+        match self {
+            InstructionDiff::Removed {
+                moment,
+                instruction,
+            } => write!(f, "moment {moment}: - {instruction}"),
+            InstructionDiff::Added {
+                moment,
+                instruction,
+            } => write!(f, "moment {moment}: + {instruction}"),
+            InstructionDiff::Changed {
+                moment,
+                before,
+                after,
+            } => write!(f, "moment {moment}: ~ {before} -> {after}"),
+        }
+    }
+}
+
+// a named block of classical bits, like a QASM creg. Registers are laid out one after the
+// other, so bit k of the nth register is a single flat bit index.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ClassicalRegister {
+    pub name: String,
+    pub offset: u32,
+    pub size: u32,
+}
+
+// a parity of measurement outcomes that comes out 0 every shot when nothing goes wrong, like
+// stim's DETECTOR, so a 1 flags an error. Measurements are numbered from 0 in the order they
+// happen. The coordinates are only for decoders and plots.
+//
+// An erasure flag is a detector that heralds an error rather than checking a parity, like the
+// readout of an erasure-conversion ancilla. When it fires, decoders know where the error
+// happened (see matching::MatchingGraph).
+#[derive(Debug, Clone, PartialEq)]
+pub struct Detector {
+    pub measurements: Vec<usize>,
+    pub coords: Vec<f64>,
+    pub erasure_flag: bool,
+}
+
+// a flat list of instructions, plus the size of the register they act on.
+// The register grows to cover any qubit an instruction mentions, so you only
+// need to declare its size up front if you want trailing idle qubits.
+//
+// Qubits can also be annotated with coordinates (QubitCoords(q, [x, y, ...])), which don't
+// change what the circuit does, but let decoders and plots place qubits geometrically.
+// Detectors and logical observables are annotations on the measurement record in the same
+// spirit: they say which outcomes to combine, but don't change how the circuit runs.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Circuit {
+    num_qubits: usize,
+    instructions: Vec<Instruction>,
+    qubit_coords: BTreeMap<Qubit, Vec<f64>>,
+    classical_registers: Vec<ClassicalRegister>,
+    detectors: Vec<Detector>,
+    observables: Vec<Vec<usize>>,
+    // kept up to date by push, so resolving a lookback doesn't rescan the whole circuit.
+    num_measurements: usize,
+}
+
+fn parity_of(indices: &[usize], measurements: &[bool]) -> bool {
+    indices
+        .iter()
+        .filter(|&&index| measurements.get(index).is_some_and(|&bit| bit))
+        .count()
+        % 2
+        == 1
+}
+
+impl Circuit {
+    pub fn new(num_qubits: usize) -> Circuit {
+        Circuit {
+            num_qubits,
+            instructions: Vec::new(),
+            qubit_coords: BTreeMap::new(),
+            classical_registers: Vec::new(),
+            detectors: Vec::new(),
+            observables: Vec::new(),
+            num_measurements: 0,
+        }
+    }
+
+    pub fn bell_pair(a: Qubit, b: Qubit) -> Circuit {
+        // (|00> + |11>)/sqrt(2) on qubits a and b, assuming they start in |0>.
+        Circuit::ghz(&[a, b])
+    }
+
+    pub fn ghz(qubits: &[Qubit]) -> Circuit {
+        // (|0...0> + |1...1>)/sqrt(2) on the given qubits, assuming they start in |0>.
+        // Hadamard the first qubit, then fan it out with a chain of CXs.
+        let mut circuit = Circuit::default();
+        if let Some(&first) = qubits.first() {
+            circuit.gate(Gate::H(first));
+        }
+        for pair in qubits.windows(2) {
+            circuit.gate(Gate::Cx(pair[0], pair[1]));
+        }
+        circuit
+    }
+
+    pub fn teleportation(source: Qubit, ancilla: Qubit, target: Qubit) -> Circuit {
+        // moves the state of `source` onto `target` (which, like `ancilla`, should start in |0>).
+        // Share a bell pair between ancilla and target, measure source and ancilla in the bell
+        // basis, then fix up the target with X if the ancilla measured 1 and Z if the source did.
+        let mut circuit = Circuit::bell_pair(ancilla, target);
+        circuit
+            .gate(Gate::Cx(source, ancilla))
+            .gate(Gate::H(source))
+            .measure(source)
+            .measure(ancilla)
+            .conditional(1, Gate::X(target))
+            .conditional(2, Gate::Z(target));
+        circuit
+    }
+
+    pub fn t_injection(data: Qubit, ancilla: Qubit) -> Circuit {
+        // applies T to `data` by consuming a magic state |T> = T|+> held in `ancilla`.
+        // After the CX, measuring the ancilla leaves T|psi> on data if the outcome is 0, and
+        // T^dagger|psi> if it's 1, which the S correction turns back into T|psi>.
+        //
+        // The simulator can't represent |T>, so this only builds the gadget. Preparing the
+        // ancilla (e.g. from a distillation circuit) is up to whoever runs it.
+        let mut circuit = Circuit::new(0);
+        circuit
+            .gate(Gate::Cx(data, ancilla))
+            .measure(ancilla)
+            .conditional(1, Gate::S(data));
+        circuit
+    }
+
+    pub fn conditional(&mut self, lookback: u32, gate: Gate) -> &mut Circuit {
+        self.push(Instruction::Conditional { lookback, gate });
+        self
+    }
+
+    pub fn empty_like(&self) -> Circuit {
+        // same register and annotations, but no instructions. Handy for passes that
+        // rebuild a circuit instruction by instruction.
+        Circuit {
+            num_qubits: self.num_qubits,
+            instructions: Vec::new(),
+            qubit_coords: self.qubit_coords.clone(),
+            classical_registers: self.classical_registers.clone(),
+            detectors: self.detectors.clone(),
+            observables: self.observables.clone(),
+            num_measurements: 0,
+        }
+    }
+
+    pub fn append(&mut self, other: &Circuit) -> Result<(), &'static str> {
+        // other's instructions after this circuit's, on the same qubits.
+        self.append_at(other, 0)
+    }
+
+    pub fn append_at(&mut self, other: &Circuit, qubit_offset: usize) -> Result<(), &'static str> {
+        // other's instructions after this circuit's, with other's qubit q moved to
+        // q + qubit_offset, so a subcircuit built on qubits 0..k can be placed anywhere.
+        // Annotations come along: qubit coords move with their qubits, detectors and
+        // observables shift to the measurements' new places in the record (observable k of
+        // other adds to observable k here), and classical registers are matched by name,
+        // with other's new ones declared after this circuit's. Lookbacks and sweep bits are
+        // relative to the whole circuit, so they're copied as they are.
+        let offset = |qubit: Qubit| Qubit((qubit.index() + qubit_offset) as u32);
+        let mut bit_map = BTreeMap::new();
+        for register in &other.classical_registers {
+            let offset = match self.classical_register(&register.name) {
+                Some(existing) if existing.size == register.size => existing.offset,
+                Some(_) => return Err("Classical registers with the same name differ in size."),
+                None => self.add_classical_register(&register.name, register.size)?,
+            };
+            for k in 0..register.size {
+                bit_map.insert(register.offset + k, offset + k);
+            }
+        }
+        let map_bit = |bit: u32| *bit_map.get(&bit).unwrap_or(&bit);
+        let first_measurement = self.num_measurements;
+        self.num_qubits = self.num_qubits.max(other.num_qubits + qubit_offset);
+        for instruction in &other.instructions {
+            self.push(match instruction.remap(offset) {
+                Instruction::MeasureToBit { qubit, bit } => Instruction::MeasureToBit {
+                    qubit,
+                    bit: map_bit(bit),
+                },
+                Instruction::ConditionalOnBit { bit, gate } => Instruction::ConditionalOnBit {
+                    bit: map_bit(bit),
+                    gate,
+                },
+                instruction => instruction,
+            });
+        }
+        for (qubit, coords) in &other.qubit_coords {
+            self.qubit_coords.insert(offset(*qubit), coords.clone());
+        }
+        for detector in &other.detectors {
+            self.detectors.push(Detector {
+                measurements: detector
+                    .measurements
+                    .iter()
+                    .map(|m| m + first_measurement)
+                    .collect(),
+                ..detector.clone()
+            });
+        }
+        if other.observables.len() > self.observables.len() {
+            self.observables.resize(other.observables.len(), Vec::new());
+        }
+        for (observable, measurements) in self.observables.iter_mut().zip(&other.observables) {
+            observable.extend(measurements.iter().map(|m| m + first_measurement));
+        }
+        Ok(())
+    }
+
+    pub fn remap_qubits(&self, map: &[Option<Qubit>]) -> Result<Circuit, &'static str> {
+        // the same circuit with qubit q renamed map[q], and qubits mapped to None dropped
+        // along with their coords. The register shrinks or grows to fit the new names. The
+        // measurement record doesn't change, so the other annotations carry over as they are.
+        let renamed = |qubit: Qubit| map.get(qubit.index()).copied().flatten();
+        let mut remapped = Circuit {
+            num_qubits: map
+                .iter()
+                .flatten()
+                .map(|q| q.index() + 1)
+                .max()
+                .unwrap_or(0),
+            instructions: Vec::with_capacity(self.instructions.len()),
+            qubit_coords: BTreeMap::new(),
+            num_measurements: 0,
+            ..self.empty_like()
+        };
+        for instruction in &self.instructions {
+            if instruction.qubits().iter().any(|&q| renamed(q).is_none()) {
+                return Err("Instruction acts on a qubit the map drops.");
+            }
+            remapped.push(instruction.remap(|q| renamed(q).unwrap()));
+        }
+        for (qubit, coords) in &self.qubit_coords {
+            if let Some(qubit) = renamed(*qubit) {
+                remapped.qubit_coords.insert(qubit, coords.clone());
+            }
+        }
+        Ok(remapped)
+    }
+
+    pub fn slice(&self, range: impl RangeBounds<usize>) -> Result<Circuit, &'static str> {
+        // the instructions in range, as a circuit of their own on the same register, with the
+        // same coords and classical registers. Detectors are kept, renumbered, when every
+        // measurement they read is inside the slice, and dropped otherwise. Observables keep
+        // their numbers, and come out empty unless they're entirely inside. A conditional
+        // reading a measurement, or a classical bit written, from before the slice is an
+        // error, since the slice on its own would read something else.
+        let start = match range.start_bound() {
+            Bound::Included(&start) => start,
+            Bound::Excluded(&start) => start + 1,
+            Bound::Unbounded => 0,
+        };
+        let end = match range.end_bound() {
+            Bound::Included(&end) => end + 1,
+            Bound::Excluded(&end) => end,
+            Bound::Unbounded => self.instructions.len(),
+        };
+        if start > end || end > self.instructions.len() {
+            return Err("Slice is out of the circuit's range.");
+        }
+        let is_measurement = |instruction: &Instruction| {
+            matches!(
+                instruction,
+                Instruction::Measure(_) | Instruction::MeasureToBit { .. }
+            )
+        };
+        let before = self.instructions[..start]
+            .iter()
+            .filter(|instruction| is_measurement(instruction))
+            .count();
+        // classical bits written before the slice and not yet written again inside it.
+        let mut stale: BTreeSet<u32> = self.instructions[..start]
+            .iter()
+            .filter_map(|instruction| match *instruction {
+                Instruction::MeasureToBit { bit, .. } => Some(bit),
+                _ => None,
+            })
+            .collect();
+        let mut slice = self.empty_like();
+        slice.detectors.clear();
+        slice.observables.clear();
+        for instruction in &self.instructions[start..end] {
+            match *instruction {
+                Instruction::Conditional { lookback, .. }
+                    if lookback as usize > slice.num_measurements =>
+                {
+                    return Err("Slice cuts a conditional off from the measurement it reads.");
+                }
+                Instruction::ConditionalOnBit { bit, .. } if stale.contains(&bit) => {
+                    return Err("Slice cuts a conditional off from the classical bit it reads.");
+                }
+                Instruction::MeasureToBit { bit, .. } => {
+                    stale.remove(&bit);
+                }
+                _ => {}
+            }
+            slice.push(*instruction);
+        }
+        let window = before..before + slice.num_measurements;
+        let inside = |measurements: &[usize]| measurements.iter().all(|m| window.contains(m));
+        let renumber = |measurements: &[usize]| measurements.iter().map(|m| m - before).collect();
+        for detector in &self.detectors {
+            if inside(&detector.measurements) {
+                slice.detectors.push(Detector {
+                    measurements: renumber(&detector.measurements),
+                    ..detector.clone()
+                });
+            }
+        }
+        slice.observables = self
+            .observables
+            .iter()
+            .map(|observable| {
+                if inside(observable) {
+                    renumber(observable)
+                } else {
+                    vec![]
+                }
+            })
+            .collect();
+        Ok(slice)
+    }
+
+    pub fn lightcone_of(&self, target: LightconeTarget) -> Result<Lightcone, &'static str> {
+        // walks backwards from the target's measurements, keeping whatever can reach them:
+        // like transpiler::eliminate_dead_gates, a qubit is live when something later on it
+        // matters, gates touching a live qubit are kept and make all their qubits live, and a
+        // kept reset kills its qubit. Measurements of live qubits are kept too, since they
+        // collapse it. On top of that, a kept controlled gate needs the measurement (or the
+        // latest write of the bit) it reads, so that's pulled in along with its own past.
+        //
+        // The lightcone has its own, shorter record, so lookbacks are recounted, detectors
+        // entirely inside it are renumbered, and observables are dropped.
+        let mut record_index = vec![None; self.instructions.len()];
+        let mut reads = vec![None; self.instructions.len()];
+        let mut count: usize = 0;
+        for (index, instruction) in self.instructions.iter().enumerate() {
+            match *instruction {
+                Instruction::Measure(_) | Instruction::MeasureToBit { .. } => {
+                    record_index[index] = Some(count);
+                    count += 1;
+                }
+                Instruction::Conditional { lookback, .. } => {
+                    reads[index] = count.checked_sub(lookback as usize);
+                }
+                _ => {}
+            }
+        }
+        let mut needed: BTreeSet<usize> = match target {
+            LightconeTarget::Measurement(measurement) if measurement < count => {
+                BTreeSet::from([measurement])
+            }
+            LightconeTarget::Detector(detector) if detector < self.detectors.len() => self
+                .detectors[detector]
+                .measurements
+                .iter()
+                .copied()
+                .collect(),
+            _ => return Err("Lightcone target isn't in the circuit."),
+        };
+        let mut needed_bits = BTreeSet::new();
+        let mut live = vec![false; self.num_qubits];
+        let mut keep = vec![false; self.instructions.len()];
+        for (index, instruction) in self.instructions.iter().enumerate().rev() {
+            let qubits = instruction.qubits();
+            let touches_live = qubits.iter().any(|qubit| live[qubit.index()]);
+            keep[index] = match *instruction {
+                Instruction::Measure(_) | Instruction::MeasureToBit { .. } => {
+                    let wanted = needed.remove(&record_index[index].unwrap());
+                    let wanted_bit = match *instruction {
+                        Instruction::MeasureToBit { bit, .. } => needed_bits.remove(&bit),
+                        _ => false,
+                    };
+                    wanted || wanted_bit || touches_live
+                }
+                Instruction::Reset(qubit)
+                | Instruction::PrepX(qubit)
+                | Instruction::PrepY(qubit) => {
+                    live[qubit.index()] = false;
+                    touches_live
+                }
+                Instruction::Tick => true,
+                _ => touches_live,
+            };
+            if !keep[index] {
+                continue;
+            }
+            match *instruction {
+                Instruction::Reset(_) | Instruction::PrepX(_) | Instruction::PrepY(_) => continue,
+                Instruction::Conditional { .. } => {
+                    needed.insert(reads[index].ok_or("Conditional reads before the record.")?);
+                }
+                Instruction::ConditionalOnBit { bit, .. } => {
+                    needed_bits.insert(bit);
+                }
+                _ => {}
+            }
+            for qubit in qubits {
+                live[qubit.index()] = true;
+            }
+        }
+
+        let mut lightcone = self.empty_like();
+        lightcone.detectors.clear();
+        lightcone.observables.clear();
+        let mut instructions = vec![];
+        let mut measurements = vec![];
+        let mut new_index = BTreeMap::new();
+        let mut used = vec![false; self.num_qubits];
+        for (index, instruction) in self.instructions.iter().enumerate() {
+            if !keep[index] {
+                continue;
+            }
+            instructions.push(index);
+            for qubit in instruction.qubits() {
+                used[qubit.index()] = true;
+            }
+            if let Some(measurement) = record_index[index] {
+                new_index.insert(measurement, measurements.len());
+                measurements.push(measurement);
+            }
+            lightcone.push(match *instruction {
+                Instruction::Conditional { gate, .. } => Instruction::Conditional {
+                    lookback: (lightcone.num_measurements - new_index[&reads[index].unwrap()])
+                        as u32,
+                    gate,
+                },
+                instruction => instruction,
+            });
+        }
+        for detector in &self.detectors {
+            if let Some(renumbered) = detector
+                .measurements
+                .iter()
+                .map(|m| new_index.get(m).copied())
+                .collect::<Option<Vec<usize>>>()
+            {
+                lightcone.detectors.push(Detector {
+                    measurements: renumbered,
+                    ..detector.clone()
+                });
+            }
+        }
+        let qubits: Vec<Qubit> = Qubit::range(self.num_qubits)
+            .filter(|qubit| used[qubit.index()])
+            .collect();
+        let mut map = vec![None; self.num_qubits];
+        for (new, qubit) in qubits.iter().enumerate() {
+            map[qubit.index()] = Some(Qubit(new as u32));
+        }
+        Ok(Lightcone {
+            circuit: lightcone.remap_qubits(&map)?,
+            qubits,
+            instructions,
+            measurements,
+        })
+    }
+
+    pub fn num_qubits(&self) -> usize {
+        self.num_qubits
+    }
+
+    pub fn instructions(&self) -> &[Instruction] {
+        &self.instructions
+    }
+
+    pub fn push(&mut self, instruction: Instruction) {
+        for qubit in instruction.qubits() {
+            self.num_qubits = self.num_qubits.max(qubit.index() + 1);
+        }
+        if let Instruction::Measure(_) | Instruction::MeasureToBit { .. } = instruction {
+            self.num_measurements += 1;
+        }
+        self.instructions.push(instruction);
+    }
+
+    pub fn gate(&mut self, gate: Gate) -> &mut Circuit {
+        self.push(Instruction::Gate(gate));
+        self
+    }
+
+    pub fn measure(&mut self, qubit: Qubit) -> &mut Circuit {
+        self.push(Instruction::Measure(qubit));
+        self
+    }
+
+    pub fn reset(&mut self, qubit: Qubit) -> &mut Circuit {
+        self.push(Instruction::Reset(qubit));
+        self
+    }
+
+    pub fn prep_x(&mut self, qubit: Qubit) -> &mut Circuit {
+        self.push(Instruction::PrepX(qubit));
+        self
+    }
+
+    pub fn prep_y(&mut self, qubit: Qubit) -> &mut Circuit {
+        self.push(Instruction::PrepY(qubit));
+        self
+    }
+
+    pub fn measure_to_bit(&mut self, qubit: Qubit, bit: u32) -> &mut Circuit {
+        self.push(Instruction::MeasureToBit { qubit, bit });
+        self
+    }
+
+    pub fn conditional_on_bit(&mut self, bit: u32, gate: Gate) -> &mut Circuit {
+        self.push(Instruction::ConditionalOnBit { bit, gate });
+        self
+    }
+
+    pub fn sweep_conditional(&mut self, bit: u32, gate: Gate) -> &mut Circuit {
+        self.push(Instruction::SweepConditional { bit, gate });
+        self
+    }
+
+    pub fn num_sweep_bits(&self) -> usize {
+        // one more than the highest sweep bit any instruction reads.
+        self.instructions
+            .iter()
+            .filter_map(|instruction| match instruction {
+                Instruction::SweepConditional { bit, .. } => Some(*bit as usize + 1),
+                _ => None,
+            })
+            .max()
+            .unwrap_or(0)
+    }
+
+    pub fn add_classical_register(&mut self, name: &str, size: u32) -> Result<u32, &'static str> {
+        // declares the register and returns the flat index of its first bit.
+        if self.classical_register(name).is_some() {
+            return Err("There's already a classical register with that name.");
+        }
+        let offset = self.num_classical_bits();
+        if offset.checked_add(size).is_none() {
+            return Err("Classical registers add up to too many bits.");
+        }
+        self.classical_registers.push(ClassicalRegister {
+            name: name.to_string(),
+            offset,
+            size,
+        });
+        Ok(offset)
+    }
+
+    pub fn classical_registers(&self) -> &[ClassicalRegister] {
+        &self.classical_registers
+    }
+
+    pub fn classical_register(&self, name: &str) -> Option<&ClassicalRegister> {
+        self.classical_registers
+            .iter()
+            .find(|register| register.name == name)
+    }
+
+    pub fn classical_bit(&self, name: &str, index: u32) -> Option<u32> {
+        // the flat index of name[index].
+        let register = self.classical_register(name)?;
+        (index < register.size).then_some(register.offset + index)
+    }
+
+    pub fn num_classical_bits(&self) -> u32 {
+        self.classical_registers
+            .last()
+            .map_or(0, |register| register.offset + register.size)
+    }
+
+    pub fn check_classical_dataflow(&self) -> Result<(), &'static str> {
+        // every bit an instruction touches has to belong to a declared register, and no
+        // conditional can read a bit before some measurement has written it.
+        let mut written = vec![false; self.num_classical_bits() as usize];
+        for instruction in &self.instructions {
+            match *instruction {
+                Instruction::MeasureToBit { bit, .. } => match written.get_mut(bit as usize) {
+                    Some(written) => *written = true,
+                    None => return Err("Measurement writes to an undeclared classical bit."),
+                },
+                Instruction::ConditionalOnBit { bit, .. } => match written.get(bit as usize) {
+                    Some(true) => {}
+                    Some(false) => {
+                        return Err("Conditional reads a classical bit before it's written.")
+                    }
+                    None => return Err("Conditional reads an undeclared classical bit."),
+                },
+                _ => {}
+            }
+        }
+        Ok(())
+    }
+
+    pub fn num_measurements(&self) -> usize {
+        self.num_measurements
+    }
+
+    fn resolve_lookbacks(&self, lookbacks: &[u32]) -> Result<Vec<usize>, &'static str> {
+        // rec[-k] targets, relative to the end of the circuit so far, as measurement numbers.
+        let num_measurements = self.num_measurements();
+        lookbacks
+            .iter()
+            .map(|&lookback| {
+                if lookback == 0 || lookback as usize > num_measurements {
+                    return Err(
+                        "Measurement record lookback reaches before the first measurement.",
+                    );
+                }
+                Ok(num_measurements - lookback as usize)
+            })
+            .collect()
+    }
+
+    pub fn add_detector(
+        &mut self,
+        lookbacks: &[u32],
+        coords: &[f64],
+    ) -> Result<usize, &'static str> {
+        // declares a detector over the measurements `lookbacks` back from the end of the
+        // circuit so far (1 being the latest), and returns its index.
+        let measurements = self.resolve_lookbacks(lookbacks)?;
+        self.detectors.push(Detector {
+            measurements,
+            coords: coords.to_vec(),
+            erasure_flag: false,
+        });
+        Ok(self.detectors.len() - 1)
+    }
+
+    pub fn add_erasure_flag(
+        &mut self,
+        lookbacks: &[u32],
+        coords: &[f64],
+    ) -> Result<usize, &'static str> {
+        // like add_detector, but the detector heralds errors instead of checking a parity.
+        let detector = self.add_detector(lookbacks, coords)?;
+        self.detectors[detector].erasure_flag = true;
+        Ok(detector)
+    }
+
+    pub fn include_in_observable(
+        &mut self,
+        observable: usize,
+        lookbacks: &[u32],
+    ) -> Result<(), &'static str> {
+        // adds measurements to a logical observable's parity, like OBSERVABLE_INCLUDE.
+        let measurements = self.resolve_lookbacks(lookbacks)?;
+        if observable >= self.observables.len() {
+            self.observables.resize(observable + 1, Vec::new());
+        }
+        self.observables[observable].extend(measurements);
+        Ok(())
+    }
+
+    pub fn detectors(&self) -> &[Detector] {
+        &self.detectors
+    }
+
+    pub fn observables(&self) -> &[Vec<usize>] {
+        &self.observables
+    }
+
+    pub fn detector_values(&self, measurements: &[bool]) -> Vec<bool> {
+        // each detector's parity over a shot's measurement record. Detection events are
+        // where these differ from a noiseless shot's.
+        self.detectors
+            .iter()
+            .map(|detector| parity_of(&detector.measurements, measurements))
+            .collect()
+    }
+
+    pub fn observable_values(&self, measurements: &[bool]) -> Vec<bool> {
+        self.observables
+            .iter()
+            .map(|observable| parity_of(observable, measurements))
+            .collect()
+    }
+
+    pub fn set_qubit_coords(&mut self, qubit: Qubit, coords: &[f64]) -> &mut Circuit {
+        self.num_qubits = self.num_qubits.max(qubit.index() + 1);
+        self.qubit_coords.insert(qubit, coords.to_vec());
+        self
+    }
+
+    pub fn qubit_coords(&self, qubit: Qubit) -> Option<&[f64]> {
+        self.qubit_coords
+            .get(&qubit)
+            .map(|coords| coords.as_slice())
+    }
+
+    pub fn all_qubit_coords(&self) -> impl Iterator<Item = (Qubit, &[f64])> {
+        self.qubit_coords
+            .iter()
+            .map(|(qubit, coords)| (*qubit, coords.as_slice()))
+    }
+
+    pub fn x_layer(&mut self, bits: &[bool]) -> &mut Circuit {
+        // X on every qubit whose bit is set, taking |0...0> to |bits>.
+        for (qubit, &bit) in bits.iter().enumerate() {
+            if bit {
+                self.gate(Gate::X(Qubit(qubit as u32)));
+            }
+        }
+        self
+    }
+
+    pub fn tick(&mut self) -> &mut Circuit {
+        self.push(Instruction::Tick);
+        self
+    }
+
+    pub fn is_scheduled(&self) -> bool {
+        self.instructions.contains(&Instruction::Tick)
+    }
+
+    pub fn assign_moments(&self) -> Vec<Option<usize>> {
+        // the moment each instruction happens in, or None for the TICKs themselves.
+        // If the circuit has TICKs, it's already scheduled and the moment is just how many
+        // TICKs came before the instruction. Otherwise, every instruction is placed as early
+        // as possible: one moment after the latest instruction that touched any of its qubits.
+        //
+        // Measurements and classically controlled gates also keep their order, so listing
+        // the moments one after another leaves the measurement record, and every lookback
+        // into it, as it was. A controlled gate goes at least a moment after the last
+        // measurement before it, and a measurement can share a moment with the controlled
+        // gates before it but not go earlier.
+        if self.is_scheduled() {
+            let mut moment = 0;
+            return self
+                .instructions
+                .iter()
+                .map(|instruction| {
+                    if *instruction == Instruction::Tick {
+                        moment += 1;
+                        None
+                    } else {
+                        Some(moment)
+                    }
+                })
+                .collect();
+        }
+        let mut next_free_moment = vec![0; self.num_qubits];
+        // the earliest moments a measurement and a controlled gate can go in.
+        let (mut next_measurement, mut next_controlled) = (0, 0);
+        self.instructions
+            .iter()
+            .map(|instruction| {
+                let qubits = instruction.qubits();
+                let earliest = match instruction {
+                    Instruction::Measure(_) | Instruction::MeasureToBit { .. } => next_measurement,
+                    Instruction::Conditional { .. } | Instruction::ConditionalOnBit { .. } => {
+                        next_controlled
+                    }
+                    _ => 0,
+                };
+                let moment = qubits
+                    .iter()
+                    .map(|qubit| next_free_moment[qubit.index()])
+                    .max()
+                    .unwrap_or(0)
+                    .max(earliest);
+                for qubit in qubits {
+                    next_free_moment[qubit.index()] = moment + 1;
+                }
+                match instruction {
+                    Instruction::Measure(_) | Instruction::MeasureToBit { .. } => {
+                        next_measurement = moment;
+                        next_controlled = moment + 1;
+                    }
+                    Instruction::Conditional { .. } | Instruction::ConditionalOnBit { .. } => {
+                        next_measurement = next_measurement.max(moment);
+                        next_controlled = next_controlled.max(moment);
+                    }
+                    _ => {}
+                }
+                Some(moment)
+            })
+            .collect()
+    }
+
+    pub fn moments(&self) -> Vec<Vec<Instruction>> {
+        // the instructions grouped into moments, in order, as assign_moments places them.
+        // Moments can be empty, when a scheduled circuit has TICKs back to back.
+        let mut moments: Vec<Vec<Instruction>> = vec![vec![]; self.depth()];
+        for (moment, instruction) in self.assign_moments().into_iter().zip(&self.instructions) {
+            if let Some(moment) = moment {
+                moments[moment].push(*instruction);
+            }
+        }
+        moments
+    }
+
+    pub fn schedule_asap(&self) -> Circuit {
+        // the same circuit with its instructions regrouped into as-early-as-possible moments,
+        // a TICK after each. Any TICKs it already had are dropped first, so this also
+        // reschedules a circuit that was scheduled by hand. Annotations carry over, and the
+        // measurement record comes out in the same order.
+        let mut unscheduled = self.empty_like();
+        for instruction in &self.instructions {
+            if *instruction != Instruction::Tick {
+                unscheduled.push(*instruction);
+            }
+        }
+        let mut scheduled = self.empty_like();
+        for moment in unscheduled.moments() {
+            for instruction in moment {
+                scheduled.push(instruction);
+            }
+            scheduled.tick();
+        }
+        scheduled
+    }
+
+    pub fn depth(&self) -> usize {
+        self.assign_moments()
+            .into_iter()
+            .flatten()
+            .map(|moment| moment + 1)
+            .max()
+            .unwrap_or(0)
+    }
+
+    pub fn two_qubit_depth(&self) -> usize {
+        // for a scheduled circuit, the number of moments that contain a two-qubit gate.
+        // For an unscheduled one, the longest chain of two-qubit gates that have to happen
+        // one after the other -- single-qubit gates and measurements are treated as free.
+        if self.is_scheduled() {
+            let mut moments: Vec<usize> = self
+                .assign_moments()
+                .into_iter()
+                .zip(&self.instructions)
+                .filter(|(_, instruction)| instruction.is_two_qubit_gate())
+                .filter_map(|(moment, _)| moment)
+                .collect();
+            moments.dedup();
+            return moments.len();
+        }
+        let mut two_qubit_circuit = self.empty_like();
+        for instruction in &self.instructions {
+            if instruction.is_two_qubit_gate() {
+                two_qubit_circuit.push(*instruction);
+            }
+        }
+        two_qubit_circuit.depth()
+    }
+
+    pub fn diff(&self, other: &Circuit) -> Vec<InstructionDiff> {
+        // what changed going from self to other, moment by moment: instructions that appear
+        // in the same moment on both sides match up, then what's left over on one side is
+        // paired with an instruction on the other that shares a qubit with it, as a change,
+        // and anything still left over was removed or added. The list is sorted by moment,
+        // then by lowest qubit. Annotations and coordinates aren't compared.
+        //
+        // Unscheduled circuits are compared by their ASAP moments, so removing a gate can
+        // pull everything after it on those qubits a moment earlier and show up as more
+        // than one change. Scheduling both first (schedule_asap) doesn't help that, but
+        // keeping the original TICKs in an optimizer's output does.
+        let (before, after) = (self.moments(), other.moments());
+        let mut diffs = vec![];
+        for moment in 0..before.len().max(after.len()) {
+            let mut removed: Vec<Instruction> = before.get(moment).cloned().unwrap_or_default();
+            let mut added = vec![];
+            for instruction in after.get(moment).into_iter().flatten() {
+                match removed.iter().position(|other| other == instruction) {
+                    Some(index) => {
+                        removed.remove(index);
+                    }
+                    None => added.push(*instruction),
+                }
+            }
+            for instruction in removed {
+                let qubits = instruction.qubits();
+                let partner = added
+                    .iter()
+                    .position(|other| other.qubits().iter().any(|qubit| qubits.contains(qubit)));
+                diffs.push(match partner {
+                    Some(index) => InstructionDiff::Changed {
+                        moment,
+                        before: instruction,
+                        after: added.remove(index),
+                    },
+                    None => InstructionDiff::Removed {
+                        moment,
+                        instruction,
+                    },
+                });
+            }
+            diffs.extend(added.into_iter().map(|instruction| InstructionDiff::Added {
+                moment,
+                instruction,
+            }));
+        }
+        diffs.sort_by_key(|diff| (diff.moment(), diff.first_qubit()));
+        diffs
+    }
+
+    pub fn content_hash(&self) -> u64 {
+        // a fingerprint of the register size and instructions (not the coordinates), for
+        // tagging results with the circuit that produced them. FNV-1a over the register size
+        // and then each instruction in the text format, a line apiece. Unlike std's hasher or
+        // Debug output, both are fixed, so the hash stays the same across runs, platforms and
+        // compiler versions.
+        let mut text = format!("{}\n", self.num_qubits);
+        for instruction in &self.instructions {
+            text.push_str(&format!("{instruction}\n"));
+        }
+        fnv1a(&text)
+    }
+}
+
+pub(crate) fn fnv1a(text: &str) -> u64 {
+    let mut hash: u64 = 0xcbf2_9ce4_8422_2325;
+    for byte in text.bytes() {
+        hash ^= byte as u64;
+        hash = hash.wrapping_mul(0x0000_0100_0000_01b3);
+    }
+    hash
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::stabilizer_simulator::StabilizerSimulator;
+
+    #[test]
+    fn test_unscheduled_moments_are_asap() {
+        let mut circuit = Circuit::new(4);
+        circuit
+            .gate(Gate::H(Qubit(0)))
+            .gate(Gate::Cx(Qubit(0), Qubit(1)))
+            .gate(Gate::Cx(Qubit(2), Qubit(3)))
+            .gate(Gate::Cx(Qubit(1), Qubit(2)))
+            .measure(Qubit(0));
+        assert_eq!(
+            circuit.assign_moments(),
+            vec![Some(0), Some(1), Some(0), Some(2), Some(2)]
+        );
+        assert_eq!(circuit.depth(), 3);
+        assert_eq!(circuit.two_qubit_depth(), 2);
+    }
+
+    #[test]
+    fn test_ticks_define_moments() {
+        let mut circuit = Circuit::new(2);
+        circuit
+            .gate(Gate::H(Qubit(0)))
+            .tick()
+            .gate(Gate::H(Qubit(1)))
+            .tick()
+            .tick()
+            .gate(Gate::Cx(Qubit(0), Qubit(1)))
+            .tick();
+        assert_eq!(
+            circuit.assign_moments(),
+            vec![Some(0), None, Some(1), None, None, Some(3), None]
+        );
+        assert_eq!(circuit.depth(), 4);
+        assert_eq!(circuit.two_qubit_depth(), 1);
+    }
+
+    #[test]
+    fn test_moments_keep_the_measurement_record_in_order() {
+        // measuring qubit 1 could go first, but then the record would come out backwards, and
+        // the conditional has to wait a moment for the measurement it reads.
+        let mut circuit = Circuit::new(3);
+        circuit
+            .gate(Gate::X(Qubit(0)))
+            .gate(Gate::X(Qubit(0)))
+            .measure(Qubit(0))
+            .measure(Qubit(1))
+            .conditional(1, Gate::X(Qubit(2)))
+            .gate(Gate::H(Qubit(2)));
+        circuit.add_detector(&[1, 2], &[]).unwrap();
+        assert_eq!(
+            circuit.assign_moments(),
+            vec![Some(0), Some(1), Some(2), Some(2), Some(3), Some(4)]
+        );
+        let moments = circuit.moments();
+        assert_eq!(moments.len(), 5);
+        assert_eq!(
+            moments[2],
+            vec![
+                Instruction::Measure(Qubit(0)),
+                Instruction::Measure(Qubit(1))
+            ]
+        );
+
+        let scheduled = circuit.schedule_asap();
+        assert!(scheduled.is_scheduled());
+        assert_eq!(scheduled.moments(), moments);
+        assert_eq!(scheduled.detectors(), circuit.detectors());
+        assert_eq!(
+            scheduled.schedule_asap().instructions(),
+            scheduled.instructions()
+        );
+        assert_eq!(
+            scheduled
+                .instructions()
+                .iter()
+                .filter(|instruction| **instruction == Instruction::Tick)
+                .count(),
+            5
+        );
+    }
+
+    #[test]
+    fn test_diff() {
+        let mut before = Circuit::new(3);
+        before
+            .gate(Gate::H(Qubit(0)))
+            .gate(Gate::H(Qubit(2)))
+            .tick()
+            .gate(Gate::Cx(Qubit(0), Qubit(1)))
+            .tick()
+            .measure(Qubit(1));
+        assert!(before.diff(&before).is_empty());
+
+        let mut after = Circuit::new(3);
+        after
+            .gate(Gate::H(Qubit(0)))
+            .gate(Gate::S(Qubit(1)))
+            .tick()
+            .gate(Gate::Cz(Qubit(0), Qubit(1)))
+            .tick()
+            .measure(Qubit(1));
+        let diffs = before.diff(&after);
+        assert_eq!(
+            diffs,
+            vec![
+                InstructionDiff::Added {
+                    moment: 0,
+                    instruction: Instruction::Gate(Gate::S(Qubit(1))),
+                },
+                InstructionDiff::Removed {
+                    moment: 0,
+                    instruction: Instruction::Gate(Gate::H(Qubit(2))),
+                },
+                InstructionDiff::Changed {
+                    moment: 1,
+                    before: Instruction::Gate(Gate::Cx(Qubit(0), Qubit(1))),
+                    after: Instruction::Gate(Gate::Cz(Qubit(0), Qubit(1))),
+                },
+            ]
+        );
+        assert_eq!(diffs[1].to_string(), "moment 0: - H 2");
+        assert_eq!(diffs[2].to_string(), "moment 1: ~ CX 0 1 -> CZ 0 1");
+
+        // a moment only one side has shows up as additions.
+        let mut longer = before.clone();
+        longer.tick().reset(Qubit(2));
+        assert_eq!(
+            before.diff(&longer),
+            vec![InstructionDiff::Added {
+                moment: 3,
+                instruction: Instruction::Reset(Qubit(2)),
+            }]
+        );
+    }
+
+    #[test]
+    fn test_lightcone_of() {
+        // the last measurement depends on qubit 2's outcome through the conditional X, which
+        // pulls in everything that measurement depended on. Qubit 3 stays out of it.
+        let q: Vec<Qubit> = Qubit::range(4).collect();
+        let mut circuit = Circuit::new(4);
+        circuit
+            .gate(Gate::H(q[0]))
+            .gate(Gate::Cx(q[0], q[1]))
+            .gate(Gate::H(q[3]))
+            .measure(q[3])
+            .gate(Gate::Cx(q[1], q[2]))
+            .measure(q[2])
+            .conditional(1, Gate::X(q[0]))
+            .measure(q[0]);
+        circuit.add_detector(&[2, 1], &[]).unwrap();
+        circuit.add_detector(&[3], &[]).unwrap();
+
+        let lightcone = circuit
+            .lightcone_of(LightconeTarget::Measurement(1))
+            .unwrap();
+        assert_eq!(lightcone.qubits, vec![q[0], q[1], q[2]]);
+        assert_eq!(lightcone.instructions, vec![0, 1, 4, 5]);
+        assert_eq!(lightcone.measurements, vec![1]);
+
+        let lightcone = circuit.lightcone_of(LightconeTarget::Detector(0)).unwrap();
+        assert_eq!(lightcone.instructions, vec![0, 1, 4, 5, 6, 7]);
+        assert_eq!(lightcone.measurements, vec![1, 2]);
+        assert_eq!(
+            lightcone.circuit.instructions()[4],
+            Instruction::Conditional {
+                lookback: 1,
+                gate: Gate::X(q[0]),
+            }
+        );
+        assert_eq!(lightcone.circuit.detectors().len(), 1);
+        assert_eq!(lightcone.circuit.detectors()[0].measurements, vec![0, 1]);
+
+        let lightcone = circuit.lightcone_of(LightconeTarget::Detector(1)).unwrap();
+        assert_eq!(lightcone.qubits, vec![q[3]]);
+        assert_eq!(lightcone.circuit.num_qubits(), 1);
+        assert!(circuit
+            .lightcone_of(LightconeTarget::Measurement(3))
+            .is_err());
+    }
+
+    #[test]
+    fn test_append_and_slice() {
+        // a bell pair check, built once and placed on qubits 0, 1 and then 2, 3.
+        let mut pair = Circuit::bell_pair(Qubit(0), Qubit(1));
+        pair.measure(Qubit(0)).measure(Qubit(1));
+        pair.add_detector(&[1, 2], &[0.0]).unwrap();
+        pair.include_in_observable(0, &[1]).unwrap();
+        pair.set_qubit_coords(Qubit(1), &[1.0]);
+        let mut circuit = pair.clone();
+        circuit.append_at(&pair, 2).unwrap();
+        assert_eq!(circuit.num_qubits(), 4);
+        assert_eq!(
+            circuit.instructions()[4],
+            Instruction::Gate(Gate::H(Qubit(2)))
+        );
+        assert_eq!(circuit.detectors()[1].measurements, vec![3, 2]);
+        assert_eq!(circuit.observables(), &[vec![1, 3]]);
+        assert_eq!(circuit.qubit_coords(Qubit(3)), Some(&[1.0][..]));
+
+        // cutting between the copies gives each back, the second with its own numbering.
+        let second = circuit.slice(4..).unwrap();
+        assert_eq!(second.detectors(), pair.detectors());
+        assert_eq!(second.observables(), &[Vec::<usize>::new()]);
+        let mut rejoined = circuit.slice(..4).unwrap();
+        rejoined.append(&second).unwrap();
+        assert_eq!(rejoined.instructions(), circuit.instructions());
+        assert_eq!(rejoined.detectors(), circuit.detectors());
+        assert_eq!(circuit.slice(3..6).unwrap().detectors().len(), 0);
+        assert!(circuit.slice(..9).is_err());
+
+        let mut conditional = Circuit::new(1);
+        conditional
+            .measure(Qubit(0))
+            .conditional(1, Gate::X(Qubit(0)));
+        assert!(conditional.slice(1..).is_err());
+
+        // the same goes for classical bits, unless the slice writes the bit again first.
+        let mut on_bit = Circuit::new(1);
+        let flag = on_bit.add_classical_register("flag", 1).unwrap();
+        on_bit
+            .measure_to_bit(Qubit(0), flag)
+            .conditional_on_bit(flag, Gate::X(Qubit(0)))
+            .measure_to_bit(Qubit(0), flag)
+            .conditional_on_bit(flag, Gate::X(Qubit(0)));
+        assert!(on_bit.slice(1..).is_err());
+        assert_eq!(
+            on_bit.slice(2..).unwrap().instructions(),
+            &on_bit.instructions()[2..]
+        );
+        assert!(on_bit.slice(..2).is_ok());
+
+        let mut registers = Circuit::new(1);
+        let c = registers.add_classical_register("c", 1).unwrap();
+        registers.measure_to_bit(Qubit(0), c);
+        let mut other = Circuit::new(1);
+        other.add_classical_register("d", 2).unwrap();
+        let d = other.classical_bit("d", 1).unwrap();
+        other.measure_to_bit(Qubit(0), d);
+        registers.append(&other).unwrap();
+        assert_eq!(registers.classical_bit("d", 1), Some(2));
+        assert_eq!(
+            registers.instructions()[1],
+            Instruction::MeasureToBit {
+                qubit: Qubit(0),
+                bit: 2
+            }
+        );
+        let mut clash = Circuit::new(1);
+        clash.add_classical_register("c", 3).unwrap();
+        assert!(registers.append(&clash).is_err());
+    }
+
+    #[test]
+    fn test_qubit_coords_survive_rebuilding() {
+        let mut circuit = Circuit::new(1);
+        circuit
+            .set_qubit_coords(Qubit(2), &[1.0, 0.5])
+            .gate(Gate::H(Qubit(0)));
+        assert_eq!(circuit.num_qubits(), 3);
+        let rebuilt = circuit.empty_like();
+        assert_eq!(rebuilt.qubit_coords(Qubit(2)), Some(&[1.0, 0.5][..]));
+        assert_eq!(rebuilt.qubit_coords(Qubit(0)), None);
+        assert!(rebuilt.instructions().is_empty());
+    }
+
+    #[test]
+    fn test_ghz_measurements_agree() {
+        let mut circuit = Circuit::ghz(&[Qubit(3), Qubit(1), Qubit(0)]);
+        circuit
+            .measure(Qubit(0))
+            .measure(Qubit(1))
+            .measure(Qubit(3));
+        assert_eq!(circuit.num_qubits(), 4);
+        for seed in 0..10 {
+            let record = StabilizerSimulator::new(4, seed).run(&circuit).unwrap();
+            assert!(record.iter().all(|outcome| *outcome == record[0]));
+        }
+        assert!(Circuit::ghz(&[]).instructions().is_empty());
+    }
+
+    #[test]
+    fn test_x_layer_prepares_bitstring() {
+        let mut circuit = Circuit::new(4);
+        circuit.x_layer(&[false, true, true, false]);
+        for qubit in Qubit::range(4) {
+            circuit.measure(qubit);
+        }
+        let mut simulator = StabilizerSimulator::seeded(4);
+        assert_eq!(
+            simulator.run(&circuit).unwrap(),
+            vec![false, true, true, false]
+        );
+    }
+
+    #[test]
+    fn test_classical_registers() {
+        let mut circuit = Circuit::new(2);
+        assert_eq!(circuit.add_classical_register("syndrome", 2), Ok(0));
+        assert_eq!(circuit.add_classical_register("flag", 1), Ok(2));
+        assert!(circuit.add_classical_register("flag", 3).is_err());
+        let flag = circuit.classical_bit("flag", 0).unwrap();
+        assert_eq!(circuit.classical_bit("flag", 1), None);
+
+        circuit
+            .gate(Gate::X(Qubit(0)))
+            .conditional_on_bit(flag, Gate::X(Qubit(1)));
+        assert!(circuit.check_classical_dataflow().is_err());
+        let mut circuit = circuit.empty_like();
+        circuit
+            .gate(Gate::X(Qubit(0)))
+            .measure_to_bit(Qubit(0), flag)
+            .conditional_on_bit(flag, Gate::X(Qubit(1)))
+            .measure(Qubit(1));
+        assert_eq!(circuit.check_classical_dataflow(), Ok(()));
+        let mut simulator = StabilizerSimulator::seeded(2);
+        assert_eq!(simulator.run(&circuit), Ok(vec![true, true]));
+        assert_eq!(simulator.classical_bits(), &[false, false, true]);
+
+        circuit.measure_to_bit(Qubit(1), 3);
+        assert!(circuit.check_classical_dataflow().is_err());
+    }
+}
@@ -0,0 +1,524 @@
+use crate::circuit::{self, Circuit, Instruction};
+use crate::gates::Gate;
+use crate::gf2::BitMatrix;
+use crate::pauli::{self, PauliString};
+use crate::qubit::Qubit;
+use rand::Rng;
+use std::collections::{HashMap, VecDeque};
+
+// a clifford unitary, up to global phase, pinned down by where it sends each X_q and Z_q
+// under conjugation, U X_q U^dagger and U Z_q U^dagger. Two cliffords are equal exactly when
+// all those images are, so operators can be compared and hashed directly.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct CliffordOperator {
+    x_images: Vec<PauliString>,
+    z_images: Vec<PauliString>,
+}
+
+impl CliffordOperator {
+    pub fn identity(num_qubits: usize) -> CliffordOperator {
+        let single = |qubit, x, z| {
+            let mut pauli = PauliString::identity(num_qubits);
+            pauli.x_bits[qubit] = x;
+            pauli.z_bits[qubit] = z;
+            pauli
+        };
+        CliffordOperator {
+            x_images: (0..num_qubits).map(|q| single(q, true, false)).collect(),
+            z_images: (0..num_qubits).map(|q| single(q, false, true)).collect(),
+        }
+    }
+
+    pub fn from_gates(num_qubits: usize, gates: &[Gate]) -> Result<CliffordOperator, &'static str> {
+        let mut operator = CliffordOperator::identity(num_qubits);
+        for gate in gates {
+            if gate
+                .qubits()
+                .iter()
+                .any(|qubit| qubit.index() >= num_qubits)
+            {
+                return Err("Gate acts on a qubit outside the operator.");
+            }
+            operator.then(gate);
+        }
+        Ok(operator)
+    }
+
+    pub fn from_images(
+        x_images: Vec<PauliString>,
+        z_images: Vec<PauliString>,
+    ) -> Result<CliffordOperator, &'static str> {
+        // the images have to commute like the paulis they replace: everything commutes
+        // except the X and Z images of the same qubit.
+        let num_qubits = x_images.len();
+        if z_images.len() != num_qubits
+            || x_images
+                .iter()
+                .chain(&z_images)
+                .any(|image| image.num_qubits() != num_qubits)
+        {
+            return Err("Expected an X and a Z image on every qubit, over the same qubits.");
+        }
+        for (i, (x, z)) in x_images.iter().zip(&z_images).enumerate() {
+            for (j, (other_x, other_z)) in x_images.iter().zip(&z_images).enumerate() {
+                if !x.commutes_with(other_x)
+                    || !z.commutes_with(other_z)
+                    || x.commutes_with(other_z) == (i == j)
+                {
+                    return Err("Images don't commute like X and Z, so they aren't a Clifford.");
+                }
+            }
+        }
+        Ok(CliffordOperator { x_images, z_images })
+    }
+
+    pub fn num_qubits(&self) -> usize {
+        self.x_images.len()
+    }
+
+    pub fn x_image(&self, qubit: Qubit) -> &PauliString {
+        &self.x_images[qubit.index()]
+    }
+
+    pub fn z_image(&self, qubit: Qubit) -> &PauliString {
+        &self.z_images[qubit.index()]
+    }
+
+    pub fn then(&mut self, gate: &Gate) -> &mut CliffordOperator {
+        // follows the operator with a gate: G U P U^dagger G^dagger is the old image pushed
+        // through G.
+        for image in self.x_images.iter_mut().chain(&mut self.z_images) {
+            image.conjugate_by(gate);
+        }
+        self
+    }
+
+    pub fn is_identity(&self) -> bool {
+        *self == CliffordOperator::identity(self.num_qubits())
+    }
+
+    pub fn to_gates(&self) -> Vec<Gate> {
+        // a circuit for the operator. Gates get stacked on a copy of it until it's the
+        // identity, a qubit at a time: the X image of qubit j is turned into X_j, then the Z
+        // image into Z_j with gates that leave X_j alone. Images of qubit j commute with the
+        // X and Z of every qubit already done, so they're only supported on qubits j and up,
+        // and so are the gates, which never undo earlier qubits. The operator is then the
+        // inverse of those gates.
+        let num_qubits = self.num_qubits();
+        let mut operator = self.clone();
+        let mut word = vec![];
+        let mut apply = |operator: &mut CliffordOperator, gate| {
+            operator.then(&gate);
+            word.push(gate);
+        };
+        for j in 0..num_qubits {
+            let qubit = Qubit(j as u32);
+            // X image: turn every factor into an X, get one onto qubit j, then fold the rest
+            // into it.
+            for k in j..num_qubits {
+                let image = &operator.x_images[j];
+                match (image.x_bits[k], image.z_bits[k]) {
+                    (false, true) => apply(&mut operator, Gate::H(Qubit(k as u32))),
+                    (true, true) => apply(&mut operator, Gate::S(Qubit(k as u32))),
+                    _ => {}
+                }
+            }
+            if !operator.x_images[j].x_bits[j] {
+                let k = (j + 1..num_qubits)
+                    .find(|&k| operator.x_images[j].x_bits[k])
+                    .expect("images aren't the identity");
+                apply(&mut operator, Gate::Swap(qubit, Qubit(k as u32)));
+            }
+            for k in j + 1..num_qubits {
+                if operator.x_images[j].x_bits[k] {
+                    apply(&mut operator, Gate::Cx(qubit, Qubit(k as u32)));
+                }
+            }
+            // Z image: it anticommutes with X_j so it has a Z on qubit j. Turn its other
+            // factors into Zs and fold them in, then turn a leftover Y_j into Z_j with H S H,
+            // which fixes X_j.
+            for k in j + 1..num_qubits {
+                let image = &operator.z_images[j];
+                let other = Qubit(k as u32);
+                match (image.x_bits[k], image.z_bits[k]) {
+                    (true, false) => apply(&mut operator, Gate::H(other)),
+                    (true, true) => {
+                        apply(&mut operator, Gate::S(other));
+                        apply(&mut operator, Gate::H(other));
+                    }
+                    _ => {}
+                }
+            }
+            for k in j + 1..num_qubits {
+                if operator.z_images[j].z_bits[k] {
+                    apply(&mut operator, Gate::Cx(Qubit(k as u32), qubit));
+                }
+            }
+            if operator.z_images[j].x_bits[j] {
+                for gate in [Gate::H(qubit), Gate::S(qubit), Gate::H(qubit)] {
+                    apply(&mut operator, gate);
+                }
+            }
+            if operator.x_images[j].phase_is_negated {
+                apply(&mut operator, Gate::Z(qubit));
+            }
+            if operator.z_images[j].phase_is_negated {
+                apply(&mut operator, Gate::X(qubit));
+            }
+        }
+        // every gate used is its own inverse except S, whose inverse is S three times.
+        word.iter()
+            .rev()
+            .flat_map(|&gate| match gate {
+                Gate::S(_) => vec![gate; 3],
+                _ => vec![gate],
+            })
+            .collect()
+    }
+
+    pub fn to_circuit(&self) -> Circuit {
+        let mut circuit = Circuit::new(self.num_qubits());
+        for gate in self.to_gates() {
+            circuit.gate(gate);
+        }
+        circuit
+    }
+}
+
+impl Circuit {
+    pub fn conjugate_pauli(&self, pauli: &PauliString) -> Result<PauliString, &'static str> {
+        // U P U^dagger, sign included: an observable pushed forward through the circuit, so
+        // measuring the image after the circuit is the same as measuring P before it. Only
+        // gates and TICKs have a well defined action on an observable.
+        let gates = self.unitary_gates(pauli.num_qubits())?;
+        let mut image = pauli.clone();
+        for gate in &gates {
+            image.conjugate_by(gate);
+        }
+        Ok(image)
+    }
+
+    pub fn conjugate_pauli_backward(
+        &self,
+        pauli: &PauliString,
+    ) -> Result<PauliString, &'static str> {
+        // U^dagger P U, the heisenberg picture: what P measured after the circuit was before
+        // it. It's the inverse circuit's forward image, so the inverse gates in reverse order.
+        let gates = self.unitary_gates(pauli.num_qubits())?;
+        let mut image = pauli.clone();
+        for gate in gates.iter().rev().flat_map(Gate::inverse) {
+            image.conjugate_by(&gate);
+        }
+        Ok(image)
+    }
+
+    fn unitary_gates(&self, num_qubits: usize) -> Result<Vec<Gate>, &'static str> {
+        let mut gates = vec![];
+        for instruction in self.instructions() {
+            match instruction {
+                Instruction::Gate(gate) => {
+                    if gate
+                        .qubits()
+                        .iter()
+                        .any(|qubit| qubit.index() >= num_qubits)
+                    {
+                        return Err("Gate acts on a qubit outside the pauli string.");
+                    }
+                    gates.push(*gate);
+                }
+                Instruction::Tick => {}
+                _ => return Err("Only gates can conjugate a pauli string."),
+            }
+        }
+        Ok(gates)
+    }
+
+    pub fn clifford_fingerprint(&self) -> u64 {
+        // a hash of what the circuit does rather than how it's spelled. The gates between
+        // one non-unitary instruction and the next are collapsed into the clifford they make,
+        // which is hashed by its images, so any two gate sequences for the same clifford (up
+        // to global phase) hash the same. The non-unitary instructions in between, and the
+        // detectors and observables, are hashed as they are. TICKs and coordinates are left
+        // out.
+        //
+        // It doesn't try to move gates past measurements, so gates on other qubits placed
+        // before rather than after a measurement change the fingerprint.
+        let n = self.num_qubits();
+        let mut text = format!("{n}");
+        let mut segment = CliffordOperator::identity(n);
+        let close = |segment: &mut CliffordOperator, text: &mut String| {
+            for image in segment.x_images.iter().chain(&segment.z_images) {
+                text.push_str(&format!(" {image}"));
+            }
+            *segment = CliffordOperator::identity(n);
+        };
+        for instruction in self.instructions() {
+            match instruction {
+                Instruction::Gate(gate) => {
+                    segment.then(gate);
+                }
+                Instruction::Tick => {}
+                _ => {
+                    close(&mut segment, &mut text);
+                    text.push_str(&format!(" | {instruction} |"));
+                }
+            }
+        }
+        close(&mut segment, &mut text);
+        for detector in self.detectors() {
+            text.push_str(&format!(" D{:?}", detector.measurements));
+        }
+        for observable in self.observables() {
+            text.push_str(&format!(" L{observable:?}"));
+        }
+        circuit::fnv1a(&text)
+    }
+}
+
+pub fn random_clifford(num_qubits: usize, rng: &mut impl Rng) -> CliffordOperator {
+    // uniformly random over the n-qubit Clifford group, up to global phase. The X and Z
+    // images, without signs, are a symplectic basis, picked a qubit at a time: the X image is
+    // a uniformly random nonzero vector, and the Z image a uniformly random one it
+    // anticommutes with, both from the symplectic complement of the earlier qubits' images.
+    // Every symplectic basis comes out equally often, and uniformly random signs on top
+    // cover each Clifford exactly once.
+    //
+    // The complement is kept as a symplectic basis of its own, the pairs of rows of
+    // `complement`, so a random vector in it is a random combination of the rows.
+    let n = num_qubits;
+    let mut complement = BitMatrix::new(2 * n, 2 * n);
+    for q in 0..n {
+        complement.set(2 * q, q, true);
+        complement.set(2 * q + 1, n + q, true);
+    }
+    let product = |a: &[bool], b: &[bool]| {
+        let [a_x, a_z, b_x, b_z] = [&a[..n], &a[n..], &b[..n], &b[n..]].map(pauli::pack_bits);
+        pauli::symplectic_product(&a_x, &a_z, &b_x, &b_z)
+    };
+    let image = |bits: &[bool], rng: &mut dyn rand::RngCore| PauliString {
+        phase_is_negated: rng.gen(),
+        x_bits: bits[..n].to_vec(),
+        z_bits: bits[n..].to_vec(),
+    };
+    let mut x_images = vec![];
+    let mut z_images = vec![];
+    for _ in 0..n {
+        let combination = |rng: &mut dyn rand::RngCore| {
+            let mut vector = vec![false; 2 * n];
+            for row in complement.rows() {
+                if rng.gen() {
+                    for (bit, &other) in vector.iter_mut().zip(&row) {
+                        *bit ^= other;
+                    }
+                }
+            }
+            vector
+        };
+        let x = loop {
+            let x = combination(rng);
+            if x.iter().any(|&bit| bit) {
+                break x;
+            }
+        };
+        let z = loop {
+            let z = combination(rng);
+            if product(&x, &z) {
+                break z;
+            }
+        };
+        // pushes the rest of the complement off x and z (u += <u, z> x + <u, x> z makes u
+        // commute with both), and pairs what's left back up.
+        let projected: Vec<Vec<bool>> = complement
+            .rows()
+            .map(|mut row| {
+                let (with_x, with_z) = (product(&row, &x), product(&row, &z));
+                for ((bit, &x_bit), &z_bit) in row.iter_mut().zip(&x).zip(&z) {
+                    *bit ^= (with_z && x_bit) ^ (with_x && z_bit);
+                }
+                row
+            })
+            .collect();
+        complement = if projected.is_empty() {
+            BitMatrix::new(0, 2 * n)
+        } else {
+            BitMatrix::from_rows(&projected)
+                .and_then(|rows| rows.symplectic_gram_schmidt())
+                .expect("rows are all 2n long")
+                .pairs
+        };
+        x_images.push(image(&x, rng));
+        z_images.push(image(&z, rng));
+    }
+    CliffordOperator { x_images, z_images }
+}
+
+fn enumerate(num_qubits: usize, generators: &[Gate]) -> Vec<(CliffordOperator, Vec<Gate>)> {
+    // breadth first search from the identity, so each element comes with one of its shortest
+    // words in the generators, and the order (and so the words) never changes between runs.
+    let identity = CliffordOperator::identity(num_qubits);
+    let mut found = HashMap::from([(identity.clone(), 0)]);
+    let mut group = vec![(identity, vec![])];
+    let mut queue = VecDeque::from([0]);
+    while let Some(index) = queue.pop_front() {
+        for gate in generators {
+            let mut next = group[index].0.clone();
+            next.then(gate);
+            if !found.contains_key(&next) {
+                let mut word = group[index].1.clone();
+                word.push(*gate);
+                found.insert(next.clone(), group.len());
+                queue.push_back(group.len());
+                group.push((next, word));
+            }
+        }
+    }
+    group
+}
+
+pub fn single_qubit_cliffords() -> Vec<(CliffordOperator, Vec<Gate>)> {
+    // all 24, as shortest H/S words on qubit 0.
+    enumerate(1, &[Gate::H(Qubit(0)), Gate::S(Qubit(0))])
+}
+
+pub fn two_qubit_cliffords() -> Vec<(CliffordOperator, Vec<Gate>)> {
+    // all 11520, as shortest H/S/CX words on qubits 0 and 1.
+    let (a, b) = (Qubit(0), Qubit(1));
+    enumerate(
+        2,
+        &[
+            Gate::H(a),
+            Gate::H(b),
+            Gate::S(a),
+            Gate::S(b),
+            Gate::Cx(a, b),
+        ],
+    )
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::stabilizer_simulator::StabilizerSimulator;
+    use rand::SeedableRng;
+
+    #[test]
+    fn test_conjugate_pauli() {
+        // H then CX takes Z on the first qubit to XX, and the S turns the second X into a Y.
+        // Pushing the image back through gets Z again.
+        let (a, b) = (Qubit(0), Qubit(1));
+        let mut circuit = Circuit::new(2);
+        circuit
+            .gate(Gate::H(a))
+            .gate(Gate::Cx(a, b))
+            .gate(Gate::S(b));
+        let z = PauliString::z(2, a);
+        let image = circuit.conjugate_pauli(&z).unwrap();
+        assert_eq!(image.to_string(), "+XY");
+        assert_eq!(circuit.conjugate_pauli_backward(&image).unwrap(), z);
+
+        // agrees with the operator's images, for a random circuit.
+        let mut rng = rand::rngs::StdRng::seed_from_u64(8);
+        let operator = random_clifford(3, &mut rng);
+        let circuit = operator.to_circuit();
+        let x = circuit
+            .conjugate_pauli(&CliffordOperator::identity(3).x_image(Qubit(1)).clone())
+            .unwrap();
+        assert_eq!(&x, operator.x_image(Qubit(1)));
+
+        let mut measured = Circuit::new(1);
+        measured.measure(a);
+        assert!(measured.conjugate_pauli(&PauliString::z(1, a)).is_err());
+        assert!(circuit.conjugate_pauli(&PauliString::z(2, a)).is_err());
+    }
+
+    #[test]
+    fn test_clifford_fingerprint() {
+        // SS is Z, and HZH is X, so the first four gates are just an X.
+        let (a, b) = (Qubit(0), Qubit(1));
+        let mut spelled_out = Circuit::new(2);
+        spelled_out
+            .gate(Gate::H(a))
+            .gate(Gate::S(a))
+            .gate(Gate::S(a))
+            .gate(Gate::H(a))
+            .gate(Gate::Cx(a, b))
+            .tick()
+            .measure(b);
+        let mut short = Circuit::new(2);
+        short.gate(Gate::X(a)).gate(Gate::Cx(a, b)).measure(b);
+        assert_eq!(
+            spelled_out.clifford_fingerprint(),
+            short.clifford_fingerprint()
+        );
+        assert_ne!(spelled_out.content_hash(), short.content_hash());
+
+        // a different clifford, a different measurement, or a detector all change it.
+        let mut other = Circuit::new(2);
+        other.gate(Gate::Z(a)).gate(Gate::Cx(a, b)).measure(b);
+        assert_ne!(other.clifford_fingerprint(), short.clifford_fingerprint());
+        let mut other = Circuit::new(2);
+        other.gate(Gate::X(a)).gate(Gate::Cx(a, b)).measure(a);
+        assert_ne!(other.clifford_fingerprint(), short.clifford_fingerprint());
+        let mut other = short.clone();
+        other.add_detector(&[1], &[]).unwrap();
+        assert_ne!(other.clifford_fingerprint(), short.clifford_fingerprint());
+        // and so does the register size.
+        let mut wider = Circuit::new(3);
+        wider.gate(Gate::X(a)).gate(Gate::Cx(a, b)).measure(b);
+        assert_ne!(wider.clifford_fingerprint(), short.clifford_fingerprint());
+    }
+
+    #[test]
+    fn test_group_sizes() {
+        let single = single_qubit_cliffords();
+        assert_eq!(single.len(), 24);
+        assert!(single[0].0.is_identity() && single[0].1.is_empty());
+        let two = two_qubit_cliffords();
+        assert_eq!(two.len(), 11520);
+        for (operator, word) in two.iter().step_by(97) {
+            assert_eq!(&CliffordOperator::from_gates(2, word).unwrap(), operator);
+        }
+        assert!(CliffordOperator::from_gates(1, &[Gate::Cx(Qubit(0), Qubit(1))]).is_err());
+    }
+
+    #[test]
+    fn test_random_cliffords() {
+        // all 24 single-qubit cliffords, about equally often.
+        let mut rng = rand::rngs::StdRng::seed_from_u64(5);
+        let mut counts: HashMap<CliffordOperator, usize> = HashMap::new();
+        for _ in 0..24000 {
+            *counts.entry(random_clifford(1, &mut rng)).or_default() += 1;
+        }
+        assert_eq!(counts.len(), 24);
+        assert!(counts.values().all(|&count| (850..1150).contains(&count)));
+
+        // the synthesized circuit is the operator, and images commute the way they should.
+        for num_qubits in [1, 2, 3, 5] {
+            let operator = random_clifford(num_qubits, &mut rng);
+            let rebuilt = CliffordOperator::from_gates(num_qubits, &operator.to_gates()).unwrap();
+            assert_eq!(rebuilt, operator);
+            assert_eq!(
+                CliffordOperator::from_images(operator.x_images.clone(), operator.z_images.clone()),
+                Ok(operator.clone())
+            );
+            assert_eq!(operator.to_circuit().num_qubits(), num_qubits);
+        }
+        let x = CliffordOperator::identity(2).x_images;
+        assert!(CliffordOperator::from_images(x.clone(), x).is_err());
+    }
+
+    #[test]
+    fn test_words_match_the_tableau() {
+        // starting from |00>, the stabilizers after a word are the images of Z_0 and Z_1.
+        for (operator, word) in two_qubit_cliffords().iter().step_by(37) {
+            let mut simulator = StabilizerSimulator::seeded(2);
+            simulator.apply_gates(word.iter().copied());
+            let stabilizers: Vec<PauliString> = simulator
+                .stabilizer_rows()
+                .map(|row| row.to_pauli_string())
+                .collect();
+            assert_eq!(stabilizers[0], *operator.z_image(Qubit(0)));
+            assert_eq!(stabilizers[1], *operator.z_image(Qubit(1)));
+        }
+    }
+}
@@ -0,0 +1,1018 @@
+use crate::circuit::{Circuit, Instruction};
+use crate::gates::Gate;
+use crate::gf2::BitMatrix;
+use crate::pauli::PauliString;
+use crate::qubit::Qubit;
+use std::fmt;
+
+// a stabilizer code on n physical qubits: independent, commuting stabilizer generators, plus a
+// logical X and Z for each of the k = n - (number of generators) logical qubits. Logical
+// operators commute with the stabilizers, and logical X_i anticommutes with logical Z_j
+// exactly when i == j, so the codespace looks like k ordinary qubits.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StabilizerCode {
+    num_qubits: usize,
+    stabilizers: Vec<PauliString>,
+    logical_xs: Vec<PauliString>,
+    logical_zs: Vec<PauliString>,
+}
+
+// local clifford deformations of the rotated surface code, which keep its layout and change
+// which pauli each check applies on each qubit. Xzzx swaps X and Z on every other data qubit
+// (a checkerboard of H), so every check reads XZZX, and Xy turns every Z into a Y. Under
+// noise biased toward Z they behave very differently from the plain CSS code.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SurfaceCodeVariant {
+    Css,
+    Xzzx,
+    Xy,
+}
+
+fn reduce(generators: &[PauliString], pauli: &mut PauliString) -> Result<usize, &'static str> {
+    // brings commuting generators to row echelon form with rowsums, which keeps track of
+    // signs, reducing pauli against each pivot on the way. Returns the number of independent
+    // generators. pauli ends up the identity (up to sign) exactly when the generators hold it
+    // up to sign, and then it's been multiplied by that group element.
+    let num_qubits = pauli.num_qubits();
+    let mut rows = generators.to_vec();
+    let mut rank = 0;
+    for column in 0..2 * num_qubits {
+        let bit = |pauli: &PauliString| {
+            if column < num_qubits {
+                pauli.x_bits[column]
+            } else {
+                pauli.z_bits[column - num_qubits]
+            }
+        };
+        let Some(pivot) = (rank..rows.len()).find(|&i| bit(&rows[i])) else {
+            continue;
+        };
+        rows.swap(rank, pivot);
+        let pivot_row = rows[rank].clone();
+        for row in rows.iter_mut().skip(rank + 1) {
+            if bit(row) {
+                row.rowsum(&pivot_row.view())?;
+            }
+        }
+        if bit(pauli) {
+            pauli.rowsum(&pivot_row.view())?;
+        }
+        rank += 1;
+    }
+    Ok(rank)
+}
+
+impl StabilizerCode {
+    pub fn new(
+        num_qubits: usize,
+        stabilizers: Vec<PauliString>,
+        logical_xs: Vec<PauliString>,
+        logical_zs: Vec<PauliString>,
+    ) -> Result<StabilizerCode, &'static str> {
+        let operators = || stabilizers.iter().chain(&logical_xs).chain(&logical_zs);
+        if operators().any(|operator| operator.num_qubits() != num_qubits) {
+            return Err("Code operators act on a different number of qubits than the code.");
+        }
+        if logical_xs.len() != logical_zs.len() {
+            return Err("Every logical qubit needs both a logical X and a logical Z.");
+        }
+        for a in &stabilizers {
+            if operators().any(|b| !a.commutes_with(b)) {
+                return Err("Stabilizers must commute with each other and the logical operators.");
+            }
+        }
+        if BitMatrix::from_paulis(num_qubits, &stabilizers)?.rank() != stabilizers.len() {
+            return Err("Stabilizers must be independent.");
+        }
+        for (i, x) in logical_xs.iter().enumerate() {
+            for (j, (other_x, z)) in logical_xs.iter().zip(&logical_zs).enumerate() {
+                if !x.commutes_with(other_x)
+                    || !logical_zs[i].commutes_with(z)
+                    || x.commutes_with(z) == (i == j)
+                {
+                    return Err("Logical operators must pair up like single-qubit X and Z.");
+                }
+            }
+        }
+        if stabilizers.len() + logical_xs.len() != num_qubits {
+            return Err("A code needs one logical qubit for each missing stabilizer.");
+        }
+        Ok(StabilizerCode {
+            num_qubits,
+            stabilizers,
+            logical_xs,
+            logical_zs,
+        })
+    }
+
+    pub fn from_stabilizers(
+        num_qubits: usize,
+        stabilizers: &[PauliString],
+    ) -> Result<StabilizerCode, &'static str> {
+        // a code from commuting generators alone, which can be redundant: the first
+        // independent ones are kept, and logical operators are found by symplectic
+        // gram-schmidt on the normalizer. The normalizer's isotropic part is the stabilizer
+        // group, and each pair it splits off is a logical X and Z.
+        let rows = BitMatrix::from_paulis(num_qubits, stabilizers)?;
+        let kept: Vec<PauliString> = rows
+            .transpose()
+            .row_reduce()
+            .into_iter()
+            .map(|row| stabilizers[row].clone())
+            .collect();
+        // <s, v> = s_z . v_x + s_x . v_z, so the normalizer is the kernel of the rows with
+        // their halves swapped.
+        let mut swapped = BitMatrix::new(kept.len(), 2 * num_qubits);
+        for (i, stabilizer) in kept.iter().enumerate() {
+            for q in 0..num_qubits {
+                swapped.set(i, q, stabilizer.z_bits[q]);
+                swapped.set(i, num_qubits + q, stabilizer.x_bits[q]);
+            }
+        }
+        let pairs = swapped.kernel().symplectic_gram_schmidt()?.pairs;
+        let logical = |row: usize| {
+            let bits = pairs.row(row);
+            PauliString {
+                phase_is_negated: false,
+                x_bits: bits[..num_qubits].to_vec(),
+                z_bits: bits[num_qubits..].to_vec(),
+            }
+        };
+        let num_logical_qubits = pairs.num_rows() / 2;
+        StabilizerCode::new(
+            num_qubits,
+            kept,
+            (0..num_logical_qubits).map(|k| logical(2 * k)).collect(),
+            (0..num_logical_qubits)
+                .map(|k| logical(2 * k + 1))
+                .collect(),
+        )
+    }
+
+    pub fn hypergraph_product(
+        first: &BitMatrix,
+        second: &BitMatrix,
+    ) -> Result<StabilizerCode, &'static str> {
+        // the hypergraph product of classical codes with parity checks H1 (r1 x n1) and
+        // H2 (r2 x n2), a CSS code on n1 n2 + r1 r2 qubits with
+        //
+        //     H_X = [H1 (x) I_n2 | I_r1 (x) H2^T],    H_Z = [I_n1 (x) H2 | H1^T (x) I_r2].
+        //
+        // Qubit i n2 + j is bit i of the first code with bit j of the second, and qubit
+        // n1 n2 + a r2 + b is check a of the first with check b of the second. X checks come
+        // before Z checks, with redundant ones left out.
+        let (r1, n1) = (first.num_rows(), first.num_columns());
+        let (r2, n2) = (second.num_rows(), second.num_columns());
+        let num_qubits = n1 * n2 + r1 * r2;
+        let bit = |i: usize, j: usize| i * n2 + j;
+        let check = |a: usize, b: usize| n1 * n2 + a * r2 + b;
+        let mut stabilizers = vec![];
+        for a in 0..r1 {
+            for j in 0..n2 {
+                let mut x = PauliString::identity(num_qubits);
+                for i in (0..n1).filter(|&i| first.get(a, i)) {
+                    x.x_bits[bit(i, j)] = true;
+                }
+                for b in (0..r2).filter(|&b| second.get(b, j)) {
+                    x.x_bits[check(a, b)] = true;
+                }
+                stabilizers.push(x);
+            }
+        }
+        for i in 0..n1 {
+            for b in 0..r2 {
+                let mut z = PauliString::identity(num_qubits);
+                for j in (0..n2).filter(|&j| second.get(b, j)) {
+                    z.z_bits[bit(i, j)] = true;
+                }
+                for a in (0..r1).filter(|&a| first.get(a, i)) {
+                    z.z_bits[check(a, b)] = true;
+                }
+                stabilizers.push(z);
+            }
+        }
+        stabilizers.retain(|stabilizer| {
+            stabilizer
+                .x_bits
+                .iter()
+                .chain(&stabilizer.z_bits)
+                .any(|&bit| bit)
+        });
+        StabilizerCode::from_stabilizers(num_qubits, &stabilizers)
+    }
+
+    pub fn rotated_surface_code(
+        distance: usize,
+        variant: SurfaceCodeVariant,
+    ) -> Result<StabilizerCode, &'static str> {
+        // data qubit r d + c sits at row r and column c of a d x d grid. Checks sit on the
+        // faces between four data qubits, X type and Z type in a checkerboard, plus weight
+        // two X checks along the top and bottom edges and Z checks along the left and right.
+        // Before deformation, logical X runs down the left column and logical Z along the top
+        // row.
+        if distance < 3 || distance.is_multiple_of(2) {
+            return Err("Surface code distance must be odd and at least 3.");
+        }
+        let d = distance;
+        let num_qubits = d * d;
+        let deform = |mut pauli: PauliString| {
+            for q in 0..num_qubits {
+                let (x, z) = (pauli.x_bits[q], pauli.z_bits[q]);
+                match variant {
+                    SurfaceCodeVariant::Css => {}
+                    SurfaceCodeVariant::Xzzx => {
+                        if (q / d + q % d) % 2 == 1 {
+                            (pauli.x_bits[q], pauli.z_bits[q]) = (z, x);
+                        }
+                    }
+                    SurfaceCodeVariant::Xy => pauli.x_bits[q] = x || z,
+                }
+            }
+            pauli
+        };
+        let mut stabilizers = vec![];
+        for (i, j) in surface_code_faces(d) {
+            let is_x = (i + j) % 2 == 0;
+            let mut check = PauliString::identity(num_qubits);
+            for r in i.saturating_sub(1)..(i + 1).min(d) {
+                for c in j.saturating_sub(1)..(j + 1).min(d) {
+                    let bits = if is_x {
+                        &mut check.x_bits
+                    } else {
+                        &mut check.z_bits
+                    };
+                    bits[r * d + c] = true;
+                }
+            }
+            stabilizers.push(deform(check));
+        }
+        let mut logical_x = PauliString::identity(num_qubits);
+        let mut logical_z = PauliString::identity(num_qubits);
+        for k in 0..d {
+            logical_x.x_bits[k * d] = true;
+            logical_z.z_bits[k] = true;
+        }
+        StabilizerCode::new(
+            num_qubits,
+            stabilizers,
+            vec![deform(logical_x)],
+            vec![deform(logical_z)],
+        )
+    }
+
+    pub fn num_qubits(&self) -> usize {
+        self.num_qubits
+    }
+
+    pub fn num_logical_qubits(&self) -> usize {
+        self.logical_xs.len()
+    }
+
+    pub fn stabilizers(&self) -> &[PauliString] {
+        &self.stabilizers
+    }
+
+    pub fn logical_xs(&self) -> &[PauliString] {
+        &self.logical_xs
+    }
+
+    pub fn logical_zs(&self) -> &[PauliString] {
+        &self.logical_zs
+    }
+
+    pub fn contains(&self, pauli: &PauliString) -> bool {
+        // is this signed pauli string an element of the stabilizer group?
+        self.group_sign(pauli) == Some(false)
+    }
+
+    pub fn syndrome_schedule(&self) -> Vec<Vec<(usize, usize)>> {
+        // when each stabilizer's controlled pauli hits each of its data qubits, as layers of
+        // (stabilizer, qubit) pairs that never use a stabilizer or qubit twice, so a layer runs
+        // in one time step.
+        //
+        // Interleaving two stabilizers' gates is only safe when they agree on every qubit they
+        // share, since then their gates all commute. So stabilizers are split into groups that
+        // agree with each other (X checks and Z checks, for a CSS code), and each group runs
+        // after the last. Within a group, the layers are a coloring of the stabilizer to qubit
+        // edges, which takes exactly as many colors as the busiest stabilizer or qubit has
+        // edges, the least depth possible.
+        let num_qubits = self.num_qubits;
+        let support = |s: usize| {
+            let stabilizer = &self.stabilizers[s];
+            (0..num_qubits).filter(move |&q| stabilizer.x_bits[q] || stabilizer.z_bits[q])
+        };
+        let agree = |a: usize, b: usize| {
+            let (a, b) = (&self.stabilizers[a], &self.stabilizers[b]);
+            (0..num_qubits).all(|q| {
+                let (p, r) = ((a.x_bits[q], a.z_bits[q]), (b.x_bits[q], b.z_bits[q]));
+                p == (false, false) || r == (false, false) || p == r
+            })
+        };
+        // the groups are a coloring of the graph of disagreeing stabilizers, by dsatur: color
+        // next whichever stabilizer already sees the most groups among its neighbours, with
+        // the lowest group it can join. That's exact for two groups, as with CSS codes.
+        // Stabilizers with no qubit in common always agree, so only ones sharing a qubit are
+        // compared.
+        let num_stabilizers = self.stabilizers.len();
+        let mut on_qubit: Vec<Vec<usize>> = vec![vec![]; num_qubits];
+        for s in 0..num_stabilizers {
+            for q in support(s) {
+                on_qubit[q].push(s);
+            }
+        }
+        let neighbours: Vec<Vec<usize>> = (0..num_stabilizers)
+            .map(|a| {
+                let mut sharing: Vec<usize> = support(a)
+                    .flat_map(|q| on_qubit[q].iter().copied())
+                    .collect();
+                sharing.sort();
+                sharing.dedup();
+                sharing.retain(|&b| !agree(a, b));
+                sharing
+            })
+            .collect();
+        let mut group_of: Vec<Option<usize>> = vec![None; num_stabilizers];
+        let mut groups: Vec<Vec<usize>> = vec![];
+        while let Some(s) = (0..num_stabilizers)
+            .filter(|&s| group_of[s].is_none())
+            .max_by_key(|&s| {
+                let mut seen: Vec<usize> =
+                    neighbours[s].iter().filter_map(|&n| group_of[n]).collect();
+                seen.sort();
+                seen.dedup();
+                (seen.len(), neighbours[s].len(), std::cmp::Reverse(s))
+            })
+        {
+            let group = (0..)
+                .find(|&g| neighbours[s].iter().all(|&n| group_of[n] != Some(g)))
+                .expect("some group is free");
+            if group == groups.len() {
+                groups.push(vec![]);
+            }
+            groups[group].push(s);
+            group_of[s] = Some(group);
+        }
+        let mut layers = vec![];
+        for group in groups {
+            let edges: Vec<(usize, usize)> = group
+                .iter()
+                .flat_map(|&s| support(s).map(move |q| (s, q)))
+                .collect();
+            let colors = color_edges(&edges, self.stabilizers.len(), num_qubits);
+            let depth = colors.iter().max().map_or(0, |&color| color + 1);
+            let mut group_layers = vec![vec![]; depth];
+            for (&edge, &color) in edges.iter().zip(&colors) {
+                group_layers[color].push(edge);
+            }
+            layers.extend(group_layers);
+        }
+        layers
+    }
+
+    pub fn syndrome_extraction_circuit(&self, rounds: usize) -> Circuit {
+        // measures every stabilizer `rounds` times, each with its own ancilla: qubits 0..n
+        // are the data and ancilla n + s measures stabilizer s, by preparing |+>, applying the
+        // stabilizer's paulis controlled on it, and measuring it in the X basis. The
+        // controlled paulis follow syndrome_schedule, a TICK after each layer. Detectors
+        // compare each stabilizer with itself a round earlier, at coords (s, round), so a
+        // noiseless run never fires one whatever state the data starts in.
+        let num_stabilizers = self.stabilizers.len();
+        let ancilla = |s: usize| Qubit((self.num_qubits + s) as u32);
+        let schedule = self.syndrome_schedule();
+        let mut circuit = Circuit::new(self.num_qubits + num_stabilizers);
+        for round in 0..rounds {
+            for s in 0..num_stabilizers {
+                circuit.reset(ancilla(s)).gate(Gate::H(ancilla(s)));
+            }
+            circuit.tick();
+            for layer in &schedule {
+                for &(s, q) in layer {
+                    let data = Qubit(q as u32);
+                    match (self.stabilizers[s].x_bits[q], self.stabilizers[s].z_bits[q]) {
+                        (true, false) => {
+                            circuit.gate(Gate::Cx(ancilla(s), data));
+                        }
+                        (false, true) => {
+                            circuit.gate(Gate::Cz(ancilla(s), data));
+                        }
+                        _ => {
+                            // controlled-Y is S controlled-X S^dagger.
+                            for _ in 0..3 {
+                                circuit.gate(Gate::S(data));
+                            }
+                            circuit.gate(Gate::Cx(ancilla(s), data)).gate(Gate::S(data));
+                        }
+                    }
+                }
+                circuit.tick();
+            }
+            for s in 0..num_stabilizers {
+                circuit.gate(Gate::H(ancilla(s))).measure(ancilla(s));
+            }
+            if round > 0 {
+                for s in 0..num_stabilizers {
+                    let lookback = (num_stabilizers - s) as u32;
+                    circuit
+                        .add_detector(
+                            &[lookback, lookback + num_stabilizers as u32],
+                            &[s as f64, round as f64],
+                        )
+                        .expect("the previous round is in the record");
+                }
+            }
+            circuit.tick();
+        }
+        circuit
+    }
+
+    fn group_sign(&self, pauli: &PauliString) -> Option<bool> {
+        // whether the stabilizer group holds pauli (Some(false)) or -pauli (Some(true)).
+        if self.stabilizers.iter().any(|s| !s.commutes_with(pauli)) {
+            return None;
+        }
+        let mut remainder = pauli.clone();
+        reduce(&self.stabilizers, &mut remainder).ok()?;
+        let is_identity = remainder
+            .x_bits
+            .iter()
+            .chain(&remainder.z_bits)
+            .all(|&bit| !bit);
+        is_identity.then_some(remainder.phase_is_negated)
+    }
+
+    fn logical_operator(&self, logical: &PauliString) -> PauliString {
+        // the physical representative of a pauli string over the logical qubits, taking
+        // logical Y_j to be i X_j Z_j like a physical Y.
+        let mut representative = PauliString::identity(self.num_qubits);
+        for j in 0..self.num_logical_qubits() {
+            let (x, z) = (&self.logical_xs[j], &self.logical_zs[j]);
+            let factor = match (logical.x_bits[j], logical.z_bits[j]) {
+                (false, false) => continue,
+                (true, false) => x.clone(),
+                (false, true) => z.clone(),
+                (true, true) => {
+                    // X_j Z_j anticommute, so their product carries an odd power of i, which
+                    // the extra i makes real again.
+                    let exponent = 1
+                        + 2 * (x.phase_is_negated as i32 + z.phase_is_negated as i32)
+                        + x.view().product_phase_exponent(&z.view());
+                    PauliString {
+                        phase_is_negated: exponent.rem_euclid(4) == 2,
+                        x_bits: x.x_bits.iter().zip(&z.x_bits).map(|(a, b)| a ^ b).collect(),
+                        z_bits: x.z_bits.iter().zip(&z.z_bits).map(|(a, b)| a ^ b).collect(),
+                    }
+                }
+            };
+            representative
+                .rowsum(&factor.view())
+                .expect("logical operators on different logical qubits commute");
+        }
+        representative.phase_is_negated ^= logical.phase_is_negated;
+        representative
+    }
+
+    fn logical_image(&self, image: &PauliString) -> Option<PauliString> {
+        // writes an element of the normalizer as a logical pauli string times a stabilizer.
+        // Which logical paulis it's made of is read off its commutation with the logical
+        // operators, and the sign from what's left over once that's divided out.
+        let mut logical = PauliString::identity(self.num_logical_qubits());
+        for j in 0..self.num_logical_qubits() {
+            logical.x_bits[j] = !image.commutes_with(&self.logical_zs[j]);
+            logical.z_bits[j] = !image.commutes_with(&self.logical_xs[j]);
+        }
+        let mut residual = image.clone();
+        residual
+            .rowsum(&self.logical_operator(&logical).view())
+            .ok()?;
+        logical.phase_is_negated = self.group_sign(&residual)?;
+        Some(logical)
+    }
+}
+
+// what a codespace-preserving circuit does to the encoded qubits: where each logical X and Z
+// ends up under conjugation, as pauli strings over the logical qubits. That pins down the
+// logical clifford up to a global phase.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LogicalAction {
+    pub x_images: Vec<PauliString>,
+    pub z_images: Vec<PauliString>,
+}
+
+impl LogicalAction {
+    pub fn is_identity(&self) -> bool {
+        let is_unchanged = |images: &[PauliString], x: bool| {
+            images.iter().enumerate().all(|(j, image)| {
+                !image.phase_is_negated
+                    && (0..image.num_qubits()).all(|i| {
+                        image.x_bits[i] == (x && i == j) && image.z_bits[i] == (!x && i == j)
+                    })
+            })
+        };
+        is_unchanged(&self.x_images, true) && is_unchanged(&self.z_images, false)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Violation {
+    QubitCountMismatch,
+    // measurements, resets and classically controlled gates don't have a fixed action.
+    NotUnitary { instruction: usize },
+    // stabilizer `index` was conjugated to `image`, which isn't in the stabilizer group.
+    StabilizerNotPreserved { index: usize, image: PauliString },
+}
+
+impl fmt::Display for Violation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Violation::QubitCountMismatch => {
+                write!(f, "circuit and code act on different numbers of qubits")
+            }
+            Violation::NotUnitary { instruction } => {
+                write!(f, "instruction {instruction} isn't a unitary gate")
+            }
+            Violation::StabilizerNotPreserved { index, image } => {
+                write!(
+                    f,
+                    "stabilizer {index} is mapped to {image}, outside the stabilizer group"
+                )
+            }
+        }
+    }
+}
+
+impl Circuit {
+    pub fn preserves_code(&self, code: &StabilizerCode) -> Result<LogicalAction, Violation> {
+        // conjugates the stabilizers through the circuit. The codespace is preserved when
+        // every stabilizer lands back in the stabilizer group, and then the logical operators
+        // land in the normalizer, which says which logical clifford the circuit implements.
+        if self.num_qubits() != code.num_qubits() {
+            return Err(Violation::QubitCountMismatch);
+        }
+        let mut gates: Vec<Gate> = Vec::new();
+        for (index, instruction) in self.instructions().iter().enumerate() {
+            match instruction {
+                Instruction::Gate(gate) => gates.push(*gate),
+                Instruction::Tick => {}
+                _ => return Err(Violation::NotUnitary { instruction: index }),
+            }
+        }
+        let conjugate = |pauli: &PauliString| {
+            let mut image = pauli.clone();
+            for gate in &gates {
+                image.conjugate_by(gate);
+            }
+            image
+        };
+        for (index, stabilizer) in code.stabilizers().iter().enumerate() {
+            let image = conjugate(stabilizer);
+            if !code.contains(&image) {
+                return Err(Violation::StabilizerNotPreserved { index, image });
+            }
+        }
+        // conjugation keeps commutation relations, so with the stabilizer group preserved the
+        // logical operators' images commute with all of it, and are in the normalizer.
+        let logical_images = |logicals: &[PauliString]| {
+            logicals
+                .iter()
+                .map(|logical| {
+                    code.logical_image(&conjugate(logical))
+                        .expect("images of logical operators are in the normalizer")
+                })
+                .collect()
+        };
+        Ok(LogicalAction {
+            x_images: logical_images(code.logical_xs()),
+            z_images: logical_images(code.logical_zs()),
+        })
+    }
+}
+
+fn color_edges(edges: &[(usize, usize)], num_left: usize, num_right: usize) -> Vec<usize> {
+    // colors a bipartite graph's edges so no two edges at a node share a color, with as many
+    // colors as the largest degree (konig's theorem). Each edge takes a color a free at its
+    // left end; if a is taken at the right end, the path of edges alternating a and a color
+    // b free there gets its colors swapped, which frees a without touching the left end.
+    let ends: Vec<(usize, usize)> = edges
+        .iter()
+        .map(|&(left, right)| (left, num_left + right))
+        .collect();
+    let mut degrees = vec![0; num_left + num_right];
+    for &(u, v) in &ends {
+        degrees[u] += 1;
+        degrees[v] += 1;
+    }
+    let num_colors = degrees.into_iter().max().unwrap_or(0);
+    // at[node][color] is the edge with that color at the node.
+    let mut at: Vec<Vec<Option<usize>>> = vec![vec![None; num_colors]; num_left + num_right];
+    let mut colors = vec![0; edges.len()];
+    for (edge, &(u, v)) in ends.iter().enumerate() {
+        let free = |at: &[Option<usize>]| (0..num_colors).find(|&c| at[c].is_none());
+        let a = free(&at[u]).expect("a node has fewer edges than colors");
+        let b = free(&at[v]).expect("a node has fewer edges than colors");
+        if at[v][a].is_some() {
+            let mut path = vec![];
+            let (mut node, mut color) = (v, a);
+            while let Some(next) = at[node][color] {
+                path.push(next);
+                let (x, y) = ends[next];
+                node = if node == x { y } else { x };
+                color = if color == a { b } else { a };
+            }
+            for &next in &path {
+                let (x, y) = ends[next];
+                at[x][colors[next]] = None;
+                at[y][colors[next]] = None;
+            }
+            for &next in &path {
+                let (x, y) = ends[next];
+                colors[next] = if colors[next] == a { b } else { a };
+                at[x][colors[next]] = Some(next);
+                at[y][colors[next]] = Some(next);
+            }
+        }
+        colors[edge] = a;
+        at[u][a] = Some(edge);
+        at[v][a] = Some(edge);
+    }
+    colors
+}
+
+fn surface_code_faces(d: usize) -> Vec<(usize, usize)> {
+    // the faces of the d x d grid that get a check in rotated_surface_code, in stabilizer
+    // order. Face (i, j) is the corner shared by data qubits (i - 1, j - 1) to (i, j).
+    let mut faces = vec![];
+    for i in 0..=d {
+        for j in 0..=d {
+            let is_x = (i + j) % 2 == 0;
+            let kept = if i % d == 0 && j % d == 0 {
+                false
+            } else if i == 0 || i == d {
+                is_x
+            } else if j == 0 || j == d {
+                !is_x
+            } else {
+                true
+            };
+            if kept {
+                faces.push((i, j));
+            }
+        }
+    }
+    faces
+}
+
+pub fn repetition_code_memory(distance: usize, rounds: usize) -> Result<Circuit, &'static str> {
+    // a bit flip code memory experiment: data qubits 2i and ancillas 2i + 1 in a line, each
+    // ancilla checking the Z Z parity of its neighbours every round. Detectors compare each
+    // check with the round before (the first round with nothing, since data starts in |0>),
+    // then the data is read out and checked against the last round. Observable 0 is data
+    // qubit 0, which a logical X flips. Detector coords are (check, round).
+    if distance < 2 || rounds == 0 {
+        return Err("A repetition code memory needs distance 2 or more and at least one round.");
+    }
+    let num_checks = distance - 1;
+    let m = num_checks as u32;
+    let mut circuit = Circuit::new(2 * distance - 1);
+    for q in 0..2 * distance - 1 {
+        circuit.set_qubit_coords(Qubit(q as u32), &[q as f64]);
+    }
+    for round in 0..rounds {
+        for i in 0..num_checks as u32 {
+            let ancilla = Qubit(2 * i + 1);
+            circuit
+                .gate(Gate::Cx(Qubit(2 * i), ancilla))
+                .gate(Gate::Cx(Qubit(2 * i + 2), ancilla));
+        }
+        for i in 0..num_checks as u32 {
+            circuit.measure(Qubit(2 * i + 1)).reset(Qubit(2 * i + 1));
+        }
+        for i in 0..m {
+            let coords = [i as f64, round as f64];
+            if round == 0 {
+                circuit.add_detector(&[m - i], &coords)?;
+            } else {
+                circuit.add_detector(&[m - i, 2 * m - i], &coords)?;
+            }
+        }
+        circuit.tick();
+    }
+    let d = distance as u32;
+    for i in 0..d {
+        circuit.measure(Qubit(2 * i));
+    }
+    for i in 0..m {
+        circuit.add_detector(&[d - i, d - i - 1, d + m - i], &[i as f64, rounds as f64])?;
+    }
+    circuit.include_in_observable(0, &[d])?;
+    Ok(circuit)
+}
+
+pub fn surface_code_memory(distance: usize, rounds: usize) -> Result<Circuit, &'static str> {
+    // a Z basis memory experiment on the rotated surface code (see rotated_surface_code and
+    // syndrome_extraction_circuit for the layout): the data starts in |0...0>, every check is
+    // measured `rounds` times, then the data is read out. Only the Z checks get detectors,
+    // since the X checks start out random and logical Z doesn't care about Z errors: each Z
+    // check against the round before (the first round against +1), and at the end against
+    // the product of its data readouts. Observable 0 is logical Z, along the top row.
+    // Detector coords are (check, round), like syndrome_extraction_circuit's. Qubit coords
+    // are (x, y) on a doubled grid: data qubit r d + c at (2c + 1, 2r + 1), and each check's
+    // ancilla on the face between its data qubits, at even coordinates.
+    if rounds == 0 {
+        return Err("A surface code memory needs at least one round.");
+    }
+    let code = StabilizerCode::rotated_surface_code(distance, SurfaceCodeVariant::Css)?;
+    let n = code.num_qubits();
+    let num_checks = code.stabilizers().len();
+    let z_checks: Vec<usize> = (0..num_checks)
+        .filter(|&s| code.stabilizers()[s].x_bits.iter().all(|&x| !x))
+        .collect();
+    let round = code.syndrome_extraction_circuit(1);
+    let mut circuit = Circuit::new(n + num_checks);
+    for q in 0..n {
+        let (r, c) = (q / distance, q % distance);
+        circuit.set_qubit_coords(Qubit(q as u32), &[(2 * c + 1) as f64, (2 * r + 1) as f64]);
+    }
+    for (s, (i, j)) in surface_code_faces(distance).into_iter().enumerate() {
+        circuit.set_qubit_coords(Qubit((n + s) as u32), &[(2 * j) as f64, (2 * i) as f64]);
+    }
+    for q in 0..n {
+        circuit.reset(Qubit(q as u32));
+    }
+    circuit.tick();
+    let m = num_checks as u32;
+    for r in 0..rounds {
+        circuit.append(&round)?;
+        for &s in &z_checks {
+            let lookback = m - s as u32;
+            let coords = [s as f64, r as f64];
+            if r == 0 {
+                circuit.add_detector(&[lookback], &coords)?;
+            } else {
+                circuit.add_detector(&[lookback, lookback + m], &coords)?;
+            }
+        }
+    }
+    for q in 0..n {
+        circuit.measure(Qubit(q as u32));
+    }
+    let readouts = |pauli: &PauliString| -> Vec<u32> {
+        (0..n)
+            .filter(|&q| pauli.z_bits[q])
+            .map(|q| (n - q) as u32)
+            .collect()
+    };
+    for &s in &z_checks {
+        let mut lookbacks = readouts(&code.stabilizers()[s]);
+        lookbacks.push((n + num_checks - s) as u32);
+        circuit.add_detector(&lookbacks, &[s as f64, rounds as f64])?;
+    }
+    circuit.include_in_observable(0, &readouts(&code.logical_zs()[0]))?;
+    Ok(circuit)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn paulis(texts: &[&str]) -> Vec<PauliString> {
+        texts.iter().map(|text| text.parse().unwrap()).collect()
+    }
+
+    fn four_two_two() -> StabilizerCode {
+        StabilizerCode::new(
+            4,
+            paulis(&["XXXX", "ZZZZ"]),
+            paulis(&["XXII", "XIXI"]),
+            paulis(&["ZIZI", "ZZII"]),
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn test_code_validation() {
+        let code = four_two_two();
+        // XXXX ZZZZ = (XZ)^4 = (-iY)^4 = YYYY.
+        assert!(code.contains(&"YYYY".parse().unwrap()));
+        assert!(!code.contains(&"-YYYY".parse().unwrap()));
+        assert!(!code.contains(&"XXII".parse().unwrap()));
+        assert!(StabilizerCode::new(2, paulis(&["XX", "ZI"]), vec![], vec![]).is_err());
+        assert!(StabilizerCode::new(2, paulis(&["ZZ", "ZZ"]), vec![], vec![]).is_err());
+        assert!(StabilizerCode::new(2, paulis(&["ZZ"]), paulis(&["XX"]), paulis(&["XX"])).is_err());
+        assert!(StabilizerCode::new(2, paulis(&["ZZ"]), vec![], vec![]).is_err());
+    }
+
+    #[test]
+    fn test_hypergraph_product() {
+        // the product of two distance 3 repetition codes is a distance 3 surface code, with
+        // 9 + 4 qubits and one logical qubit.
+        let repetition =
+            BitMatrix::from_rows(&[vec![true, true, false], vec![false, true, true]]).unwrap();
+        let code = StabilizerCode::hypergraph_product(&repetition, &repetition).unwrap();
+        assert_eq!(code.num_qubits(), 13);
+        assert_eq!(code.num_logical_qubits(), 1);
+        assert_eq!(code.stabilizers().len(), 12);
+        // every logical operator's support reaches across the code.
+        for logical in code.logical_xs().iter().chain(code.logical_zs()) {
+            let weight = (0..13)
+                .filter(|&q| logical.x_bits[q] || logical.z_bits[q])
+                .count();
+            assert!(weight >= 3);
+        }
+
+        // redundant generators are dropped.
+        let cycle = BitMatrix::from_rows(&[
+            vec![true, true, false],
+            vec![false, true, true],
+            vec![true, false, true],
+        ])
+        .unwrap();
+        let code = StabilizerCode::hypergraph_product(&cycle, &cycle).unwrap();
+        assert_eq!(code.num_qubits(), 18);
+        assert_eq!(code.num_logical_qubits(), 2);
+        assert!(StabilizerCode::from_stabilizers(2, &paulis(&["XI", "ZI", "ZZ"])).is_err());
+    }
+
+    #[test]
+    fn test_surface_code_variants() {
+        let letters = |pauli: &PauliString| {
+            (0..pauli.num_qubits())
+                .filter_map(|q| match (pauli.x_bits[q], pauli.z_bits[q]) {
+                    (false, false) => None,
+                    (true, false) => Some('X'),
+                    (false, true) => Some('Z'),
+                    (true, true) => Some('Y'),
+                })
+                .collect::<String>()
+        };
+        let css = StabilizerCode::rotated_surface_code(3, SurfaceCodeVariant::Css).unwrap();
+        assert_eq!(css.num_qubits(), 9);
+        assert_eq!(css.stabilizers().len(), 8);
+        let xzzx = StabilizerCode::rotated_surface_code(5, SurfaceCodeVariant::Xzzx).unwrap();
+        assert_eq!(xzzx.stabilizers().len(), 24);
+        for check in xzzx.stabilizers() {
+            let check = letters(check);
+            assert!(check == "XZZX" || check == "ZXXZ" || check.len() == 2);
+        }
+        let xy = StabilizerCode::rotated_surface_code(3, SurfaceCodeVariant::Xy).unwrap();
+        assert!(xy
+            .stabilizers()
+            .iter()
+            .all(|check| !letters(check).contains('Z')));
+        assert!(StabilizerCode::rotated_surface_code(4, SurfaceCodeVariant::Css).is_err());
+
+        // Y checks go through controlled-Y, and still never fire without noise.
+        let circuit = xy.syndrome_extraction_circuit(2);
+        for shot in crate::sampler::Sampler::new(&circuit, 2)
+            .sample(20)
+            .unwrap()
+        {
+            for detector in circuit.detectors() {
+                let parity = detector.measurements.iter().filter(|&&m| shot[m]).count();
+                assert_eq!(parity % 2, 0);
+            }
+        }
+    }
+
+    #[test]
+    fn test_syndrome_schedule() {
+        let code = StabilizerCode::rotated_surface_code(5, SurfaceCodeVariant::Css).unwrap();
+        let schedule = code.syndrome_schedule();
+        // X checks then Z checks, four layers each.
+        assert_eq!(schedule.len(), 8);
+        let mut seen = std::collections::HashSet::new();
+        for layer in &schedule {
+            let stabilizers: std::collections::HashSet<_> = layer.iter().map(|e| e.0).collect();
+            let qubits: std::collections::HashSet<_> = layer.iter().map(|e| e.1).collect();
+            assert_eq!(stabilizers.len(), layer.len());
+            assert_eq!(qubits.len(), layer.len());
+            seen.extend(layer.iter().copied());
+        }
+        let num_edges: usize = code
+            .stabilizers()
+            .iter()
+            .map(|s| (0..25).filter(|&q| s.x_bits[q] || s.z_bits[q]).count())
+            .sum();
+        assert_eq!(seen.len(), num_edges);
+
+        // the xzzx code's checks disagree with their neighbours, so they come in more groups,
+        // and measuring them is still consistent.
+        let xzzx = StabilizerCode::rotated_surface_code(3, SurfaceCodeVariant::Xzzx).unwrap();
+        let circuit = xzzx.syndrome_extraction_circuit(2);
+        for shot in crate::sampler::Sampler::new(&circuit, 4)
+            .sample(20)
+            .unwrap()
+        {
+            assert!(circuit.detector_values(&shot).iter().all(|&value| !value));
+        }
+    }
+
+    #[test]
+    fn test_syndrome_extraction() {
+        let code = four_two_two();
+        let circuit = code.syndrome_extraction_circuit(3);
+        assert_eq!(circuit.num_qubits(), 6);
+        assert_eq!(circuit.num_measurements(), 6);
+        assert_eq!(circuit.detectors().len(), 4);
+        // starting from |0000>, the first XXXX outcome is random, and later rounds repeat it.
+        for shot in crate::sampler::Sampler::new(&circuit, 1)
+            .sample(20)
+            .unwrap()
+        {
+            for detector in circuit.detectors() {
+                let parity = detector.measurements.iter().filter(|&&m| shot[m]).count();
+                assert_eq!(parity % 2, 0);
+            }
+        }
+    }
+
+    #[test]
+    fn test_surface_code_memory() {
+        let circuit = surface_code_memory(3, 2).unwrap();
+        assert_eq!(circuit.num_qubits(), 17);
+        assert_eq!(circuit.num_measurements(), 2 * 8 + 9);
+        assert_eq!(circuit.detectors().len(), 3 * 4);
+        assert_eq!(circuit.observables()[0].len(), 3);
+        // every check's ancilla sits diagonally next to each data qubit it checks.
+        let code = StabilizerCode::rotated_surface_code(3, SurfaceCodeVariant::Css).unwrap();
+        assert_eq!(circuit.all_qubit_coords().count(), 17);
+        assert_eq!(circuit.qubit_coords(Qubit(5)), Some(&[5.0, 3.0][..]));
+        for (s, check) in code.stabilizers().iter().enumerate() {
+            let ancilla = circuit.qubit_coords(Qubit(9 + s as u32)).unwrap();
+            for q in (0..9).filter(|&q| check.x_bits[q] || check.z_bits[q]) {
+                let data = circuit.qubit_coords(Qubit(q as u32)).unwrap();
+                assert_eq!((data[0] - ancilla[0]).abs(), 1.0);
+                assert_eq!((data[1] - ancilla[1]).abs(), 1.0);
+            }
+        }
+        for shot in crate::sampler::Sampler::new(&circuit, 2).sample(5).unwrap() {
+            assert!(circuit.detector_values(&shot).iter().all(|&fired| !fired));
+            assert_eq!(circuit.observable_values(&shot), vec![false]);
+        }
+        assert!(surface_code_memory(4, 2).is_err());
+        assert!(surface_code_memory(3, 0).is_err());
+    }
+
+    #[test]
+    fn test_preserves_code() {
+        // transversal H on the [[4,2,2]] code swaps the two stabilizers, and acts as H on
+        // both logical qubits followed by a logical swap.
+        let code = four_two_two();
+        let mut transversal_h = Circuit::new(4);
+        for qubit in Qubit::range(4) {
+            transversal_h.gate(Gate::H(qubit));
+        }
+        let action = transversal_h.preserves_code(&code).unwrap();
+        assert_eq!(action.x_images, paulis(&["IZ", "ZI"]));
+        assert_eq!(action.z_images, paulis(&["IX", "XI"]));
+        assert!(!action.is_identity());
+
+        // on the bit flip code, transversal X is logical X, and flips logical Z.
+        let repetition = StabilizerCode::new(
+            3,
+            paulis(&["ZZI", "IZZ"]),
+            paulis(&["XXX"]),
+            paulis(&["ZII"]),
+        )
+        .unwrap();
+        let mut transversal_x = Circuit::new(3);
+        transversal_x
+            .gate(Gate::X(Qubit(0)))
+            .tick()
+            .gate(Gate::X(Qubit(1)))
+            .gate(Gate::X(Qubit(2)));
+        let action = transversal_x.preserves_code(&repetition).unwrap();
+        assert_eq!(action.x_images, paulis(&["X"]));
+        assert_eq!(action.z_images, paulis(&["-Z"]));
+        assert!(Circuit::new(3)
+            .preserves_code(&repetition)
+            .unwrap()
+            .is_identity());
+
+        // and on a bare qubit, S takes X to Y.
+        let bare = StabilizerCode::new(1, vec![], paulis(&["X"]), paulis(&["Z"])).unwrap();
+        let mut s = Circuit::new(1);
+        s.gate(Gate::S(Qubit(0)));
+        assert_eq!(s.preserves_code(&bare).unwrap().x_images, paulis(&["Y"]));
+        s.gate(Gate::S(Qubit(0)));
+        assert_eq!(s.preserves_code(&bare).unwrap().x_images, paulis(&["-X"]));
+
+        let mut broken = Circuit::new(3);
+        broken.gate(Gate::Cx(Qubit(0), Qubit(1)));
+        assert_eq!(
+            broken.preserves_code(&repetition),
+            Err(Violation::StabilizerNotPreserved {
+                index: 0,
+                image: "IZI".parse().unwrap()
+            })
+        );
+        broken.measure(Qubit(0));
+        assert_eq!(
+            broken.preserves_code(&repetition),
+            Err(Violation::NotUnitary { instruction: 1 })
+        );
+        assert_eq!(
+            transversal_h.preserves_code(&repetition),
+            Err(Violation::QubitCountMismatch)
+        );
+    }
+}
@@ -0,0 +1,104 @@
+use crate::qubit::Qubit;
+use std::collections::VecDeque;
+
+// which pairs of physical qubits a device can apply two-qubit gates between.
+// Couplings are treated as undirected -- a CX can be flipped with hadamards, so
+// direction only matters to a later rebasing step, not to routing.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CouplingMap {
+    neighbors: Vec<Vec<Qubit>>,
+}
+
+impl CouplingMap {
+    pub fn new(num_qubits: usize, edges: &[(Qubit, Qubit)]) -> Result<CouplingMap, &'static str> {
+        let mut neighbors = vec![Vec::new(); num_qubits];
+        for &(a, b) in edges {
+            if a.index() >= num_qubits || b.index() >= num_qubits {
+                return Err("Coupling map edge refers to a qubit outside the device.");
+            }
+            if a == b {
+                return Err("Coupling map edge can't couple a qubit to itself.");
+            }
+            if !neighbors[a.index()].contains(&b) {
+                neighbors[a.index()].push(b);
+                neighbors[b.index()].push(a);
+            }
+        }
+        Ok(CouplingMap { neighbors })
+    }
+
+    pub fn line(num_qubits: usize) -> CouplingMap {
+        let edges: Vec<(Qubit, Qubit)> = (1..num_qubits as u32)
+            .map(|q| (Qubit(q - 1), Qubit(q)))
+            .collect();
+        CouplingMap::new(num_qubits, &edges).unwrap()
+    }
+
+    pub fn grid(rows: usize, columns: usize) -> CouplingMap {
+        let mut edges = Vec::new();
+        for row in 0..rows {
+            for column in 0..columns {
+                let qubit = (row * columns + column) as u32;
+                if column + 1 < columns {
+                    edges.push((Qubit(qubit), Qubit(qubit + 1)));
+                }
+                if row + 1 < rows {
+                    edges.push((Qubit(qubit), Qubit(qubit + columns as u32)));
+                }
+            }
+        }
+        CouplingMap::new(rows * columns, &edges).unwrap()
+    }
+
+    pub fn num_qubits(&self) -> usize {
+        self.neighbors.len()
+    }
+
+    pub fn neighbors(&self, qubit: Qubit) -> &[Qubit] {
+        &self.neighbors[qubit.index()]
+    }
+
+    pub fn edges(&self) -> Vec<(Qubit, Qubit)> {
+        let mut edges = Vec::new();
+        for (a, neighbors) in Qubit::range(self.num_qubits()).zip(&self.neighbors) {
+            for &b in neighbors {
+                if a < b {
+                    edges.push((a, b));
+                }
+            }
+        }
+        edges
+    }
+
+    pub fn are_adjacent(&self, a: Qubit, b: Qubit) -> bool {
+        self.neighbors[a.index()].contains(&b)
+    }
+
+    pub fn shortest_path(&self, from: Qubit, to: Qubit) -> Option<Vec<Qubit>> {
+        // plain BFS -- the devices we care about are small and unweighted.
+        let mut previous = vec![None; self.num_qubits()];
+        let mut visited = vec![false; self.num_qubits()];
+        let mut queue = VecDeque::from([from]);
+        visited[from.index()] = true;
+        while let Some(qubit) = queue.pop_front() {
+            if qubit == to {
+                let mut path = vec![to];
+                let mut current = to;
+                while let Some(before) = previous[current.index()] {
+                    path.push(before);
+                    current = before;
+                }
+                path.reverse();
+                return Some(path);
+            }
+            for &neighbor in &self.neighbors[qubit.index()] {
+                if !visited[neighbor.index()] {
+                    visited[neighbor.index()] = true;
+                    previous[neighbor.index()] = Some(qubit);
+                    queue.push_back(neighbor);
+                }
+            }
+        }
+        None
+    }
+}
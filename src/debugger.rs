@@ -0,0 +1,135 @@
+use crate::circuit::{Circuit, Instruction};
+use crate::pauli::PauliString;
+use crate::stabilizer_simulator::StabilizerSimulator;
+
+// executes a circuit one instruction at a time, so you can stop anywhere and
+// poke at the generators and the measurement record to see where a circuit
+// starts misbehaving.
+pub struct Debugger<'a> {
+    circuit: &'a Circuit,
+    simulator: StabilizerSimulator,
+    next_instruction: usize,
+}
+
+impl<'a> Debugger<'a> {
+    pub fn new(circuit: &'a Circuit, seed: u64) -> Debugger<'a> {
+        Debugger {
+            circuit,
+            simulator: StabilizerSimulator::new(circuit.num_qubits(), seed),
+            next_instruction: 0,
+        }
+    }
+
+    pub fn position(&self) -> usize {
+        // index of the next instruction step() will execute.
+        self.next_instruction
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.next_instruction >= self.circuit.instructions().len()
+    }
+
+    pub fn next_instruction(&self) -> Option<&'a Instruction> {
+        self.circuit.instructions().get(self.next_instruction)
+    }
+
+    pub fn step(&mut self) -> Result<Option<&'a Instruction>, &'static str> {
+        // executes the next instruction and returns it, or None if the circuit is done.
+        let Some(instruction) = self.next_instruction() else {
+            return Ok(None);
+        };
+        self.simulator.apply_instruction(instruction)?;
+        self.next_instruction += 1;
+        Ok(Some(instruction))
+    }
+
+    pub fn run_until(&mut self, index: usize) -> Result<(), &'static str> {
+        // executes every instruction before `index`, so that step() would execute
+        // instruction `index` next. Can't go backwards.
+        if index < self.next_instruction {
+            return Err("Debugger already executed past the requested instruction.");
+        }
+        if index > self.circuit.instructions().len() {
+            return Err("Requested instruction is past the end of the circuit.");
+        }
+        while self.next_instruction < index {
+            self.step()?;
+        }
+        Ok(())
+    }
+
+    pub fn run_to_end(&mut self) -> Result<(), &'static str> {
+        self.run_until(self.circuit.instructions().len())
+    }
+
+    pub fn measurement_record(&self) -> &[bool] {
+        self.simulator.measurement_record()
+    }
+
+    pub fn stabilizers(&self) -> Vec<PauliString> {
+        self.simulator.stabilizers()
+    }
+
+    pub fn destabilizers(&self) -> Vec<PauliString> {
+        self.simulator.destabilizers()
+    }
+
+    pub fn simulator(&self) -> &StabilizerSimulator {
+        &self.simulator
+    }
+
+    pub fn enable_trace(&mut self) {
+        // from here on, each gate step() runs leaves an explanation in the trace log (see
+        // StabilizerSimulator::enable_trace).
+        self.simulator.enable_trace();
+    }
+
+    pub fn take_trace_log(&mut self) -> Vec<String> {
+        self.simulator.take_trace_log()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::gates::Gate;
+    use crate::qubit::Qubit;
+
+    #[test]
+    fn test_step_through_bell_pair() {
+        let mut circuit = Circuit::new(2);
+        circuit
+            .gate(Gate::H(Qubit(0)))
+            .gate(Gate::Cx(Qubit(0), Qubit(1)))
+            .measure(Qubit(0))
+            .measure(Qubit(1));
+        let mut debugger = Debugger::new(&circuit, 0);
+        assert_eq!(
+            debugger.step().unwrap(),
+            Some(&Instruction::Gate(Gate::H(Qubit(0))))
+        );
+        let stabilizers: Vec<String> = debugger
+            .stabilizers()
+            .iter()
+            .map(|s| s.to_string())
+            .collect();
+        assert_eq!(stabilizers, vec!["+XI", "+IZ"]);
+
+        debugger.run_until(2).unwrap();
+        let stabilizers: Vec<String> = debugger
+            .stabilizers()
+            .iter()
+            .map(|s| s.to_string())
+            .collect();
+        assert_eq!(stabilizers, vec!["+XX", "+ZZ"]);
+        assert!(debugger.measurement_record().is_empty());
+
+        debugger.run_to_end().unwrap();
+        assert!(debugger.is_finished());
+        assert_eq!(debugger.step().unwrap(), None);
+        let record = debugger.measurement_record();
+        assert_eq!(record.len(), 2);
+        assert_eq!(record[0], record[1]);
+        assert!(debugger.run_until(1).is_err());
+    }
+}
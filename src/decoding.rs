@@ -0,0 +1,755 @@
+use crate::circuit::Circuit;
+use crate::dem::DetectorErrorModel;
+use crate::export::RunMetadata;
+use crate::matching::MatchingGraph;
+use crate::noise::NoiseModel;
+use crate::run_log::RunLog;
+use crate::sampler::Sampler;
+use std::cmp::Ordering;
+use std::collections::BinaryHeap;
+use std::io::Write;
+use std::mem;
+use std::sync::mpsc;
+use std::sync::Mutex;
+use std::thread;
+
+// decoders turn a shot's detection events into a guess at which logical observables its
+// errors flipped, and logical_error_rate runs the whole pipeline: sample a noisy circuit,
+// decode every shot, and count the guesses that were wrong.
+
+pub trait Decoder {
+    // detection events in, one predicted flip per observable out.
+    fn decode(&self, events: &[bool]) -> Result<Vec<bool>, &'static str>;
+}
+
+// a decoder for repetition codes and other small matching problems: exact minimum weight
+// matching of the detection events, over shortest paths in the matching graph, by dynamic
+// programming over subsets of events. On a single round of a repetition code that's a
+// majority vote over the data. The subsets make it exponential in the number of events, so
+// it's capped at MAX_EVENTS per shot, which is plenty at the low error rates where codes
+// are worth decoding.
+pub struct RepetitionDecoder {
+    graph: MatchingGraph,
+    num_observables: usize,
+    // per node, its (neighbour, edge index) pairs. Node num_detectors is the boundary.
+    adjacency: Vec<Vec<(usize, usize)>>,
+}
+
+const MAX_EVENTS: usize = 20;
+
+#[derive(PartialEq)]
+struct Candidate {
+    distance: f64,
+    node: usize,
+}
+
+impl Eq for Candidate {}
+
+impl Ord for Candidate {
+    fn cmp(&self, other: &Candidate) -> Ordering {
+        // reversed, so the heap pops the closest node first.
+        other.distance.total_cmp(&self.distance)
+    }
+}
+
+impl PartialOrd for Candidate {
+    fn partial_cmp(&self, other: &Candidate) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl RepetitionDecoder {
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(
+            level = "debug",
+            skip_all,
+            fields(
+                detectors = model.num_detectors(),
+                errors = model.errors().len(),
+            )
+        )
+    )]
+    pub fn new(model: &DetectorErrorModel) -> Result<RepetitionDecoder, &'static str> {
+        let graph = MatchingGraph::from_dem(model)?;
+        let boundary = graph.num_detectors();
+        let mut adjacency = vec![vec![]; boundary + 1];
+        for (index, edge) in graph.edges().iter().enumerate() {
+            let (a, b) = (edge.nodes.0, edge.nodes.1.unwrap_or(boundary));
+            adjacency[a].push((b, index));
+            adjacency[b].push((a, index));
+        }
+        Ok(RepetitionDecoder {
+            graph,
+            num_observables: model.num_observables(),
+            adjacency,
+        })
+    }
+
+    fn shortest_paths(&self, source: usize, weights: &[f64]) -> (Vec<f64>, Vec<Option<usize>>) {
+        // dijkstra from source: every node's distance, and the edge it was reached by.
+        let mut distances = vec![f64::INFINITY; self.adjacency.len()];
+        let mut via = vec![None; self.adjacency.len()];
+        let mut heap = BinaryHeap::from([Candidate {
+            distance: 0.0,
+            node: source,
+        }]);
+        distances[source] = 0.0;
+        while let Some(Candidate { distance, node }) = heap.pop() {
+            if distance > distances[node] {
+                continue;
+            }
+            for &(neighbour, edge) in &self.adjacency[node] {
+                let next = distance + weights[edge];
+                if next < distances[neighbour] {
+                    distances[neighbour] = next;
+                    via[neighbour] = Some(edge);
+                    heap.push(Candidate {
+                        distance: next,
+                        node: neighbour,
+                    });
+                }
+            }
+        }
+        (distances, via)
+    }
+
+    fn flip_path(&self, flips: &mut [bool], via: &[Option<usize>], source: usize, target: usize) {
+        // walks back from target to source, flipping the observables of every edge on the way.
+        let boundary = self.graph.num_detectors();
+        let mut node = target;
+        while node != source {
+            let edge = &self.graph.edges()[via[node].expect("target is reachable")];
+            for &observable in &edge.observables {
+                flips[observable] ^= true;
+            }
+            let (a, b) = (edge.nodes.0, edge.nodes.1.unwrap_or(boundary));
+            node = if node == a { b } else { a };
+        }
+    }
+}
+
+impl Decoder for RepetitionDecoder {
+    fn decode(&self, events: &[bool]) -> Result<Vec<bool>, &'static str> {
+        let syndrome = self.graph.syndrome(events);
+        if syndrome.len() > MAX_EVENTS {
+            return Err("Too many detection events for the repetition decoder.");
+        }
+        // weights above 0 keep dijkstra honest; edges more likely than not to have an error
+        // would be negative, and are treated as free instead.
+        let weights: Vec<f64> = self
+            .graph
+            .weights(events)
+            .into_iter()
+            .map(|weight| weight.max(0.0))
+            .collect();
+        let paths: Vec<_> = syndrome
+            .iter()
+            .map(|&event| self.shortest_paths(event, &weights))
+            .collect();
+        let boundary = self.graph.num_detectors();
+
+        // best[mask] is the lightest matching of the events in mask, each paired with
+        // another or the boundary, and partner[mask] who its lowest event got.
+        let k = syndrome.len();
+        let mut best = vec![f64::INFINITY; 1 << k];
+        let mut partner = vec![None; 1 << k];
+        best[0] = 0.0;
+        for mask in 1usize..1 << k {
+            let i = mask.trailing_zeros() as usize;
+            let rest = mask & !(1 << i);
+            let mut choice = (paths[i].0[boundary] + best[rest], None);
+            for j in (i + 1..k).filter(|&j| rest >> j & 1 == 1) {
+                let cost = paths[i].0[syndrome[j]] + best[rest & !(1 << j)];
+                if cost < choice.0 {
+                    choice = (cost, Some(j));
+                }
+            }
+            (best[mask], partner[mask]) = (choice.0, Some(choice.1));
+        }
+        let mut mask = (1 << k) - 1;
+        if best[mask].is_infinite() {
+            return Err("Detection events can't be matched in the graph.");
+        }
+        let mut flips = vec![false; self.num_observables];
+        while mask != 0 {
+            let i = mask.trailing_zeros() as usize;
+            let with = partner[mask].expect("every reachable mask has a partner");
+            let target = with.map_or(boundary, |j| syndrome[j]);
+            self.flip_path(&mut flips, &paths[i].1, syndrome[i], target);
+            mask &= !(1 << i);
+            if let Some(j) = with {
+                mask &= !(1 << j);
+            }
+        }
+        Ok(flips)
+    }
+}
+
+// a union-find decoder (Delfosse and Nickerson's), for when there are too many detection
+// events for exact matching. Clusters grow out from the detection events along the matching
+// graph's edges, at a rate set by the edge weights, and merge when they meet. A cluster stops
+// growing once it holds an even number of events or reaches the boundary. Then the grown
+// edges are peeled: a spanning tree of each cluster is walked from its leaves in, keeping the
+// edge to a leaf whenever the leaf still has an event to pair up. Each cluster keeps a list
+// of the edges around it, so a growth step only touches the edges around growing clusters
+// rather than the whole graph. It's not much less accurate than matching.
+pub struct UnionFindDecoder {
+    graph: MatchingGraph,
+    num_observables: usize,
+    // per node, its (neighbour, edge index) pairs. Node num_detectors is the boundary.
+    adjacency: Vec<Vec<(usize, usize)>>,
+}
+
+struct Clusters {
+    parent: Vec<usize>,
+    // whether the cluster rooted here holds an odd number of events, and touches the boundary.
+    odd: Vec<bool>,
+    boundary: Vec<bool>,
+    // the edges out of the cluster rooted here that might still grow, once per end inside
+    // it. Edges that have filled up are dropped lazily.
+    frontier: Vec<Vec<usize>>,
+}
+
+impl Clusters {
+    fn find(&mut self, mut node: usize) -> usize {
+        while self.parent[node] != node {
+            self.parent[node] = self.parent[self.parent[node]];
+            node = self.parent[node];
+        }
+        node
+    }
+
+    fn union(&mut self, a: usize, b: usize) {
+        let (a, b) = (self.find(a), self.find(b));
+        if a != b {
+            self.parent[b] = a;
+            self.odd[a] ^= self.odd[b];
+            self.boundary[a] |= self.boundary[b];
+            // the shorter list is the one copied, so no edge moves more than log(n) times.
+            let mut moved = mem::take(&mut self.frontier[b]);
+            if moved.len() > self.frontier[a].len() {
+                mem::swap(&mut moved, &mut self.frontier[a]);
+            }
+            self.frontier[a].extend(moved);
+        }
+    }
+
+    fn is_growing(&mut self, node: usize) -> bool {
+        let root = self.find(node);
+        self.odd[root] && !self.boundary[root]
+    }
+}
+
+impl UnionFindDecoder {
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(
+            level = "debug",
+            skip_all,
+            fields(
+                detectors = model.num_detectors(),
+                errors = model.errors().len(),
+            )
+        )
+    )]
+    pub fn new(model: &DetectorErrorModel) -> Result<UnionFindDecoder, &'static str> {
+        let graph = MatchingGraph::from_dem(model)?;
+        let boundary = graph.num_detectors();
+        let mut adjacency = vec![vec![]; boundary + 1];
+        for (index, edge) in graph.edges().iter().enumerate() {
+            let (a, b) = (edge.nodes.0, edge.nodes.1.unwrap_or(boundary));
+            adjacency[a].push((b, index));
+            adjacency[b].push((a, index));
+        }
+        Ok(UnionFindDecoder {
+            graph,
+            num_observables: model.num_observables(),
+            adjacency,
+        })
+    }
+
+    fn ends(&self, edge: usize) -> (usize, usize) {
+        let nodes = self.graph.edges()[edge].nodes;
+        (nodes.0, nodes.1.unwrap_or(self.graph.num_detectors()))
+    }
+
+    fn grow(&self, syndrome: &[usize], weights: &[f64]) -> Result<Vec<bool>, &'static str> {
+        // which edges end up fully grown. Each step grows every edge by as much as it takes
+        // to fill the next one, with an edge growing from both ends when both are in growing
+        // clusters.
+        let boundary = self.graph.num_detectors();
+        let mut clusters = Clusters {
+            parent: (0..=boundary).collect(),
+            odd: vec![false; boundary + 1],
+            boundary: vec![false; boundary + 1],
+            frontier: self
+                .adjacency
+                .iter()
+                .map(|neighbours| neighbours.iter().map(|&(_, edge)| edge).collect())
+                .collect(),
+        };
+        clusters.boundary[boundary] = true;
+        for &event in syndrome {
+            clusters.odd[event] ^= true;
+        }
+        let mut grown = vec![0.0; weights.len()];
+        let mut full = vec![false; weights.len()];
+        // an edge grows at one unit per end in a growing cluster.
+        let mut rates = vec![0.0; weights.len()];
+        let mut growing = Vec::new();
+        loop {
+            let mut roots = Vec::new();
+            for &event in syndrome {
+                if clusters.is_growing(event) {
+                    roots.push(clusters.find(event));
+                }
+            }
+            if roots.is_empty() {
+                break;
+            }
+            roots.sort_unstable();
+            roots.dedup();
+            growing.clear();
+            for root in roots {
+                clusters.frontier[root].retain(|&edge| !full[edge]);
+                for &edge in &clusters.frontier[root] {
+                    if rates[edge] == 0.0 {
+                        growing.push(edge);
+                    }
+                    rates[edge] += 1.0;
+                }
+            }
+            let step = growing
+                .iter()
+                .map(|&edge| (weights[edge] - grown[edge]) / rates[edge])
+                .fold(f64::INFINITY, f64::min);
+            if step.is_infinite() {
+                return Err("Detection events can't be matched in the graph.");
+            }
+            for &edge in &growing {
+                grown[edge] += rates[edge] * step;
+                rates[edge] = 0.0;
+                if grown[edge] >= weights[edge] - 1e-9 {
+                    full[edge] = true;
+                    let (a, b) = self.ends(edge);
+                    clusters.union(a, b);
+                }
+            }
+        }
+        Ok(full)
+    }
+}
+
+impl Decoder for UnionFindDecoder {
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(
+            level = "trace",
+            skip_all,
+            fields(detection_events = events.iter().filter(|&&event| event).count())
+        )
+    )]
+    fn decode(&self, events: &[bool]) -> Result<Vec<bool>, &'static str> {
+        let syndrome = self.graph.syndrome(events);
+        // as in RepetitionDecoder, edges more likely than not to have an error are free.
+        let weights: Vec<f64> = self
+            .graph
+            .weights(events)
+            .into_iter()
+            .map(|weight| weight.max(0.0))
+            .collect();
+        let full = self.grow(&syndrome, &weights)?;
+
+        // spanning trees of the grown clusters, by breadth first search from the boundary
+        // first, so the clusters that reach it are rooted there and can leave an event on it.
+        let boundary = self.graph.num_detectors();
+        let mut via = vec![None; boundary + 1];
+        let mut visited = vec![false; boundary + 1];
+        let mut order = Vec::new();
+        for root in std::iter::once(boundary).chain(0..boundary) {
+            if visited[root] {
+                continue;
+            }
+            visited[root] = true;
+            order.push(root);
+            let mut next = order.len() - 1;
+            while next < order.len() {
+                let node = order[next];
+                next += 1;
+                for &(neighbour, edge) in &self.adjacency[node] {
+                    if full[edge] && !visited[neighbour] {
+                        visited[neighbour] = true;
+                        via[neighbour] = Some(edge);
+                        order.push(neighbour);
+                    }
+                }
+            }
+        }
+
+        let mut unpaired = vec![false; boundary + 1];
+        for &event in &syndrome {
+            unpaired[event] = true;
+        }
+        let mut flips = vec![false; self.num_observables];
+        for &node in order.iter().rev() {
+            let Some(edge) = via[node].filter(|_| unpaired[node]) else {
+                continue;
+            };
+            for &observable in &self.graph.edges()[edge].observables {
+                flips[observable] ^= true;
+            }
+            let (a, b) = self.ends(edge);
+            unpaired[node] = false;
+            unpaired[if node == a { b } else { a }] ^= true;
+        }
+        #[cfg(feature = "tracing")]
+        tracing::trace!(
+            grown_edges = full.iter().filter(|&&full| full).count(),
+            "matched detection events"
+        );
+        Ok(flips)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LogicalErrorRate {
+    pub shots: usize,
+    // shots where the decoder got any observable wrong.
+    pub errors: usize,
+}
+
+impl LogicalErrorRate {
+    pub fn rate(&self) -> Option<f64> {
+        // the fraction of shots decoded wrong, or None when there were no shots to go by.
+        (self.shots > 0).then(|| self.errors as f64 / self.shots as f64)
+    }
+}
+
+// turns shots into (detection events, observable flips) pairs. Both are measured against a
+// noiseless reference shot, so detectors and observables with a deterministic value of 1
+// work too.
+struct Reference<'a> {
+    circuit: &'a Circuit,
+    detectors: Vec<bool>,
+    observables: Vec<bool>,
+}
+
+impl<'a> Reference<'a> {
+    fn new(circuit: &'a Circuit, seed: u64) -> Result<Reference<'a>, &'static str> {
+        let reference = Sampler::new(circuit, seed).sample_shot()?;
+        Ok(Reference {
+            circuit,
+            detectors: circuit.detector_values(&reference),
+            observables: circuit.observable_values(&reference),
+        })
+    }
+
+    fn events_and_flips(&self, shot: &[bool]) -> (Vec<bool>, Vec<bool>) {
+        let differences = |values: Vec<bool>, reference: &[bool]| -> Vec<bool> {
+            values.iter().zip(reference).map(|(a, b)| a ^ b).collect()
+        };
+        (
+            differences(self.circuit.detector_values(shot), &self.detectors),
+            differences(self.circuit.observable_values(shot), &self.observables),
+        )
+    }
+}
+
+pub fn detection_events(
+    circuit: &Circuit,
+    shots: &[Vec<bool>],
+    seed: u64,
+) -> Result<Vec<Vec<bool>>, &'static str> {
+    // each shot's detection events, the way logical_error_rate hands them to decoders: which
+    // detectors came out different from a noiseless reference shot sampled with `seed`.
+    let reference = Reference::new(circuit, seed)?;
+    Ok(shots
+        .iter()
+        .map(|shot| reference.events_and_flips(shot).0)
+        .collect())
+}
+
+#[cfg_attr(
+    feature = "tracing",
+    tracing::instrument(level = "info", skip_all, fields(shots, seed))
+)]
+pub fn logical_error_rate(
+    circuit: &Circuit,
+    noise: &NoiseModel,
+    decoder: &impl Decoder,
+    shots: usize,
+    seed: u64,
+) -> Result<LogicalErrorRate, &'static str> {
+    let reference = Reference::new(circuit, seed)?;
+    let mut errors = 0;
+    for shot in Sampler::new(circuit, seed)
+        .with_noise(noise.clone())
+        .sample(shots)?
+    {
+        let (events, flips) = reference.events_and_flips(&shot);
+        if decoder.decode(&events)? != flips {
+            errors += 1;
+        }
+    }
+    #[cfg(feature = "tracing")]
+    tracing::debug!(shots, errors, "decoded every shot");
+    Ok(LogicalErrorRate { shots, errors })
+}
+
+pub fn logical_error_rate_logged(
+    circuit: &Circuit,
+    noise: &NoiseModel,
+    decoder: &impl Decoder,
+    shots: usize,
+    seed: u64,
+    log: &mut RunLog<impl Write>,
+) -> Result<LogicalErrorRate, &'static str> {
+    // logical_error_rate, writing the run to a JSON Lines log as it goes (see run_log):
+    // every shot's seed and measurements, its detection events and whether the decoder got
+    // it wrong, batch stats, and the final count.
+    let failed = |_| "Couldn't write to the run log.";
+    let reference = Reference::new(circuit, seed)?;
+    let mut sampler = Sampler::new(circuit, seed).with_noise(noise.clone());
+    log.run_started(&RunMetadata::new(circuit, seed, shots))
+        .map_err(failed)?;
+    let mut errors = 0;
+    for index in 0..shots as u64 {
+        let shot = sampler.sample_shot_logged(log).map_err(failed)?;
+        let (events, flips) = reference.events_and_flips(&shot);
+        let wrong = decoder.decode(&events)? != flips;
+        errors += wrong as usize;
+        log.shot_decoded(index, &events, wrong).map_err(failed)?;
+    }
+    sampler.finish_log(log).map_err(failed)?;
+    log.run_finished(shots, Some(errors)).map_err(failed)?;
+    Ok(LogicalErrorRate { shots, errors })
+}
+
+// how logical_error_rate_pipelined splits up the work: sampler threads turn batches of shots
+// into detection events, and send them over a channel holding at most channel_capacity
+// batches to the decoder threads, so a slow decoder holds the samplers back instead of
+// letting batches pile up in memory.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PipelineConfig {
+    pub sampler_threads: usize,
+    pub decoder_threads: usize,
+    pub batch_size: usize,
+    pub channel_capacity: usize,
+}
+
+impl Default for PipelineConfig {
+    fn default() -> PipelineConfig {
+        // half the cores sampling and half decoding.
+        let cores = thread::available_parallelism().map_or(2, |cores| cores.get());
+        PipelineConfig {
+            sampler_threads: (cores / 2).max(1),
+            decoder_threads: (cores / 2).max(1),
+            batch_size: 256,
+            channel_capacity: 8,
+        }
+    }
+}
+
+type Batch = Result<Vec<(Vec<bool>, Vec<bool>)>, &'static str>;
+
+#[cfg_attr(
+    feature = "tracing",
+    tracing::instrument(
+        level = "info",
+        skip_all,
+        fields(
+            shots,
+            seed,
+            sampler_threads = config.sampler_threads,
+            decoder_threads = config.decoder_threads,
+            batch_size = config.batch_size,
+        )
+    )
+)]
+pub fn logical_error_rate_pipelined(
+    circuit: &Circuit,
+    noise: &NoiseModel,
+    decoder: &(impl Decoder + Sync),
+    shots: usize,
+    seed: u64,
+    config: &PipelineConfig,
+) -> Result<LogicalErrorRate, &'static str> {
+    // the same count as logical_error_rate, with sampling and decoding overlapped. Shot i
+    // always runs with shot_seed(seed, i) whichever thread samples it, so the result doesn't
+    // depend on the thread counts or batch size.
+    if config.sampler_threads == 0 || config.decoder_threads == 0 || config.batch_size == 0 {
+        return Err("A pipeline needs at least one thread of each kind and nonempty batches.");
+    }
+    let reference = Reference::new(circuit, seed)?;
+    let num_batches = shots.div_ceil(config.batch_size);
+    let (sender, receiver) = mpsc::sync_channel::<Batch>(config.channel_capacity);
+    let receiver = Mutex::new(receiver);
+    thread::scope(|scope| {
+        for first in 0..config.sampler_threads {
+            let sender = sender.clone();
+            let reference = &reference;
+            scope.spawn(move || {
+                // batches first, first + sampler_threads, ... so the threads interleave.
+                for batch in (first..num_batches).step_by(config.sampler_threads) {
+                    let start = batch * config.batch_size;
+                    let count = config.batch_size.min(shots - start);
+                    let sampled = Sampler::new(circuit, seed)
+                        .with_noise(noise.clone())
+                        .starting_at(start as u64)
+                        .sample(count)
+                        .map(|shots| {
+                            shots
+                                .iter()
+                                .map(|shot| reference.events_and_flips(shot))
+                                .collect()
+                        });
+                    let failed = sampled.is_err();
+                    if sender.send(sampled).is_err() || failed {
+                        return;
+                    }
+                }
+            });
+        }
+        // the channel closes once every sampler's sender is gone.
+        drop(sender);
+        let decoders: Vec<_> = (0..config.decoder_threads)
+            .map(|_| {
+                let receiver = &receiver;
+                scope.spawn(move || -> Result<usize, &'static str> {
+                    // after a failure keep draining, so no sampler is left blocked on a full
+                    // channel.
+                    let mut outcome = Ok(0);
+                    loop {
+                        let batch = receiver.lock().expect("no decoder panicked").recv();
+                        let Ok(batch) = batch else {
+                            return outcome;
+                        };
+                        let Ok(errors) = outcome.as_mut() else {
+                            continue;
+                        };
+                        let decoded = batch.and_then(|batch| {
+                            let mut wrong = 0;
+                            for (events, flips) in &batch {
+                                wrong += (decoder.decode(events)? != *flips) as usize;
+                            }
+                            #[cfg(feature = "tracing")]
+                            tracing::debug!(shots = batch.len(), errors = wrong, "decoded a batch");
+                            Ok(wrong)
+                        });
+                        match decoded {
+                            Ok(wrong) => *errors += wrong,
+                            Err(error) => outcome = Err(error),
+                        }
+                    }
+                })
+            })
+            .collect();
+        let mut errors = 0;
+        for decoder in decoders {
+            errors += decoder.join().expect("decoder thread panicked")?;
+        }
+        #[cfg(feature = "tracing")]
+        tracing::debug!(shots, errors, "decoded every shot");
+        Ok(LogicalErrorRate { shots, errors })
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::code::repetition_code_memory;
+
+    #[test]
+    fn test_union_find_decoder() {
+        let circuit = repetition_code_memory(5, 3).unwrap();
+        let noise = NoiseModel::uniform_depolarizing(0.01);
+        let model = DetectorErrorModel::from_circuit(&circuit, &noise).unwrap();
+        let decoder = UnionFindDecoder::new(&model).unwrap();
+        for error in model.errors() {
+            let mut events = vec![false; model.num_detectors()];
+            for &detector in &error.detectors {
+                events[detector] = true;
+            }
+            let mut flips = vec![false; model.num_observables()];
+            for &observable in &error.observables {
+                flips[observable] = true;
+            }
+            assert_eq!(decoder.decode(&events), Ok(flips));
+        }
+        let decoded = logical_error_rate(&circuit, &noise, &decoder, 500, 7).unwrap();
+        assert!(decoded.rate().unwrap() < 0.02);
+        let nothing = logical_error_rate(&circuit, &noise, &decoder, 0, 7).unwrap();
+        assert_eq!(nothing.rate(), None);
+        let mut log = RunLog::new(Vec::new());
+        let logged = logical_error_rate_logged(&circuit, &noise, &decoder, 100, 7, &mut log);
+        assert_eq!(
+            logged,
+            logical_error_rate(&circuit, &noise, &decoder, 100, 7)
+        );
+        let text = String::from_utf8(log.into_inner()).unwrap();
+        assert_eq!(text.matches("\"event\":\"shot_decoded\"").count(), 100);
+        assert!(text.ends_with(&format!(
+            "{{\"event\":\"run_finished\",\"shots\":100,\"logical_errors\":{}}}\n",
+            logged.unwrap().errors
+        )));
+
+        // far more events than the repetition decoder takes: errors on every fourth check.
+        let circuit = repetition_code_memory(101, 1).unwrap();
+        let model = DetectorErrorModel::from_circuit(&circuit, &noise).unwrap();
+        let decoder = UnionFindDecoder::new(&model).unwrap();
+        let mut events = vec![false; model.num_detectors()];
+        for check in (0..100).step_by(4) {
+            // the check's measurement flipped, which it and the final readout see.
+            events[check] ^= true;
+            events[100 + check] ^= true;
+        }
+        assert!(events.iter().filter(|&&event| event).count() > MAX_EVENTS);
+        assert_eq!(decoder.decode(&events), Ok(vec![false]));
+    }
+
+    #[test]
+    fn test_repetition_decoder_corrects_two_faults() {
+        let circuit = repetition_code_memory(5, 3).unwrap();
+        let noise = NoiseModel::uniform_depolarizing(0.01);
+        let model = DetectorErrorModel::from_circuit(&circuit, &noise).unwrap();
+        let decoder = RepetitionDecoder::new(&model).unwrap();
+        // any one error, and any two, is corrected at distance 5.
+        for a in model.errors() {
+            for b in model.errors() {
+                let mut events = vec![false; model.num_detectors()];
+                let mut flips = vec![false; model.num_observables()];
+                for error in [a, b] {
+                    for &detector in &error.detectors {
+                        events[detector] ^= true;
+                    }
+                    for &observable in &error.observables {
+                        flips[observable] ^= true;
+                    }
+                }
+                assert_eq!(decoder.decode(&events), Ok(flips));
+            }
+        }
+
+        let decoded = logical_error_rate(&circuit, &noise, &decoder, 500, 7).unwrap();
+        assert_eq!(decoded.shots, 500);
+        assert!(decoded.rate().unwrap() < 0.02);
+
+        // same shots, same count, however the work is split up.
+        let noise = NoiseModel::uniform_depolarizing(0.05);
+        let serial = logical_error_rate(&circuit, &noise, &decoder, 300, 3).unwrap();
+        assert!(serial.errors > 0);
+        for (sampler_threads, decoder_threads, batch_size) in [(1, 1, 300), (3, 2, 7)] {
+            let config = PipelineConfig {
+                sampler_threads,
+                decoder_threads,
+                batch_size,
+                channel_capacity: 2,
+            };
+            assert_eq!(
+                logical_error_rate_pipelined(&circuit, &noise, &decoder, 300, 3, &config),
+                Ok(serial)
+            );
+        }
+    }
+}
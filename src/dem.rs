@@ -0,0 +1,426 @@
+use crate::circuit::Circuit;
+use crate::faults;
+use crate::gf2::BitMatrix;
+use crate::noise::NoiseModel;
+use crate::parser::ParseError;
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt;
+
+// a detector error model: the independent error mechanisms of a noisy circuit, each as the
+// probability it happens and the detectors and observables it flips. That's everything a
+// decoder needs, without the circuit. The text format is stim's, so models can go back and
+// forth with pymatching and friends:
+//
+//     # comments run to the end of the line
+//     error(0.001) D0 D1 L0         # flips detectors 0 and 1 and logical observable 0
+//     error(0.002) D1 ^ D2          # ^ separates a suggested decomposition, which is ignored
+//     detector(1, 2) D0             # coordinates for detector 0
+//     detector D7                   # declares a detector nothing flips
+//     detector[erasure] D3          # an erasure flag, which heralds errors rather than checking
+//     logical_observable L1
+//     shift_detectors(0, 1) 4       # later detector indices are offset by 4, coordinates by (0, 1)
+//
+// Other tags, like error[tag](0.1), are ignored, and repeat blocks aren't supported.
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct DemError {
+    pub probability: f64,
+    // both sorted, without repeats.
+    pub detectors: Vec<usize>,
+    pub observables: Vec<usize>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct DetectorErrorModel {
+    num_detectors: usize,
+    num_observables: usize,
+    errors: Vec<DemError>,
+    detector_coords: BTreeMap<usize, Vec<f64>>,
+    erasure_flags: BTreeSet<usize>,
+}
+
+fn parity(mut indices: Vec<usize>) -> Vec<usize> {
+    // sorted, keeping the indices that show up an odd number of times, since flipping
+    // something twice undoes it.
+    indices.sort();
+    let mut kept: Vec<usize> = Vec::with_capacity(indices.len());
+    for index in indices {
+        if kept.last() == Some(&index) {
+            kept.pop();
+        } else {
+            kept.push(index);
+        }
+    }
+    kept
+}
+
+impl DetectorErrorModel {
+    pub fn new() -> DetectorErrorModel {
+        DetectorErrorModel::default()
+    }
+
+    pub fn from_circuit(
+        circuit: &Circuit,
+        noise: &NoiseModel,
+    ) -> Result<DetectorErrorModel, &'static str> {
+        // faults with the same symptoms are indistinguishable to a decoder, so they merge into
+        // one error that happens when an odd number of them do.
+        let mut model = DetectorErrorModel::new();
+        model.num_detectors = circuit.detectors().len();
+        model.num_observables = circuit.observables().len();
+        for (index, detector) in circuit.detectors().iter().enumerate() {
+            if !detector.coords.is_empty() {
+                model.set_detector_coords(index, &detector.coords);
+            }
+            if detector.erasure_flag {
+                model.mark_erasure_flag(index);
+            }
+        }
+        let mut merged: BTreeMap<(Vec<usize>, Vec<usize>), usize> = BTreeMap::new();
+        for fault in faults::analyze_faults(circuit, noise)? {
+            if fault.detectors.is_empty() && fault.observables.is_empty() {
+                continue;
+            }
+            let symptoms = (fault.detectors, fault.observables);
+            match merged.get(&symptoms) {
+                Some(&index) => {
+                    let p = model.errors[index].probability;
+                    let q = fault.probability;
+                    model.errors[index].probability = p * (1.0 - q) + q * (1.0 - p);
+                }
+                None => {
+                    merged.insert(symptoms.clone(), model.errors.len());
+                    model.add_error(fault.probability, &symptoms.0, &symptoms.1)?;
+                }
+            }
+        }
+        Ok(model)
+    }
+
+    pub fn num_detectors(&self) -> usize {
+        self.num_detectors
+    }
+
+    pub fn num_observables(&self) -> usize {
+        self.num_observables
+    }
+
+    pub fn errors(&self) -> &[DemError] {
+        &self.errors
+    }
+
+    pub fn check_matrix(&self) -> BitMatrix {
+        // detectors by errors, with a 1 where the error flips the detector. A shot's
+        // detection events are this times the vector of errors that happened.
+        let mut matrix = BitMatrix::new(self.num_detectors, self.errors.len());
+        for (column, error) in self.errors.iter().enumerate() {
+            for &detector in &error.detectors {
+                matrix.set(detector, column, true);
+            }
+        }
+        matrix
+    }
+
+    pub fn observable_matrix(&self) -> BitMatrix {
+        // the same for logical observables.
+        let mut matrix = BitMatrix::new(self.num_observables, self.errors.len());
+        for (column, error) in self.errors.iter().enumerate() {
+            for &observable in &error.observables {
+                matrix.set(observable, column, true);
+            }
+        }
+        matrix
+    }
+
+    pub fn detector_coords(&self, detector: usize) -> Option<&[f64]> {
+        self.detector_coords.get(&detector).map(Vec::as_slice)
+    }
+
+    pub fn is_erasure_flag(&self, detector: usize) -> bool {
+        self.erasure_flags.contains(&detector)
+    }
+
+    pub fn erasure_flags(&self) -> impl Iterator<Item = usize> + '_ {
+        self.erasure_flags.iter().copied()
+    }
+
+    pub fn mark_erasure_flag(&mut self, detector: usize) -> &mut DetectorErrorModel {
+        // the detector heralds errors instead of checking a parity (see circuit::Detector).
+        self.num_detectors = self.num_detectors.max(detector + 1);
+        self.erasure_flags.insert(detector);
+        self
+    }
+
+    pub fn add_error(
+        &mut self,
+        probability: f64,
+        detectors: &[usize],
+        observables: &[usize],
+    ) -> Result<&mut DetectorErrorModel, &'static str> {
+        if !(0.0..=1.0).contains(&probability) {
+            return Err("Error probability must be between 0 and 1.");
+        }
+        let detectors = parity(detectors.to_vec());
+        let observables = parity(observables.to_vec());
+        if let Some(&last) = detectors.last() {
+            self.num_detectors = self.num_detectors.max(last + 1);
+        }
+        if let Some(&last) = observables.last() {
+            self.num_observables = self.num_observables.max(last + 1);
+        }
+        self.errors.push(DemError {
+            probability,
+            detectors,
+            observables,
+        });
+        Ok(self)
+    }
+
+    pub fn set_detector_coords(
+        &mut self,
+        detector: usize,
+        coords: &[f64],
+    ) -> &mut DetectorErrorModel {
+        self.num_detectors = self.num_detectors.max(detector + 1);
+        self.detector_coords.insert(detector, coords.to_vec());
+        self
+    }
+
+    pub fn parse(text: &str) -> Result<DetectorErrorModel, ParseError> {
+        let mut model = DetectorErrorModel::new();
+        let mut detector_offset = 0;
+        let mut coord_offset: Vec<f64> = Vec::new();
+        for (index, line) in text.lines().enumerate() {
+            let error = |reason| ParseError {
+                line: index + 1,
+                reason,
+            };
+            model
+                .parse_line(line, &mut detector_offset, &mut coord_offset)
+                .map_err(error)?;
+        }
+        Ok(model)
+    }
+
+    fn parse_line(
+        &mut self,
+        line: &str,
+        detector_offset: &mut usize,
+        coord_offset: &mut Vec<f64>,
+    ) -> Result<(), &'static str> {
+        let line = line.split('#').next().unwrap_or("").trim();
+        if line.is_empty() {
+            return Ok(());
+        }
+        let name_end = line
+            .find(|c: char| c == '(' || c == '[' || c.is_whitespace())
+            .unwrap_or(line.len());
+        let (name, mut rest) = line.split_at(name_end);
+        let mut tag = "";
+        if let Some(tagged) = rest.strip_prefix('[') {
+            (tag, rest) = tagged.split_once(']').ok_or("Missing a closing bracket.")?;
+        }
+        let erasure_flag = tag.eq_ignore_ascii_case("erasure");
+        let (arguments, targets) = match rest.trim_start().strip_prefix('(') {
+            Some(rest) => rest
+                .split_once(')')
+                .ok_or("Missing a closing parenthesis.")?,
+            None => ("", rest),
+        };
+        let arguments: Vec<f64> = arguments
+            .split(',')
+            .map(str::trim)
+            .filter(|argument| !argument.is_empty())
+            .map(|argument| argument.parse().map_err(|_| "Expected a number."))
+            .collect::<Result<_, _>>()?;
+
+        let mut detectors = Vec::new();
+        let mut observables = Vec::new();
+        let mut counts: Vec<usize> = Vec::new();
+        for target in targets.split_whitespace() {
+            if target == "^" {
+                continue;
+            }
+            if let Some(detector) = target.strip_prefix('D') {
+                let detector: usize = detector.parse().map_err(|_| "Expected a detector index.")?;
+                detectors.push(detector + *detector_offset);
+            } else if let Some(observable) = target.strip_prefix('L') {
+                observables.push(
+                    observable
+                        .parse()
+                        .map_err(|_| "Expected an observable index.")?,
+                );
+            } else {
+                counts.push(target.parse().map_err(|_| "Unknown target.")?);
+            }
+        }
+
+        match name.to_ascii_lowercase().as_str() {
+            "error" => {
+                let [probability] = arguments[..] else {
+                    return Err("error takes one probability, like error(0.01).");
+                };
+                if !counts.is_empty() {
+                    return Err("error only takes D and L targets.");
+                }
+                self.add_error(probability, &detectors, &observables)?;
+            }
+            "detector" => {
+                if !observables.is_empty() || !counts.is_empty() {
+                    return Err("detector only takes D targets.");
+                }
+                for detector in detectors {
+                    if name.eq_ignore_ascii_case("detector") && erasure_flag {
+                        self.mark_erasure_flag(detector);
+                    }
+                    if arguments.is_empty() {
+                        self.num_detectors = self.num_detectors.max(detector + 1);
+                        continue;
+                    }
+                    let coords: Vec<f64> = arguments
+                        .iter()
+                        .enumerate()
+                        .map(|(axis, x)| x + coord_offset.get(axis).unwrap_or(&0.0))
+                        .collect();
+                    self.set_detector_coords(detector, &coords);
+                }
+            }
+            "logical_observable" => {
+                if !arguments.is_empty() || !detectors.is_empty() || !counts.is_empty() {
+                    return Err("logical_observable only takes L targets.");
+                }
+                for observable in observables {
+                    self.num_observables = self.num_observables.max(observable + 1);
+                }
+            }
+            "shift_detectors" => {
+                let [shift] = counts[..] else {
+                    return Err("shift_detectors takes one count, like shift_detectors 4.");
+                };
+                if !detectors.is_empty() || !observables.is_empty() {
+                    return Err("shift_detectors takes one count, like shift_detectors 4.");
+                }
+                *detector_offset += shift;
+                if coord_offset.len() < arguments.len() {
+                    coord_offset.resize(arguments.len(), 0.0);
+                }
+                for (offset, shift) in coord_offset.iter_mut().zip(arguments) {
+                    *offset += shift;
+                }
+            }
+            "repeat" => return Err("repeat blocks aren't supported."),
+            _ => return Err("Unknown detector error model instruction."),
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for DetectorErrorModel {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        // errors first, then the detector coordinates, then declarations for anything that
+        // wouldn't show up otherwise, so the counts survive a round trip.
+        let mut mentioned_detectors = vec![false; self.num_detectors];
+        let mut mentioned_observables = vec![false; self.num_observables];
+        for error in &self.errors {
+            write!(f, "error({})", error.probability)?;
+            for &detector in &error.detectors {
+                mentioned_detectors[detector] = true;
+                write!(f, " D{}", detector)?;
+            }
+            for &observable in &error.observables {
+                mentioned_observables[observable] = true;
+                write!(f, " L{}", observable)?;
+            }
+            writeln!(f)?;
+        }
+        let tag = |detector| match self.is_erasure_flag(detector) {
+            true => "[erasure]",
+            false => "",
+        };
+        for (&detector, coords) in &self.detector_coords {
+            mentioned_detectors[detector] = true;
+            let coords: Vec<String> = coords.iter().map(|x| x.to_string()).collect();
+            let tag = tag(detector);
+            writeln!(f, "detector{}({}) D{}", tag, coords.join(", "), detector)?;
+        }
+        for detector in self.erasure_flags() {
+            if !self.detector_coords.contains_key(&detector) {
+                mentioned_detectors[detector] = true;
+                writeln!(f, "detector[erasure] D{}", detector)?;
+            }
+        }
+        for (detector, mentioned) in mentioned_detectors.iter().enumerate() {
+            if !mentioned {
+                writeln!(f, "detector D{}", detector)?;
+            }
+        }
+        for (observable, mentioned) in mentioned_observables.iter().enumerate() {
+            if !mentioned {
+                writeln!(f, "logical_observable L{}", observable)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::gates::Gate;
+    use crate::qubit::Qubit;
+
+    #[test]
+    fn test_text_round_trip() {
+        let model = DetectorErrorModel::parse(
+            "# a small model
+             error(0.125) D0 D1 L0
+             error(0.25) D1 ^ D2 D2   # the repeated D2 cancels
+             detector(1, 2) D0
+             shift_detectors(10) 3
+             detector(0.5) D0
+             detector D4
+             detector[erasure] D1
+             error[hook](0.5) D3
+             logical_observable L2",
+        )
+        .unwrap();
+        assert_eq!(model.num_detectors(), 8);
+        assert!(model.is_erasure_flag(4) && !model.is_erasure_flag(1));
+        assert_eq!(model.num_observables(), 3);
+        assert_eq!(model.errors()[1].detectors, vec![1]);
+        assert_eq!(model.detector_coords(3), Some(&[10.5][..]));
+        assert_eq!(model.check_matrix().row(1), vec![true, true, false]);
+        assert_eq!(model.observable_matrix().row(0), vec![true, false, false]);
+        assert_eq!(DetectorErrorModel::parse(&model.to_string()), Ok(model));
+
+        let error = DetectorErrorModel::parse("error(0.1) D0\nerror(2) D1").unwrap_err();
+        assert_eq!(error.line, 2);
+        assert!(DetectorErrorModel::parse("repeat 3 {").is_err());
+        assert!(DetectorErrorModel::parse("error(0.1) Q0").is_err());
+    }
+
+    #[test]
+    fn test_from_circuit_merges_symptoms() {
+        // X and Y errors on the data qubit both flip the one detector, so they merge.
+        let mut circuit = Circuit::new(2);
+        circuit
+            .gate(Gate::H(Qubit(0)))
+            .gate(Gate::H(Qubit(0)))
+            .gate(Gate::Cx(Qubit(0), Qubit(1)))
+            .measure(Qubit(1));
+        circuit.add_detector(&[1], &[0.0, 1.0]).unwrap();
+        let noise = NoiseModel::uniform_depolarizing(0.3);
+        let model = DetectorErrorModel::from_circuit(&circuit, &noise).unwrap();
+        assert_eq!(model.num_detectors(), 1);
+        assert_eq!(model.detector_coords(0), Some(&[0.0, 1.0][..]));
+        assert!(model
+            .errors()
+            .iter()
+            .all(|error| error.detectors == vec![0]));
+        assert_eq!(model.errors().len(), 1);
+        assert_eq!(
+            DetectorErrorModel::parse(&model.to_string()).unwrap(),
+            model
+        );
+    }
+}
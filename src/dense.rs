@@ -0,0 +1,354 @@
+use crate::circuit::{Circuit, Instruction};
+use crate::complex::Complex;
+use crate::gates::Gate;
+use crate::pauli::PauliString;
+use crate::qubit::Qubit;
+use crate::stabilizer_simulator::StabilizerSimulator;
+use std::f64::consts::FRAC_1_SQRT_2;
+
+// a deliberately naive statevector simulator, used as a reference to check the
+// tableau update rules against. It stores all 2^n amplitudes, so it's only meant
+// for small registers.
+pub const MAX_DENSE_QUBITS: usize = 12;
+
+const TOLERANCE: f64 = 1e-9;
+
+// bit q of an amplitude's index is the value of qubit q.
+pub(crate) struct DenseSimulator {
+    amplitudes: Vec<Complex>,
+}
+
+impl DenseSimulator {
+    pub(crate) fn new(num_qubits: usize) -> DenseSimulator {
+        let mut amplitudes = vec![Complex::ZERO; 1 << num_qubits];
+        amplitudes[0] = Complex::ONE;
+        DenseSimulator { amplitudes }
+    }
+
+    pub(crate) fn apply_gate(&mut self, gate: &Gate) {
+        match *gate {
+            Gate::H(qubit) => {
+                let mask = qubit.mask();
+                for index in 0..self.amplitudes.len() {
+                    if index & mask == 0 {
+                        let zero = self.amplitudes[index];
+                        let one = self.amplitudes[index | mask];
+                        self.amplitudes[index] = (zero + one).scale(FRAC_1_SQRT_2);
+                        self.amplitudes[index | mask] =
+                            (zero + one.scale(-1.0)).scale(FRAC_1_SQRT_2);
+                    }
+                }
+            }
+            Gate::S(qubit) => {
+                for (index, amplitude) in self.amplitudes.iter_mut().enumerate() {
+                    if index & qubit.mask() != 0 {
+                        *amplitude = *amplitude * Complex::I;
+                    }
+                }
+            }
+            Gate::X(qubit) => {
+                for index in 0..self.amplitudes.len() {
+                    if index & qubit.mask() == 0 {
+                        self.amplitudes.swap(index, index | qubit.mask());
+                    }
+                }
+            }
+            Gate::Y(qubit) => {
+                // Y|0> = i|1>, Y|1> = -i|0>
+                for index in 0..self.amplitudes.len() {
+                    if index & qubit.mask() == 0 {
+                        let zero = self.amplitudes[index];
+                        let one = self.amplitudes[index | qubit.mask()];
+                        self.amplitudes[index] = one * Complex::I.scale(-1.0);
+                        self.amplitudes[index | qubit.mask()] = zero * Complex::I;
+                    }
+                }
+            }
+            Gate::Z(qubit) => {
+                for (index, amplitude) in self.amplitudes.iter_mut().enumerate() {
+                    if index & qubit.mask() != 0 {
+                        *amplitude = amplitude.scale(-1.0);
+                    }
+                }
+            }
+            Gate::Cx(control, target) => {
+                for index in 0..self.amplitudes.len() {
+                    if index & control.mask() != 0 && index & target.mask() == 0 {
+                        self.amplitudes.swap(index, index | target.mask());
+                    }
+                }
+            }
+            Gate::Cz(a, b) => {
+                for (index, amplitude) in self.amplitudes.iter_mut().enumerate() {
+                    if index & a.mask() != 0 && index & b.mask() != 0 {
+                        *amplitude = amplitude.scale(-1.0);
+                    }
+                }
+            }
+            Gate::Swap(a, b) => {
+                for index in 0..self.amplitudes.len() {
+                    if index & a.mask() != 0 && index & b.mask() == 0 {
+                        self.amplitudes.swap(index, index ^ a.mask() ^ b.mask());
+                    }
+                }
+            }
+        }
+    }
+
+    #[cfg(test)]
+    pub(crate) fn amplitude(&self, index: usize) -> Complex {
+        self.amplitudes[index]
+    }
+
+    pub(crate) fn probability_of_one(&self, qubit: Qubit) -> f64 {
+        self.amplitudes
+            .iter()
+            .enumerate()
+            .filter(|(index, _)| index & qubit.mask() != 0)
+            .map(|(_, amplitude)| amplitude.norm_squared())
+            .sum()
+    }
+
+    pub(crate) fn collapse(&mut self, qubit: Qubit, outcome: bool) {
+        // project onto the given outcome and renormalize. The outcome must have nonzero probability.
+        let probability = if outcome {
+            self.probability_of_one(qubit)
+        } else {
+            1.0 - self.probability_of_one(qubit)
+        };
+        let renormalization = 1.0 / probability.sqrt();
+        for (index, amplitude) in self.amplitudes.iter_mut().enumerate() {
+            if (index & qubit.mask() != 0) == outcome {
+                *amplitude = amplitude.scale(renormalization);
+            } else {
+                *amplitude = Complex::ZERO;
+            }
+        }
+    }
+
+    pub(crate) fn expectation(&self, pauli: &PauliString) -> f64 {
+        // <psi|P|psi>. P maps |index> to phase * |index ^ x_mask>, where each qubit contributes
+        // X|b> = |!b>, Z|b> = (-1)^b |b>, and Y|b> = i(-1)^b |!b>.
+        let mut total = Complex::ZERO;
+        for (index, amplitude) in self.amplitudes.iter().enumerate() {
+            let mut phase = if pauli.phase_is_negated {
+                Complex::ONE.scale(-1.0)
+            } else {
+                Complex::ONE
+            };
+            let mut image = index;
+            for qubit in 0..pauli.num_qubits() {
+                let bit = index & (1 << qubit) != 0;
+                if pauli.z_bits[qubit] && bit {
+                    phase = phase.scale(-1.0);
+                }
+                if pauli.x_bits[qubit] {
+                    image ^= 1 << qubit;
+                    if pauli.z_bits[qubit] {
+                        phase = phase * Complex::I;
+                    }
+                }
+            }
+            total = total + self.amplitudes[image].conj() * phase * *amplitude;
+        }
+        total.re
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DenseMismatch {
+    // index of the first instruction after which the two simulators disagreed,
+    // or None if the circuit couldn't be checked at all.
+    pub instruction_index: Option<usize>,
+    pub reason: &'static str,
+}
+
+pub fn verify_against_dense(circuit: &Circuit, seed: u64) -> Result<(), DenseMismatch> {
+    // runs the circuit on the stabilizer simulator and on the dense reference side by side.
+    // Every measurement has to agree on its distribution: a deterministic tableau measurement
+    // needs the dense probability to be 0 or 1 and match the outcome, and a random one needs
+    // it to be 1/2. The dense state then collapses to whatever the tableau picked, so both
+    // follow the same branch. After every instruction, each stabilizer generator (sign included)
+    // also has to have expectation +1 in the dense state, which pins down the whole state.
+    if circuit.num_qubits() > MAX_DENSE_QUBITS {
+        return Err(DenseMismatch {
+            instruction_index: None,
+            reason: "Circuit is too large for the dense reference simulator.",
+        });
+    }
+    let mut stabilizer = StabilizerSimulator::new(circuit.num_qubits(), seed);
+    let mut dense = DenseSimulator::new(circuit.num_qubits());
+    for (instruction_index, instruction) in circuit.instructions().iter().enumerate() {
+        let mismatch = |reason| DenseMismatch {
+            instruction_index: Some(instruction_index),
+            reason,
+        };
+        match instruction {
+            Instruction::Gate(gate) => {
+                stabilizer.apply_gate(gate);
+                dense.apply_gate(gate);
+            }
+            Instruction::Measure(qubit) | Instruction::MeasureToBit { qubit, .. } => {
+                let deterministic = stabilizer.is_deterministic(*qubit);
+                let outcome = stabilizer
+                    .measure(*qubit)
+                    .map_err(|_| mismatch("Stabilizer simulator failed to measure."))?;
+                let probability_of_one = dense.probability_of_one(*qubit);
+                if deterministic {
+                    let expected = if outcome { 1.0 } else { 0.0 };
+                    if (probability_of_one - expected).abs() > TOLERANCE {
+                        return Err(mismatch(
+                            "Deterministic measurement disagrees with the dense state.",
+                        ));
+                    }
+                } else if (probability_of_one - 0.5).abs() > TOLERANCE {
+                    return Err(mismatch(
+                        "Random measurement isn't 50/50 in the dense state.",
+                    ));
+                }
+                dense.collapse(*qubit, outcome);
+                if let Instruction::MeasureToBit { bit, .. } = *instruction {
+                    stabilizer.set_classical_bit(bit, outcome);
+                }
+            }
+            Instruction::Reset(qubit) | Instruction::PrepX(qubit) | Instruction::PrepY(qubit) => {
+                // follow the tableau's branch of the collapse, as for a measurement.
+                let outcome = stabilizer
+                    .collapse(*qubit)
+                    .map_err(|_| mismatch("Stabilizer simulator failed to reset."))?;
+                let probability_of_one = dense.probability_of_one(*qubit);
+                let probability = if outcome {
+                    probability_of_one
+                } else {
+                    1.0 - probability_of_one
+                };
+                if probability < TOLERANCE {
+                    return Err(mismatch("Reset collapsed to an impossible outcome."));
+                }
+                dense.collapse(*qubit, outcome);
+                let mut gates = instruction.preparation_gates();
+                if outcome {
+                    gates.insert(0, Gate::X(*qubit));
+                }
+                for gate in gates {
+                    stabilizer.apply_gate(&gate);
+                    dense.apply_gate(&gate);
+                }
+            }
+            Instruction::Conditional { lookback, gate } => {
+                let condition = stabilizer.lookup_measurement(*lookback).map_err(|_| {
+                    mismatch("Conditional gate looks back past the measurement record.")
+                })?;
+                if condition {
+                    stabilizer.apply_gate(gate);
+                    dense.apply_gate(gate);
+                }
+            }
+            Instruction::ConditionalOnBit { bit, gate } => {
+                if stabilizer.classical_bit(*bit) {
+                    stabilizer.apply_gate(gate);
+                    dense.apply_gate(gate);
+                }
+            }
+            Instruction::SweepConditional { bit, gate } => {
+                if stabilizer.sweep_bit(*bit) {
+                    stabilizer.apply_gate(gate);
+                    dense.apply_gate(gate);
+                }
+            }
+            Instruction::Tick => {}
+        }
+        for generator in stabilizer.stabilizers() {
+            if (dense.expectation(&generator) - 1.0).abs() > TOLERANCE {
+                return Err(mismatch(
+                    "Stabilizer generator doesn't stabilize the dense state.",
+                ));
+            }
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use rand::{Rng, SeedableRng};
+
+    #[test]
+    fn test_random_circuits_match_dense_reference() {
+        let mut rng = rand::rngs::StdRng::seed_from_u64(7);
+        for seed in 0..50 {
+            let num_qubits = rng.gen_range(1..=5u32);
+            let mut circuit = Circuit::new(num_qubits as usize);
+            for _ in 0..40 {
+                let a = rng.gen_range(0..num_qubits);
+                let b = Qubit((a + rng.gen_range(1..num_qubits.max(2))) % num_qubits);
+                let a = Qubit(a);
+                match rng.gen_range(0..9) {
+                    0 | 1 => circuit.gate(Gate::H(a)),
+                    7 => circuit.gate([Gate::X(a), Gate::Y(a), Gate::Z(a)][rng.gen_range(0..3)]),
+                    2 | 3 => circuit.gate(Gate::S(a)),
+                    4 if a != b => circuit.gate(Gate::Cx(a, b)),
+                    5 if a != b => circuit.gate(Gate::Cz(a, b)),
+                    6 if a != b => circuit.gate(Gate::Swap(a, b)),
+                    8 => [Circuit::reset, Circuit::prep_x, Circuit::prep_y][rng.gen_range(0..3)](
+                        &mut circuit,
+                        a,
+                    ),
+                    _ => circuit.measure(a),
+                };
+            }
+            assert_eq!(verify_against_dense(&circuit, seed), Ok(()));
+        }
+    }
+
+    #[test]
+    fn test_dense_detects_wrong_state() {
+        let mut dense = DenseSimulator::new(1);
+        dense.apply_gate(&Gate::H(Qubit(0)));
+        let minus_x = PauliString {
+            phase_is_negated: true,
+            x_bits: vec![true],
+            z_bits: vec![false],
+        };
+        assert!((dense.expectation(&minus_x) + 1.0).abs() < TOLERANCE);
+    }
+
+    #[test]
+    fn test_t_injection_gadget() {
+        // the stabilizer simulator can't hold a magic state, but the dense one can. Start with
+        // data in |+> and the ancilla in |T> = (|0> + e^(i pi/4)|1>)/sqrt(2), then follow both
+        // measurement branches of the gadget and check the data ends up in T|+>.
+        let magic = Complex {
+            re: FRAC_1_SQRT_2,
+            im: FRAC_1_SQRT_2,
+        };
+        let gadget = Circuit::t_injection(Qubit(0), Qubit(1));
+        for outcome in [false, true] {
+            let mut dense = DenseSimulator::new(2);
+            dense.amplitudes = vec![
+                Complex::ONE.scale(0.5),
+                Complex::ONE.scale(0.5),
+                magic.scale(0.5),
+                magic.scale(0.5),
+            ];
+            for instruction in gadget.instructions() {
+                match instruction {
+                    Instruction::Gate(gate) => dense.apply_gate(gate),
+                    Instruction::Measure(qubit) => dense.collapse(*qubit, outcome),
+                    Instruction::Conditional { gate, .. } if outcome => dense.apply_gate(gate),
+                    _ => {}
+                }
+            }
+            // T|+> points halfway between +X and +Y on the bloch sphere.
+            for (x, z) in [(true, false), (true, true)] {
+                let pauli = PauliString {
+                    phase_is_negated: false,
+                    x_bits: vec![x, false],
+                    z_bits: vec![z, false],
+                };
+                assert!((dense.expectation(&pauli) - FRAC_1_SQRT_2).abs() < TOLERANCE);
+            }
+        }
+    }
+}
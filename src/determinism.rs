@@ -0,0 +1,403 @@
+use crate::circuit::{Circuit, Instruction};
+use crate::gates::Gate;
+use crate::pauli::PauliString;
+use crate::qubit::Qubit;
+use crate::simd;
+use std::collections::HashMap;
+
+// which measurements of a noiseless circuit are decided before they happen, found in one run
+// without sampling anything. The run is a stabilizer tableau whose signs are symbolic: each
+// random measurement outcome gets a variable of its own, and every sign is a constant xored
+// with some of them. The signs only ever get xored together, so they stay that way, and a
+// deterministic measurement's value comes out as a parity of earlier random ones.
+//
+// Paulis controlled by a measurement are linear in it too (they flip the sign of every row
+// they anticommute with, by the control's value), so corrections like teleportation's are
+// followed exactly. Any other controlled gate has to have a control that's known up front.
+//
+// Resets are measurements too, just not recorded ones, so they get variables of their own,
+// numbered after the recorded measurements. A value that depends on one of those is as good as
+// random as far as the record goes.
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum MeasurementValue {
+    // can't be predicted from the earlier outcomes.
+    Random,
+    // the parity of these earlier random measurements, flipped or not. With no measurements,
+    // it's the same value every shot.
+    Determined {
+        parity_of: Vec<usize>,
+        flipped: bool,
+    },
+}
+
+impl MeasurementValue {
+    pub fn is_deterministic(&self) -> bool {
+        matches!(self, MeasurementValue::Determined { .. })
+    }
+
+    pub fn constant(&self) -> Option<bool> {
+        // the value, for a measurement that comes out the same every shot.
+        match self {
+            MeasurementValue::Determined { parity_of, flipped } if parity_of.is_empty() => {
+                Some(*flipped)
+            }
+            _ => None,
+        }
+    }
+}
+
+pub fn analyze_determinism(circuit: &Circuit) -> Result<Vec<MeasurementValue>, &'static str> {
+    let num_measurements = circuit.num_measurements();
+    let num_resets = circuit
+        .instructions()
+        .iter()
+        .filter(|instruction| {
+            matches!(
+                instruction,
+                Instruction::Reset(_) | Instruction::PrepX(_) | Instruction::PrepY(_)
+            )
+        })
+        .count();
+    let mut tableau = SymbolicTableau::new(circuit.num_qubits(), num_measurements + num_resets);
+    let mut next_reset = num_measurements;
+    let mut record: Vec<Sign> = vec![];
+    let mut bits: HashMap<u32, Sign> = HashMap::new();
+    let mut values = vec![];
+    for instruction in circuit.instructions() {
+        match *instruction {
+            Instruction::Gate(gate) => tableau.apply_gate(&gate),
+            Instruction::Measure(qubit) | Instruction::MeasureToBit { qubit, .. } => {
+                let (value, random) = tableau.measure(qubit, record.len())?;
+                values.push(if random {
+                    MeasurementValue::Random
+                } else {
+                    value.to_value(num_measurements)
+                });
+                if let Instruction::MeasureToBit { bit, .. } = *instruction {
+                    bits.insert(bit, value.clone());
+                }
+                record.push(value);
+            }
+            Instruction::Reset(_) | Instruction::PrepX(_) | Instruction::PrepY(_) => {
+                // a measurement nobody records, then an X controlled by it.
+                let qubit = instruction.qubits()[0];
+                let (value, _) = tableau.measure(qubit, next_reset)?;
+                next_reset += 1;
+                tableau.apply_controlled(&Gate::X(qubit), &value)?;
+                for gate in instruction.preparation_gates() {
+                    tableau.apply_gate(&gate);
+                }
+            }
+            Instruction::Conditional { lookback, gate } => {
+                let index = record
+                    .len()
+                    .checked_sub(lookback as usize)
+                    .ok_or("Conditional looks back before the first measurement.")?;
+                let control = record[index].clone();
+                tableau.apply_controlled(&gate, &control)?;
+            }
+            Instruction::ConditionalOnBit { bit, gate } => {
+                let control = bits.get(&bit).cloned().unwrap_or_default();
+                tableau.apply_controlled(&gate, &control)?;
+            }
+            Instruction::SweepConditional { .. } => {
+                return Err(
+                    "Sweep bits are inputs to the circuit, so the analysis can't see them.",
+                );
+            }
+            Instruction::Tick => {}
+        }
+    }
+    Ok(values)
+}
+
+pub fn deterministic_detectors(values: &[MeasurementValue]) -> Vec<Vec<usize>> {
+    // a detector for every deterministic measurement whose parity comes out 0: the
+    // measurement together with the random ones it's the parity of.
+    values
+        .iter()
+        .enumerate()
+        .filter_map(|(index, value)| match value {
+            MeasurementValue::Determined {
+                parity_of,
+                flipped: false,
+            } => {
+                let mut detector = parity_of.clone();
+                detector.push(index);
+                Some(detector)
+            }
+            _ => None,
+        })
+        .collect()
+}
+
+// a sign: a constant, xored with the outcomes of some random measurements, packed as bits by
+// measurement index.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+struct Sign {
+    flipped: bool,
+    variables: Vec<u64>,
+}
+
+impl Sign {
+    fn xor_with(&mut self, other: &Sign) {
+        self.flipped ^= other.flipped;
+        if self.variables.len() < other.variables.len() {
+            self.variables.resize(other.variables.len(), 0);
+        }
+        simd::xor_into(
+            &mut self.variables[..other.variables.len()],
+            &other.variables,
+        );
+    }
+
+    fn variable(index: usize) -> Sign {
+        let mut variables = vec![0; index / 64 + 1];
+        variables[index / 64] |= 1 << (index % 64);
+        Sign {
+            flipped: false,
+            variables,
+        }
+    }
+
+    fn is_constant(&self) -> bool {
+        self.variables.iter().all(|&word| word == 0)
+    }
+
+    fn to_value(&self, num_recorded: usize) -> MeasurementValue {
+        let parity_of: Vec<usize> = (0..self.variables.len() * 64)
+            .filter(|&index| self.variables[index / 64] >> (index % 64) & 1 == 1)
+            .collect();
+        if parity_of.last().is_some_and(|&index| index >= num_recorded) {
+            return MeasurementValue::Random;
+        }
+        MeasurementValue::Determined {
+            parity_of,
+            flipped: self.flipped,
+        }
+    }
+}
+
+// a row's pauli keeps the constant part of its sign in its phase, which the gate and rowsum
+// rules already handle, and the variables ride along beside it.
+#[derive(Debug, Clone)]
+struct Row {
+    pauli: PauliString,
+    variables: Vec<u64>,
+}
+
+impl Row {
+    fn rowsum(&mut self, other: &Row) -> Result<(), &'static str> {
+        self.pauli.rowsum(&other.pauli.view())?;
+        simd::xor_into(&mut self.variables, &other.variables);
+        Ok(())
+    }
+
+    fn sign(&self) -> Sign {
+        Sign {
+            flipped: self.pauli.phase_is_negated,
+            variables: self.variables.clone(),
+        }
+    }
+}
+
+struct SymbolicTableau {
+    destabilizers: Vec<Row>,
+    stabilizers: Vec<Row>,
+    num_words: usize,
+}
+
+impl SymbolicTableau {
+    fn new(num_qubits: usize, num_variables: usize) -> SymbolicTableau {
+        let num_words = num_variables.div_ceil(64);
+        let row = |x: bool, q: usize| {
+            let mut pauli = PauliString::identity(num_qubits);
+            pauli.x_bits[q] = x;
+            pauli.z_bits[q] = !x;
+            Row {
+                pauli,
+                variables: vec![0; num_words],
+            }
+        };
+        SymbolicTableau {
+            destabilizers: (0..num_qubits).map(|q| row(true, q)).collect(),
+            stabilizers: (0..num_qubits).map(|q| row(false, q)).collect(),
+            num_words,
+        }
+    }
+
+    fn apply_gate(&mut self, gate: &Gate) {
+        for row in self.destabilizers.iter_mut().chain(&mut self.stabilizers) {
+            row.pauli.conjugate_by(gate);
+        }
+    }
+
+    fn apply_controlled(&mut self, gate: &Gate, control: &Sign) -> Result<(), &'static str> {
+        // a pauli flips the sign of every row it anticommutes with, by the control's value.
+        // Anything else needs the control to be a constant.
+        let q = gate.qubits()[0].index();
+        let anticommutes = |pauli: &PauliString| match gate {
+            Gate::X(_) => pauli.z_bits[q],
+            Gate::Z(_) => pauli.x_bits[q],
+            _ => pauli.x_bits[q] ^ pauli.z_bits[q],
+        };
+        match gate {
+            Gate::X(_) | Gate::Y(_) | Gate::Z(_) => {
+                for row in self.destabilizers.iter_mut().chain(&mut self.stabilizers) {
+                    if anticommutes(&row.pauli) {
+                        let mut sign = row.sign();
+                        sign.xor_with(control);
+                        row.pauli.phase_is_negated = sign.flipped;
+                        row.variables = sign.variables;
+                        row.variables.resize(self.num_words, 0);
+                    }
+                }
+            }
+            _ if !control.is_constant() => {
+                return Err("A controlled gate other than a pauli depends on a random outcome.");
+            }
+            _ if control.flipped => self.apply_gate(gate),
+            _ => {}
+        }
+        Ok(())
+    }
+
+    fn measure(&mut self, qubit: Qubit, variable: usize) -> Result<(Sign, bool), &'static str> {
+        // the outcome's sign, and whether it was random. A random outcome is the variable
+        // numbered `variable`.
+        let q = qubit.index();
+        let n = self.stabilizers.len();
+        let Some(p) = self.stabilizers.iter().position(|row| row.pauli.x_bits[q]) else {
+            let mut scratch = Row {
+                pauli: PauliString::identity(n),
+                variables: vec![0; self.num_words],
+            };
+            for (destabilizer, stabilizer) in self.destabilizers.iter().zip(&self.stabilizers) {
+                if destabilizer.pauli.x_bits[q] {
+                    scratch.rowsum(stabilizer)?;
+                }
+            }
+            return Ok((scratch.sign(), false));
+        };
+        let pivot = self.stabilizers[p].clone();
+        for i in 0..n {
+            if i != p && self.stabilizers[i].pauli.x_bits[q] {
+                self.stabilizers[i].rowsum(&pivot)?;
+            }
+            if self.destabilizers[i].pauli.x_bits[q] {
+                self.destabilizers[i].rowsum(&pivot)?;
+            }
+        }
+        self.destabilizers[p] = pivot;
+        let outcome = Sign::variable(variable);
+        let mut variables = outcome.variables.clone();
+        variables.resize(self.num_words, 0);
+        let stabilizer = &mut self.stabilizers[p];
+        stabilizer.pauli = PauliString::z(n, qubit);
+        stabilizer.variables = variables;
+        Ok((outcome, true))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::stabilizer_simulator::StabilizerSimulator;
+
+    #[test]
+    fn test_determinism_of_bell_and_teleportation() {
+        let (a, b, c) = (Qubit(0), Qubit(1), Qubit(2));
+        let mut circuit = Circuit::bell_pair(a, b);
+        circuit
+            .measure(a)
+            .measure(b)
+            .gate(Gate::X(c))
+            .measure(c)
+            .reset(c)
+            .measure(c);
+        let values = analyze_determinism(&circuit).unwrap();
+        assert_eq!(
+            values,
+            vec![
+                MeasurementValue::Random,
+                MeasurementValue::Determined {
+                    parity_of: vec![0],
+                    flipped: false,
+                },
+                MeasurementValue::Determined {
+                    parity_of: vec![],
+                    flipped: true,
+                },
+                MeasurementValue::Determined {
+                    parity_of: vec![],
+                    flipped: false,
+                },
+            ]
+        );
+        assert_eq!(values[2].constant(), Some(true));
+        assert_eq!(values[1].constant(), None);
+        assert_eq!(deterministic_detectors(&values), vec![vec![0, 1], vec![3]]);
+
+        // resetting half a bell pair leaves the other half random, even though the reset's
+        // outcome never made it into the record.
+        let mut half = Circuit::bell_pair(a, b);
+        half.reset(a).measure(b).measure(a);
+        assert_eq!(
+            analyze_determinism(&half).unwrap(),
+            vec![
+                MeasurementValue::Random,
+                MeasurementValue::Determined {
+                    parity_of: vec![],
+                    flipped: false,
+                },
+            ]
+        );
+
+        // teleporting |1> lands a 1 on the target whatever the bell measurement said, which
+        // only holds if the corrections are followed.
+        let mut teleport = Circuit::new(3);
+        teleport.gate(Gate::X(a));
+        teleport.append(&Circuit::teleportation(a, b, c)).unwrap();
+        teleport.measure(c);
+        let values = analyze_determinism(&teleport).unwrap();
+        assert_eq!(
+            values[..2],
+            [MeasurementValue::Random, MeasurementValue::Random]
+        );
+        assert_eq!(values[2].constant(), Some(true));
+        let mut uncorrected = teleport.clone();
+        uncorrected.sweep_conditional(0, Gate::X(a));
+        assert!(analyze_determinism(&uncorrected).is_err());
+    }
+
+    #[test]
+    fn test_parities_hold_in_simulation() {
+        // a GHZ state measured twice over: the first outcome is random, and everything after
+        // it is a parity the simulator has to agree with.
+        let qubits: Vec<Qubit> = Qubit::range(4).collect();
+        let mut circuit = Circuit::ghz(&qubits);
+        for &qubit in &qubits {
+            circuit.measure(qubit);
+        }
+        circuit.gate(Gate::Cx(Qubit(0), Qubit(3)));
+        for &qubit in &qubits {
+            circuit.measure(qubit);
+        }
+        let values = analyze_determinism(&circuit).unwrap();
+        assert_eq!(
+            values
+                .iter()
+                .filter(|value| !value.is_deterministic())
+                .count(),
+            1
+        );
+        for seed in 0..8 {
+            let record = StabilizerSimulator::new(4, seed).run(&circuit).unwrap();
+            for detector in deterministic_detectors(&values) {
+                let parity = detector.iter().filter(|&&index| record[index]).count() % 2;
+                assert_eq!(parity, 0, "seed {seed}, detector {detector:?}");
+            }
+        }
+    }
+}
@@ -0,0 +1,444 @@
+use crate::circuit::{Circuit, Instruction};
+use crate::gates::Gate;
+use crate::qubit::Qubit;
+
+// circuits drawn as text or SVG, one wire per qubit and one column per moment:
+//
+//     q0: ─H──●──●──M─────────────
+//     q1: ────X──┼─────X^rec[-1]──
+//     q2: ───────X────────────────
+//
+// Instructions in the same moment whose wires would cross get columns of their own, so a
+// vertical line through a wire (┼) always belongs to the one gate spanning it.
+//
+// The SVG has the same layout, with boxes for gates, the usual dots and circles for CX and CZ,
+// and meters for measurements. Classical controls are written under the gate they control.
+
+// one column of the diagram: the label on each wire, if any, the range of wires each of its
+// instructions covers, and the instructions themselves, by index into the circuit.
+struct Column {
+    wires: Vec<Option<String>>,
+    spans: Vec<(usize, usize)>,
+    instructions: Vec<usize>,
+}
+
+// what to_svg_with draws on top of the circuit: each detector's name (D0, D1, ...) under the
+// moment its last measurement happens in, with the measurements it compares on hover, and
+// the coordinates of the qubits and detectors that have them.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct SvgOptions {
+    pub detectors: bool,
+    pub coordinates: bool,
+}
+
+// sizes in the SVG, in pixels. Text is sized for a 14px monospace font, about 8.5px a
+// character, and captions for a 10px one.
+const CELL: f64 = 48.0;
+const MARGIN: f64 = 24.0;
+const CHAR: f64 = 8.5;
+const CAPTION_CHAR: f64 = 6.0;
+const BOX: f64 = 30.0;
+
+fn gate_cells(gate: &Gate) -> Vec<(usize, String)> {
+    match *gate {
+        Gate::Cx(control, target) => vec![
+            (control.index(), "●".to_string()),
+            (target.index(), "X".to_string()),
+        ],
+        Gate::Cz(a, b) => vec![(a.index(), "●".to_string()), (b.index(), "●".to_string())],
+        Gate::Swap(a, b) => vec![(a.index(), "×".to_string()), (b.index(), "×".to_string())],
+        gate => vec![(gate.qubits()[0].index(), gate.name().to_string())],
+    }
+}
+
+fn control(instruction: &Instruction) -> Option<(Gate, String)> {
+    // the gate and what it's conditioned on, for a classically controlled gate.
+    match *instruction {
+        Instruction::Conditional { lookback, gate } => Some((gate, format!("rec[-{lookback}]"))),
+        Instruction::ConditionalOnBit { bit, gate } => Some((gate, format!("bit[{bit}]"))),
+        Instruction::SweepConditional { bit, gate } => Some((gate, format!("sweep[{bit}]"))),
+        _ => None,
+    }
+}
+
+fn cells(instruction: &Instruction) -> Vec<(usize, String)> {
+    // what to write on each wire the instruction touches. A classical control is written
+    // after the gate's first cell, like X^rec[-1].
+    if let Some((gate, control)) = control(instruction) {
+        let mut cells = gate_cells(&gate);
+        cells[0].1.push_str(&format!("^{control}"));
+        return cells;
+    }
+    match instruction {
+        Instruction::Gate(gate) => gate_cells(gate),
+        Instruction::Measure(q) => vec![(q.index(), "M".to_string())],
+        Instruction::MeasureToBit { qubit, bit } => vec![(qubit.index(), format!("M:bit[{bit}]"))],
+        Instruction::Reset(q) => vec![(q.index(), "R".to_string())],
+        Instruction::PrepX(q) => vec![(q.index(), "RX".to_string())],
+        Instruction::PrepY(q) => vec![(q.index(), "RY".to_string())],
+        _ => vec![],
+    }
+}
+
+fn layout(circuit: &Circuit) -> Vec<Column> {
+    let n = circuit.num_qubits();
+    let mut moments: Vec<Vec<usize>> = vec![vec![]; circuit.depth()];
+    for (i, moment) in circuit.assign_moments().into_iter().enumerate() {
+        if let Some(moment) = moment {
+            moments[moment].push(i);
+        }
+    }
+    let mut columns: Vec<Column> = vec![];
+    for moment in moments {
+        let first = columns.len();
+        for i in moment {
+            let cells = cells(&circuit.instructions()[i]);
+            let Some(low) = cells.iter().map(|&(q, _)| q).min() else {
+                continue;
+            };
+            let high = cells.iter().map(|&(q, _)| q).max().unwrap();
+            let free = columns[first..]
+                .iter()
+                .position(|column| column.spans.iter().all(|&(a, b)| high < a || b < low));
+            let column = match free {
+                Some(k) => first + k,
+                None => {
+                    columns.push(Column {
+                        wires: vec![None; n],
+                        spans: vec![],
+                        instructions: vec![],
+                    });
+                    columns.len() - 1
+                }
+            };
+            columns[column].spans.push((low, high));
+            columns[column].instructions.push(i);
+            for (q, label) in cells {
+                columns[column].wires[q] = Some(label);
+            }
+        }
+    }
+    columns
+}
+
+pub(crate) fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+}
+
+fn format_coords(coords: &[f64]) -> String {
+    let coords: Vec<String> = coords.iter().map(f64::to_string).collect();
+    format!("({})", coords.join(", "))
+}
+
+// the pieces of an SVG, written into one string as they're drawn.
+struct Svg(String);
+
+impl Svg {
+    fn line(&mut self, (x1, y1): (f64, f64), (x2, y2): (f64, f64)) {
+        self.0.push_str(&format!(
+            "<line x1=\"{x1}\" y1=\"{y1}\" x2=\"{x2}\" y2=\"{y2}\" stroke=\"black\"/>\n"
+        ));
+    }
+
+    fn circle(&mut self, (x, y): (f64, f64), r: f64, fill: &str) {
+        self.0.push_str(&format!(
+            "<circle cx=\"{x}\" cy=\"{y}\" r=\"{r}\" fill=\"{fill}\" stroke=\"black\"/>\n"
+        ));
+    }
+
+    fn text(&mut self, (x, y): (f64, f64), anchor: &str, size: u32, text: &str) {
+        self.0.push_str(&format!(
+            "<text x=\"{x}\" y=\"{y}\" text-anchor=\"{anchor}\" dominant-baseline=\"middle\" \
+             font-size=\"{size}\">{}</text>\n",
+            escape(text)
+        ));
+    }
+
+    fn gate_box(&mut self, (x, y): (f64, f64), label: &str) {
+        let width = BOX.max(label.chars().count() as f64 * CHAR + 8.0);
+        self.0.push_str(&format!(
+            "<rect x=\"{}\" y=\"{}\" width=\"{width}\" height=\"{BOX}\" fill=\"white\" \
+             stroke=\"black\"/>\n",
+            x - width / 2.0,
+            y - BOX / 2.0
+        ));
+        self.text((x, y), "middle", 14, label);
+    }
+
+    fn meter(&mut self, (x, y): (f64, f64)) {
+        self.gate_box((x, y), "");
+        self.0.push_str(&format!(
+            "<path d=\"M {} {} A 10 10 0 0 1 {} {}\" fill=\"none\" stroke=\"black\"/>\n",
+            x - 10.0,
+            y + 6.0,
+            x + 10.0,
+            y + 6.0
+        ));
+        self.line((x, y + 6.0), (x + 7.0, y - 8.0));
+    }
+
+    fn gate(&mut self, gate: &Gate, x: f64, y: impl Fn(usize) -> f64) {
+        let qubits: Vec<f64> = gate.qubits().iter().map(|q| y(q.index())).collect();
+        if let [a, b] = qubits[..] {
+            self.line((x, a), (x, b));
+        }
+        match *gate {
+            Gate::Cx(..) => {
+                self.circle((x, qubits[0]), 5.0, "black");
+                self.circle((x, qubits[1]), 10.0, "white");
+                self.line((x - 10.0, qubits[1]), (x + 10.0, qubits[1]));
+                self.line((x, qubits[1] - 10.0), (x, qubits[1] + 10.0));
+            }
+            Gate::Cz(..) => {
+                self.circle((x, qubits[0]), 5.0, "black");
+                self.circle((x, qubits[1]), 5.0, "black");
+            }
+            Gate::Swap(..) => {
+                for y in qubits {
+                    self.line((x - 6.0, y - 6.0), (x + 6.0, y + 6.0));
+                    self.line((x - 6.0, y + 6.0), (x + 6.0, y - 6.0));
+                }
+            }
+            gate => self.gate_box((x, qubits[0]), gate.name()),
+        }
+    }
+}
+
+impl Circuit {
+    pub fn to_text_diagram(&self) -> String {
+        let n = self.num_qubits();
+        let columns = layout(self);
+        let labels: Vec<String> = (0..n).map(|q| format!("q{q}: ")).collect();
+        let label_width = labels.iter().map(String::len).max().unwrap_or(0);
+        let mut lines: Vec<String> = labels
+            .into_iter()
+            .map(|label| format!("{label:<label_width$}─"))
+            .collect();
+        for Column { wires, spans, .. } in &columns {
+            let width = wires
+                .iter()
+                .flatten()
+                .map(|label| label.chars().count())
+                .max()
+                .unwrap_or(1);
+            for (q, line) in lines.iter_mut().enumerate() {
+                let crossed = spans.iter().any(|&(a, b)| a < q && q < b);
+                let cell = match &wires[q] {
+                    Some(label) => label.clone(),
+                    None if crossed => "┼".to_string(),
+                    None => String::new(),
+                };
+                line.push_str(&cell);
+                line.push_str(&"─".repeat(width - cell.chars().count() + 2));
+            }
+        }
+        lines.iter().map(|line| format!("{line}\n")).collect()
+    }
+
+    pub fn to_svg(&self) -> String {
+        self.to_svg_with(&SvgOptions::default())
+    }
+
+    pub fn to_svg_with(&self, options: &SvgOptions) -> String {
+        let n = self.num_qubits();
+        let columns = layout(self);
+        let instructions = self.instructions();
+
+        let labels: Vec<String> = (0..n)
+            .map(|q| {
+                let coords = self.qubit_coords(Qubit(q as u32));
+                match coords {
+                    Some(coords) if options.coordinates => {
+                        format!("q{q} {}", format_coords(coords))
+                    }
+                    _ => format!("q{q}"),
+                }
+            })
+            .collect();
+        let label_width = labels
+            .iter()
+            .map(|label| label.chars().count() as f64 * CHAR)
+            .fold(0.0, f64::max);
+
+        // columns are as wide as their widest box or caption needs.
+        let mut x = vec![];
+        let mut right = MARGIN + label_width + CELL / 2.0;
+        for column in &columns {
+            let widest = column
+                .instructions
+                .iter()
+                .map(|&i| match (control(&instructions[i]), &instructions[i]) {
+                    (Some((_, control)), _) => control.len() as f64 * CAPTION_CHAR,
+                    (_, Instruction::MeasureToBit { bit, .. }) => {
+                        format!("bit[{bit}]").len() as f64 * CAPTION_CHAR
+                    }
+                    (_, instruction) => cells(instruction)
+                        .iter()
+                        .map(|(_, label)| label.chars().count() as f64 * CHAR + 8.0)
+                        .fold(0.0, f64::max),
+                })
+                .fold(CELL, |widest, width| widest.max(width + 12.0));
+            x.push(right + widest / 2.0);
+            right += widest;
+        }
+
+        // detector names stack up under the column of their last measurement.
+        let mut column_of = vec![0; instructions.len()];
+        for (c, column) in columns.iter().enumerate() {
+            for &i in &column.instructions {
+                column_of[i] = c;
+            }
+        }
+        let measurement_columns: Vec<usize> = instructions
+            .iter()
+            .enumerate()
+            .filter(|(_, instruction)| {
+                matches!(
+                    instruction,
+                    Instruction::Measure(_) | Instruction::MeasureToBit { .. }
+                )
+            })
+            .map(|(i, _)| column_of[i])
+            .collect();
+        let mut stacked = vec![0; columns.len()];
+        let mut detectors = vec![];
+        if options.detectors {
+            for (k, detector) in self.detectors().iter().enumerate() {
+                let Some(&last) = detector.measurements.iter().max() else {
+                    continue;
+                };
+                let column = measurement_columns[last];
+                detectors.push((k, column, stacked[column]));
+                stacked[column] += 1;
+            }
+        }
+
+        let wire_y = |q: usize| MARGIN + q as f64 * CELL + CELL / 2.0;
+        let width = right + CELL / 2.0 + MARGIN;
+        let detector_rows = stacked.iter().max().copied().unwrap_or(0);
+        let height = 2.0 * MARGIN + n as f64 * CELL + detector_rows as f64 * 18.0;
+        let mut svg = Svg(format!(
+            "<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"{width}\" height=\"{height}\" \
+             viewBox=\"0 0 {width} {height}\" font-family=\"monospace\">\n\
+             <rect width=\"100%\" height=\"100%\" fill=\"white\"/>\n"
+        ));
+        for (q, label) in labels.iter().enumerate() {
+            svg.text((MARGIN + label_width, wire_y(q)), "end", 14, label);
+            svg.line(
+                (MARGIN + label_width + 8.0, wire_y(q)),
+                (right + CELL / 2.0, wire_y(q)),
+            );
+        }
+        for (column, &x) in columns.iter().zip(&x) {
+            for &i in &column.instructions {
+                let instruction = &instructions[i];
+                if let Some((gate, control)) = control(instruction) {
+                    svg.gate(&gate, x, wire_y);
+                    let below = wire_y(gate.qubits()[0].index()) + BOX / 2.0 + 8.0;
+                    svg.text((x, below), "middle", 10, &control);
+                    continue;
+                }
+                match *instruction {
+                    Instruction::Gate(gate) => svg.gate(&gate, x, wire_y),
+                    Instruction::Measure(q) => svg.meter((x, wire_y(q.index()))),
+                    Instruction::MeasureToBit { qubit, bit } => {
+                        let y = wire_y(qubit.index());
+                        svg.meter((x, y));
+                        svg.text(
+                            (x, y + BOX / 2.0 + 8.0),
+                            "middle",
+                            10,
+                            &format!("bit[{bit}]"),
+                        );
+                    }
+                    Instruction::Reset(q) => svg.gate_box((x, wire_y(q.index())), "R"),
+                    Instruction::PrepX(q) => svg.gate_box((x, wire_y(q.index())), "RX"),
+                    Instruction::PrepY(q) => svg.gate_box((x, wire_y(q.index())), "RY"),
+                    _ => {}
+                }
+            }
+        }
+        for (k, column, stack) in detectors {
+            let detector = &self.detectors()[k];
+            let mut label = format!("D{k}");
+            if options.coordinates && !detector.coords.is_empty() {
+                label = format!("{label} {}", format_coords(&detector.coords));
+            }
+            let mut measurements = detector.measurements.clone();
+            measurements.sort_unstable();
+            let measurements: Vec<String> = measurements.iter().map(usize::to_string).collect();
+            let kind = if detector.erasure_flag {
+                "erasure flag on measurement"
+            } else {
+                "parity of measurements"
+            };
+            let y = MARGIN + n as f64 * CELL + 9.0 + stack as f64 * 18.0;
+            svg.0.push_str(&format!(
+                "<text x=\"{}\" y=\"{y}\" text-anchor=\"middle\" dominant-baseline=\"middle\" \
+                 font-size=\"12\" fill=\"#1f5fbf\"><title>{kind} {}</title>{}</text>\n",
+                x[column],
+                measurements.join(", "),
+                escape(&label)
+            ));
+        }
+        svg.0.push_str("</svg>\n");
+        svg.0
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_text_diagram() {
+        let mut circuit = Circuit::new(3);
+        circuit
+            .gate(Gate::H(Qubit(0)))
+            .gate(Gate::Cx(Qubit(0), Qubit(1)))
+            .gate(Gate::Cx(Qubit(0), Qubit(2)))
+            .measure(Qubit(0));
+        circuit.push(Instruction::Conditional {
+            lookback: 1,
+            gate: Gate::X(Qubit(1)),
+        });
+        assert_eq!(
+            circuit.to_text_diagram(),
+            "q0: ─H──●──●──M─────────────\n\
+             q1: ────X──┼─────X^rec[-1]──\n\
+             q2: ───────X────────────────\n"
+        );
+        assert_eq!(Circuit::new(0).to_text_diagram(), "");
+    }
+
+    #[test]
+    fn test_svg() {
+        let mut circuit = Circuit::bell_pair(Qubit(0), Qubit(1));
+        circuit.measure(Qubit(0)).measure(Qubit(1));
+        circuit.push(Instruction::ConditionalOnBit {
+            bit: 0,
+            gate: Gate::S(Qubit(1)),
+        });
+        circuit.set_qubit_coords(Qubit(1), &[0.5, 2.0]);
+        circuit.add_detector(&[1, 2], &[3.0]).unwrap();
+
+        let svg = circuit.to_svg();
+        assert!(svg.starts_with("<svg xmlns=\"http://www.w3.org/2000/svg\""));
+        assert!(svg.ends_with("</svg>\n"));
+        // two wires, the CX's vertical line, its target's cross, and two meter needles.
+        assert_eq!(svg.matches("<line").count(), 7);
+        assert_eq!(svg.matches("<path").count(), 2);
+        assert!(svg.contains(">bit[0]</text>"));
+        assert!(!svg.contains("D0"));
+        assert!(!svg.contains("(0.5, 2)"));
+
+        let svg = circuit.to_svg_with(&SvgOptions {
+            detectors: true,
+            coordinates: true,
+        });
+        assert!(svg.contains("<title>parity of measurements 0, 1</title>D0 (3)</text>"));
+        assert!(svg.contains(">q1 (0.5, 2)</text>"));
+    }
+}
@@ -0,0 +1,87 @@
+use crate::circuit::Circuit;
+use crate::diagram::escape;
+use crate::pauli::PauliString;
+use crate::stabilizer_simulator::StabilizerSimulator;
+
+// rich display in Rust Jupyter notebooks. evcxr calls a value's evcxr_display method when a
+// cell evaluates to it, and shows whatever's printed between the EVCXR_BEGIN_CONTENT and
+// EVCXR_END_CONTENT markers with the MIME type given. Circuits show as their text diagram
+// (see Circuit::to_text_diagram), and simulators as a table of their tableau, one row per
+// generator with its sign and a pauli per qubit. No dependency on evcxr is needed, it's all
+// just stdout.
+
+fn show_html(html: &str) {
+    println!("EVCXR_BEGIN_CONTENT text/html\n{html}\nEVCXR_END_CONTENT");
+}
+
+fn generator_rows(label: &str, generators: &[PauliString], html: &mut String) {
+    for (i, generator) in generators.iter().enumerate() {
+        let sign = if generator.phase_is_negated { "-" } else { "+" };
+        html.push_str(&format!("<tr><th>{label} {i}</th><td>{sign}</td>"));
+        for (&x, &z) in generator.x_bits.iter().zip(&generator.z_bits) {
+            let pauli = match (x, z) {
+                (false, false) => "_",
+                (true, false) => "X",
+                (true, true) => "Y",
+                (false, true) => "Z",
+            };
+            html.push_str(&format!("<td>{pauli}</td>"));
+        }
+        html.push_str("</tr>");
+    }
+}
+
+impl Circuit {
+    pub fn to_html(&self) -> String {
+        format!("<pre>{}</pre>", escape(&self.to_text_diagram()))
+    }
+
+    pub fn evcxr_display(&self) {
+        show_html(&self.to_html());
+    }
+}
+
+impl StabilizerSimulator {
+    pub fn to_html(&self) -> String {
+        let mut html =
+            String::from("<table style=\"font-family: monospace\"><tr><th></th><th>sign</th>");
+        for q in 0..self.num_qubits() {
+            html.push_str(&format!("<th>q{q}</th>"));
+        }
+        html.push_str("</tr>");
+        generator_rows("destabilizer", &self.destabilizers(), &mut html);
+        generator_rows("stabilizer", &self.stabilizers(), &mut html);
+        html.push_str("</table>");
+        html
+    }
+
+    pub fn evcxr_display(&self) {
+        show_html(&self.to_html());
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::gates::Gate;
+    use crate::qubit::Qubit;
+
+    #[test]
+    fn test_html() {
+        let mut simulator = StabilizerSimulator::seeded(2);
+        simulator.apply_gate(&Gate::H(Qubit(0)));
+        simulator.apply_gate(&Gate::Cx(Qubit(0), Qubit(1)));
+        let html = simulator.to_html();
+        assert!(html.starts_with("<table"));
+        assert!(html.contains("<tr><th>stabilizer 0</th><td>+</td><td>X</td><td>X</td></tr>"));
+        assert!(html.contains("<tr><th>stabilizer 1</th><td>+</td><td>Z</td><td>Z</td></tr>"));
+        assert_eq!(html.matches("<tr>").count(), 5);
+
+        let circuit = Circuit::bell_pair(Qubit(0), Qubit(1));
+        assert_eq!(
+            circuit.to_html(),
+            format!("<pre>{}</pre>", circuit.to_text_diagram())
+        );
+        assert_eq!(escape("a<b & c>d"), "a&lt;b &amp; c&gt;d");
+    }
+}
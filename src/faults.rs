@@ -0,0 +1,424 @@
+use crate::circuit::{Circuit, Instruction};
+use crate::gates::Gate;
+use crate::noise::{self, NoiseModel};
+use crate::pauli::PauliString;
+use std::collections::{HashMap, VecDeque};
+
+// which detectors and observables each elementary fault of a noise model flips. That's the
+// raw material for a detector error model, and a quick way to find the faults a circuit
+// doesn't catch (hook errors and the like).
+//
+// Faults are followed as a pauli frame: the error is pushed through the rest of the circuit
+// by conjugation, and flips every Z measurement it reaches with an X or Y component. That's
+// exact for clifford circuits, since a pauli error only ever changes measurement outcomes by
+// a fixed flip, whatever branch the shot takes. Classically controlled paulis are followed
+// too, when the measurement they read was flipped.
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum FaultKind {
+    // a pauli error right after a gate (or on an idle qubit at a TICK), as the paulis it's
+    // made of, e.g. [X 0, Z 1].
+    Pauli(Vec<Gate>),
+    // the measurement reports the wrong outcome, without disturbing the state.
+    MeasurementFlip,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Fault {
+    // the gate or TICK the error follows, or the measurement that gets flipped.
+    pub instruction: usize,
+    pub kind: FaultKind,
+    pub probability: f64,
+    pub detectors: Vec<usize>,
+    pub observables: Vec<usize>,
+}
+
+fn is_pauli(gate: &Gate) -> bool {
+    matches!(gate, Gate::X(_) | Gate::Y(_) | Gate::Z(_))
+}
+
+fn apply_pauli(frame: &mut PauliString, gate: &Gate) {
+    // multiplies the frame by a pauli gate. Signs don't matter to a frame.
+    match *gate {
+        Gate::X(qubit) => frame.x_bits[qubit.index()] ^= true,
+        Gate::Z(qubit) => frame.z_bits[qubit.index()] ^= true,
+        Gate::Y(qubit) => {
+            frame.x_bits[qubit.index()] ^= true;
+            frame.z_bits[qubit.index()] ^= true;
+        }
+        _ => {}
+    }
+}
+
+fn measurement_flips(circuit: &Circuit, location: usize, kind: &FaultKind) -> Vec<bool> {
+    // which measurements come out flipped when this fault happens, by measurement number.
+    let mut frame = PauliString::identity(circuit.num_qubits());
+    let mut flips = Vec::with_capacity(circuit.num_measurements());
+    let mut bit_flips: HashMap<u32, bool> = HashMap::new();
+    for (index, instruction) in circuit.instructions().iter().enumerate() {
+        if index < location {
+            // nothing's gone wrong yet.
+            if matches!(
+                instruction,
+                Instruction::Measure(_) | Instruction::MeasureToBit { .. }
+            ) {
+                flips.push(false);
+            }
+            continue;
+        }
+        match *instruction {
+            Instruction::Gate(gate) => frame.conjugate_by(&gate),
+            Instruction::Measure(qubit) => flips.push(frame.x_bits[qubit.index()]),
+            Instruction::MeasureToBit { qubit, bit } => {
+                flips.push(frame.x_bits[qubit.index()]);
+                bit_flips.insert(bit, frame.x_bits[qubit.index()]);
+            }
+            Instruction::Reset(qubit) | Instruction::PrepX(qubit) | Instruction::PrepY(qubit) => {
+                frame.x_bits[qubit.index()] = false;
+                frame.z_bits[qubit.index()] = false;
+            }
+            Instruction::Conditional { lookback, gate } => {
+                let flipped = flips
+                    .len()
+                    .checked_sub(lookback as usize)
+                    .is_some_and(|measurement| flips[measurement]);
+                if flipped {
+                    apply_pauli(&mut frame, &gate);
+                }
+            }
+            Instruction::ConditionalOnBit { bit, gate } => {
+                if bit_flips.get(&bit).copied().unwrap_or(false) {
+                    apply_pauli(&mut frame, &gate);
+                }
+            }
+            Instruction::SweepConditional { .. } | Instruction::Tick => {}
+        }
+        if index == location {
+            match kind {
+                FaultKind::Pauli(paulis) => {
+                    for pauli in paulis {
+                        apply_pauli(&mut frame, pauli);
+                    }
+                }
+                FaultKind::MeasurementFlip => {
+                    if let Some(flip) = flips.last_mut() {
+                        *flip ^= true;
+                    }
+                    if let Instruction::MeasureToBit { bit, .. } = instruction {
+                        *bit_flips.entry(*bit).or_insert(false) ^= true;
+                    }
+                }
+            }
+        }
+    }
+    flips
+}
+
+fn flipped_parities(parities: &[&[usize]], flips: &[bool]) -> Vec<usize> {
+    parities
+        .iter()
+        .enumerate()
+        .filter(|(_, measurements)| {
+            measurements
+                .iter()
+                .fold(false, |parity, &measurement| parity ^ flips[measurement])
+        })
+        .map(|(index, _)| index)
+        .collect()
+}
+
+pub fn analyze_faults(circuit: &Circuit, noise: &NoiseModel) -> Result<Vec<Fault>, &'static str> {
+    // every fault the noise model can produce, one at a time, in circuit order: each of the
+    // 4^k - 1 paulis after every gate, any crosstalk, the 3 paulis on every qubit a TICK finds
+    // idle, and a flip of every measurement. Faults with zero probability are left out.
+    let num_measurements = circuit.num_measurements();
+    let detectors: Vec<&[usize]> = circuit
+        .detectors()
+        .iter()
+        .map(|detector| detector.measurements.as_slice())
+        .collect();
+    let observables: Vec<&[usize]> = circuit.observables().iter().map(Vec::as_slice).collect();
+    if detectors
+        .iter()
+        .chain(&observables)
+        .any(|measurements| measurements.iter().any(|&m| m >= num_measurements))
+    {
+        return Err("Detector or observable refers to a measurement the circuit doesn't make.");
+    }
+    let idle = noise::idle_qubits(circuit);
+    let mut faults = Vec::new();
+    for (index, instruction) in circuit.instructions().iter().enumerate() {
+        let mut kinds = Vec::new();
+        for &qubit in idle.get(&index).into_iter().flatten() {
+            let p = noise.idle_probability(qubit) / 3.0;
+            for pauli in 1..4 {
+                kinds.push((FaultKind::Pauli(noise::pauli_error(&[qubit], pauli)), p));
+            }
+        }
+        match instruction {
+            Instruction::Gate(gate) => {
+                let qubits = gate.qubits();
+                for (pauli, p) in noise.gate_errors(gate) {
+                    kinds.push((FaultKind::Pauli(noise::pauli_error(&qubits, pauli)), p));
+                }
+                for (qubit, spectator) in noise.crosstalk_pairs(gate) {
+                    kinds.push((
+                        FaultKind::Pauli(vec![Gate::Z(qubit), Gate::Z(spectator)]),
+                        noise.crosstalk_probability(),
+                    ));
+                }
+            }
+            Instruction::Measure(qubit) | Instruction::MeasureToBit { qubit, .. } => {
+                kinds.push((
+                    FaultKind::MeasurementFlip,
+                    noise.measurement_flip_probability(*qubit),
+                ));
+            }
+            Instruction::Conditional { gate, .. }
+            | Instruction::ConditionalOnBit { gate, .. }
+            | Instruction::SweepConditional { gate, .. }
+                if !is_pauli(gate) =>
+            {
+                return Err("Fault analysis can't follow classically controlled non-pauli gates.");
+            }
+            _ => {}
+        }
+        for (kind, probability) in kinds {
+            if probability <= 0.0 {
+                continue;
+            }
+            let flips = measurement_flips(circuit, index, &kind);
+            faults.push(Fault {
+                instruction: index,
+                kind,
+                probability,
+                detectors: flipped_parities(&detectors, &flips),
+                observables: flipped_parities(&observables, &flips),
+            });
+        }
+    }
+    Ok(faults)
+}
+
+fn symmetric_difference(a: &[usize], b: &[usize]) -> Vec<usize> {
+    // of two sorted index lists, which stays sorted.
+    let (mut i, mut j) = (0, 0);
+    let mut difference = Vec::with_capacity(a.len() + b.len());
+    while i < a.len() || j < b.len() {
+        match (a.get(i), b.get(j)) {
+            (Some(x), Some(y)) if x == y => {
+                i += 1;
+                j += 1;
+            }
+            (Some(&x), Some(&y)) if x < y => {
+                difference.push(x);
+                i += 1;
+            }
+            (Some(&x), None) => {
+                difference.push(x);
+                i += 1;
+            }
+            (_, Some(&y)) => {
+                difference.push(y);
+                j += 1;
+            }
+            (None, None) => unreachable!(),
+        }
+    }
+    difference
+}
+
+pub fn search_for_undetectable_logical_errors(
+    faults: &[Fault],
+    max_weight: usize,
+) -> Option<Vec<usize>> {
+    // the smallest set of faults that flips some observable without flipping any detector, as
+    // indices into faults. Its size is the circuit-level distance. Breadth first over the
+    // combined detector and observable flips of every fault set, so the answer is exact, but
+    // the number of states can grow quickly: sets larger than max_weight aren't looked at,
+    // and None means there's no undetectable logical error up to that weight.
+    //
+    // Faults with the same symptoms are interchangeable, so only the first of each is tried.
+    let mut seen_symptoms = HashMap::new();
+    for (index, fault) in faults.iter().enumerate() {
+        if !fault.detectors.is_empty() || !fault.observables.is_empty() {
+            seen_symptoms
+                .entry((fault.detectors.clone(), fault.observables.clone()))
+                .or_insert(index);
+        }
+    }
+    let mut candidates: Vec<usize> = seen_symptoms.into_values().collect();
+    candidates.sort();
+
+    // each state remembers the state it came from and the fault that got it here.
+    type Symptoms = (Vec<usize>, Vec<usize>);
+    let mut states: Vec<(Symptoms, usize, usize)> = vec![((vec![], vec![]), 0, 0)];
+    let mut visited: HashMap<Symptoms, usize> = HashMap::from([((vec![], vec![]), 0)]);
+    let mut queue = VecDeque::from([(0, 0)]);
+    while let Some((state, weight)) = queue.pop_front() {
+        if weight == max_weight {
+            continue;
+        }
+        for &candidate in &candidates {
+            let ((detectors, observables), _, _) = &states[state];
+            let next = (
+                symmetric_difference(detectors, &faults[candidate].detectors),
+                symmetric_difference(observables, &faults[candidate].observables),
+            );
+            if visited.contains_key(&next) {
+                continue;
+            }
+            let is_logical_error = next.0.is_empty() && !next.1.is_empty();
+            visited.insert(next.clone(), states.len());
+            states.push((next, state, candidate));
+            if is_logical_error {
+                let mut path = Vec::new();
+                let mut current = states.len() - 1;
+                while current != 0 {
+                    let (_, parent, fault) = &states[current];
+                    path.push(*fault);
+                    current = *parent;
+                }
+                path.reverse();
+                return Some(path);
+            }
+            queue.push_back((states.len() - 1, weight + 1));
+        }
+    }
+    None
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::qubit::Qubit;
+
+    #[test]
+    fn test_repetition_code_faults() {
+        // one round of the 3-qubit bit flip code: data 0, 1, 2, with ancillas 3 and 4
+        // measuring Z0 Z1 and Z1 Z2, and the data read out at the end.
+        let mut circuit = Circuit::new(5);
+        circuit
+            .gate(Gate::Cx(Qubit(0), Qubit(3)))
+            .gate(Gate::Cx(Qubit(1), Qubit(3)))
+            .gate(Gate::Cx(Qubit(1), Qubit(4)))
+            .gate(Gate::Cx(Qubit(2), Qubit(4)))
+            .measure(Qubit(3))
+            .measure(Qubit(4))
+            .measure(Qubit(0))
+            .measure(Qubit(1))
+            .measure(Qubit(2));
+        circuit.add_detector(&[5], &[]).unwrap();
+        circuit.add_detector(&[4], &[]).unwrap();
+        // final data parities, compared against the syndrome.
+        circuit.add_detector(&[5, 3, 2], &[]).unwrap();
+        circuit.add_detector(&[4, 2, 1], &[]).unwrap();
+        circuit.include_in_observable(0, &[3]).unwrap();
+
+        let faults = analyze_faults(&circuit, &NoiseModel::uniform_depolarizing(0.03)).unwrap();
+        // 15 paulis after each of the 4 CXs, and 5 measurement flips.
+        assert_eq!(faults.len(), 4 * 15 + 5);
+        assert!(faults.iter().all(|fault| fault.probability > 0.0));
+        let fault = |instruction, kind: FaultKind| {
+            faults
+                .iter()
+                .find(|fault| fault.instruction == instruction && fault.kind == kind)
+                .unwrap()
+        };
+        // X on data qubit 0 after its CX is missed by the syndrome, and caught at readout.
+        let x0 = fault(0, FaultKind::Pauli(vec![Gate::X(Qubit(0))]));
+        assert_eq!(
+            (x0.detectors.clone(), x0.observables.clone()),
+            (vec![2], vec![0])
+        );
+        // X on qubit 1 before its second CX trips the second syndrome bit only.
+        let x1 = fault(1, FaultKind::Pauli(vec![Gate::X(Qubit(1))]));
+        assert_eq!(x1.detectors, vec![1, 2]);
+        // Z errors never flip Z measurements.
+        let z = fault(
+            2,
+            FaultKind::Pauli(vec![Gate::Z(Qubit(1)), Gate::Z(Qubit(4))]),
+        );
+        assert!(z.detectors.is_empty() && z.observables.is_empty());
+        let flip = fault(4, FaultKind::MeasurementFlip);
+        assert_eq!(flip.detectors, vec![0, 2]);
+        assert!((flip.probability - 0.03).abs() < 1e-12);
+        assert!((x0.probability - 0.002).abs() < 1e-12);
+
+        assert!(analyze_faults(&circuit, &NoiseModel::noiseless())
+            .unwrap()
+            .is_empty());
+        circuit.conditional(1, Gate::H(Qubit(0)));
+        assert!(analyze_faults(&circuit, &NoiseModel::noiseless()).is_err());
+    }
+
+    #[test]
+    fn test_feedback_carries_flips() {
+        // teleportation's corrections depend on the bell measurement, so flipping that
+        // measurement's outcome moves an error onto the target.
+        let mut circuit = Circuit::teleportation(Qubit(0), Qubit(1), Qubit(2));
+        circuit.measure(Qubit(2));
+        circuit.add_detector(&[1], &[]).unwrap();
+        let noise = NoiseModel {
+            single_qubit_depolarization: 0.0,
+            two_qubit_depolarization: 0.0,
+            measurement_flip: 0.01,
+            idle_depolarization: 0.0,
+            single_qubit_channel: None,
+            two_qubit_channel: None,
+            per_qubit: None,
+            crosstalk: None,
+        };
+        let faults = analyze_faults(&circuit, &noise).unwrap();
+        let detectors: Vec<Vec<usize>> =
+            faults.iter().map(|fault| fault.detectors.clone()).collect();
+        // flipping the ancilla's outcome applies a stray X to the target, while flipping the
+        // source's applies a Z, which doesn't show up in Z.
+        assert_eq!(detectors, vec![vec![], vec![0], vec![0]]);
+    }
+
+    #[test]
+    fn test_repetition_code_distance() {
+        // a distance 3 bit flip code memory: data 0, 2 and 4, with ancillas 1 and 3, measured
+        // for two rounds and then read out. Only X errors matter, and it takes three of them
+        // on the data to flip the logical without a trace.
+        let mut circuit = Circuit::new(5);
+        for round in 0..2 {
+            for (data, ancilla) in [(0, 1), (2, 1), (2, 3), (4, 3)] {
+                circuit.gate(Gate::Cx(Qubit(data), Qubit(ancilla)));
+            }
+            circuit
+                .measure(Qubit(1))
+                .measure(Qubit(3))
+                .reset(Qubit(1))
+                .reset(Qubit(3));
+            for lookbacks in [[2, 4], [1, 3]] {
+                if round == 0 {
+                    circuit.add_detector(&lookbacks[..1], &[]).unwrap();
+                } else {
+                    circuit.add_detector(&lookbacks, &[]).unwrap();
+                }
+            }
+        }
+        circuit
+            .measure(Qubit(0))
+            .measure(Qubit(2))
+            .measure(Qubit(4));
+        circuit.add_detector(&[3, 2, 5], &[]).unwrap();
+        circuit.add_detector(&[2, 1, 4], &[]).unwrap();
+        circuit.include_in_observable(0, &[3]).unwrap();
+
+        let faults = analyze_faults(&circuit, &NoiseModel::uniform_depolarizing(0.001)).unwrap();
+        assert_eq!(search_for_undetectable_logical_errors(&faults, 2), None);
+        let logical_error = search_for_undetectable_logical_errors(&faults, 5).unwrap();
+        assert_eq!(logical_error.len(), 3);
+        let mut detectors = vec![];
+        let mut observables = vec![];
+        for &fault in &logical_error {
+            detectors = symmetric_difference(&detectors, &faults[fault].detectors);
+            observables = symmetric_difference(&observables, &faults[fault].observables);
+        }
+        assert!(detectors.is_empty());
+        assert_eq!(observables, vec![0]);
+    }
+}
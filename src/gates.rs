@@ -1,10 +1,103 @@
+use crate::qubit::Qubit;
+use std::fmt;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub enum Gate {
-    // keep qubit indiciis as u32 for some
-    // semblance of an upper bound on the number of qubits.
-    // has the benefit of preparing the code to handle
-    // type indirection between qubit register vector indexing
-    // (in usize) and qubit index (in u32 for now).
-    H(u32),
-    S(u32),
-    Cx(u32, u32),
-}
\ No newline at end of file
+    // qubit indices are Qubits (a u32 underneath), kept apart from
+    // register vector indexing (in usize) -- see qubit.rs.
+    H(Qubit),
+    S(Qubit),
+    X(Qubit),
+    Y(Qubit),
+    Z(Qubit),
+    Cx(Qubit, Qubit),
+    Cz(Qubit, Qubit),
+    Swap(Qubit, Qubit),
+}
+
+impl Gate {
+    pub fn qubits(&self) -> Vec<Qubit> {
+        match *self {
+            Gate::H(qubit) | Gate::S(qubit) | Gate::X(qubit) | Gate::Y(qubit) | Gate::Z(qubit) => {
+                vec![qubit]
+            }
+            Gate::Cx(a, b) | Gate::Cz(a, b) | Gate::Swap(a, b) => vec![a, b],
+        }
+    }
+
+    pub fn remap(&self, map: impl Fn(Qubit) -> Qubit) -> Gate {
+        match *self {
+            Gate::H(qubit) => Gate::H(map(qubit)),
+            Gate::S(qubit) => Gate::S(map(qubit)),
+            Gate::X(qubit) => Gate::X(map(qubit)),
+            Gate::Y(qubit) => Gate::Y(map(qubit)),
+            Gate::Z(qubit) => Gate::Z(map(qubit)),
+            Gate::Cx(control, target) => Gate::Cx(map(control), map(target)),
+            Gate::Cz(a, b) => Gate::Cz(map(a), map(b)),
+            Gate::Swap(a, b) => Gate::Swap(map(a), map(b)),
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        // the name the text format spells the gate with.
+        match self {
+            Gate::H(_) => "H",
+            Gate::S(_) => "S",
+            Gate::X(_) => "X",
+            Gate::Y(_) => "Y",
+            Gate::Z(_) => "Z",
+            Gate::Cx(..) => "CX",
+            Gate::Cz(..) => "CZ",
+            Gate::Swap(..) => "SWAP",
+        }
+    }
+
+    pub fn inverse(&self) -> Vec<Gate> {
+        // the gates that undo this one. Everything but S is its own inverse, and S^dagger
+        // isn't in the gate set, so it's undone by S^3.
+        match *self {
+            Gate::S(qubit) => vec![Gate::S(qubit); 3],
+            gate => vec![gate],
+        }
+    }
+}
+
+impl fmt::Display for Gate {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        // the text format's spelling, e.g. "CX 0 1". parser::parse_line reads it back.
+        write!(f, "{}", self.name())?;
+        for qubit in self.qubits() {
+            write!(f, " {qubit}")?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::stabilizer_simulator::StabilizerSimulator;
+
+    #[test]
+    fn test_inverse_undoes_every_gate() {
+        for gate in [
+            Gate::H(Qubit(0)),
+            Gate::S(Qubit(1)),
+            Gate::X(Qubit(0)),
+            Gate::Y(Qubit(1)),
+            Gate::Z(Qubit(0)),
+            Gate::Cx(Qubit(0), Qubit(1)),
+            Gate::Cz(Qubit(1), Qubit(0)),
+            Gate::Swap(Qubit(0), Qubit(1)),
+        ] {
+            let mut simulator = StabilizerSimulator::seeded(2);
+            simulator.apply_gate(&Gate::H(Qubit(0)));
+            simulator.apply_gate(&Gate::S(Qubit(0)));
+            simulator.apply_gate(&Gate::Cx(Qubit(0), Qubit(1)));
+            let before = simulator.stabilizers();
+            simulator.apply_gate(&gate);
+            simulator.apply_inverse(&gate);
+            assert_eq!(simulator.stabilizers(), before, "{gate:?}");
+        }
+    }
+}
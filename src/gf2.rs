@@ -0,0 +1,348 @@
+use crate::pauli::PauliString;
+use crate::simd::{self, WORD_BITS};
+
+// linear algebra over GF(2) on bit matrices packed 64 columns to a word. A pauli string
+// without its sign is a row of 2n bits, x bits then z bits, and two paulis commute exactly
+// when the symplectic form
+//
+//     <a, b> = a_x . b_z + a_z . b_x  (mod 2)
+//
+// of their rows is 0, so stabilizer group questions (is this independent, what commutes with
+// all of these, which pairs make up logical qubits) come down to the matrices here.
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct BitMatrix {
+    num_rows: usize,
+    num_columns: usize,
+    words_per_row: usize,
+    words: Vec<u64>,
+}
+
+impl BitMatrix {
+    pub fn new(num_rows: usize, num_columns: usize) -> BitMatrix {
+        let words_per_row = num_columns.div_ceil(WORD_BITS);
+        BitMatrix {
+            num_rows,
+            num_columns,
+            words_per_row,
+            words: vec![0; num_rows * words_per_row],
+        }
+    }
+
+    pub fn identity(size: usize) -> BitMatrix {
+        let mut matrix = BitMatrix::new(size, size);
+        for i in 0..size {
+            matrix.set(i, i, true);
+        }
+        matrix
+    }
+
+    pub fn from_rows(rows: &[Vec<bool>]) -> Result<BitMatrix, &'static str> {
+        let num_columns = rows.first().map_or(0, |row| row.len());
+        if rows.iter().any(|row| row.len() != num_columns) {
+            return Err("Rows of a bit matrix must all be the same length.");
+        }
+        let mut matrix = BitMatrix::new(rows.len(), num_columns);
+        for (i, row) in rows.iter().enumerate() {
+            for (j, &bit) in row.iter().enumerate() {
+                matrix.set(i, j, bit);
+            }
+        }
+        Ok(matrix)
+    }
+
+    pub fn from_paulis(
+        num_qubits: usize,
+        paulis: &[PauliString],
+    ) -> Result<BitMatrix, &'static str> {
+        // one row per pauli, x bits in columns 0..n and z bits in n..2n. Signs are dropped.
+        if paulis.iter().any(|pauli| pauli.num_qubits() != num_qubits) {
+            return Err("Paulis must all act on the same number of qubits.");
+        }
+        let mut matrix = BitMatrix::new(paulis.len(), 2 * num_qubits);
+        for (i, pauli) in paulis.iter().enumerate() {
+            for q in 0..num_qubits {
+                matrix.set(i, q, pauli.x_bits[q]);
+                matrix.set(i, num_qubits + q, pauli.z_bits[q]);
+            }
+        }
+        Ok(matrix)
+    }
+
+    pub fn num_rows(&self) -> usize {
+        self.num_rows
+    }
+
+    pub fn num_columns(&self) -> usize {
+        self.num_columns
+    }
+
+    pub fn get(&self, row: usize, column: usize) -> bool {
+        assert!(row < self.num_rows && column < self.num_columns);
+        let word = self.words[row * self.words_per_row + column / WORD_BITS];
+        (word >> (column % WORD_BITS)) & 1 == 1
+    }
+
+    pub fn set(&mut self, row: usize, column: usize, value: bool) {
+        assert!(row < self.num_rows && column < self.num_columns);
+        let word = &mut self.words[row * self.words_per_row + column / WORD_BITS];
+        let mask = 1 << (column % WORD_BITS);
+        if value {
+            *word |= mask;
+        } else {
+            *word &= !mask;
+        }
+    }
+
+    pub fn row(&self, row: usize) -> Vec<bool> {
+        (0..self.num_columns).map(|j| self.get(row, j)).collect()
+    }
+
+    pub fn rows(&self) -> impl Iterator<Item = Vec<bool>> + '_ {
+        (0..self.num_rows).map(|i| self.row(i))
+    }
+
+    fn row_words(&self, row: usize) -> &[u64] {
+        &self.words[row * self.words_per_row..(row + 1) * self.words_per_row]
+    }
+
+    fn row_is_zero(&self, row: usize) -> bool {
+        self.row_words(row).iter().all(|&word| word == 0)
+    }
+
+    pub fn swap_rows(&mut self, a: usize, b: usize) {
+        if a != b {
+            for k in 0..self.words_per_row {
+                self.words
+                    .swap(a * self.words_per_row + k, b * self.words_per_row + k);
+            }
+        }
+    }
+
+    pub fn xor_row(&mut self, target: usize, source: usize) {
+        // row target += row source.
+        let width = self.words_per_row;
+        if target == source {
+            self.words[target * width..(target + 1) * width].fill(0);
+            return;
+        }
+        let split = target.max(source) * width;
+        let (low, high) = self.words.split_at_mut(split);
+        let (target_words, source_words) = if target < source {
+            (
+                &mut low[target * width..(target + 1) * width],
+                &high[..width],
+            )
+        } else {
+            (
+                &mut high[..width],
+                &low[source * width..(source + 1) * width],
+            )
+        };
+        simd::xor_into(target_words, source_words);
+    }
+
+    pub fn row_weight(&self, row: usize) -> usize {
+        // how many ones the row has.
+        simd::popcount(self.row_words(row)) as usize
+    }
+
+    pub fn transpose(&self) -> BitMatrix {
+        let mut transpose = BitMatrix::new(self.num_columns, self.num_rows);
+        for i in 0..self.num_rows {
+            for j in 0..self.num_columns {
+                if self.get(i, j) {
+                    transpose.set(j, i, true);
+                }
+            }
+        }
+        transpose
+    }
+
+    pub fn row_reduce(&mut self) -> Vec<usize> {
+        // brings the matrix to reduced row echelon form in place and returns the pivot
+        // columns, one per nonzero row, which are the leading rows.
+        let mut pivots = vec![];
+        for column in 0..self.num_columns {
+            let rank = pivots.len();
+            let Some(pivot) = (rank..self.num_rows).find(|&i| self.get(i, column)) else {
+                continue;
+            };
+            self.swap_rows(rank, pivot);
+            for i in 0..self.num_rows {
+                if i != rank && self.get(i, column) {
+                    self.xor_row(i, rank);
+                }
+            }
+            pivots.push(column);
+        }
+        pivots
+    }
+
+    pub fn rank(&self) -> usize {
+        self.clone().row_reduce().len()
+    }
+
+    pub fn kernel(&self) -> BitMatrix {
+        // a basis of the vectors v with M v = 0, one per row: each free column set to 1, the
+        // other free columns 0, and the pivot columns whatever cancels it.
+        let mut reduced = self.clone();
+        let pivots = reduced.row_reduce();
+        let free: Vec<usize> = (0..self.num_columns)
+            .filter(|column| !pivots.contains(column))
+            .collect();
+        let mut kernel = BitMatrix::new(free.len(), self.num_columns);
+        for (k, &column) in free.iter().enumerate() {
+            kernel.set(k, column, true);
+            for (i, &pivot) in pivots.iter().enumerate() {
+                if reduced.get(i, column) {
+                    kernel.set(k, pivot, true);
+                }
+            }
+        }
+        kernel
+    }
+
+    pub fn symplectic_product(&self, a: usize, b: usize) -> bool {
+        // <row a, row b>, reading each row as x bits then z bits.
+        let n = self.num_columns / 2;
+        (0..n).fold(false, |product, q| {
+            product ^ (self.get(a, q) & self.get(b, n + q)) ^ (self.get(a, n + q) & self.get(b, q))
+        })
+    }
+
+    pub fn symplectic_gram_schmidt(&self) -> Result<SymplecticBasis, &'static str> {
+        // splits the row space into hyperbolic pairs and an isotropic part. Take a row, find
+        // another it anticommutes with and pair them up, then fix every remaining row to
+        // commute with both (u += <u, b> a + <u, a> b). A row with no partner commutes with
+        // everything left, and with all the pairs so far, so it's in the isotropic part.
+        if !self.num_columns.is_multiple_of(2) {
+            return Err("Symplectic rows need an even number of columns, x bits then z bits.");
+        }
+        let mut work = self.clone();
+        let mut remaining: Vec<usize> = (0..self.num_rows)
+            .filter(|&i| !self.row_is_zero(i))
+            .collect();
+        let mut pairs = vec![];
+        let mut isotropic = vec![];
+        while let Some(a) = remaining.pop() {
+            let Some(position) = remaining
+                .iter()
+                .position(|&b| work.symplectic_product(a, b))
+            else {
+                isotropic.push(a);
+                continue;
+            };
+            let b = remaining.remove(position);
+            for &u in &remaining {
+                let (with_a, with_b) =
+                    (work.symplectic_product(u, a), work.symplectic_product(u, b));
+                if with_b {
+                    work.xor_row(u, a);
+                }
+                if with_a {
+                    work.xor_row(u, b);
+                }
+            }
+            remaining.retain(|&u| !work.row_is_zero(u));
+            pairs.push((a, b));
+        }
+        let mut hyperbolic = BitMatrix::new(2 * pairs.len(), self.num_columns);
+        for (k, &(a, b)) in pairs.iter().enumerate() {
+            hyperbolic.copy_row_from(2 * k, &work, a);
+            hyperbolic.copy_row_from(2 * k + 1, &work, b);
+        }
+        // isotropic rows can still depend on each other, so keep just a basis of their span.
+        let mut center = BitMatrix::new(isotropic.len(), self.num_columns);
+        for (k, &a) in isotropic.iter().enumerate() {
+            center.copy_row_from(k, &work, a);
+        }
+        let rank = center.row_reduce().len();
+        center.num_rows = rank;
+        center.words.truncate(rank * center.words_per_row);
+        Ok(SymplecticBasis {
+            pairs: hyperbolic,
+            isotropic: center,
+        })
+    }
+
+    fn copy_row_from(&mut self, row: usize, other: &BitMatrix, other_row: usize) {
+        let range = row * self.words_per_row..(row + 1) * self.words_per_row;
+        self.words[range].copy_from_slice(other.row_words(other_row));
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SymplecticBasis {
+    // rows 2k and 2k + 1 anticommute with each other and commute with every other row, like
+    // the X and Z of a qubit.
+    pub pairs: BitMatrix,
+    // rows that commute with everything in the span, in reduced row echelon form.
+    pub isotropic: BitMatrix,
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn matrix(rows: &[&str]) -> BitMatrix {
+        let rows: Vec<Vec<bool>> = rows
+            .iter()
+            .map(|row| row.chars().map(|c| c == '1').collect())
+            .collect();
+        BitMatrix::from_rows(&rows).unwrap()
+    }
+
+    #[test]
+    fn test_rank_and_kernel() {
+        let mut m = matrix(&["1101", "0111", "1010"]);
+        assert_eq!(m.rank(), 2);
+        let kernel = m.kernel();
+        assert_eq!(kernel.num_rows(), 2);
+        for v in kernel.rows() {
+            for row in m.rows() {
+                let dot = row.iter().zip(&v).filter(|&(&a, &b)| a && b).count();
+                assert_eq!(dot % 2, 0);
+            }
+        }
+        assert_eq!(m.row_reduce(), vec![0, 1]);
+        assert_eq!(m, matrix(&["1010", "0111", "0000"]));
+        assert_eq!(m.transpose().num_rows(), 4);
+
+        // rows spanning several words.
+        let wide = BitMatrix::identity(130);
+        assert_eq!(wide.rank(), 130);
+        assert_eq!(wide.kernel().num_rows(), 0);
+        let mut wide_rows = wide.clone();
+        wide_rows.xor_row(129, 0);
+        assert_eq!(wide_rows.row_weight(129), 2);
+        wide_rows.xor_row(0, 0);
+        assert_eq!(wide_rows.row_weight(0), 0);
+        assert!(BitMatrix::from_rows(&[vec![true], vec![]]).is_err());
+    }
+
+    #[test]
+    fn test_symplectic_gram_schmidt() {
+        // XX, ZZ, XI, and XX again, on two qubits (columns x0 x1 z0 z1). XI and ZZ pair up,
+        // and XX, which commutes with both, is the isotropic part, however many times it's
+        // listed.
+        let m = matrix(&["1100", "0011", "1000", "1100"]);
+        let basis = m.symplectic_gram_schmidt().unwrap();
+        assert_eq!(basis.pairs.num_rows(), 2);
+        assert_eq!(basis.isotropic.num_rows(), 1);
+        let mut all = basis.pairs.clone();
+        for i in 0..all.num_rows() {
+            for j in 0..all.num_rows() {
+                assert_eq!(all.symplectic_product(i, j), i / 2 == j / 2 && i != j);
+            }
+        }
+        assert_eq!(all.row_reduce().len() + basis.isotropic.rank(), m.rank());
+
+        // commuting stabilizers are all isotropic.
+        let stabilizers = matrix(&["110000", "011000", "000111"]);
+        let basis = stabilizers.symplectic_gram_schmidt().unwrap();
+        assert_eq!(basis.pairs.num_rows(), 0);
+        assert_eq!(basis.isotropic.num_rows(), 3);
+        assert!(matrix(&["101"]).symplectic_gram_schmidt().is_err());
+    }
+}
@@ -0,0 +1,156 @@
+use crate::gates::Gate;
+use crate::qubit::Qubit;
+use rand::Rng;
+
+// a simple model of leakage out of the computational subspace. A qubit can leak right after
+// a gate acts on it, and stays leaked until it's reset (or seeps back on its own). While it's
+// leaked, gates that involve it don't happen: the other qubits of the gate are hit by a pauli
+// channel instead, and measuring it reports a fixed or random outcome rather than its state.
+// With heralding on, every measurement also says whether its qubit was leaked, like the
+// leakage detection some hardware has, which is what leakage-aware decoders get to see.
+//
+// Resets always bring a qubit back, in |0> (or the state being prepared).
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LeakedMeasurement {
+    Zero,
+    One,
+    Random,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct LeakageModel {
+    // the chance that each qubit of a gate leaks right after it.
+    pub leak: f64,
+    // the chance that a leaked qubit drops back into a random basis state, after each gate
+    // that involves it.
+    pub seep: f64,
+    // the X, Y and Z probabilities of the pauli channel on the unleaked qubits of a gate that
+    // involves a leaked one.
+    pub leaked_gate_channel: [f64; 3],
+    pub leaked_measurement: LeakedMeasurement,
+    pub heralded: bool,
+}
+
+impl LeakageModel {
+    pub fn new(leak: f64, seep: f64) -> LeakageModel {
+        // leaked qubits read out as 1, as |2> usually does, and fully depolarize their partners.
+        LeakageModel {
+            leak,
+            seep,
+            leaked_gate_channel: [0.25; 3],
+            leaked_measurement: LeakedMeasurement::One,
+            heralded: false,
+        }
+    }
+
+    pub fn with_herald(mut self) -> LeakageModel {
+        self.heralded = true;
+        self
+    }
+
+    pub(crate) fn validate(&self) -> Result<(), &'static str> {
+        let channel_total: f64 = self.leaked_gate_channel.iter().sum();
+        let probabilities = [self.leak, self.seep, channel_total];
+        if self.leaked_gate_channel.iter().any(|&p| p < 0.0)
+            || probabilities.iter().any(|p| !(0.0..=1.0).contains(p))
+        {
+            return Err("Leakage probabilities must be between 0 and 1.");
+        }
+        Ok(())
+    }
+
+    pub(crate) fn leaks(&self, rng: &mut impl Rng) -> bool {
+        self.leak > 0.0 && rng.gen_bool(self.leak)
+    }
+
+    pub(crate) fn seeps(&self, rng: &mut impl Rng) -> bool {
+        self.seep > 0.0 && rng.gen_bool(self.seep)
+    }
+
+    pub(crate) fn leaked_gate_error(&self, qubit: Qubit, rng: &mut impl Rng) -> Option<Gate> {
+        // the pauli, if any, the channel puts on a partner of a leaked qubit.
+        let [x, y, z] = self.leaked_gate_channel;
+        let r: f64 = rng.gen();
+        if r < x {
+            Some(Gate::X(qubit))
+        } else if r < x + y {
+            Some(Gate::Y(qubit))
+        } else if r < x + y + z {
+            Some(Gate::Z(qubit))
+        } else {
+            None
+        }
+    }
+
+    pub(crate) fn leaked_outcome(&self, rng: &mut impl Rng) -> bool {
+        match self.leaked_measurement {
+            LeakedMeasurement::Zero => false,
+            LeakedMeasurement::One => true,
+            LeakedMeasurement::Random => rng.gen(),
+        }
+    }
+}
+
+// the measurement record of one leaky shot, and with heralding on, whether each measured
+// qubit was leaked at the time.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LeakyShot {
+    pub measurements: Vec<bool>,
+    pub heralds: Option<Vec<bool>>,
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::circuit::Circuit;
+    use crate::noise::NoiseModel;
+    use crate::stabilizer_simulator::StabilizerSimulator;
+
+    #[test]
+    fn test_leaked_qubits_skip_gates_until_reset() {
+        // qubit 0 leaks after the X, so the CX never happens and qubit 1 takes an X from the
+        // channel instead. The reset brings qubit 0 back.
+        let mut circuit = Circuit::new(2);
+        circuit
+            .gate(Gate::X(Qubit(0)))
+            .gate(Gate::Cx(Qubit(0), Qubit(1)))
+            .measure(Qubit(0))
+            .measure(Qubit(1))
+            .reset(Qubit(0))
+            .measure(Qubit(0));
+        let mut leakage = LeakageModel::new(1.0, 0.0).with_herald();
+        leakage.leaked_gate_channel = [1.0, 0.0, 0.0];
+        leakage.leaked_measurement = LeakedMeasurement::Zero;
+        let shot = StabilizerSimulator::seeded(2)
+            .run_leaky(&circuit, &NoiseModel::noiseless(), &leakage)
+            .unwrap();
+        assert_eq!(shot.measurements, vec![false, true, false]);
+        assert_eq!(shot.heralds, Some(vec![true, false, false]));
+
+        leakage.heralded = false;
+        leakage.leaked_measurement = LeakedMeasurement::One;
+        let shot = StabilizerSimulator::seeded(2)
+            .run_leaky(&circuit, &NoiseModel::noiseless(), &leakage)
+            .unwrap();
+        assert_eq!(shot.measurements, vec![true, true, false]);
+        assert_eq!(shot.heralds, None);
+
+        // without leakage it's just the circuit.
+        let shot = StabilizerSimulator::seeded(2)
+            .run_leaky(
+                &circuit,
+                &NoiseModel::noiseless(),
+                &LeakageModel::new(0.0, 0.0),
+            )
+            .unwrap();
+        assert_eq!(shot.measurements, vec![true, true, false]);
+        assert!(StabilizerSimulator::seeded(2)
+            .run_leaky(
+                &circuit,
+                &NoiseModel::noiseless(),
+                &LeakageModel::new(1.5, 0.0)
+            )
+            .is_err());
+    }
+}
@@ -1,2 +1,59 @@
+#[cfg(feature = "proptest")]
+pub mod arbitrary;
+pub mod bell_inequalities;
+pub mod benchmarks;
+pub mod bitmap;
+#[cfg(feature = "braket")]
+pub mod braket;
+pub mod calibration;
+pub mod ch_form;
+pub mod circuit;
+pub mod clifford;
+pub mod code;
+pub mod complex;
+pub mod coupling_map;
+pub mod debugger;
+pub mod decoding;
+pub mod dem;
+pub mod dense;
+pub mod determinism;
+pub mod diagram;
+#[cfg(feature = "evcxr")]
+pub mod evcxr;
+pub mod export;
+pub mod faults;
 pub mod gates;
+pub mod gf2;
+pub mod leakage;
+pub mod matching;
+pub mod mbqc;
+pub mod near_clifford;
+pub mod noise;
+pub mod observable;
+pub mod paired;
+pub mod parser;
+pub mod pauli;
+#[cfg(feature = "pytket")]
+pub mod pytket;
+pub mod qasm;
+pub mod qubit;
+pub mod randomized_benchmarking;
+pub mod results;
+pub mod run_log;
+pub mod sampler;
+#[cfg(feature = "server")]
+pub mod server;
+pub mod shadows;
+pub mod shot_format;
+pub mod simd;
+pub mod simulator;
 pub mod stabilizer_simulator;
+pub mod stats;
+pub mod timeline;
+pub mod tomography;
+mod trace;
+pub mod transpiler;
+#[cfg(feature = "tui")]
+pub mod tui;
+pub mod twirl;
+pub mod zx;
@@ -0,0 +1,153 @@
+use crate::dem::DetectorErrorModel;
+use std::collections::HashMap;
+
+// the matching graph of a detector error model: one node per detector, and one edge per
+// error, between the (at most two) detectors it flips, or out to the boundary when it flips
+// just one. Edges are weighted ln((1 - p) / p), so the most likely explanation of a syndrome
+// is the lightest set of edges that pairs its detection events up.
+//
+// Erasure flags aren't nodes. An error that raises one keeps it on its edge, and in shots
+// where the flag fires, the edge's location is known and it weighs nothing, as if its
+// probability were 1/2. That's what lets erasure conversion pay off in a decoder.
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct MatchingEdge {
+    // a detector, and the other detector or None for the boundary.
+    pub nodes: (usize, Option<usize>),
+    pub probability: f64,
+    pub observables: Vec<usize>,
+    pub erasure_flags: Vec<usize>,
+}
+
+impl MatchingEdge {
+    pub fn weight(&self) -> f64 {
+        ((1.0 - self.probability) / self.probability).ln()
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct MatchingGraph {
+    num_detectors: usize,
+    erasure_flags: Vec<bool>,
+    edges: Vec<MatchingEdge>,
+}
+
+impl MatchingGraph {
+    pub fn from_dem(model: &DetectorErrorModel) -> Result<MatchingGraph, &'static str> {
+        // errors along the same edge merge into one that happens when an odd number of them
+        // do. Errors that don't flip any detectors can't be matched, and are left out.
+        let mut edges: Vec<MatchingEdge> = Vec::new();
+        let mut merged = HashMap::new();
+        for error in model.errors() {
+            let (erasure_flags, checks): (Vec<usize>, Vec<usize>) = error
+                .detectors
+                .iter()
+                .partition(|&&detector| model.is_erasure_flag(detector));
+            let nodes = match checks[..] {
+                [] => continue,
+                [a] => (a, None),
+                [a, b] => (a, Some(b)),
+                _ => return Err("Error flips more than two detectors, so it isn't an edge."),
+            };
+            let key = (nodes, error.observables.clone(), erasure_flags.clone());
+            match merged.get(&key) {
+                Some(&index) => {
+                    let edge: &mut MatchingEdge = &mut edges[index];
+                    let (p, q) = (edge.probability, error.probability);
+                    edge.probability = p * (1.0 - q) + q * (1.0 - p);
+                }
+                None => {
+                    merged.insert(key, edges.len());
+                    edges.push(MatchingEdge {
+                        nodes,
+                        probability: error.probability,
+                        observables: error.observables.clone(),
+                        erasure_flags,
+                    });
+                }
+            }
+        }
+        let mut flags = vec![false; model.num_detectors()];
+        for detector in model.erasure_flags() {
+            flags[detector] = true;
+        }
+        Ok(MatchingGraph {
+            num_detectors: model.num_detectors(),
+            erasure_flags: flags,
+            edges,
+        })
+    }
+
+    pub fn num_detectors(&self) -> usize {
+        self.num_detectors
+    }
+
+    pub fn edges(&self) -> &[MatchingEdge] {
+        &self.edges
+    }
+
+    pub fn syndrome(&self, events: &[bool]) -> Vec<usize> {
+        // the detectors that fired in a shot, leaving out erasure flags.
+        events
+            .iter()
+            .enumerate()
+            .filter(|&(detector, &fired)| {
+                fired && !self.erasure_flags.get(detector).is_some_and(|&flag| flag)
+            })
+            .map(|(detector, _)| detector)
+            .collect()
+    }
+
+    pub fn weights(&self, events: &[bool]) -> Vec<f64> {
+        // every edge's weight in a shot with these detection events: erased edges, whose
+        // flags fired, weigh 0.
+        self.edges
+            .iter()
+            .map(|edge| {
+                let erased = edge
+                    .erasure_flags
+                    .iter()
+                    .any(|&flag| events.get(flag).is_some_and(|&fired| fired));
+                if erased {
+                    0.0
+                } else {
+                    edge.weight()
+                }
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_erased_edges_weigh_nothing() {
+        // a line of two detectors, where the middle error is heralded by flag D2.
+        let model = DetectorErrorModel::parse(
+            "error(0.1) D0
+             error(0.1) D0 D1 D2
+             error(0.1) D1 L0
+             error(0.2) D1 L0
+             error(0.1) D2
+             detector[erasure] D2",
+        )
+        .unwrap();
+        let graph = MatchingGraph::from_dem(&model).unwrap();
+        assert_eq!(graph.edges().len(), 3);
+        assert_eq!(graph.edges()[1].nodes, (0, Some(1)));
+        assert_eq!(graph.edges()[1].erasure_flags, vec![2]);
+        assert!((graph.edges()[2].probability - 0.26).abs() < 1e-12);
+
+        let events = [true, true, true];
+        assert_eq!(graph.syndrome(&events), vec![0, 1]);
+        let weights = graph.weights(&events);
+        assert_eq!(weights[1], 0.0);
+        assert_eq!(weights[0], graph.edges()[0].weight());
+        assert!(graph.weights(&[true, true, false])[1] > 0.0);
+
+        let hyperedge = DetectorErrorModel::parse("error(0.1) D0 D1 D2").unwrap();
+        assert!(MatchingGraph::from_dem(&hyperedge).is_err());
+    }
+}
@@ -0,0 +1,552 @@
+use crate::circuit::{Circuit, Instruction};
+use crate::gates::Gate;
+use crate::gf2::BitMatrix;
+use crate::pauli::PauliString;
+use crate::qubit::Qubit;
+use crate::stabilizer_simulator::StabilizerSimulator;
+
+// measurement-based quantum computing: entangle a bunch of qubits into a graph state,
+// then drive the computation purely by measuring them one at a time, feeding earlier
+// outcomes forward into how later outcomes are interpreted and into pauli corrections
+// on the output qubits.
+//
+// Everything here is Clifford, so measurements are in the X, Y or Z basis. For pauli
+// measurements, "adapting the basis" to an earlier outcome only ever negates it (X -> -X),
+// which is the same as measuring the unadapted basis and flipping the outcome. So a
+// measurement's adaptivity is described by its signal: the earlier measurements whose
+// outcomes get xored into its own.
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct GraphState {
+    num_qubits: usize,
+    edges: Vec<(Qubit, Qubit)>,
+}
+
+impl GraphState {
+    pub fn new(num_qubits: usize, edges: &[(Qubit, Qubit)]) -> Result<GraphState, &'static str> {
+        for &(a, b) in edges {
+            if a == b || a.index() >= num_qubits || b.index() >= num_qubits {
+                return Err("Graph state edge must join two different qubits of the graph.");
+            }
+        }
+        Ok(GraphState {
+            num_qubits,
+            edges: edges.to_vec(),
+        })
+    }
+
+    pub fn linear_cluster(num_qubits: usize) -> GraphState {
+        let edges: Vec<(Qubit, Qubit)> = (1..num_qubits as u32)
+            .map(|q| (Qubit(q - 1), Qubit(q)))
+            .collect();
+        GraphState::new(num_qubits, &edges).unwrap()
+    }
+
+    pub fn square_cluster(rows: usize, columns: usize) -> GraphState {
+        let mut edges = Vec::new();
+        for row in 0..rows {
+            for column in 0..columns {
+                let qubit = (row * columns + column) as u32;
+                if column + 1 < columns {
+                    edges.push((Qubit(qubit), Qubit(qubit + 1)));
+                }
+                if row + 1 < rows {
+                    edges.push((Qubit(qubit), Qubit(qubit + columns as u32)));
+                }
+            }
+        }
+        GraphState::new(rows * columns, &edges).unwrap()
+    }
+
+    pub fn num_qubits(&self) -> usize {
+        self.num_qubits
+    }
+
+    pub fn edges(&self) -> &[(Qubit, Qubit)] {
+        &self.edges
+    }
+
+    pub fn preparation(&self, inputs: &[Qubit]) -> Circuit {
+        // every qubit that isn't an input starts in |+>, then each edge gets a CZ.
+        // Inputs keep whatever state they were handed.
+        let mut circuit = Circuit::new(self.num_qubits);
+        for qubit in Qubit::range(self.num_qubits) {
+            if !inputs.contains(&qubit) {
+                circuit.gate(Gate::H(qubit));
+            }
+        }
+        for &(a, b) in &self.edges {
+            circuit.gate(Gate::Cz(a, b));
+        }
+        circuit
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MeasurementBasis {
+    X,
+    Y,
+    Z,
+}
+
+impl MeasurementBasis {
+    pub(crate) fn rotation_to_z(&self, qubit: Qubit) -> Vec<Gate> {
+        // the gates that turn a measurement in this basis into a Z measurement.
+        // For Y, S^dagger then H takes Y to Z.
+        match self {
+            MeasurementBasis::X => vec![Gate::H(qubit)],
+            MeasurementBasis::Y => vec![
+                Gate::S(qubit),
+                Gate::S(qubit),
+                Gate::S(qubit),
+                Gate::H(qubit),
+            ],
+            MeasurementBasis::Z => vec![],
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PatternMeasurement {
+    pub qubit: Qubit,
+    pub basis: MeasurementBasis,
+    // indices of earlier measurements in the pattern whose outcomes flip this one's.
+    pub signal: Vec<usize>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PatternCorrection {
+    // a pauli gate on an output qubit...
+    pub gate: Gate,
+    // ...applied if the parity of these measurements' (signal-adjusted) outcomes is odd.
+    pub domain: Vec<usize>,
+}
+
+// how far a pattern is from a reference circuit, when the two only differ by paulis on the
+// outputs: the pattern implements the reference exactly once `constant` is applied to the
+// outputs and `corrections` are added to its own.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct PauliFrame {
+    pub constant: Vec<Gate>,
+    pub corrections: Vec<PatternCorrection>,
+}
+
+impl PauliFrame {
+    pub fn is_empty(&self) -> bool {
+        self.constant.is_empty() && self.corrections.is_empty()
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Pattern {
+    pub graph: GraphState,
+    pub inputs: Vec<Qubit>,
+    pub outputs: Vec<Qubit>,
+    pub measurements: Vec<PatternMeasurement>,
+    pub corrections: Vec<PatternCorrection>,
+}
+
+impl Pattern {
+    fn validate(&self) -> Result<(), &'static str> {
+        if self.inputs.len() != self.outputs.len() {
+            return Err("Pattern needs as many outputs as inputs.");
+        }
+        for (index, measurement) in self.measurements.iter().enumerate() {
+            if measurement.signal.iter().any(|&earlier| earlier >= index) {
+                return Err("A measurement's signal can only depend on earlier measurements.");
+            }
+            if self.outputs.contains(&measurement.qubit) {
+                return Err("Output qubits can't be measured.");
+            }
+        }
+        for correction in &self.corrections {
+            if !matches!(correction.gate, Gate::X(_) | Gate::Y(_) | Gate::Z(_)) {
+                return Err("Pattern corrections must be pauli gates.");
+            }
+            if correction
+                .domain
+                .iter()
+                .any(|&m| m >= self.measurements.len())
+            {
+                return Err("Correction depends on a measurement that isn't in the pattern.");
+            }
+        }
+        Ok(())
+    }
+
+    fn raw_domain(&self, domain: &[usize]) -> Vec<bool> {
+        // which raw measurement outcomes the parity of these signal-adjusted outcomes depends on.
+        // Each adjusted outcome is its raw outcome xored with its own signal's adjusted outcomes,
+        // so unroll that back to raw outcomes, cancelling anything that appears twice.
+        let mut raw = vec![false; self.measurements.len()];
+        for &index in domain {
+            raw[index] ^= true;
+            for (earlier, included) in self
+                .raw_domain(&self.measurements[index].signal)
+                .into_iter()
+                .enumerate()
+            {
+                raw[earlier] ^= included;
+            }
+        }
+        raw
+    }
+
+    pub fn outcomes(&self, raw_record: &[bool]) -> Vec<bool> {
+        // the signal-adjusted outcomes, given the raw outcomes of running to_circuit().
+        let mut outcomes: Vec<bool> = Vec::with_capacity(self.measurements.len());
+        for (measurement, raw) in self.measurements.iter().zip(raw_record) {
+            let flip = measurement
+                .signal
+                .iter()
+                .fold(false, |parity, &earlier| parity ^ outcomes[earlier]);
+            outcomes.push(raw ^ flip);
+        }
+        outcomes
+    }
+
+    pub fn to_circuit(&self) -> Result<Circuit, &'static str> {
+        // graph state preparation, then every measurement (rotated into the Z basis), then the
+        // corrections. A correction depending on several outcomes is applied once per outcome
+        // that's 1, since paulis square to the identity that gives it the right parity.
+        self.validate()?;
+        let mut circuit = self.graph.preparation(&self.inputs);
+        for measurement in &self.measurements {
+            let qubit = measurement.qubit;
+            for gate in measurement.basis.rotation_to_z(qubit) {
+                circuit.gate(gate);
+            }
+            circuit.measure(qubit);
+        }
+        let num_measurements = self.measurements.len();
+        for correction in &self.corrections {
+            for (index, included) in self.raw_domain(&correction.domain).into_iter().enumerate() {
+                if included {
+                    circuit.conditional((num_measurements - index) as u32, correction.gate);
+                }
+            }
+        }
+        Ok(circuit)
+    }
+
+    pub fn implements(&self, reference: &Circuit) -> Result<bool, &'static str> {
+        // checks that the pattern implements the same Clifford as `reference`, where reference
+        // qubit i corresponds to inputs[i] going in and outputs[i] coming out.
+        //
+        // Compare Choi states: entangle every input with its own spectator qubit, run the pattern,
+        // and reset the measured qubits to |0>. Separately, entangle the outputs with the same
+        // spectators and run the reference on them directly. The two unitaries are equal (up to
+        // global phase) exactly when the two final states are. The pattern's measurement outcomes
+        // are random, so we try a handful of seeds to make sure the corrections hold up.
+        let pattern_circuit = self.to_circuit()?;
+        self.check_reference(reference)?;
+        let num_qubits = self.graph.num_qubits() + self.inputs.len();
+
+        let mut expected = StabilizerSimulator::seeded(num_qubits);
+        for (i, &output) in self.outputs.iter().enumerate() {
+            expected.prepare_bell_pair(self.spectator(i), output);
+        }
+        for instruction in reference.instructions() {
+            if let Instruction::Gate(gate) = instruction {
+                expected.apply_gate(&gate.remap(|q| self.outputs[q.index()]));
+            }
+        }
+        let expected = expected.canonical_stabilizers()?;
+
+        for seed in 0..16 {
+            let actual = self.run_on_choi_state(&pattern_circuit, seed)?;
+            if actual.canonical_stabilizers()? != expected {
+                return Ok(false);
+            }
+        }
+        Ok(true)
+    }
+
+    pub fn implements_up_to_pauli(&self, reference: &Circuit) -> Result<bool, &'static str> {
+        Ok(self.pauli_frame(reference)?.is_some())
+    }
+
+    pub fn pauli_frame(&self, reference: &Circuit) -> Result<Option<PauliFrame>, &'static str> {
+        // the paulis missing from the pattern's corrections, if paulis on the outputs are all
+        // that stand between it and `reference` (None otherwise). An empty frame means the
+        // pattern implements the reference as it is.
+        //
+        // The Choi states again: the reference's is stabilized by X and Z on each spectator
+        // times the images of X and Z on its output. If the pattern's state is that one with
+        // a pauli P on the outputs, each of those generators has expectation +-1 in it, and
+        // the -1s say which images P anticommutes with. A pauli P' anticommuting with exactly
+        // the same X_i and Z_i, pushed through the reference, is P.
+        //
+        // For a clifford pattern P's bits are an affine function of the raw outcomes, which
+        // the samples pin down: enough seeds that the random outcomes span everything they
+        // can take, then solved for over GF(2) and turned back into domains of adjusted
+        // outcomes, the way corrections are written.
+        let pattern_circuit = self.to_circuit()?;
+        self.check_reference(reference)?;
+        let n = self.inputs.len();
+        let num_qubits = self.graph.num_qubits() + n;
+        let mut gates: Vec<Gate> = vec![];
+        for instruction in reference.instructions() {
+            if let Instruction::Gate(gate) = instruction {
+                gates.push(*gate);
+            }
+        }
+        let generator = |i: usize, x: bool| {
+            let mut generator = PauliString::identity(num_qubits);
+            for qubit in [self.spectator(i), self.outputs[i]] {
+                generator.x_bits[qubit.index()] = x;
+                generator.z_bits[qubit.index()] = !x;
+            }
+            for gate in &gates {
+                generator.conjugate_by(&gate.remap(|q| self.outputs[q.index()]));
+            }
+            generator
+        };
+        let generators: Vec<(PauliString, PauliString)> = (0..n)
+            .map(|i| (generator(i, true), generator(i, false)))
+            .collect();
+
+        // one row per sample: the raw outcomes, a 1 for the constant term, then P's x bits and
+        // z bits.
+        let m = self.measurements.len();
+        let mut samples = vec![];
+        for seed in 0..(m + 32) as u64 {
+            let actual = self.run_on_choi_state(&pattern_circuit, seed)?;
+            let mut flipped = PauliString::identity(n);
+            for (i, (x, z)) in generators.iter().enumerate() {
+                match (actual.pauli_expectation(x)?, actual.pauli_expectation(z)?) {
+                    (0, _) | (_, 0) => return Ok(None),
+                    (x, z) => {
+                        flipped.z_bits[i] = x < 0;
+                        flipped.x_bits[i] = z < 0;
+                    }
+                }
+            }
+            for gate in &gates {
+                flipped.conjugate_by(gate);
+            }
+            let mut row = actual.measurement_record()[..m].to_vec();
+            row.push(true);
+            row.extend(flipped.x_bits.iter().chain(&flipped.z_bits));
+            samples.push(row);
+        }
+        let mut system = BitMatrix::from_rows(&samples)?;
+        let pivots = system.row_reduce();
+        if pivots.iter().any(|&pivot| pivot > m) {
+            // no affine function of the outcomes fits, so it isn't a pauli frame after all.
+            return Ok(None);
+        }
+
+        let mut frame = PauliFrame::default();
+        for bit in 0..2 * n {
+            let output = self.outputs[bit % n];
+            let gate = if bit < n {
+                Gate::X(output)
+            } else {
+                Gate::Z(output)
+            };
+            // free columns are taken as 0, so each pivot row gives its column's coefficient.
+            let mut domain = vec![false; m];
+            for (row, &pivot) in pivots.iter().enumerate() {
+                if !system.get(row, m + 1 + bit) {
+                    continue;
+                }
+                if pivot == m {
+                    frame.constant.push(gate);
+                    continue;
+                }
+                // raw outcome r_i is the adjusted a_i xored with its signal's adjusted outcomes.
+                domain[pivot] ^= true;
+                for &earlier in &self.measurements[pivot].signal {
+                    domain[earlier] ^= true;
+                }
+            }
+            let domain: Vec<usize> = (0..m).filter(|&index| domain[index]).collect();
+            if !domain.is_empty() {
+                frame.corrections.push(PatternCorrection { gate, domain });
+            }
+        }
+        Ok(Some(frame))
+    }
+
+    fn check_reference(&self, reference: &Circuit) -> Result<(), &'static str> {
+        if reference
+            .instructions()
+            .iter()
+            .any(|instruction| !matches!(instruction, Instruction::Gate(_)))
+        {
+            return Err("Reference circuit must only contain gates.");
+        }
+        if reference.num_qubits() > self.inputs.len() {
+            return Err("Reference circuit acts on more qubits than the pattern has inputs.");
+        }
+        Ok(())
+    }
+
+    fn spectator(&self, i: usize) -> Qubit {
+        Qubit((self.graph.num_qubits() + i) as u32)
+    }
+
+    fn run_on_choi_state(
+        &self,
+        pattern_circuit: &Circuit,
+        seed: u64,
+    ) -> Result<StabilizerSimulator, &'static str> {
+        // the pattern run with each input entangled with its spectator, and the measured
+        // qubits reset to |0> afterwards.
+        let num_measurements = self.measurements.len();
+        let mut actual =
+            StabilizerSimulator::new(self.graph.num_qubits() + self.inputs.len(), seed);
+        for (i, &input) in self.inputs.iter().enumerate() {
+            actual.prepare_bell_pair(self.spectator(i), input);
+        }
+        actual.run(pattern_circuit)?;
+        for (index, measurement) in self.measurements.iter().enumerate() {
+            actual.apply_instruction(&Instruction::Conditional {
+                lookback: (num_measurements - index) as u32,
+                gate: Gate::X(measurement.qubit),
+            })?;
+        }
+        Ok(actual)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn wire(length: usize, basis: MeasurementBasis) -> Pattern {
+        // a 1D cluster with the input on one end and the output on the other.
+        let mut measurements = Vec::new();
+        for qubit in Qubit::range(length - 1) {
+            measurements.push(PatternMeasurement {
+                qubit,
+                basis,
+                signal: vec![],
+            });
+        }
+        Pattern {
+            graph: GraphState::linear_cluster(length),
+            inputs: vec![Qubit(0)],
+            outputs: vec![Qubit(length as u32 - 1)],
+            measurements,
+            corrections: vec![],
+        }
+    }
+
+    #[test]
+    fn test_two_qubit_wire_implements_hadamard() {
+        let mut pattern = wire(2, MeasurementBasis::X);
+        let mut hadamard = Circuit::new(1);
+        hadamard.gate(Gate::H(Qubit(0)));
+        // without the byproduct correction it only works when the outcome happens to be 0.
+        assert!(!pattern.implements(&hadamard).unwrap());
+        pattern.corrections.push(PatternCorrection {
+            gate: Gate::X(Qubit(1)),
+            domain: vec![0],
+        });
+        assert!(pattern.implements(&hadamard).unwrap());
+        assert!(!pattern.implements(&Circuit::new(1)).unwrap());
+    }
+
+    #[test]
+    fn test_three_qubit_wire_implements_identity() {
+        let mut pattern = wire(3, MeasurementBasis::X);
+        pattern.corrections = vec![
+            PatternCorrection {
+                gate: Gate::X(Qubit(2)),
+                domain: vec![1],
+            },
+            PatternCorrection {
+                gate: Gate::Z(Qubit(2)),
+                domain: vec![0],
+            },
+        ];
+        assert!(pattern.implements(&Circuit::new(1)).unwrap());
+    }
+
+    #[test]
+    fn test_signal_adjusts_later_outcomes() {
+        // same wire, but the first outcome is folded into the second measurement's signal,
+        // so the adjusted second outcome is r1 ^ r0. The X byproduct on the output is still
+        // X^r1, so its correction has to cancel r0 back out.
+        let mut pattern = wire(3, MeasurementBasis::X);
+        pattern.measurements[1].signal = vec![0];
+        pattern.corrections = vec![
+            PatternCorrection {
+                gate: Gate::X(Qubit(2)),
+                domain: vec![1],
+            },
+            PatternCorrection {
+                gate: Gate::Z(Qubit(2)),
+                domain: vec![0],
+            },
+        ];
+        assert_eq!(pattern.outcomes(&[true, true]), vec![true, false]);
+        assert!(!pattern.implements(&Circuit::new(1)).unwrap());
+        pattern.corrections[0].domain = vec![1, 0];
+        assert!(pattern.implements(&Circuit::new(1)).unwrap());
+    }
+
+    #[test]
+    fn test_pauli_frame_recovers_the_corrections() {
+        // without corrections, the three qubit wire is the identity up to X^r1 Z^r0 on the
+        // output, and the frame says exactly that.
+        let mut pattern = wire(3, MeasurementBasis::X);
+        let identity = Circuit::new(1);
+        assert!(!pattern.implements(&identity).unwrap());
+        let frame = pattern.pauli_frame(&identity).unwrap().unwrap();
+        assert_eq!(
+            frame,
+            PauliFrame {
+                constant: vec![],
+                corrections: vec![
+                    PatternCorrection {
+                        gate: Gate::X(Qubit(2)),
+                        domain: vec![1],
+                    },
+                    PatternCorrection {
+                        gate: Gate::Z(Qubit(2)),
+                        domain: vec![0],
+                    },
+                ],
+            }
+        );
+        pattern.corrections = frame.corrections;
+        assert!(pattern.implements(&identity).unwrap());
+        assert!(pattern.pauli_frame(&identity).unwrap().unwrap().is_empty());
+
+        // with a signal, the domains come back in terms of adjusted outcomes.
+        pattern.corrections.clear();
+        pattern.measurements[1].signal = vec![0];
+        let frame = pattern.pauli_frame(&identity).unwrap().unwrap();
+        assert_eq!(frame.corrections[0].domain, vec![0, 1]);
+        pattern.corrections = frame.corrections;
+        assert!(pattern.implements(&identity).unwrap());
+
+        // against an X, the frame gains a constant X on the output, and a hadamard isn't
+        // a pauli away at all.
+        let mut x = Circuit::new(1);
+        x.gate(Gate::X(Qubit(0)));
+        let frame = pattern.pauli_frame(&x).unwrap().unwrap();
+        assert_eq!(frame.constant, vec![Gate::X(Qubit(2))]);
+        assert!(frame.corrections.is_empty());
+        let mut hadamard = Circuit::new(1);
+        hadamard.gate(Gate::H(Qubit(0)));
+        assert!(!pattern.implements_up_to_pauli(&hadamard).unwrap());
+    }
+
+    #[test]
+    fn test_edge_between_inputs_implements_cz() {
+        let pattern = Pattern {
+            graph: GraphState::new(2, &[(Qubit(0), Qubit(1))]).unwrap(),
+            inputs: vec![Qubit(0), Qubit(1)],
+            outputs: vec![Qubit(0), Qubit(1)],
+            measurements: vec![],
+            corrections: vec![],
+        };
+        let mut cz = Circuit::new(2);
+        cz.gate(Gate::Cz(Qubit(0), Qubit(1)));
+        assert!(pattern.implements(&cz).unwrap());
+    }
+}
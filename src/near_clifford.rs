@@ -0,0 +1,514 @@
+use crate::complex::Complex;
+use crate::gates::Gate;
+use crate::pauli::PauliString;
+use crate::qubit::Qubit;
+use crate::sampler::shot_seed;
+use rand::{Rng, SeedableRng};
+use std::collections::BTreeMap;
+use std::f64::consts::{FRAC_1_SQRT_2, FRAC_PI_8};
+
+// weak simulation of Clifford+T circuits with a few T gates, by stabilizer rank.
+// Each T splits into two Clifford branches,
+//
+//     T = a I + b Z,    a = (1 + e^(i pi/4))/2,  b = (1 - e^(i pi/4))/2,
+//
+// so a circuit with t T gates prepares a sum of 2^t stabilizer states. Rather than keeping
+// every branch, we sparsify (Bravyi & Gosset): sample L branches with probability
+// proportional to |coefficient|. The resulting state is within roughly ||c||_1 / sqrt(L) of
+// the true one, so the accuracy budget epsilon sets L = ||c||_1^2 / epsilon^2, with
+// ||c||_1 = (cos(pi/8) + sin(pi/8))^t.
+//
+// Every branch is the Clifford part C of the circuit with some Zs slotted in, and pulling
+// those Zs back to the start turns each branch into C|x> for a basis state x (up to phase).
+// So the sparsified state is C|chi>, with chi a sparse vector over at most L basis states.
+// Shots measure qubit by qubit: Z_q on C|chi> is the pauli C^dagger Z_q C on chi, and a
+// handful of Clifford gates on chi turn that into a single Z, whose exact marginal is a sum
+// over chi. Nothing is ever tabulated over all 2^n basis states.
+pub const DEFAULT_MAX_NEAR_CLIFFORD_TERMS: usize = 1 << 20;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum NearCliffordGate {
+    Clifford(Gate),
+    T(Qubit),
+}
+
+// the sparsified state's chi, by basis state. BTreeMap so shots don't depend on hash order.
+type Amplitudes = BTreeMap<Vec<bool>, Complex>;
+
+// amplitudes smaller than this are interference leftovers, and are dropped so chi stays sparse.
+const NEGLIGIBLE_NORM_SQUARED: f64 = 1e-24;
+
+pub struct NearCliffordSampler {
+    chi: Amplitudes,
+    // C^dagger Z_q C for each qubit q, what measuring q at the end reads out of chi.
+    observables: Vec<PauliString>,
+    num_terms: usize,
+    // each shot draws from its own rng, seeded by shot_seed(seed, shot index).
+    seed: u64,
+    next_shot: u64,
+}
+
+impl NearCliffordSampler {
+    pub fn new(
+        num_qubits: usize,
+        gates: &[NearCliffordGate],
+        epsilon: f64,
+        seed: u64,
+    ) -> Result<NearCliffordSampler, &'static str> {
+        NearCliffordSampler::with_max_terms(
+            num_qubits,
+            gates,
+            epsilon,
+            seed,
+            DEFAULT_MAX_NEAR_CLIFFORD_TERMS,
+        )
+    }
+
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(
+            level = "debug",
+            skip_all,
+            fields(
+                num_qubits,
+                gates = gates.len(),
+                epsilon,
+                max_terms,
+            )
+        )
+    )]
+    pub fn with_max_terms(
+        num_qubits: usize,
+        gates: &[NearCliffordGate],
+        epsilon: f64,
+        seed: u64,
+        max_terms: usize,
+    ) -> Result<NearCliffordSampler, &'static str> {
+        // like new, but errs rather than keeping more than max_terms branches, since the
+        // count grows exponentially with the number of T gates.
+        if epsilon.is_nan() || epsilon <= 0.0 {
+            return Err("Accuracy budget epsilon must be positive.");
+        }
+        let qubits = gates.iter().flat_map(|gate| match *gate {
+            NearCliffordGate::Clifford(gate) => gate.qubits(),
+            NearCliffordGate::T(qubit) => vec![qubit],
+        });
+        if qubits.into_iter().any(|q| q.index() >= num_qubits) {
+            return Err("Gate acts on a qubit outside the register.");
+        }
+        let omega = Complex {
+            re: FRAC_1_SQRT_2,
+            im: FRAC_1_SQRT_2,
+        };
+        let identity_coefficient = (Complex::ONE + omega).scale(0.5);
+        let z_coefficient = (Complex::ONE + omega.scale(-1.0)).scale(0.5);
+        let unit = |c: Complex| c.scale(1.0 / c.norm_squared().sqrt());
+        let probability_of_identity = FRAC_PI_8.cos() / (FRAC_PI_8.cos() + FRAC_PI_8.sin());
+
+        // the Clifford part, and each T's Z pulled back through the Cliffords before it.
+        let mut cliffords = Vec::new();
+        let mut pulled_back_zs = Vec::new();
+        for gate in gates {
+            match *gate {
+                NearCliffordGate::Clifford(gate) => cliffords.push(gate),
+                NearCliffordGate::T(qubit) => {
+                    let mut z = PauliString::z(num_qubits, qubit);
+                    pull_back(&mut z, &cliffords);
+                    pulled_back_zs.push(z);
+                }
+            }
+        }
+        let num_t_gates = pulled_back_zs.len();
+        let l1_norm = (FRAC_PI_8.cos() + FRAC_PI_8.sin()).powi(num_t_gates as i32);
+        let num_terms = if num_t_gates == 0 {
+            1.0
+        } else {
+            (l1_norm * l1_norm / (epsilon * epsilon)).ceil()
+        };
+        if num_terms > max_terms as f64 {
+            return Err(
+                "Too many stabilizer terms for the accuracy budget; raise epsilon or max_terms.",
+            );
+        }
+        let num_terms = num_terms as usize;
+
+        let mut rand: rand::rngs::StdRng = SeedableRng::seed_from_u64(seed);
+        let mut chi = Amplitudes::new();
+        for _ in 0..num_terms {
+            let mut bits = vec![false; num_qubits];
+            let mut phase = Complex::ONE;
+            for z in &pulled_back_zs {
+                if rand.gen_bool(probability_of_identity) {
+                    phase = phase * unit(identity_coefficient);
+                } else {
+                    phase = phase * unit(z_coefficient) * apply_pauli(z, &mut bits);
+                }
+            }
+            let amplitude = chi.entry(bits).or_insert(Complex::ZERO);
+            *amplitude = *amplitude + phase;
+        }
+        // the sparsified state isn't normalized, so normalize chi instead.
+        if normalize(&mut chi) <= 0.0 {
+            return Err("Sparsified state vanished; try a smaller epsilon.");
+        }
+
+        let observables = (0..num_qubits)
+            .map(|q| {
+                let mut z = PauliString::z(num_qubits, Qubit(q as u32));
+                pull_back(&mut z, &cliffords);
+                z
+            })
+            .collect();
+        #[cfg(feature = "tracing")]
+        tracing::debug!(
+            num_terms,
+            basis_states = chi.len(),
+            "sparsified stabilizer decomposition"
+        );
+        Ok(NearCliffordSampler {
+            chi,
+            observables,
+            num_terms,
+            seed,
+            next_shot: 0,
+        })
+    }
+
+    pub fn num_terms(&self) -> usize {
+        // how many stabilizer states the sparsified decomposition kept.
+        self.num_terms
+    }
+
+    pub fn sample(&mut self) -> Vec<bool> {
+        // one shot of measuring every qubit in the Z basis at the end of the circuit.
+        let mut rand: rand::rngs::StdRng =
+            SeedableRng::seed_from_u64(shot_seed(self.seed, self.next_shot));
+        self.next_shot += 1;
+        let mut chi = self.chi.clone();
+        let mut observables = self.observables.clone();
+        let mut shot = Vec::with_capacity(observables.len());
+        for q in 0..observables.len() {
+            // if the observable flips basis states, rotate chi (and the observables still to
+            // come) until it's a single Z, which is diagonal.
+            let frame = diagonalize(&observables[q]);
+            for gate in &frame {
+                apply_gate(gate, &mut chi);
+                for observable in &mut observables[q..] {
+                    observable.conjugate_by(gate);
+                }
+            }
+            let observable = &observables[q];
+            let reads_one = |bits: &[bool]| {
+                let parity = (0..bits.len()).filter(|&j| observable.z_bits[j] && bits[j]);
+                observable.phase_is_negated ^ (parity.count() % 2 == 1)
+            };
+            let probability_of_one: f64 = chi
+                .iter()
+                .filter(|(bits, _)| reads_one(bits))
+                .map(|(_, amplitude)| amplitude.norm_squared())
+                .sum();
+            let outcome = rand.gen::<f64>() < probability_of_one;
+            chi.retain(|bits, _| reads_one(bits) == outcome);
+            normalize(&mut chi);
+            shot.push(outcome);
+        }
+        shot
+    }
+}
+
+fn pull_back(pauli: &mut PauliString, cliffords: &[Gate]) {
+    // P -> C^dagger P C, undoing conjugate_by gate by gate. S is the only gate here that
+    // isn't its own inverse, and S^dagger = S^3.
+    for gate in cliffords.iter().rev() {
+        let repeats = if matches!(gate, Gate::S(_)) { 3 } else { 1 };
+        for _ in 0..repeats {
+            pauli.conjugate_by(gate);
+        }
+    }
+}
+
+fn apply_pauli(pauli: &PauliString, bits: &mut [bool]) -> Complex {
+    // P|bits> = phase |bits'>: updates bits in place and returns the phase. Y = iXZ, so Z
+    // goes first.
+    let mut phase = if pauli.phase_is_negated {
+        Complex::ONE.scale(-1.0)
+    } else {
+        Complex::ONE
+    };
+    for (j, bit) in bits.iter_mut().enumerate() {
+        if pauli.z_bits[j] && *bit {
+            phase = phase.scale(-1.0);
+        }
+        if pauli.x_bits[j] && pauli.z_bits[j] {
+            phase = phase * Complex::I;
+        }
+        *bit ^= pauli.x_bits[j];
+    }
+    phase
+}
+
+fn diagonalize(observable: &PauliString) -> Vec<Gate> {
+    // Clifford gates V with V P V^dagger = +-Z_p: CXs gather the X part onto one qubit p, an
+    // S turns a Y there into an X, CZs clear the remaining Zs, and H makes the X a Z.
+    let Some(p) = observable.x_bits.iter().position(|&x| x) else {
+        return Vec::new();
+    };
+    let mut pauli = observable.clone();
+    let mut frame = Vec::new();
+    let mut push = |gate: Gate, pauli: &mut PauliString| {
+        pauli.conjugate_by(&gate);
+        frame.push(gate);
+    };
+    let qubit = |j: usize| Qubit(j as u32);
+    let spread: Vec<usize> = (0..pauli.num_qubits())
+        .filter(|&j| j != p && pauli.x_bits[j])
+        .collect();
+    for j in spread {
+        push(Gate::Cx(qubit(p), qubit(j)), &mut pauli);
+    }
+    if pauli.z_bits[p] {
+        push(Gate::S(qubit(p)), &mut pauli);
+    }
+    let phased: Vec<usize> = (0..pauli.num_qubits())
+        .filter(|&j| j != p && pauli.z_bits[j])
+        .collect();
+    for j in phased {
+        push(Gate::Cz(qubit(p), qubit(j)), &mut pauli);
+    }
+    push(Gate::H(qubit(p)), &mut pauli);
+    frame
+}
+
+fn apply_gate(gate: &Gate, chi: &mut Amplitudes) {
+    // gate * chi, for the Cliffords diagonalize uses. Only H can change how many basis
+    // states chi has.
+    let phases = |chi: &mut Amplitudes, phase: &dyn Fn(&[bool]) -> Complex| {
+        for (bits, amplitude) in chi.iter_mut() {
+            *amplitude = *amplitude * phase(bits);
+        }
+    };
+    match *gate {
+        Gate::H(q) => {
+            let q = q.index();
+            let mut rotated = Amplitudes::new();
+            for (bits, amplitude) in std::mem::take(chi) {
+                let sign = if bits[q] {
+                    -FRAC_1_SQRT_2
+                } else {
+                    FRAC_1_SQRT_2
+                };
+                for (value, scale) in [(false, FRAC_1_SQRT_2), (true, sign)] {
+                    let mut bits = bits.clone();
+                    bits[q] = value;
+                    let entry = rotated.entry(bits).or_insert(Complex::ZERO);
+                    *entry = *entry + amplitude.scale(scale);
+                }
+            }
+            rotated.retain(|_, amplitude| amplitude.norm_squared() > NEGLIGIBLE_NORM_SQUARED);
+            *chi = rotated;
+        }
+        Gate::S(q) => phases(chi, &|bits| {
+            if bits[q.index()] {
+                Complex::I
+            } else {
+                Complex::ONE
+            }
+        }),
+        Gate::Cz(a, b) => phases(chi, &|bits| {
+            if bits[a.index()] && bits[b.index()] {
+                Complex::ONE.scale(-1.0)
+            } else {
+                Complex::ONE
+            }
+        }),
+        Gate::Cx(control, target) => {
+            *chi = std::mem::take(chi)
+                .into_iter()
+                .map(|(mut bits, amplitude)| {
+                    bits[target.index()] ^= bits[control.index()];
+                    (bits, amplitude)
+                })
+                .collect();
+        }
+        _ => unreachable!("diagonalize only emits H, S, CX and CZ"),
+    }
+}
+
+fn normalize(chi: &mut Amplitudes) -> f64 {
+    // rescales chi to unit norm, returning the squared norm it had.
+    let norm_squared: f64 = chi.values().map(|amplitude| amplitude.norm_squared()).sum();
+    if norm_squared > 0.0 {
+        for amplitude in chi.values_mut() {
+            *amplitude = amplitude.scale(1.0 / norm_squared.sqrt());
+        }
+    }
+    norm_squared
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn frequency_of_zero(sampler: &mut NearCliffordSampler, shots: usize) -> f64 {
+        (0..shots).filter(|_| !sampler.sample()[0]).count() as f64 / shots as f64
+    }
+
+    #[test]
+    fn test_single_t_gate() {
+        // H T H |0> measures 0 with probability cos^2(pi/8) ~ 0.854.
+        let gates = [
+            NearCliffordGate::Clifford(Gate::H(Qubit(0))),
+            NearCliffordGate::T(Qubit(0)),
+            NearCliffordGate::Clifford(Gate::H(Qubit(0))),
+        ];
+        let mut sampler = NearCliffordSampler::new(1, &gates, 0.05, 0).unwrap();
+        let expected = FRAC_PI_8.cos().powi(2);
+        assert!((frequency_of_zero(&mut sampler, 4000) - expected).abs() < 0.05);
+
+        // S H T S H |0>: the Z branch pulls back to a Y, whose phase decides this one.
+        let gates = [
+            NearCliffordGate::Clifford(Gate::S(Qubit(0))),
+            NearCliffordGate::Clifford(Gate::H(Qubit(0))),
+            NearCliffordGate::T(Qubit(0)),
+            NearCliffordGate::Clifford(Gate::S(Qubit(0))),
+            NearCliffordGate::Clifford(Gate::H(Qubit(0))),
+        ];
+        let mut sampler = NearCliffordSampler::new(1, &gates, 0.05, 0).unwrap();
+        let expected = FRAC_PI_8.sin().powi(2);
+        assert!((frequency_of_zero(&mut sampler, 4000) - expected).abs() < 0.05);
+    }
+
+    #[test]
+    fn test_two_t_gates_make_s() {
+        // T T == S, and H S H |0> is 50/50.
+        let gates = [
+            NearCliffordGate::Clifford(Gate::H(Qubit(0))),
+            NearCliffordGate::T(Qubit(0)),
+            NearCliffordGate::T(Qubit(0)),
+            NearCliffordGate::Clifford(Gate::H(Qubit(0))),
+        ];
+        let mut sampler = NearCliffordSampler::new(1, &gates, 0.05, 1).unwrap();
+        assert!((frequency_of_zero(&mut sampler, 4000) - 0.5).abs() < 0.05);
+    }
+
+    #[test]
+    fn test_clifford_circuit_needs_one_term() {
+        let gates = [
+            NearCliffordGate::Clifford(Gate::H(Qubit(0))),
+            NearCliffordGate::Clifford(Gate::Cx(Qubit(0), Qubit(1))),
+        ];
+        let mut sampler = NearCliffordSampler::new(2, &gates, 0.1, 0).unwrap();
+        assert_eq!(sampler.num_terms(), 1);
+        for _ in 0..20 {
+            let shot = sampler.sample();
+            assert_eq!(shot[0], shot[1]);
+        }
+        assert!(NearCliffordSampler::new(2, &gates, 0.0, 0).is_err());
+    }
+
+    #[test]
+    fn test_wide_register() {
+        // H T H on qubit 0, then fanned out into a 40 qubit GHZ-like state: far too many
+        // qubits to tabulate, but every shot is still all zeros or all ones.
+        let mut gates = vec![
+            NearCliffordGate::Clifford(Gate::H(Qubit(0))),
+            NearCliffordGate::T(Qubit(0)),
+            NearCliffordGate::Clifford(Gate::H(Qubit(0))),
+        ];
+        for q in 1..40 {
+            gates.push(NearCliffordGate::Clifford(Gate::Cx(Qubit(0), Qubit(q))));
+        }
+        let mut sampler = NearCliffordSampler::new(40, &gates, 0.05, 2).unwrap();
+        let mut zeros = 0;
+        for _ in 0..2000 {
+            let shot = sampler.sample();
+            assert!(shot.iter().all(|&bit| bit == shot[0]));
+            zeros += !shot[0] as usize;
+        }
+        let expected = FRAC_PI_8.cos().powi(2);
+        assert!((zeros as f64 / 2000.0 - expected).abs() < 0.05);
+    }
+
+    #[test]
+    fn test_random_circuits_match_dense_distribution() {
+        // the exact distribution, from expanding both T gates into their four branches on
+        // the dense simulator, against shots from a tight epsilon.
+        use crate::dense::DenseSimulator;
+        let mut rng: rand::rngs::StdRng = SeedableRng::seed_from_u64(5);
+        let omega = Complex {
+            re: FRAC_1_SQRT_2,
+            im: FRAC_1_SQRT_2,
+        };
+        let coefficients = [
+            (Complex::ONE + omega).scale(0.5),
+            (Complex::ONE + omega.scale(-1.0)).scale(0.5),
+        ];
+        for seed in 0..4 {
+            let mut gates = Vec::new();
+            for step in 0..16 {
+                let a = Qubit(rng.gen_range(0..3));
+                let b = Qubit((a.0 + rng.gen_range(1..3)) % 3);
+                gates.push(match step {
+                    5 | 10 => NearCliffordGate::T(a),
+                    _ => NearCliffordGate::Clifford(match rng.gen_range(0..4) {
+                        0 => Gate::H(a),
+                        1 => Gate::S(a),
+                        2 => Gate::Cx(a, b),
+                        _ => Gate::Cz(a, b),
+                    }),
+                });
+            }
+            let mut expected = [Complex::ZERO; 8];
+            for branch in 0..4 {
+                let mut dense = DenseSimulator::new(3);
+                let mut coefficient = Complex::ONE;
+                let mut t_gates = 0;
+                for gate in &gates {
+                    match *gate {
+                        NearCliffordGate::Clifford(gate) => dense.apply_gate(&gate),
+                        NearCliffordGate::T(qubit) => {
+                            let z = branch >> t_gates & 1;
+                            coefficient = coefficient * coefficients[z];
+                            if z == 1 {
+                                dense.apply_gate(&Gate::Z(qubit));
+                            }
+                            t_gates += 1;
+                        }
+                    }
+                }
+                for (index, amplitude) in expected.iter_mut().enumerate() {
+                    *amplitude = *amplitude + coefficient * dense.amplitude(index);
+                }
+            }
+            let mut sampler = NearCliffordSampler::new(3, &gates, 0.02, seed).unwrap();
+            let mut counts = [0usize; 8];
+            for _ in 0..20000 {
+                let shot = sampler.sample();
+                counts[(0..3).filter(|&q| shot[q]).map(|q| 1 << q).sum::<usize>()] += 1;
+            }
+            let distance: f64 = (0..8)
+                .map(|index| {
+                    (counts[index] as f64 / 20000.0 - expected[index].norm_squared()).abs()
+                })
+                .sum();
+            assert!(distance < 0.06, "seed {seed}: {distance}");
+        }
+    }
+
+    #[test]
+    fn test_term_limit() {
+        let gates: Vec<_> = (0..80).map(|_| NearCliffordGate::T(Qubit(0))).collect();
+        assert!(NearCliffordSampler::new(1, &gates, 0.01, 0).is_err());
+        let gates = [NearCliffordGate::T(Qubit(0))];
+        assert!(NearCliffordSampler::with_max_terms(1, &gates, 0.01, 0, 100).is_err());
+        assert!(NearCliffordSampler::with_max_terms(1, &gates, 0.1, 0, 1000).is_ok());
+        assert!(
+            NearCliffordSampler::new(1, &gates, 0.1, 0)
+                .unwrap()
+                .sample()
+                .len()
+                == 1
+        );
+        assert!(NearCliffordSampler::new(1, &[NearCliffordGate::T(Qubit(1))], 0.1, 0).is_err());
+    }
+}
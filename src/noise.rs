@@ -0,0 +1,486 @@
+use crate::circuit::{Circuit, Instruction};
+use crate::coupling_map::CouplingMap;
+use crate::gates::Gate;
+use crate::qubit::Qubit;
+use rand::Rng;
+use std::collections::{BTreeMap, HashMap};
+
+// circuit-level pauli noise. After every gate, each qubit it touched is hit by a depolarizing
+// channel: with probability p, a uniformly random non-identity pauli on the gate's qubits
+// (one of 3 for single-qubit gates, one of 15 for two-qubit gates). Measurement results are
+// reported flipped with probability measurement_flip, without disturbing the collapsed state.
+// A PauliChannel for single- or two-qubit gates, if set, replaces the uniform depolarization
+// for gates of that size, e.g. the pauli twirl of a physical channel (see twirl).
+//
+// Qubits that sit out a whole moment are depolarized with probability idle_depolarization at
+// the TICK that ends it.
+//
+// Per-qubit rates, if there are any, take over from these for the qubits and pairs they
+// cover, and crosstalk comes on top (see PerQubitRates and Crosstalk).
+#[derive(Debug, Clone, PartialEq)]
+pub struct NoiseModel {
+    pub single_qubit_depolarization: f64,
+    pub two_qubit_depolarization: f64,
+    pub measurement_flip: f64,
+    pub idle_depolarization: f64,
+    pub single_qubit_channel: Option<PauliChannel>,
+    pub two_qubit_channel: Option<PauliChannel>,
+    pub per_qubit: Option<PerQubitRates>,
+    pub crosstalk: Option<Crosstalk>,
+}
+
+// a pauli channel on one or two qubits: pauli P happens with probability probabilities[P],
+// with paulis numbered like pauli_error does (two bits per qubit, 1 for X, 2 for Z, 3 for Y),
+// so entry 0 is the chance nothing happens.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PauliChannel {
+    num_qubits: usize,
+    probabilities: Vec<f64>,
+}
+
+impl PauliChannel {
+    pub fn new(num_qubits: usize, probabilities: Vec<f64>) -> Result<PauliChannel, &'static str> {
+        if !(1..=2).contains(&num_qubits) {
+            return Err("Pauli channels act on one or two qubits.");
+        }
+        if probabilities.len() != num_paulis(num_qubits) as usize {
+            return Err("A pauli channel needs one probability per pauli, 4^k in all.");
+        }
+        if probabilities.iter().any(|&p| !(0.0..=1.0).contains(&p))
+            || (probabilities.iter().sum::<f64>() - 1.0).abs() > 1e-9
+        {
+            return Err("Pauli channel probabilities must be between 0 and 1 and sum to 1.");
+        }
+        Ok(PauliChannel {
+            num_qubits,
+            probabilities,
+        })
+    }
+
+    pub fn depolarizing(num_qubits: usize, p: f64) -> Result<PauliChannel, &'static str> {
+        let num_errors = num_paulis(num_qubits.min(2)) - 1;
+        let mut probabilities = vec![p / num_errors as f64; num_errors as usize + 1];
+        probabilities[0] = 1.0 - p;
+        PauliChannel::new(num_qubits, probabilities)
+    }
+
+    pub fn num_qubits(&self) -> usize {
+        self.num_qubits
+    }
+
+    pub fn probabilities(&self) -> &[f64] {
+        &self.probabilities
+    }
+
+    pub fn error_probability(&self) -> f64 {
+        1.0 - self.probabilities[0]
+    }
+}
+
+// rates that differ from qubit to qubit, like a calibrated device has (see
+// calibration::NoiseModelBuilder). Pairs are keyed with the smaller qubit first.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct PerQubitRates {
+    pub single_qubit_depolarization: BTreeMap<Qubit, f64>,
+    pub two_qubit_depolarization: BTreeMap<(Qubit, Qubit), f64>,
+    pub measurement_flip: BTreeMap<Qubit, f64>,
+    pub idle_depolarization: BTreeMap<Qubit, f64>,
+}
+
+// residual ZZ coupling between neighbors on a device. After every two-qubit gate, each of its
+// qubits picks up a correlated ZZ error with each spectator, a neighbor in the coupling map
+// that isn't part of the gate, with probability zz per pair. Spectators are whatever is
+// nearby; whether they're busy with another gate in the same moment isn't checked.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Crosstalk {
+    pub coupling_map: CouplingMap,
+    pub zz: f64,
+}
+
+// how much noise a model puts on a particular circuit: the number of places an error can
+// happen, by kind, counting only those with a nonzero chance, and the number of errors a shot
+// sees on average (the sum of all their probabilities).
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct NoiseTotals {
+    pub gate_channels: usize,
+    pub crosstalk_channels: usize,
+    pub measurement_flips: usize,
+    pub idle_channels: usize,
+    pub expected_errors: f64,
+}
+
+impl NoiseModel {
+    pub fn noiseless() -> NoiseModel {
+        NoiseModel::uniform_depolarizing(0.0)
+    }
+
+    pub fn uniform_depolarizing(p: f64) -> NoiseModel {
+        NoiseModel {
+            single_qubit_depolarization: p,
+            two_qubit_depolarization: p,
+            measurement_flip: p,
+            idle_depolarization: 0.0,
+            single_qubit_channel: None,
+            two_qubit_channel: None,
+            per_qubit: None,
+            crosstalk: None,
+        }
+    }
+
+    pub fn with_idle_depolarization(mut self, p: f64) -> NoiseModel {
+        self.idle_depolarization = p;
+        self
+    }
+
+    pub fn with_crosstalk(mut self, coupling_map: CouplingMap, zz: f64) -> NoiseModel {
+        self.crosstalk = Some(Crosstalk { coupling_map, zz });
+        self
+    }
+
+    pub(crate) fn crosstalk_pairs(&self, gate: &Gate) -> Vec<(Qubit, Qubit)> {
+        // the (gate qubit, spectator) pairs crosstalk can hit after the gate.
+        let Some(crosstalk) = &self.crosstalk else {
+            return vec![];
+        };
+        let qubits = gate.qubits();
+        if qubits.len() != 2 || crosstalk.zz <= 0.0 {
+            return vec![];
+        }
+        let mut pairs = Vec::new();
+        for &qubit in &qubits {
+            if qubit.index() >= crosstalk.coupling_map.num_qubits() {
+                continue;
+            }
+            for &spectator in crosstalk.coupling_map.neighbors(qubit) {
+                if !qubits.contains(&spectator) {
+                    pairs.push((qubit, spectator));
+                }
+            }
+        }
+        pairs
+    }
+
+    pub(crate) fn crosstalk_probability(&self) -> f64 {
+        self.crosstalk
+            .as_ref()
+            .map_or(0.0, |crosstalk| crosstalk.zz)
+    }
+
+    pub fn with_channel(mut self, channel: PauliChannel) -> NoiseModel {
+        match channel.num_qubits() {
+            1 => self.single_qubit_channel = Some(channel),
+            _ => self.two_qubit_channel = Some(channel),
+        }
+        self
+    }
+
+    fn gate_channel(&self, gate: &Gate) -> Option<&PauliChannel> {
+        // the pauli channel after the gate, unless per-qubit rates cover it.
+        let qubits = gate.qubits();
+        let per_qubit = self.per_qubit.as_ref();
+        let (overridden, channel) = match qubits[..] {
+            [qubit] => (
+                per_qubit
+                    .is_some_and(|rates| rates.single_qubit_depolarization.contains_key(&qubit)),
+                &self.single_qubit_channel,
+            ),
+            [a, b] => (
+                per_qubit.is_some_and(|rates| {
+                    rates
+                        .two_qubit_depolarization
+                        .contains_key(&(a.min(b), a.max(b)))
+                }),
+                &self.two_qubit_channel,
+            ),
+            _ => (false, &None),
+        };
+        channel.as_ref().filter(|_| !overridden)
+    }
+
+    pub(crate) fn gate_error_probability(&self, gate: &Gate) -> f64 {
+        // the chance of some pauli error right after the gate.
+        if let Some(channel) = self.gate_channel(gate) {
+            return channel.error_probability();
+        }
+        let qubits = gate.qubits();
+        let per_qubit = self.per_qubit.as_ref();
+        match qubits[..] {
+            [qubit] => per_qubit
+                .and_then(|rates| rates.single_qubit_depolarization.get(&qubit))
+                .copied()
+                .unwrap_or(self.single_qubit_depolarization),
+            [a, b] => per_qubit
+                .and_then(|rates| rates.two_qubit_depolarization.get(&(a.min(b), a.max(b))))
+                .copied()
+                .unwrap_or(self.two_qubit_depolarization),
+            _ => self.two_qubit_depolarization,
+        }
+    }
+
+    pub(crate) fn gate_errors(&self, gate: &Gate) -> Vec<(u32, f64)> {
+        // every pauli error the gate can be followed by, numbered like pauli_error does, with
+        // its probability.
+        if let Some(channel) = self.gate_channel(gate) {
+            return (1..)
+                .zip(channel.probabilities()[1..].iter().copied())
+                .collect();
+        }
+        let num_errors = num_paulis(gate.qubits().len()) - 1;
+        let p = self.gate_error_probability(gate) / num_errors as f64;
+        (1..=num_errors).map(|pauli| (pauli, p)).collect()
+    }
+
+    pub(crate) fn measurement_flip_probability(&self, qubit: Qubit) -> f64 {
+        self.per_qubit
+            .as_ref()
+            .and_then(|rates| rates.measurement_flip.get(&qubit))
+            .copied()
+            .unwrap_or(self.measurement_flip)
+    }
+
+    pub(crate) fn errors_after(&self, instruction: &Instruction, rng: &mut impl Rng) -> Vec<Gate> {
+        // the pauli gates to apply right after an instruction, to model its noise.
+        let Instruction::Gate(gate) = instruction else {
+            return vec![];
+        };
+        let mut errors = Vec::new();
+        let p = self.gate_error_probability(gate);
+        if p > 0.0 && rng.gen_bool(p.min(1.0)) {
+            let qubits = gate.qubits();
+            let pauli = match self.gate_channel(gate) {
+                Some(channel) => {
+                    // which error, given that there is one.
+                    let mut r = rng.gen_range(0.0..p);
+                    let errors = &channel.probabilities()[1..];
+                    let chosen = errors.iter().position(|&q| {
+                        r -= q;
+                        r < 0.0
+                    });
+                    chosen.unwrap_or(errors.len() - 1) as u32 + 1
+                }
+                None => rng.gen_range(1..num_paulis(qubits.len())),
+            };
+            errors = pauli_error(&qubits, pauli);
+        }
+        for (qubit, spectator) in self.crosstalk_pairs(gate) {
+            if rng.gen_bool(self.crosstalk_probability().min(1.0)) {
+                errors.extend([Gate::Z(qubit), Gate::Z(spectator)]);
+            }
+        }
+        errors
+    }
+
+    pub(crate) fn idle_probability(&self, qubit: Qubit) -> f64 {
+        self.per_qubit
+            .as_ref()
+            .and_then(|rates| rates.idle_depolarization.get(&qubit))
+            .copied()
+            .unwrap_or(self.idle_depolarization)
+    }
+
+    pub(crate) fn idle_errors(&self, idle: &[Qubit], rng: &mut impl Rng) -> Vec<Gate> {
+        // the paulis to apply to the qubits a TICK finds idle.
+        let mut errors = Vec::new();
+        for &qubit in idle {
+            let p = self.idle_probability(qubit);
+            if p > 0.0 && rng.gen_bool(p.min(1.0)) {
+                errors.extend(pauli_error(&[qubit], rng.gen_range(1..4)));
+            }
+        }
+        errors
+    }
+
+    pub(crate) fn flips_measurement(&self, qubit: Qubit, rng: &mut impl Rng) -> bool {
+        let p = self.measurement_flip_probability(qubit);
+        p > 0.0 && rng.gen_bool(p.min(1.0))
+    }
+
+    pub fn totals(&self, circuit: &Circuit) -> NoiseTotals {
+        let mut totals = NoiseTotals::default();
+        let mut count = |p: f64, channels: fn(&mut NoiseTotals) -> &mut usize| {
+            if p > 0.0 {
+                *channels(&mut totals) += 1;
+                totals.expected_errors += p;
+            }
+        };
+        for qubits in idle_qubits(circuit).values() {
+            for &qubit in qubits {
+                count(self.idle_probability(qubit), |t| &mut t.idle_channels);
+            }
+        }
+        for instruction in circuit.instructions() {
+            match instruction {
+                Instruction::Gate(gate) => {
+                    count(self.gate_error_probability(gate), |t| &mut t.gate_channels);
+                    for _ in self.crosstalk_pairs(gate) {
+                        count(self.crosstalk_probability(), |t| &mut t.crosstalk_channels);
+                    }
+                }
+                Instruction::Measure(qubit) | Instruction::MeasureToBit { qubit, .. } => {
+                    count(self.measurement_flip_probability(*qubit), |t| {
+                        &mut t.measurement_flips
+                    })
+                }
+                _ => {}
+            }
+        }
+        totals
+    }
+}
+
+pub(crate) fn idle_qubits(circuit: &Circuit) -> HashMap<usize, Vec<Qubit>> {
+    // the qubits each TICK finds idle, by the TICK's instruction index: the ones nothing has
+    // touched since the TICK before.
+    let mut idle = HashMap::new();
+    let mut busy = vec![false; circuit.num_qubits()];
+    for (index, instruction) in circuit.instructions().iter().enumerate() {
+        if let Instruction::Tick = instruction {
+            let qubits = Qubit::range(circuit.num_qubits())
+                .filter(|qubit| !busy[qubit.index()])
+                .collect();
+            idle.insert(index, qubits);
+            busy.fill(false);
+        }
+        for qubit in instruction.qubits() {
+            busy[qubit.index()] = true;
+        }
+    }
+    idle
+}
+
+pub(crate) fn num_paulis(num_qubits: usize) -> u32 {
+    // 4^k paulis on k qubits, the identity included.
+    1u32 << (2 * num_qubits)
+}
+
+pub(crate) fn pauli_error(qubits: &[Qubit], pauli: u32) -> Vec<Gate> {
+    // pauli number `pauli` on the given qubits, two bits per qubit: 1 is X, 2 is Z and 3 is Y.
+    qubits
+        .iter()
+        .enumerate()
+        .filter_map(|(i, &qubit)| match (pauli >> (2 * i)) & 3 {
+            1 => Some(Gate::X(qubit)),
+            2 => Some(Gate::Z(qubit)),
+            3 => Some(Gate::Y(qubit)),
+            _ => None,
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use rand::SeedableRng;
+
+    #[test]
+    fn test_errors_stay_on_the_gate_qubits() {
+        let mut rng = rand::rngs::StdRng::seed_from_u64(0);
+        let always = NoiseModel::uniform_depolarizing(1.0);
+        for _ in 0..100 {
+            let errors =
+                always.errors_after(&Instruction::Gate(Gate::Cx(Qubit(2), Qubit(5))), &mut rng);
+            assert!(!errors.is_empty());
+            assert!(errors
+                .iter()
+                .all(|error| [Qubit(2), Qubit(5)].contains(&error.qubits()[0])));
+        }
+        assert!(always.errors_after(&Instruction::Tick, &mut rng).is_empty());
+        let noiseless = NoiseModel::noiseless();
+        assert!(noiseless
+            .errors_after(&Instruction::Gate(Gate::H(Qubit(0))), &mut rng)
+            .is_empty());
+        assert!(!noiseless.flips_measurement(Qubit(0), &mut rng));
+    }
+
+    #[test]
+    fn test_crosstalk_hits_spectators() {
+        // on a line of 4, a CX on 1 and 2 has spectators 0 and 3.
+        let mut rng = rand::rngs::StdRng::seed_from_u64(0);
+        let noise = NoiseModel::noiseless().with_crosstalk(CouplingMap::line(4), 1.0);
+        let cx = Instruction::Gate(Gate::Cx(Qubit(1), Qubit(2)));
+        assert_eq!(
+            noise.errors_after(&cx, &mut rng),
+            vec![
+                Gate::Z(Qubit(1)),
+                Gate::Z(Qubit(0)),
+                Gate::Z(Qubit(2)),
+                Gate::Z(Qubit(3))
+            ]
+        );
+        assert!(noise
+            .errors_after(&Instruction::Gate(Gate::H(Qubit(1))), &mut rng)
+            .is_empty());
+        assert!(NoiseModel::noiseless()
+            .with_crosstalk(CouplingMap::line(4), 0.0)
+            .errors_after(&cx, &mut rng)
+            .is_empty());
+    }
+
+    #[test]
+    fn test_idle_qubits_per_moment() {
+        let mut circuit = Circuit::new(3);
+        circuit
+            .gate(Gate::H(Qubit(0)))
+            .tick()
+            .gate(Gate::Cx(Qubit(1), Qubit(2)))
+            .tick()
+            .tick();
+        let idle = idle_qubits(&circuit);
+        assert_eq!(idle[&1], vec![Qubit(1), Qubit(2)]);
+        assert_eq!(idle[&3], vec![Qubit(0)]);
+        assert_eq!(idle[&4], Qubit::range(3).collect::<Vec<_>>());
+
+        let mut rng = rand::rngs::StdRng::seed_from_u64(0);
+        let noise = NoiseModel::noiseless().with_idle_depolarization(1.0);
+        let errors = noise.idle_errors(&idle[&1], &mut rng);
+        assert_eq!(errors.len(), 2);
+        assert!(NoiseModel::noiseless()
+            .idle_errors(&idle[&1], &mut rng)
+            .is_empty());
+    }
+
+    #[test]
+    fn test_totals() {
+        let mut circuit = Circuit::bell_pair(Qubit(0), Qubit(1));
+        circuit.tick().measure(Qubit(0)).measure(Qubit(1)).tick();
+        let noise = NoiseModel::uniform_depolarizing(0.01).with_idle_depolarization(0.5);
+        let totals = noise.totals(&circuit);
+        assert_eq!(
+            (
+                totals.gate_channels,
+                totals.crosstalk_channels,
+                totals.measurement_flips,
+                totals.idle_channels
+            ),
+            (2, 0, 2, 0)
+        );
+        assert!((totals.expected_errors - 0.04).abs() < 1e-12);
+        circuit.tick();
+        assert_eq!(noise.totals(&circuit).idle_channels, 2);
+        assert_eq!(
+            NoiseModel::noiseless().totals(&circuit),
+            NoiseTotals::default()
+        );
+    }
+
+    #[test]
+    fn test_channels_replace_depolarization() {
+        let mut rng = rand::rngs::StdRng::seed_from_u64(0);
+        let bit_flip = PauliChannel::new(1, vec![0.0, 1.0, 0.0, 0.0]).unwrap();
+        let noise = NoiseModel::uniform_depolarizing(0.5).with_channel(bit_flip);
+        for _ in 0..20 {
+            assert_eq!(
+                noise.errors_after(&Instruction::Gate(Gate::H(Qubit(3))), &mut rng),
+                vec![Gate::X(Qubit(3))]
+            );
+        }
+        assert_eq!(noise.gate_errors(&Gate::S(Qubit(0)))[0], (1, 1.0));
+        // two-qubit gates still depolarize.
+        assert_eq!(
+            noise.gate_error_probability(&Gate::Cx(Qubit(0), Qubit(1))),
+            0.5
+        );
+        assert!(PauliChannel::new(1, vec![0.5, 0.2, 0.0, 0.0]).is_err());
+        assert!(PauliChannel::depolarizing(2, 0.15).is_ok());
+    }
+}
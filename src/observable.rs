@@ -0,0 +1,85 @@
+use crate::pauli::PauliString;
+use crate::stabilizer_simulator::StabilizerSimulator;
+
+// a real weighted sum of pauli strings, like a stabilizer hamiltonian H = -sum_i c_i P_i.
+// Each term's sign lives in its pauli string, and the coefficient scales it on top.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Observable {
+    num_qubits: usize,
+    terms: Vec<(f64, PauliString)>,
+}
+
+impl Observable {
+    pub fn new(num_qubits: usize) -> Observable {
+        Observable {
+            num_qubits,
+            terms: Vec::new(),
+        }
+    }
+
+    pub fn num_qubits(&self) -> usize {
+        self.num_qubits
+    }
+
+    pub fn terms(&self) -> &[(f64, PauliString)] {
+        &self.terms
+    }
+
+    pub fn add_term(
+        &mut self,
+        coefficient: f64,
+        pauli: PauliString,
+    ) -> Result<&mut Observable, &'static str> {
+        if pauli.num_qubits() != self.num_qubits {
+            return Err("Term acts on a different number of qubits than the observable.");
+        }
+        self.terms.push((coefficient, pauli));
+        Ok(self)
+    }
+}
+
+impl StabilizerSimulator {
+    pub fn expectation(&self, observable: &Observable) -> Result<f64, &'static str> {
+        // <H> = sum_i c_i <P_i>, and every <P_i> is exactly -1, 0 or +1 in a stabilizer state,
+        // so this is exact rather than an estimate.
+        if observable.num_qubits() != self.num_qubits() {
+            return Err("Observable acts on a different number of qubits than the simulator.");
+        }
+        let mut total = 0.0;
+        for (coefficient, pauli) in observable.terms() {
+            total += coefficient * self.pauli_expectation(pauli)? as f64;
+        }
+        Ok(total)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::gates::Gate;
+    use crate::qubit::Qubit;
+
+    #[test]
+    fn test_cluster_hamiltonian_energy() {
+        // the line cluster state is the ground state of -(XZI + ZXZ + IZX), at energy -3.
+        let mut cluster = StabilizerSimulator::seeded(3);
+        cluster.apply_gates(Qubit::range(3).map(Gate::H));
+        cluster.apply_gates([Gate::Cz(Qubit(0), Qubit(1)), Gate::Cz(Qubit(1), Qubit(2))]);
+        let mut hamiltonian = Observable::new(3);
+        for term in ["XZI", "ZXZ", "IZX"] {
+            hamiltonian.add_term(-1.0, term.parse().unwrap()).unwrap();
+        }
+        assert_eq!(cluster.expectation(&hamiltonian), Ok(-3.0));
+
+        // a term the state is unbiased on doesn't shift the energy.
+        hamiltonian.add_term(0.5, "ZII".parse().unwrap()).unwrap();
+        assert_eq!(cluster.expectation(&hamiltonian), Ok(-3.0));
+
+        cluster.apply_gate(&Gate::Z(Qubit(1)));
+        assert_eq!(cluster.expectation(&hamiltonian), Ok(-1.0));
+        assert!(hamiltonian.add_term(1.0, "ZZ".parse().unwrap()).is_err());
+        assert!(StabilizerSimulator::seeded(2)
+            .expectation(&hamiltonian)
+            .is_err());
+    }
+}
@@ -0,0 +1,185 @@
+use crate::circuit::{Circuit, Instruction};
+use crate::gates::Gate;
+use crate::noise::{self, NoiseModel};
+use crate::sampler;
+use crate::stabilizer_simulator::StabilizerSimulator;
+use rand::SeedableRng;
+
+// runs a circuit twice in lockstep, once noiseless and once with noise, to find which noise
+// made a shot go wrong. Both simulators start from the same seed, and the noise is drawn
+// from an rng of its own, so the two see the same coin flips for random measurements and
+// only differ where noise made them. That holds until noise changes which measurements are
+// random at all, after which the streams can drift apart, so the first divergence is the
+// one to trust.
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum NoiseEvent {
+    // a pauli error applied after instruction `instruction` (or at it, for TICKs).
+    Error {
+        instruction: usize,
+        gate: Gate,
+    },
+    // measurement `measurement` came out flipped.
+    MeasurementFlip {
+        instruction: usize,
+        measurement: usize,
+    },
+}
+
+impl NoiseEvent {
+    pub fn instruction(&self) -> usize {
+        match *self {
+            NoiseEvent::Error { instruction, .. }
+            | NoiseEvent::MeasurementFlip { instruction, .. } => instruction,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Divergence {
+    pub measurement: usize,
+    pub instruction: usize,
+    pub ideal: bool,
+    pub noisy: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PairedRun {
+    pub ideal: Vec<bool>,
+    pub noisy: Vec<bool>,
+    // every noise event, in circuit order.
+    pub noise_events: Vec<NoiseEvent>,
+    // every measurement whose outcomes differ, in circuit order.
+    pub divergences: Vec<Divergence>,
+}
+
+impl PairedRun {
+    pub fn first_divergence(&self) -> Option<&Divergence> {
+        self.divergences.first()
+    }
+
+    pub fn suspects(&self, divergence: &Divergence) -> impl Iterator<Item = &NoiseEvent> {
+        // the noise events that happened before a divergence, any of which could have caused
+        // it.
+        let instruction = divergence.instruction;
+        self.noise_events
+            .iter()
+            .filter(move |event| event.instruction() <= instruction)
+    }
+}
+
+pub fn run_paired(
+    circuit: &Circuit,
+    noise: &NoiseModel,
+    seed: u64,
+) -> Result<PairedRun, &'static str> {
+    let mut ideal = StabilizerSimulator::new(circuit.num_qubits(), seed);
+    let mut noisy = StabilizerSimulator::new(circuit.num_qubits(), seed);
+    let mut rng = rand::rngs::StdRng::seed_from_u64(sampler::shot_seed(seed, 0));
+    let idle = noise::idle_qubits(circuit);
+    let mut noise_events = vec![];
+    let mut divergences = vec![];
+    for (index, instruction) in circuit.instructions().iter().enumerate() {
+        let mut errors = idle
+            .get(&index)
+            .map_or(vec![], |qubits| noise.idle_errors(qubits, &mut rng));
+        ideal.apply_instruction(instruction)?;
+        noisy.apply_instruction(instruction)?;
+        if let Instruction::Measure(qubit) | Instruction::MeasureToBit { qubit, .. } = *instruction
+        {
+            let measurement = noisy.measurement_record().len() - 1;
+            if noise.flips_measurement(qubit, &mut rng) {
+                noisy.flip_last_measurement(instruction);
+                noise_events.push(NoiseEvent::MeasurementFlip {
+                    instruction: index,
+                    measurement,
+                });
+            }
+            let (a, b) = (
+                ideal.measurement_record()[measurement],
+                noisy.measurement_record()[measurement],
+            );
+            if a != b {
+                divergences.push(Divergence {
+                    measurement,
+                    instruction: index,
+                    ideal: a,
+                    noisy: b,
+                });
+            }
+        }
+        // idle errors land at the TICK, before it; gate errors right after their gate. Both
+        // only touch the noisy simulator, and a TICK does nothing to the state, so applying
+        // them together here is the same as run_noisy.
+        errors.extend(noise.errors_after(instruction, &mut rng));
+        for gate in errors {
+            noisy.apply_gate(&gate);
+            noise_events.push(NoiseEvent::Error {
+                instruction: index,
+                gate,
+            });
+        }
+    }
+    Ok(PairedRun {
+        ideal: ideal.measurement_record().to_vec(),
+        noisy: noisy.measurement_record().to_vec(),
+        noise_events,
+        divergences,
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::qubit::Qubit;
+
+    #[test]
+    fn test_divergence_points_at_the_noise() {
+        // random outcomes agree between the runs, so only the readout error shows up.
+        let mut circuit = Circuit::bell_pair(Qubit(0), Qubit(1));
+        circuit.measure(Qubit(0)).measure(Qubit(1));
+        let noise = NoiseModel {
+            measurement_flip: 0.5,
+            ..NoiseModel::noiseless()
+        };
+        for seed in 0..20 {
+            let run = run_paired(&circuit, &noise, seed).unwrap();
+            assert_eq!(run.ideal[0], run.ideal[1]);
+            let flipped: Vec<usize> = run
+                .noise_events
+                .iter()
+                .filter_map(|event| match *event {
+                    NoiseEvent::MeasurementFlip { measurement, .. } => Some(measurement),
+                    NoiseEvent::Error { .. } => None,
+                })
+                .collect();
+            let diverged: Vec<usize> = run.divergences.iter().map(|d| d.measurement).collect();
+            assert_eq!(flipped, diverged);
+        }
+
+        // after an X gate, X and Y errors flip the outcome and Z errors don't.
+        let noise = NoiseModel {
+            single_qubit_depolarization: 1.0,
+            ..NoiseModel::noiseless()
+        };
+        let mut flip = Circuit::new(1);
+        flip.gate(Gate::X(Qubit(0))).measure(Qubit(0));
+        for seed in 0..20 {
+            let run = run_paired(&flip, &noise, seed).unwrap();
+            let [NoiseEvent::Error { gate, .. }] = run.noise_events[..] else {
+                panic!("expected one error");
+            };
+            assert_eq!(
+                run.first_divergence().is_some(),
+                matches!(gate, Gate::X(_) | Gate::Y(_))
+            );
+            if let Some(divergence) = run.first_divergence() {
+                assert_eq!(run.suspects(divergence).count(), 1);
+            }
+        }
+        assert!(run_paired(&circuit, &NoiseModel::noiseless(), 3)
+            .unwrap()
+            .divergences
+            .is_empty());
+    }
+}
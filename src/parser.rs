@@ -0,0 +1,618 @@
+use crate::circuit::{Circuit, Instruction};
+use crate::gates::Gate;
+use crate::qubit::Qubit;
+use std::fmt;
+use std::str::FromStr;
+
+// a line-based text format for circuits, close to stim's:
+//
+//     # comments run to the end of the line
+//     H 0
+//     CX 0 1 2 3        # several targets apply the gate to each qubit (or pair) in turn
+//     M 0 1
+//     R 0               # reset to |0>; RX and RY prepare |+> and |+i> instead
+//     TICK
+//     CZ rec[-1] 2      # stim style classical control: Z on 2 if the last measurement was 1
+//     IF rec[-2] S 0    # any other gate under classical control
+//     CX sweep[0] 1     # X on 1 in shots whose sweep bit 0 is set
+//     M bit[3] 0        # measure 0 into classical register bit 3 (see Circuit::add_classical_register)
+//     IF bit[3] H 1     # H on 1 if that bit is set
+//     CLASSICAL_REGISTER(4) flags      # declares the next 4 classical bits as register flags
+//     DETECTOR(1, 2) rec[-1] rec[-3]   # a detector at coordinates (1, 2) (see Circuit::add_detector)
+//     DETECTOR[erasure] rec[-1]        # an erasure flag (see Circuit::add_erasure_flag)
+//     OBSERVABLE_INCLUDE(0) rec[-2]    # adds a measurement to logical observable 0
+//     QUBIT_COORDS(1, 2) 5             # places qubit 5 at (1, 2) (see Circuit::set_qubit_coords)
+//
+// Gates and instructions print in this format too, and parse back with str::parse.
+//
+// Gate names are case insensitive.
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ParseError {
+    // 1-based, like an editor would show it.
+    pub line: usize,
+    pub reason: &'static str,
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "line {}: {}", self.line, self.reason)
+    }
+}
+
+fn parse_qubit(token: &str) -> Result<Qubit, &'static str> {
+    token
+        .parse()
+        .map(Qubit)
+        .map_err(|_| "Expected a qubit index.")
+}
+
+fn parse_lookback(token: &str) -> Option<Result<u32, &'static str>> {
+    // rec[-k] refers to the kth most recent measurement.
+    let inner = token.strip_prefix("rec[-")?.strip_suffix(']')?;
+    Some(match inner.parse() {
+        Ok(0) | Err(_) => Err("Measurement record target must look like rec[-1], rec[-2], ..."),
+        Ok(lookback) => Ok(lookback),
+    })
+}
+
+fn parse_sweep_bit(token: &str) -> Option<Result<u32, &'static str>> {
+    // sweep[k] refers to the kth per-shot sweep bit.
+    let inner = token.strip_prefix("sweep[")?.strip_suffix(']')?;
+    Some(
+        inner
+            .parse()
+            .map_err(|_| "Sweep target must look like sweep[0], sweep[1], ..."),
+    )
+}
+
+fn parse_register_bit(token: &str) -> Option<Result<u32, &'static str>> {
+    // bit[k] refers to classical register bit k, by flat index.
+    let inner = token.strip_prefix("bit[")?.strip_suffix(']')?;
+    Some(
+        inner
+            .parse()
+            .map_err(|_| "Classical bit target must look like bit[0], bit[1], ..."),
+    )
+}
+
+fn classical_control(
+    token: &str,
+    gate: impl FnOnce() -> Result<Gate, &'static str>,
+) -> Option<Result<Instruction, &'static str>> {
+    // the gate controlled by a rec[-k], sweep[k] or bit[k] target, if the token is one.
+    if let Some(lookback) = parse_lookback(token) {
+        return Some(lookback.and_then(|lookback| {
+            Ok(Instruction::Conditional {
+                lookback,
+                gate: gate()?,
+            })
+        }));
+    }
+    if let Some(bit) = parse_register_bit(token) {
+        return Some(bit.and_then(|bit| Ok(Instruction::ConditionalOnBit { bit, gate: gate()? })));
+    }
+    let bit = parse_sweep_bit(token)?;
+    Some(bit.and_then(|bit| Ok(Instruction::SweepConditional { bit, gate: gate()? })))
+}
+
+fn annotation_name(line: &str) -> Option<&'static str> {
+    // DETECTOR and OBSERVABLE_INCLUDE lines annotate the measurement record, QUBIT_COORDS
+    // lines the qubits, and CLASSICAL_REGISTER lines declare classical bits, instead of adding
+    // instructions. They take parenthesized arguments, so they're parsed separately.
+    let line = line.split('#').next().unwrap_or("").trim_start();
+    [
+        "DETECTOR",
+        "OBSERVABLE_INCLUDE",
+        "QUBIT_COORDS",
+        "CLASSICAL_REGISTER",
+    ]
+    .into_iter()
+    .find(|name| {
+        line.get(..name.len())
+            .is_some_and(|prefix| prefix.eq_ignore_ascii_case(name))
+            && line[name.len()..]
+                .chars()
+                .next()
+                .is_none_or(|c| c == '(' || c == '[' || c.is_whitespace())
+    })
+}
+
+fn parse_annotation(name: &str, line: &str, circuit: &mut Circuit) -> Result<(), &'static str> {
+    let line = line.split('#').next().unwrap_or("").trim_start();
+    let mut rest = line[name.len()..].trim_start();
+    // stim style tags, like DETECTOR[erasure]. Tags that don't mean anything here are ignored.
+    let mut tag = "";
+    if let Some(tagged) = rest.strip_prefix('[') {
+        (tag, rest) = tagged.split_once(']').ok_or("Missing a closing bracket.")?;
+        rest = rest.trim_start();
+    }
+    let (arguments, targets) = match rest.strip_prefix('(') {
+        Some(rest) => rest
+            .split_once(')')
+            .ok_or("Missing a closing parenthesis.")?,
+        None => ("", rest),
+    };
+    let arguments: Vec<f64> = arguments
+        .split(',')
+        .map(str::trim)
+        .filter(|argument| !argument.is_empty())
+        .map(|argument| argument.parse().map_err(|_| "Expected a number."))
+        .collect::<Result<_, _>>()?;
+    if name == "QUBIT_COORDS" {
+        // several targets all get the same coordinates, as in stim.
+        let qubits: Vec<Qubit> = targets
+            .split_whitespace()
+            .map(parse_qubit)
+            .collect::<Result<_, _>>()?;
+        if qubits.is_empty() {
+            return Err("QUBIT_COORDS needs at least one qubit.");
+        }
+        for qubit in qubits {
+            circuit.set_qubit_coords(qubit, &arguments);
+        }
+        return Ok(());
+    }
+    if name == "CLASSICAL_REGISTER" {
+        let size = match arguments[..] {
+            [size] if size >= 0.0 && size.fract() == 0.0 && size <= u32::MAX as f64 => size as u32,
+            _ => return Err("CLASSICAL_REGISTER needs a size, like CLASSICAL_REGISTER(4)."),
+        };
+        let [register] = targets.split_whitespace().collect::<Vec<_>>()[..] else {
+            return Err("CLASSICAL_REGISTER needs exactly one register name.");
+        };
+        return circuit.add_classical_register(register, size).map(|_| ());
+    }
+    let lookbacks: Vec<u32> = targets
+        .split_whitespace()
+        .map(|target| {
+            parse_lookback(target).unwrap_or(Err("Detectors and observables take rec[-k] targets."))
+        })
+        .collect::<Result<_, _>>()?;
+    if name == "DETECTOR" && tag.eq_ignore_ascii_case("erasure") {
+        return circuit.add_erasure_flag(&lookbacks, &arguments).map(|_| ());
+    }
+    if name == "DETECTOR" {
+        return circuit.add_detector(&lookbacks, &arguments).map(|_| ());
+    }
+    match arguments[..] {
+        [index] if index >= 0.0 && index.fract() == 0.0 => {
+            circuit.include_in_observable(index as usize, &lookbacks)
+        }
+        _ => Err("OBSERVABLE_INCLUDE needs an observable index, like OBSERVABLE_INCLUDE(0)."),
+    }
+}
+
+pub fn parse_line(line: &str) -> Result<Vec<Instruction>, &'static str> {
+    // the instructions on one line of text, in order. Blank and comment-only lines have none,
+    // and neither do detector, observable and qubit coordinate annotations.
+    if annotation_name(line).is_some() {
+        return Ok(vec![]);
+    }
+    let line = line.split('#').next().unwrap_or("");
+    let mut tokens = line.split_whitespace();
+    let Some(name) = tokens.next() else {
+        return Ok(vec![]);
+    };
+    let name = name.to_ascii_uppercase();
+    let targets: Vec<&str> = tokens.collect();
+
+    if name == "IF" {
+        let Some(control) = targets.first() else {
+            return Err("IF needs a control like rec[-1], sweep[0] or bit[0].");
+        };
+        // annotations and blank lines parse to no instructions, which would leave nothing
+        // for the control to apply to.
+        let controlled = targets[1..].join(" ");
+        if annotation_name(&controlled).is_some() {
+            return Err("Only gates can be classically controlled.");
+        }
+        if controlled.is_empty() {
+            return Err("IF needs a gate after its control.");
+        }
+        return parse_line(&controlled)?
+            .into_iter()
+            .map(|instruction| match instruction {
+                Instruction::Gate(gate) => classical_control(control, || Ok(gate))
+                    .unwrap_or(Err("IF needs a control like rec[-1], sweep[0] or bit[0].")),
+                _ => Err("Only gates can be classically controlled."),
+            })
+            .collect();
+    }
+    if name == "TICK" {
+        if !targets.is_empty() {
+            return Err("TICK doesn't take any targets.");
+        }
+        return Ok(vec![Instruction::Tick]);
+    }
+    if targets.is_empty() {
+        return Err("Instruction needs at least one target.");
+    }
+    if name == "M" || name == "MZ" {
+        // M bit[k] q measures q into classical bit k.
+        let mut instructions = Vec::with_capacity(targets.len());
+        let mut targets = targets.iter();
+        while let Some(target) = targets.next() {
+            instructions.push(match parse_register_bit(target) {
+                Some(bit) => Instruction::MeasureToBit {
+                    bit: bit?,
+                    qubit: parse_qubit(targets.next().ok_or("bit[k] needs a qubit after it.")?)?,
+                },
+                None => Instruction::Measure(parse_qubit(target)?),
+            });
+        }
+        return Ok(instructions);
+    }
+
+    let single: Option<fn(Qubit) -> Instruction> = match name.as_str() {
+        "H" => Some(|q| Instruction::Gate(Gate::H(q))),
+        "S" => Some(|q| Instruction::Gate(Gate::S(q))),
+        "X" => Some(|q| Instruction::Gate(Gate::X(q))),
+        "Y" => Some(|q| Instruction::Gate(Gate::Y(q))),
+        "Z" => Some(|q| Instruction::Gate(Gate::Z(q))),
+        "R" | "RZ" => Some(Instruction::Reset),
+        "RX" => Some(Instruction::PrepX),
+        "RY" => Some(Instruction::PrepY),
+        _ => None,
+    };
+    if let Some(single) = single {
+        return targets
+            .iter()
+            .map(|target| parse_qubit(target).map(single))
+            .collect();
+    }
+
+    if !matches!(name.as_str(), "CX" | "CNOT" | "CY" | "CZ" | "SWAP") {
+        return Err("Unknown instruction.");
+    }
+    if !targets.len().is_multiple_of(2) {
+        return Err("Two-qubit gates need an even number of targets.");
+    }
+    let mut instructions = Vec::with_capacity(targets.len() / 2);
+    for pair in targets.chunks(2) {
+        // a classically controlled pauli, e.g. CX rec[-1] 3 or CZ sweep[0] 2.
+        let controlled = classical_control(pair[0], || {
+            let qubit = parse_qubit(pair[1])?;
+            match name.as_str() {
+                "CX" | "CNOT" => Ok(Gate::X(qubit)),
+                "CY" => Ok(Gate::Y(qubit)),
+                "CZ" => Ok(Gate::Z(qubit)),
+                _ => Err("Only CX, CY and CZ can be classically controlled."),
+            }
+        });
+        if let Some(instruction) = controlled {
+            instructions.push(instruction?);
+            continue;
+        }
+        let (a, b) = (parse_qubit(pair[0])?, parse_qubit(pair[1])?);
+        if a == b {
+            return Err("Two-qubit gates need two different qubits.");
+        }
+        let gate = match name.as_str() {
+            "CX" | "CNOT" => Gate::Cx(a, b),
+            "CZ" => Gate::Cz(a, b),
+            "SWAP" => Gate::Swap(a, b),
+            _ => return Err("CY is only supported under classical control (CY rec[-k] q)."),
+        };
+        instructions.push(Instruction::Gate(gate));
+    }
+    Ok(instructions)
+}
+
+impl FromStr for Instruction {
+    type Err = &'static str;
+
+    fn from_str(text: &str) -> Result<Instruction, &'static str> {
+        // exactly one instruction, e.g. "CX 0 1" or "IF rec[-1] S 2".
+        match parse_line(text)?.as_slice() {
+            [instruction] => Ok(*instruction),
+            _ => Err("Expected exactly one instruction."),
+        }
+    }
+}
+
+impl FromStr for Gate {
+    type Err = &'static str;
+
+    fn from_str(text: &str) -> Result<Gate, &'static str> {
+        match text.parse()? {
+            Instruction::Gate(gate) => Ok(gate),
+            _ => Err("Expected a gate."),
+        }
+    }
+}
+
+#[cfg_attr(
+    feature = "tracing",
+    tracing::instrument(level = "debug", skip_all, fields(bytes = text.len()))
+)]
+pub fn parse(text: &str) -> Result<Circuit, ParseError> {
+    let mut circuit = Circuit::new(0);
+    for (index, line) in text.lines().enumerate() {
+        let error = |reason| ParseError {
+            line: index + 1,
+            reason,
+        };
+        if let Some(name) = annotation_name(line) {
+            parse_annotation(name, line, &mut circuit).map_err(error)?;
+            continue;
+        }
+        let instructions = parse_line(line).map_err(error)?;
+        for instruction in instructions {
+            circuit.push(instruction);
+        }
+    }
+    Ok(circuit)
+}
+
+pub fn to_text(circuit: &Circuit) -> String {
+    // the whole circuit in the text format, annotations included, so parse reads it back.
+    // Each detector goes right after the last measurement it needs (or the detector before
+    // it, to keep them in order), and observables go at the end. Qubit coordinates come
+    // first, then classical registers, in order so each lands on the same bits.
+    let detectors = circuit.detectors();
+    let coords = |coords: &[f64]| -> String {
+        let coords: Vec<String> = coords.iter().map(f64::to_string).collect();
+        match coords.is_empty() {
+            true => String::new(),
+            false => format!("({})", coords.join(", ")),
+        }
+    };
+    let mut lines: Vec<String> = circuit
+        .all_qubit_coords()
+        .map(|(qubit, qubit_coords)| format!("QUBIT_COORDS{} {qubit}", coords(qubit_coords)))
+        .chain(
+            circuit
+                .classical_registers()
+                .iter()
+                .map(|register| format!("CLASSICAL_REGISTER({}) {}", register.size, register.name)),
+        )
+        .collect();
+    let mut measured = 0;
+    let mut next_detector = 0;
+    let lookbacks = |measurements: &[usize], measured: usize| -> String {
+        measurements
+            .iter()
+            .map(|m| format!(" rec[-{}]", measured - m))
+            .collect()
+    };
+    let mut flush = |lines: &mut Vec<String>, measured: usize| {
+        while let Some(detector) = detectors.get(next_detector) {
+            if detector.measurements.iter().any(|&m| m >= measured) {
+                break;
+            }
+            let tag = if detector.erasure_flag {
+                "[erasure]"
+            } else {
+                ""
+            };
+            lines.push(format!(
+                "DETECTOR{tag}{}{}",
+                coords(&detector.coords),
+                lookbacks(&detector.measurements, measured)
+            ));
+            next_detector += 1;
+        }
+    };
+    flush(&mut lines, measured);
+    for instruction in circuit.instructions() {
+        lines.push(instruction.to_string());
+        if matches!(
+            instruction,
+            Instruction::Measure(_) | Instruction::MeasureToBit { .. }
+        ) {
+            measured += 1;
+            flush(&mut lines, measured);
+        }
+    }
+    for (index, observable) in circuit.observables().iter().enumerate() {
+        lines.push(format!(
+            "OBSERVABLE_INCLUDE({index}){}",
+            lookbacks(observable, measured)
+        ));
+    }
+    let mut text = lines.join("\n");
+    text.push('\n');
+    text
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_parse_circuit() {
+        let circuit = parse(
+            "# teleport-ish
+             h 0
+             CX 0 1 1 2
+             M 0
+             RX 0
+             TICK
+
+             CZ rec[-1] 2  # correction
+             IF rec[-1] S 1
+             CX sweep[2] 0",
+        )
+        .unwrap();
+        assert_eq!(
+            circuit.instructions(),
+            &[
+                Instruction::Gate(Gate::H(Qubit(0))),
+                Instruction::Gate(Gate::Cx(Qubit(0), Qubit(1))),
+                Instruction::Gate(Gate::Cx(Qubit(1), Qubit(2))),
+                Instruction::Measure(Qubit(0)),
+                Instruction::PrepX(Qubit(0)),
+                Instruction::Tick,
+                Instruction::Conditional {
+                    lookback: 1,
+                    gate: Gate::Z(Qubit(2))
+                },
+                Instruction::Conditional {
+                    lookback: 1,
+                    gate: Gate::S(Qubit(1))
+                },
+                Instruction::SweepConditional {
+                    bit: 2,
+                    gate: Gate::X(Qubit(0))
+                },
+            ]
+        );
+        assert_eq!(circuit.num_qubits(), 3);
+    }
+
+    #[test]
+    fn test_parse_errors_report_line() {
+        assert_eq!(
+            parse("H 0\nCX 0").unwrap_err(),
+            ParseError {
+                line: 2,
+                reason: "Two-qubit gates need an even number of targets."
+            }
+        );
+        assert_eq!(parse_line("FOO 1"), Err("Unknown instruction."));
+        assert!(parse_line("H zero").is_err());
+        assert!(parse_line("CX rec[-0] 1").is_err());
+        assert!(parse_line("SWAP rec[-1] 1").is_err());
+        assert!(parse_line("CZ sweep[x] 1").is_err());
+        assert!(parse_line("IF rec[-1] M 0").is_err());
+        assert!(parse_line("IF rec[-1] DETECTOR rec[-1]").is_err());
+        assert!(parse_line("IF bit[0] QUBIT_COORDS(1) 0").is_err());
+        assert!(parse_line("IF rec[-1]").is_err());
+        assert!(parse("M 0\nIF rec[-1] OBSERVABLE_INCLUDE(0) rec[-1]").is_err());
+    }
+
+    #[test]
+    fn test_parse_detectors_and_observables() {
+        let circuit = parse(
+            "M 0 1
+             detector(0.5, 2) rec[-1] rec[-2]
+             M 2
+             DETECTOR rec[-1]  # no coordinates
+             DETECTOR[erasure](3) rec[-1]
+             OBSERVABLE_INCLUDE(1) rec[-3]",
+        )
+        .unwrap();
+        assert!(circuit.detectors()[2].erasure_flag);
+        assert_eq!(circuit.detectors()[2].coords, vec![3.0]);
+        assert!(!circuit.detectors()[1].erasure_flag);
+        assert_eq!(circuit.instructions().len(), 3);
+        assert_eq!(circuit.detectors()[0].measurements, vec![1, 0]);
+        assert_eq!(circuit.detectors()[0].coords, vec![0.5, 2.0]);
+        assert_eq!(circuit.detectors()[1].measurements, vec![2]);
+        assert_eq!(circuit.observables(), &[vec![], vec![0]]);
+        assert!(parse("M 0\nDETECTOR rec[-2]").is_err());
+        assert!(parse("M 0\nOBSERVABLE_INCLUDE rec[-1]").is_err());
+        assert!(parse("M 0\nDETECTOR(1 rec[-1]").is_err());
+        assert!(parse("M 0\nDETECTOR 0").is_err());
+    }
+
+    #[test]
+    fn test_parse_qubit_coords() {
+        let circuit = parse("QUBIT_COORDS(1, 2.5) 0 4\nqubit_coords 2\nH 0").unwrap();
+        assert_eq!(circuit.qubit_coords(Qubit(0)), Some(&[1.0, 2.5][..]));
+        assert_eq!(circuit.qubit_coords(Qubit(4)), Some(&[1.0, 2.5][..]));
+        assert_eq!(circuit.qubit_coords(Qubit(2)), Some(&[][..]));
+        assert_eq!(circuit.qubit_coords(Qubit(1)), None);
+        assert_eq!(circuit.instructions().len(), 1);
+        // coordinates grow the register, like any other mention of a qubit.
+        assert_eq!(circuit.num_qubits(), 5);
+        assert!(parse("QUBIT_COORDS(1, 2)").is_err());
+        assert!(parse("QUBIT_COORDS(1, 2) rec[-1]").is_err());
+        assert!(parse("QUBIT_COORDS(x) 0").is_err());
+    }
+
+    #[test]
+    fn test_to_text_round_trips() {
+        let text = "H 0\nM 0\nDETECTOR rec[-1]\nCX 0 1\nM 1 0\nDETECTOR(2, 1.5) rec[-1] rec[-3]\n\
+                    DETECTOR[erasure] rec[-2]\nOBSERVABLE_INCLUDE(1) rec[-1]\n\
+                    QUBIT_COORDS(0.5, -1) 1\nQUBIT_COORDS(0, 0) 0\n";
+        let circuit = parse(text).unwrap();
+        assert_eq!(
+            to_text(&circuit),
+            "QUBIT_COORDS(0, 0) 0\nQUBIT_COORDS(0.5, -1) 1\n\
+             H 0\nM 0\nDETECTOR rec[-1]\nCX 0 1\nM 1\nM 0\nDETECTOR(2, 1.5) rec[-1] rec[-3]\n\
+             DETECTOR[erasure] rec[-2]\nOBSERVABLE_INCLUDE(0)\nOBSERVABLE_INCLUDE(1) rec[-1]\n"
+        );
+        let again = parse(&to_text(&circuit)).unwrap();
+        assert_eq!(again.instructions(), circuit.instructions());
+        assert_eq!(again.detectors(), circuit.detectors());
+        assert_eq!(again.observables(), circuit.observables());
+        assert!(again.all_qubit_coords().eq(circuit.all_qubit_coords()));
+
+        // measurements into classical bits need their registers declared to read back.
+        let mut circuit = Circuit::new(2);
+        let syndrome = circuit.add_classical_register("syndrome", 2).unwrap();
+        let flag = circuit.add_classical_register("flag", 1).unwrap();
+        circuit
+            .measure_to_bit(Qubit(0), syndrome + 1)
+            .measure_to_bit(Qubit(1), flag)
+            .conditional_on_bit(flag, Gate::H(Qubit(0)));
+        assert_eq!(
+            to_text(&circuit),
+            "CLASSICAL_REGISTER(2) syndrome\nCLASSICAL_REGISTER(1) flag\n\
+             M bit[1] 0\nM bit[2] 1\nIF bit[2] H 0\n"
+        );
+        let again = parse(&to_text(&circuit)).unwrap();
+        assert_eq!(again.check_classical_dataflow(), Ok(()));
+        assert_eq!(again.instructions(), circuit.instructions());
+        assert_eq!(again.classical_registers(), circuit.classical_registers());
+        assert!(parse("CLASSICAL_REGISTER flags").is_err());
+        assert!(parse("CLASSICAL_REGISTER(1.5) flags").is_err());
+        assert!(parse("CLASSICAL_REGISTER(2) a b").is_err());
+        assert!(parse("CLASSICAL_REGISTER(2) a\nCLASSICAL_REGISTER(1) a").is_err());
+        assert!(parse("CLASSICAL_REGISTER(4294967295) a\nCLASSICAL_REGISTER(1) b").is_err());
+    }
+
+    #[test]
+    fn test_display_round_trips() {
+        let instructions = [
+            Instruction::Gate(Gate::H(Qubit(0))),
+            Instruction::Gate(Gate::S(Qubit(1))),
+            Instruction::Gate(Gate::Y(Qubit(2))),
+            Instruction::Gate(Gate::Cx(Qubit(0), Qubit(1))),
+            Instruction::Gate(Gate::Cz(Qubit(1), Qubit(2))),
+            Instruction::Gate(Gate::Swap(Qubit(2), Qubit(0))),
+            Instruction::Measure(Qubit(3)),
+            Instruction::Reset(Qubit(0)),
+            Instruction::PrepX(Qubit(1)),
+            Instruction::PrepY(Qubit(2)),
+            Instruction::MeasureToBit {
+                qubit: Qubit(1),
+                bit: 4,
+            },
+            Instruction::Conditional {
+                lookback: 2,
+                gate: Gate::Y(Qubit(0)),
+            },
+            Instruction::Conditional {
+                lookback: 1,
+                gate: Gate::Cz(Qubit(0), Qubit(1)),
+            },
+            Instruction::ConditionalOnBit {
+                bit: 4,
+                gate: Gate::X(Qubit(2)),
+            },
+            Instruction::SweepConditional {
+                bit: 0,
+                gate: Gate::H(Qubit(1)),
+            },
+            Instruction::Tick,
+        ];
+        for instruction in instructions {
+            assert_eq!(instruction.to_string().parse(), Ok(instruction));
+        }
+        assert_eq!(Gate::Cx(Qubit(0), Qubit(1)).to_string(), "CX 0 1");
+        assert_eq!(
+            Instruction::Conditional {
+                lookback: 1,
+                gate: Gate::Z(Qubit(2))
+            }
+            .to_string(),
+            "CZ rec[-1] 2"
+        );
+        assert_eq!("cnot 3 4".parse(), Ok(Gate::Cx(Qubit(3), Qubit(4))));
+        assert!("M 0".parse::<Gate>().is_err());
+        assert!("H 0 1".parse::<Instruction>().is_err());
+    }
+}
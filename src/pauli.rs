@@ -0,0 +1,478 @@
+use crate::gates::Gate;
+use crate::qubit::Qubit;
+use crate::simd::{self, WORD_BITS};
+use std::fmt;
+use std::hash::{Hash, Hasher};
+use std::mem;
+use std::str::FromStr;
+
+pub fn symplectic_product(x1: &[u64], z1: &[u64], x2: &[u64], z2: &[u64]) -> bool {
+    // x1.z2 + z1.x2 mod 2, for pauli strings given as their x and z bits packed 64 qubits to
+    // a word (see pack_bits). Two pauli strings anticommute when they anticommute on an odd
+    // number of qubits, and single-qubit paulis anticommute when they are different and
+    // neither is I, which works out to this being 1. So it's the parity of the ones in
+    // x1 & z2 ^ z1 & x2, a word at a time.
+    assert!(
+        x1.len() == z1.len() && x1.len() == x2.len() && x1.len() == z2.len(),
+        "pauli strings of different lengths"
+    );
+    count_ones(x1.len(), |k| (x1[k] & z2[k]) ^ (z1[k] & x2[k])) % 2 == 1
+}
+
+pub fn pack_bits(bits: &[bool]) -> Vec<u64> {
+    // bit j goes in word j / 64, at bit j % 64, and the bits past the end are zero.
+    let mut words = vec![0u64; bits.len().div_ceil(WORD_BITS)];
+    for (j, &bit) in bits.iter().enumerate() {
+        words[j / WORD_BITS] |= (bit as u64) << (j % WORD_BITS);
+    }
+    words
+}
+
+pub fn phase_exponent(x1: bool, z1: bool, x2: bool, z2: bool) -> i32 {
+    // return the sign to which i is raised when the pauli matrices represented by x1*z1 and x2*z2 are multiplied.
+    // e.g. X*X = I. X*Z = iY. Z*Z = I. Z*X = -iY. etc.
+    // I've used scott aaronson's math here, and it checks out.
+    match (x1, z1) {
+        (false, false) => 0,
+        (true, true) => z2 as i32 - x2 as i32,
+        (true, false) => (z2 as i32) * (2 * x2 as i32 - 1),
+        (false, true) => (1 - 2 * z2 as i32) * x2 as i32,
+    }
+}
+
+// a signed pauli string, e.g. -XZI. This is the public face of a tableau
+// generator: bit j of x_bits and z_bits says whether the string has an X and/or Z
+// component on qubit j (both means Y).
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct PauliString {
+    pub phase_is_negated: bool,
+    pub x_bits: Vec<bool>,
+    pub z_bits: Vec<bool>,
+}
+
+impl PauliString {
+    pub fn num_qubits(&self) -> usize {
+        self.x_bits.len()
+    }
+
+    pub fn identity(num_qubits: usize) -> PauliString {
+        PauliString {
+            phase_is_negated: false,
+            x_bits: vec![false; num_qubits],
+            z_bits: vec![false; num_qubits],
+        }
+    }
+
+    pub fn z(num_qubits: usize, qubit: Qubit) -> PauliString {
+        // +Z on one qubit, the observable a computational basis measurement reads out.
+        let mut pauli = PauliString::identity(num_qubits);
+        pauli.z_bits[qubit.index()] = true;
+        pauli
+    }
+
+    pub fn conjugate_by(&mut self, gate: &Gate) {
+        // P -> U P U^dagger, for pushing a pauli string through a clifford circuit gate by
+        // gate. These are the tableau's update rules (see StabilizerSimulator's
+        // update_generators for why each one is what it is), applied to a single string.
+        let (x, z) = (&mut self.x_bits, &mut self.z_bits);
+        match *gate {
+            Gate::H(q) => {
+                self.phase_is_negated ^= x[q.index()] && z[q.index()];
+                mem::swap(&mut x[q.index()], &mut z[q.index()]);
+            }
+            Gate::S(q) => {
+                self.phase_is_negated ^= x[q.index()] && z[q.index()];
+                z[q.index()] ^= x[q.index()];
+            }
+            Gate::X(q) => self.phase_is_negated ^= z[q.index()],
+            Gate::Y(q) => self.phase_is_negated ^= x[q.index()] ^ z[q.index()],
+            Gate::Z(q) => self.phase_is_negated ^= x[q.index()],
+            Gate::Cx(control, target) => {
+                let (c, t) = (control.index(), target.index());
+                self.phase_is_negated ^= x[c] && z[t] && !(z[c] ^ x[t]);
+                x[t] ^= x[c];
+                z[c] ^= z[t];
+            }
+            Gate::Cz(a, b) => {
+                let (a, b) = (a.index(), b.index());
+                self.phase_is_negated ^= x[a] && x[b] && (z[a] ^ z[b]);
+                z[a] ^= x[b];
+                z[b] ^= x[a];
+            }
+            Gate::Swap(a, b) => {
+                x.swap(a.index(), b.index());
+                z.swap(a.index(), b.index());
+            }
+        }
+    }
+
+    pub fn commutes_with(&self, other: &PauliString) -> bool {
+        self.view().commutes_with(&other.view())
+    }
+
+    pub fn rowsum(&mut self, other: &PauliView) -> Result<(), &'static str> {
+        // aaronson and gottesman's rowsum: replaces this string with other * self. That's
+        // only another signed pauli string when the two commute, so anticommuting strings
+        // are an error, and this string is left alone.
+        self.phase_is_negated = other.product_sign(&self.view())?;
+        for j in 0..self.x_bits.len() {
+            self.x_bits[j] ^= other.x(j);
+            self.z_bits[j] ^= other.z(j);
+        }
+        Ok(())
+    }
+
+    pub fn to_gates(&self) -> Vec<Gate> {
+        // one X, Y or Z gate per qubit the string acts on. The sign is dropped, since it's
+        // only a global phase once the string is applied as gates.
+        (0..self.num_qubits())
+            .filter_map(|j| {
+                let qubit = Qubit(j as u32);
+                match (self.x_bits[j], self.z_bits[j]) {
+                    (true, true) => Some(Gate::Y(qubit)),
+                    (true, false) => Some(Gate::X(qubit)),
+                    (false, true) => Some(Gate::Z(qubit)),
+                    (false, false) => None,
+                }
+            })
+            .collect()
+    }
+
+    pub fn view(&self) -> PauliView<'_> {
+        PauliView {
+            phase_is_negated: self.phase_is_negated,
+            bits: Bits::Unpacked {
+                x: &self.x_bits,
+                z: &self.z_bits,
+            },
+        }
+    }
+}
+
+impl fmt::Display for PauliString {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.view().fmt(f)
+    }
+}
+
+impl FromStr for PauliString {
+    type Err = &'static str;
+
+    fn from_str(text: &str) -> Result<PauliString, &'static str> {
+        // the Display format, e.g. -XZI, with the sign optional (+ if it's left out).
+        let text = text.trim();
+        if text.is_empty() {
+            return Err("Expected a pauli string like +XZ.");
+        }
+        let (phase_is_negated, letters) = match text.strip_prefix('-') {
+            Some(letters) => (true, letters),
+            None => (false, text.strip_prefix('+').unwrap_or(text)),
+        };
+        let mut pauli = PauliString::identity(0);
+        pauli.phase_is_negated = phase_is_negated;
+        for c in letters.chars() {
+            let (x, z) = match c.to_ascii_uppercase() {
+                'I' => (false, false),
+                'X' => (true, false),
+                'Y' => (true, true),
+                'Z' => (false, true),
+                _ => return Err("Pauli strings are made of I, X, Y and Z."),
+            };
+            pauli.x_bits.push(x);
+            pauli.z_bits.push(z);
+        }
+        Ok(pauli)
+    }
+}
+
+// a signed pauli string borrowed from somewhere else, usually a row of a simulator's tableau.
+// Reading generators this way doesn't copy them, which adds up when a tool walks the whole
+// tableau after every gate. The bits are either a PauliString's, a bool per qubit, or a
+// tableau row's, packed 64 qubits to a word; read them with x and z.
+#[derive(Debug, Clone, Copy)]
+pub struct PauliView<'a> {
+    pub phase_is_negated: bool,
+    bits: Bits<'a>,
+}
+
+#[derive(Debug, Clone, Copy)]
+enum Bits<'a> {
+    Unpacked { x: &'a [bool], z: &'a [bool] },
+    // the x words then the z words, each num_qubits.div_ceil(64) long, with the bits past
+    // the last qubit zero.
+    Packed { num_qubits: usize, words: &'a [u64] },
+}
+
+fn count_ones(num_words: usize, word: impl Fn(usize) -> u64) -> u64 {
+    // the ones in num_words words worked out on the fly, put through simd::popcount a stack
+    // buffer at a time so nothing is allocated.
+    let mut buffer = [0u64; 32];
+    let mut total = 0;
+    for start in (0..num_words).step_by(buffer.len()) {
+        let chunk = &mut buffer[..(num_words - start).min(32)];
+        for (k, slot) in chunk.iter_mut().enumerate() {
+            *slot = word(start + k);
+        }
+        total += simd::popcount(chunk);
+    }
+    total
+}
+
+impl<'a> PauliView<'a> {
+    pub(crate) fn packed(phase_is_negated: bool, num_qubits: usize, words: &'a [u64]) -> Self {
+        debug_assert_eq!(words.len(), 2 * num_qubits.div_ceil(WORD_BITS));
+        PauliView {
+            phase_is_negated,
+            bits: Bits::Packed { num_qubits, words },
+        }
+    }
+
+    fn packed_words(&self) -> Option<(&'a [u64], &'a [u64])> {
+        match self.bits {
+            Bits::Packed { words, .. } => Some(words.split_at(words.len() / 2)),
+            Bits::Unpacked { .. } => None,
+        }
+    }
+}
+
+impl PauliView<'_> {
+    pub fn num_qubits(&self) -> usize {
+        match self.bits {
+            Bits::Unpacked { x, .. } => x.len(),
+            Bits::Packed { num_qubits, .. } => num_qubits,
+        }
+    }
+
+    pub fn x(&self, qubit: usize) -> bool {
+        // whether the string has an X component (X or Y) on the qubit.
+        match self.bits {
+            Bits::Unpacked { x, .. } => x[qubit],
+            Bits::Packed { num_qubits, words } => {
+                assert!(qubit < num_qubits, "qubit outside the pauli string");
+                words[qubit / WORD_BITS] >> (qubit % WORD_BITS) & 1 == 1
+            }
+        }
+    }
+
+    pub fn z(&self, qubit: usize) -> bool {
+        // whether the string has a Z component (Z or Y) on the qubit.
+        match self.bits {
+            Bits::Unpacked { z, .. } => z[qubit],
+            Bits::Packed { num_qubits, words } => {
+                assert!(qubit < num_qubits, "qubit outside the pauli string");
+                words[words.len() / 2 + qubit / WORD_BITS] >> (qubit % WORD_BITS) & 1 == 1
+            }
+        }
+    }
+
+    pub fn commutes_with(&self, other: &PauliView) -> bool {
+        if let (Some((x1, z1)), Some((x2, z2))) = (self.packed_words(), other.packed_words()) {
+            return !symplectic_product(x1, z1, x2, z2);
+        }
+        let mut product = false;
+        for j in 0..self.num_qubits() {
+            product ^= (self.x(j) && other.z(j)) ^ (self.z(j) && other.x(j));
+        }
+        !product
+    }
+
+    pub fn product_phase_exponent(&self, other: &PauliView) -> i32 {
+        // the power of i picked up multiplying the unsigned strings, self * other, qubit by
+        // qubit. Not reduced mod 4.
+        if let (Some((x1, z1)), Some((x2, z2))) = (self.packed_words(), other.packed_words()) {
+            // phase_exponent a word at a time: XY, YZ and ZX each give +1, and XZ, YX and ZY
+            // each give -1.
+            assert_eq!(x1.len(), x2.len(), "pauli strings of different lengths");
+            let plus = count_ones(x1.len(), |k| {
+                let (x1, z1, x2, z2) = (x1[k], z1[k], x2[k], z2[k]);
+                (x1 & !z1 & x2 & z2) | (x1 & z1 & !x2 & z2) | (!x1 & z1 & x2 & !z2)
+            });
+            let minus = count_ones(x1.len(), |k| {
+                let (x1, z1, x2, z2) = (x1[k], z1[k], x2[k], z2[k]);
+                (x1 & !z1 & !x2 & z2) | (x1 & z1 & x2 & !z2) | (!x1 & z1 & x2 & z2)
+            });
+            return plus as i32 - minus as i32;
+        }
+        (0..self.num_qubits())
+            .map(|j| phase_exponent(self.x(j), self.z(j), other.x(j), other.z(j)))
+            .sum()
+    }
+
+    pub fn product_sign(&self, other: &PauliView) -> Result<bool, &'static str> {
+        // whether self * other comes out negated. Errs when the product has an imaginary
+        // phase, which happens exactly when the two anticommute.
+        if self.num_qubits() != other.num_qubits() {
+            return Err("Pauli strings act on different numbers of qubits.");
+        }
+        let phase = 2 * (self.phase_is_negated as i32) + 2 * (other.phase_is_negated as i32);
+        match (phase + self.product_phase_exponent(other)).rem_euclid(4) {
+            0 => Ok(false),
+            2 => Ok(true),
+            // TODO -- maybe use anyhow results and dynamic strings.
+            _ => Err("Non-stabilizer rowsum"),
+        }
+    }
+
+    pub fn to_pauli_string(&self) -> PauliString {
+        PauliString {
+            phase_is_negated: self.phase_is_negated,
+            x_bits: (0..self.num_qubits()).map(|j| self.x(j)).collect(),
+            z_bits: (0..self.num_qubits()).map(|j| self.z(j)).collect(),
+        }
+    }
+}
+
+// views are equal when they're the same signed string, however their bits are stored.
+impl PartialEq for PauliView<'_> {
+    fn eq(&self, other: &PauliView) -> bool {
+        self.phase_is_negated == other.phase_is_negated
+            && self.num_qubits() == other.num_qubits()
+            && (0..self.num_qubits()).all(|j| self.x(j) == other.x(j) && self.z(j) == other.z(j))
+    }
+}
+
+impl Eq for PauliView<'_> {}
+
+impl Hash for PauliView<'_> {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.phase_is_negated.hash(state);
+        for j in 0..self.num_qubits() {
+            (self.x(j), self.z(j)).hash(state);
+        }
+    }
+}
+
+impl fmt::Display for PauliView<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", if self.phase_is_negated { '-' } else { '+' })?;
+        for j in 0..self.num_qubits() {
+            let pauli = match (self.x(j), self.z(j)) {
+                (false, false) => 'I',
+                (true, false) => 'X',
+                (true, true) => 'Y',
+                (false, true) => 'Z',
+            };
+            write!(f, "{}", pauli)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        let pauli: PauliString = "-XzI".parse().unwrap();
+        assert!(pauli.phase_is_negated);
+        assert_eq!(pauli.x_bits, [true, false, false]);
+        assert_eq!(pauli.z_bits, [false, true, false]);
+        assert_eq!(pauli.to_string(), "-XZI");
+        assert_eq!("Y".parse::<PauliString>().unwrap().to_string(), "+Y");
+        assert_eq!("+".parse::<PauliString>(), Ok(PauliString::identity(0)));
+        assert!("".parse::<PauliString>().is_err());
+        assert!("+XA".parse::<PauliString>().is_err());
+        assert!("--X".parse::<PauliString>().is_err());
+    }
+
+    #[test]
+    fn test_rowsum_multiplies_commuting_strings() {
+        // XX * ZZ = (XZ)(XZ) = (-iY)(-iY) = -YY.
+        let (xx, zz): (PauliString, PauliString) = ("XX".parse().unwrap(), "ZZ".parse().unwrap());
+        let mut row = zz.clone();
+        row.rowsum(&xx.view()).unwrap();
+        assert_eq!(row.to_string(), "-YY");
+        assert!(xx.view().commutes_with(&zz.view()));
+
+        let [zi, minus_xi, xi, x]: [PauliString; 4] =
+            ["ZI", "-XI", "XI", "X"].map(|text| text.parse().unwrap());
+        let mut row = zi.clone();
+        assert!(!row.view().commutes_with(&minus_xi.view()));
+        assert!(row.rowsum(&minus_xi.view()).is_err());
+        assert_eq!(row, zi);
+        assert_eq!(xi.view().product_phase_exponent(&zi.view()), -1);
+        assert!(x.view().product_sign(&xx.view()).is_err());
+    }
+
+    #[test]
+    fn test_commutation() {
+        // signs don't matter, and Y anticommutes with both X and Z.
+        let commute = |a: &str, b: &str| {
+            let (a, b): (PauliString, PauliString) = (a.parse().unwrap(), b.parse().unwrap());
+            a.commutes_with(&b)
+        };
+        assert!(commute("XYZ", "-XYZ"));
+        assert!(!commute("YII", "XII"));
+        assert!(commute("YYI", "XZI"));
+        let (x, z, none) = ([0b01], [0b10], [0]);
+        assert!(symplectic_product(&x, &none, &none, &x));
+        assert!(symplectic_product(&x, &none, &x, &x));
+        assert!(!symplectic_product(&x, &z, &x, &z));
+        assert_eq!(pack_bits(&[true, false, true]), [0b101]);
+        assert_eq!(pack_bits(&[true; 65]), [u64::MAX, 1]);
+    }
+
+    #[test]
+    fn test_packed_views_match_unpacked() {
+        use rand::{Rng, SeedableRng};
+        let mut rng = rand::rngs::StdRng::seed_from_u64(4);
+        let pack =
+            |pauli: &PauliString| [pack_bits(&pauli.x_bits), pack_bits(&pauli.z_bits)].concat();
+        for num_qubits in [1, 63, 64, 65, 200] {
+            let mut random = || PauliString {
+                phase_is_negated: rng.gen(),
+                x_bits: (0..num_qubits).map(|_| rng.gen()).collect(),
+                z_bits: (0..num_qubits).map(|_| rng.gen()).collect(),
+            };
+            for _ in 0..20 {
+                let (a, b) = (random(), random());
+                let (a_words, b_words) = (pack(&a), pack(&b));
+                let a_packed = PauliView::packed(a.phase_is_negated, num_qubits, &a_words);
+                let b_packed = PauliView::packed(b.phase_is_negated, num_qubits, &b_words);
+                assert_eq!(a_packed, a.view());
+                assert_eq!(a_packed.to_pauli_string(), a);
+                assert_eq!(a_packed.to_string(), a.to_string());
+                assert_eq!(
+                    a_packed.commutes_with(&b_packed),
+                    a.commutes_with(&b),
+                    "{a} {b}"
+                );
+                assert_eq!(a_packed.commutes_with(&b.view()), a.commutes_with(&b));
+                assert_eq!(
+                    a_packed.product_phase_exponent(&b_packed),
+                    a.view().product_phase_exponent(&b.view())
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_conjugate_by_matches_the_tableau() {
+        use crate::stabilizer_simulator::StabilizerSimulator;
+        let gates = [
+            Gate::H(Qubit(0)),
+            Gate::S(Qubit(1)),
+            Gate::X(Qubit(2)),
+            Gate::Y(Qubit(0)),
+            Gate::Z(Qubit(1)),
+            Gate::Cx(Qubit(2), Qubit(0)),
+            Gate::Cz(Qubit(0), Qubit(1)),
+            Gate::Swap(Qubit(1), Qubit(2)),
+        ];
+        let mut simulator = StabilizerSimulator::seeded(3);
+        simulator.apply_gates([Gate::H(Qubit(0)), Gate::S(Qubit(0)), Gate::H(Qubit(1))]);
+        simulator.apply_gates([Gate::Cx(Qubit(0), Qubit(2)), Gate::Cz(Qubit(1), Qubit(2))]);
+        for gate in gates.iter().cycle().take(24) {
+            let mut generators = simulator.stabilizers();
+            generators.extend(simulator.destabilizers());
+            simulator.apply_gate(gate);
+            for generator in &mut generators {
+                generator.conjugate_by(gate);
+            }
+            let mut expected = simulator.stabilizers();
+            expected.extend(simulator.destabilizers());
+            assert_eq!(generators, expected, "{gate}");
+        }
+    }
+}
@@ -0,0 +1,291 @@
+use crate::circuit::{Circuit, Instruction};
+use crate::gates::Gate;
+use crate::qubit::Qubit;
+use serde_json::Value;
+
+// reads pytket's circuit JSON (what Circuit.to_dict() gives, dumped with json.dumps), for
+// circuits compiled with TKET:
+//
+//     {
+//       "qubits": [["q", [0]], ["q", [1]]],
+//       "bits": [["c", [0]]],
+//       "commands": [
+//         {"op": {"type": "H"}, "args": [["q", [0]]]},
+//         {"op": {"type": "Measure"}, "args": [["q", [0]], ["c", [0]]]},
+//         {"op": {"type": "Conditional",
+//                 "conditional": {"op": {"type": "X"}, "width": 1, "value": 1}},
+//          "args": [["c", [0]], ["q", [1]]]}
+//       ],
+//       "implicit_permutation": [[["q", [0]], ["q", [0]]], [["q", [1]], ["q", [1]]]]
+//     }
+//
+// Qubits are numbered in the order they're listed. Bits become classical registers, one per
+// register name, so measurements are MeasureToBit and conditionals ConditionalOnBit.
+// Conditions have to be on a single bit; one on the value 0 runs the gates and then undoes
+// them if the bit is 1. An implicit permutation left by TKET's routing is carried out with
+// SWAPs at the end.
+//
+// Only clifford operations are supported: H, X, Y, Z, S, Sdg, SX, SXdg, V, Vdg, CX, CY, CZ,
+// SWAP, Measure, Reset, Barrier (which becomes a TICK), and Rx, Ry, Rz and U1 at multiples of
+// pi/2. pytket's angles are in half turns, so that's 0.5, 1, 1.5 and so on.
+
+pub fn parse(text: &str) -> Result<Circuit, &'static str> {
+    let json: Value = serde_json::from_str(text).map_err(|_| "Circuit isn't valid JSON.")?;
+    let list = |key: &str| match json.get(key) {
+        None => Ok(&[][..]),
+        Some(value) => value
+            .as_array()
+            .map(Vec::as_slice)
+            .ok_or("Expected a list of qubits, bits or commands."),
+    };
+
+    let qubits: Vec<&Value> = list("qubits")?.iter().collect();
+    // bits grouped into registers by name, in the order the names first show up.
+    let mut registers: Vec<(&str, u32)> = vec![];
+    for bit in list("bits")? {
+        let (name, index) = unit(bit)?;
+        match registers.iter_mut().find(|(register, _)| *register == name) {
+            Some((_, size)) => *size = (*size).max(index + 1),
+            None => registers.push((name, index + 1)),
+        }
+    }
+    let mut circuit = Circuit::new(qubits.len());
+    for &(name, size) in &registers {
+        circuit.add_classical_register(name, size)?;
+    }
+    let qubit = |arg: &Value| {
+        qubits
+            .iter()
+            .position(|&q| q == arg)
+            .map(|q| Qubit(q as u32))
+            .ok_or("Command acts on a qubit that isn't in the circuit's qubits.")
+    };
+    let bit = |circuit: &Circuit, arg: &Value| {
+        let (name, index) = unit(arg)?;
+        circuit
+            .classical_bit(name, index)
+            .ok_or("Command uses a bit that isn't in the circuit's bits.")
+    };
+
+    for command in list("commands")? {
+        let op = command.get("op").ok_or("Command has no op.")?;
+        let args = command
+            .get("args")
+            .and_then(Value::as_array)
+            .ok_or("Command has no args.")?;
+        match op.get("type").and_then(Value::as_str) {
+            Some("Measure") => {
+                let [q, c] = &args[..] else {
+                    return Err("Measure needs a qubit and a bit.");
+                };
+                let (qubit, bit) = (qubit(q)?, bit(&circuit, c)?);
+                circuit.push(Instruction::MeasureToBit { qubit, bit });
+            }
+            Some("Reset") => {
+                for arg in args {
+                    circuit.push(Instruction::Reset(qubit(arg)?));
+                }
+            }
+            Some("Barrier") => {
+                circuit.push(Instruction::Tick);
+            }
+            Some("Conditional") => {
+                let conditional = op
+                    .get("conditional")
+                    .ok_or("Conditional has no condition.")?;
+                let width = conditional.get("width").and_then(Value::as_u64);
+                let value = conditional.get("value").and_then(Value::as_u64);
+                let (Some(1), Some(value @ (0 | 1))) = (width, value) else {
+                    return Err("Conditions have to be on a single bit.");
+                };
+                let (condition, rest) = args.split_first().ok_or("Conditional has no bit.")?;
+                let bit = bit(&circuit, condition)?;
+                let inner = conditional.get("op").ok_or("Conditional has no op.")?;
+                let qubits: Vec<Qubit> = rest.iter().map(qubit).collect::<Result<_, _>>()?;
+                let gates = gates(inner, &qubits)?;
+                let on_bit = |gate| Instruction::ConditionalOnBit { bit, gate };
+                if value == 1 {
+                    for gate in gates {
+                        circuit.push(on_bit(gate));
+                    }
+                } else {
+                    for &gate in &gates {
+                        circuit.gate(gate);
+                    }
+                    for gate in gates.iter().rev().flat_map(Gate::inverse) {
+                        circuit.push(on_bit(gate));
+                    }
+                }
+            }
+            _ => {
+                let qubits: Vec<Qubit> = args.iter().map(qubit).collect::<Result<_, _>>()?;
+                for gate in gates(op, &qubits)? {
+                    circuit.gate(gate);
+                }
+            }
+        }
+    }
+
+    // the wire that starts on qubit `from` ends up as qubit `to`. Swapping each qubit's state
+    // into place in turn carries that out.
+    let mut holds: Vec<usize> = (0..qubits.len()).collect();
+    for pair in list("implicit_permutation")? {
+        let (Some(from), Some(to)) = (pair.get(0), pair.get(1)) else {
+            return Err("Expected implicit_permutation to be pairs of qubits.");
+        };
+        let (from, to) = (qubit(from)?.index(), qubit(to)?.index());
+        let at = holds.iter().position(|&wire| wire == from).unwrap();
+        if at != to {
+            circuit.gate(Gate::Swap(Qubit(at as u32), Qubit(to as u32)));
+            holds.swap(at, to);
+        }
+    }
+    Ok(circuit)
+}
+
+fn unit(value: &Value) -> Result<(&str, u32), &'static str> {
+    // a qubit or bit id like ["c", [3]]. Only one dimensional registers are supported.
+    let error = "Expected a bit like [\"c\", [0]].";
+    let name = value.get(0).and_then(Value::as_str).ok_or(error)?;
+    let index = match value.get(1).and_then(Value::as_array).map(Vec::as_slice) {
+        Some([index]) => index.as_u64().and_then(|i| u32::try_from(i).ok()),
+        _ => None,
+    };
+    Ok((name, index.ok_or(error)?))
+}
+
+fn gates(op: &Value, qubits: &[Qubit]) -> Result<Vec<Gate>, &'static str> {
+    // the gates an op stands for, on the qubits it's applied to.
+    let kind = op
+        .get("type")
+        .and_then(Value::as_str)
+        .ok_or("Op has no type.")?;
+    let quarter_turns = || {
+        // pytket's angles are in half turns, written as strings (they can be symbolic).
+        let angle = match op.pointer("/params/0") {
+            Some(Value::String(text)) => text.trim().parse::<f64>().ok(),
+            Some(value) => value.as_f64(),
+            None => None,
+        };
+        let quarters = angle.ok_or("Rotation needs a numeric angle.")? * 2.0;
+        if (quarters - quarters.round()).abs() > 1e-9 {
+            return Err("Rotation isn't a multiple of pi/2, so it isn't clifford.");
+        }
+        Ok((quarters.round() as i64).rem_euclid(4) as usize)
+    };
+    let sqrt_x = |q| [Gate::H(q), Gate::S(q), Gate::H(q)];
+    let gates = match (kind, qubits) {
+        ("noop" | "Phase", _) => vec![],
+        ("H", &[q]) => vec![Gate::H(q)],
+        ("X", &[q]) => vec![Gate::X(q)],
+        ("Y", &[q]) => vec![Gate::Y(q)],
+        ("Z", &[q]) => vec![Gate::Z(q)],
+        ("S", &[q]) => vec![Gate::S(q)],
+        ("Sdg", &[q]) => Gate::S(q).inverse(),
+        ("SX" | "V", &[q]) => sqrt_x(q).to_vec(),
+        ("SXdg" | "Vdg", &[q]) => sqrt_x(q).repeat(3),
+        ("Rz" | "U1", &[q]) => [Gate::S(q)].repeat(quarter_turns()?),
+        ("Rx", &[q]) => sqrt_x(q).repeat(quarter_turns()?),
+        // H then X is a quarter turn about Y.
+        ("Ry", &[q]) => [Gate::H(q), Gate::X(q)].repeat(quarter_turns()?),
+        (_, &[a, b]) if a == b => return Err("Two-qubit gates need two different qubits."),
+        ("CX", &[a, b]) => vec![Gate::Cx(a, b)],
+        ("CZ", &[a, b]) => vec![Gate::Cz(a, b)],
+        ("SWAP", &[a, b]) => vec![Gate::Swap(a, b)],
+        ("CY", &[a, b]) => {
+            let mut gates = Gate::S(b).inverse();
+            gates.extend([Gate::Cx(a, b), Gate::S(b)]);
+            gates
+        }
+        (
+            "H" | "X" | "Y" | "Z" | "S" | "Sdg" | "SX" | "V" | "SXdg" | "Vdg" | "Rz" | "U1" | "Rx"
+            | "Ry" | "CX" | "CZ" | "SWAP" | "CY",
+            _,
+        ) => return Err("Op has the wrong number of qubits."),
+        _ => return Err("Op isn't a clifford operation this can run."),
+    };
+    Ok(gates)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_pytket_circuit() {
+        let circuit = parse(
+            r#"{
+                "phase": "0.0",
+                "qubits": [["q", [0]], ["q", [1]], ["a", [0]]],
+                "bits": [["c", [0]], ["c", [1]], ["flag", [0]]],
+                "commands": [
+                    {"op": {"type": "H"}, "args": [["q", [0]]]},
+                    {"op": {"type": "CX"}, "args": [["q", [0]], ["a", [0]]]},
+                    {"op": {"type": "Rz", "params": ["-0.5"]}, "args": [["q", [1]]]},
+                    {"op": {"type": "Measure"}, "args": [["a", [0]], ["flag", [0]]]},
+                    {"op": {"type": "Conditional",
+                            "conditional": {"op": {"type": "CZ"}, "width": 1, "value": 1}},
+                     "args": [["flag", [0]], ["q", [0]], ["q", [1]]]},
+                    {"op": {"type": "Conditional",
+                            "conditional": {"op": {"type": "S"}, "width": 1, "value": 0}},
+                     "args": [["flag", [0]], ["q", [1]]]}
+                ],
+                "implicit_permutation": [
+                    [["q", [0]], ["q", [1]]],
+                    [["q", [1]], ["q", [0]]],
+                    [["a", [0]], ["a", [0]]]
+                ]
+            }"#,
+        )
+        .unwrap();
+        let q = Qubit;
+        let s = Gate::S(q(1));
+        let mut expected = vec![
+            Instruction::Gate(Gate::H(q(0))),
+            Instruction::Gate(Gate::Cx(q(0), q(2))),
+        ];
+        expected.extend(s.inverse().into_iter().map(Instruction::Gate));
+        expected.push(Instruction::MeasureToBit {
+            qubit: q(2),
+            bit: 2,
+        });
+        expected.push(Instruction::ConditionalOnBit {
+            bit: 2,
+            gate: Gate::Cz(q(0), q(1)),
+        });
+        expected.push(Instruction::Gate(s));
+        expected.extend(
+            s.inverse()
+                .into_iter()
+                .map(|gate| Instruction::ConditionalOnBit { bit: 2, gate }),
+        );
+        expected.push(Instruction::Gate(Gate::Swap(q(0), q(1))));
+        assert_eq!(circuit.instructions(), &expected[..]);
+        assert_eq!(circuit.classical_bit("flag", 0), Some(2));
+    }
+
+    #[test]
+    fn test_pytket_errors() {
+        let circuit = |commands: &str| {
+            parse(&format!(
+                r#"{{"qubits": [["q", [0]], ["q", [1]]], "bits": [["c", [0]], ["c", [1]]],
+                    "commands": [{commands}]}}"#
+            ))
+        };
+        assert!(circuit(r#"{"op": {"type": "T"}, "args": [["q", [0]]]}"#).is_err());
+        assert!(
+            circuit(r#"{"op": {"type": "Rx", "params": ["0.25"]}, "args": [["q", [0]]]}"#).is_err()
+        );
+        assert!(circuit(r#"{"op": {"type": "CX"}, "args": [["q", [0]]]}"#).is_err());
+        assert!(circuit(r#"{"op": {"type": "H"}, "args": [["r", [0]]]}"#).is_err());
+        assert!(circuit(
+            r#"{"op": {"type": "Conditional",
+                       "conditional": {"op": {"type": "X"}, "width": 2, "value": 3}},
+                "args": [["c", [0]], ["c", [1]], ["q", [0]]]}"#
+        )
+        .is_err());
+        assert!(
+            circuit(r#"{"op": {"type": "Rx", "params": ["1"]}, "args": [["q", [0]]]}"#).is_ok()
+        );
+    }
+}
@@ -0,0 +1,800 @@
+use crate::circuit::{Circuit, Instruction};
+use crate::gates::Gate;
+use crate::parser::ParseError;
+use crate::qubit::Qubit;
+
+// reads OpenQASM 3 (and the OpenQASM 2 spellings of the same things) into a circuit:
+//
+//     OPENQASM 3.0;
+//     include "stdgates.inc";
+//     qubit[3] q;                      // qubit registers, laid out one after the other
+//                                      // (or physical qubits $0, $1, ..., but not both)
+//     bit[2] c;                        // classical registers (see Circuit::add_classical_register)
+//     const int n = 2;
+//     h q[0];
+//     for int i in [0:n - 1] {         // constant bounds, inclusive like QASM's ranges
+//         cx q[i], q[i + 1];
+//     }
+//     c[0] = measure q[0];             // or `measure q[0] -> c[0];`, or `c = measure q;`
+//     if (c[0]) x q[1];
+//     if (c[1] == 0) { z q[2]; } else { s q[2]; }
+//     reset q;
+//     barrier q;                       // becomes a TICK
+//
+// There's no loop in the IR, so for loops are unrolled, and their bodies are read once per
+// iteration with the loop variable bound. Ifs turn into ConditionalOnBit, which only fires on
+// a 1, so a branch taken on a 0 is its gates unconditionally, followed by their inverses
+// conditioned on the bit. Conditions are on one bit at a time, and only gates can go inside
+// them. Gates are the clifford ones from stdgates.inc (h, s, sdg, sx, sxdg, x, y, z, id, cx, cy,
+// cz and swap), and Braket's names for some of them (si, v, vi and cnot); gate definitions and
+// parameterized gates aren't supported.
+//
+// Loops are only ever unrolled: Circuit has no REPEAT block to map them onto, so the
+// imported circuit is as long as every iteration written out, and nothing records that it
+// came from a loop. Unrolling is capped, so a few lines of loops can't ask for unbounded time
+// or memory: at most MAX_LOOP_ITERATIONS passes through loop bodies in the whole program,
+// nested loops included, and at most MAX_UNROLLED_INSTRUCTIONS instructions in the circuit it
+// unrolls to.
+
+pub const MAX_LOOP_ITERATIONS: usize = 1 << 20;
+pub const MAX_UNROLLED_INSTRUCTIONS: usize = 1 << 24;
+
+const TOO_MANY_ITERATIONS: &str = "Loops run too many times to unroll.";
+const TOO_MANY_INSTRUCTIONS: &str = "The program unrolls to too many instructions.";
+const TOO_MANY_REGISTER_BITS: &str = "Registers add up to too many qubits or bits.";
+const MIXED_QUBITS: &str = "Physical qubits like $0 can't be mixed with qubit registers.";
+
+struct Register {
+    name: String,
+    offset: u32,
+    size: u32,
+}
+
+struct Qasm {
+    // (token, the 1-based line it's on)
+    tokens: Vec<(String, usize)>,
+    next: usize,
+    qubits: Vec<Register>,
+    bits: Vec<Register>,
+    // constants and loop variables in scope, innermost last.
+    constants: Vec<(String, i64)>,
+    // loop body passes so far, counted against MAX_LOOP_ITERATIONS.
+    iterations: usize,
+    // whether the program has used a physical qubit $k, which can't be mixed with declared
+    // qubit registers since both would be numbered from 0.
+    physical: bool,
+}
+
+pub fn parse(text: &str) -> Result<Circuit, ParseError> {
+    let mut qasm = Qasm {
+        tokens: tokenize(text).map_err(|(line, reason)| ParseError { line, reason })?,
+        next: 0,
+        qubits: vec![],
+        bits: vec![],
+        constants: vec![],
+        iterations: 0,
+        physical: false,
+    };
+    let mut instructions = vec![];
+    while qasm.peek().is_some() {
+        let error = |qasm: &Qasm, reason| ParseError {
+            line: qasm.line(),
+            reason,
+        };
+        let statement = qasm.statement().map_err(|reason| error(&qasm, reason))?;
+        instructions.extend(statement);
+        if instructions.len() > MAX_UNROLLED_INSTRUCTIONS {
+            return Err(error(&qasm, TOO_MANY_INSTRUCTIONS));
+        }
+    }
+
+    let num_qubits = qasm.qubits.iter().map(|r| r.offset + r.size).max();
+    let mut circuit = Circuit::new(num_qubits.unwrap_or(0) as usize);
+    for register in &qasm.bits {
+        // declared in order, so the offsets come out the same as the ones used above.
+        circuit
+            .add_classical_register(&register.name, register.size)
+            .map_err(|reason| ParseError { line: 1, reason })?;
+    }
+    for instruction in instructions {
+        circuit.push(instruction);
+    }
+    Ok(circuit)
+}
+
+fn tokenize(text: &str) -> Result<Vec<(String, usize)>, (usize, &'static str)> {
+    let mut tokens = vec![];
+    let mut line = 1;
+    let mut chars = text.chars().peekable();
+    while let Some(&c) = chars.peek() {
+        if c == '\n' {
+            line += 1;
+            chars.next();
+        } else if c.is_whitespace() {
+            chars.next();
+        } else if c == '/' && text_after(&chars).starts_with("//") {
+            while chars.next_if(|&c| c != '\n').is_some() {}
+        } else if c == '/' && text_after(&chars).starts_with("/*") {
+            chars.nth(1);
+            loop {
+                match chars.next() {
+                    None => return Err((line, "Unterminated /* comment.")),
+                    Some('*') if chars.next_if_eq(&'/').is_some() => break,
+                    Some('\n') => line += 1,
+                    Some(_) => {}
+                }
+            }
+        } else if c == '"' {
+            chars.next();
+            let mut string = String::from('"');
+            loop {
+                match chars.next() {
+                    None | Some('\n') => return Err((line, "Unterminated string.")),
+                    Some('"') => break,
+                    Some(c) => string.push(c),
+                }
+            }
+            tokens.push((string, line));
+        } else if c.is_alphanumeric() || c == '_' || c == '$' || c == '.' {
+            let mut word = String::new();
+            while let Some(c) = chars.next_if(|&c| c.is_alphanumeric() || "_$.".contains(c)) {
+                word.push(c);
+            }
+            tokens.push((word, line));
+        } else {
+            chars.next();
+            let pair: String = [c].into_iter().chain(chars.peek().copied()).collect();
+            if ["==", "!=", "->", "<=", ">="].contains(&pair.as_str()) {
+                chars.next();
+                tokens.push((pair, line));
+            } else {
+                tokens.push((c.to_string(), line));
+            }
+        }
+    }
+    Ok(tokens)
+}
+
+fn text_after(chars: &std::iter::Peekable<std::str::Chars>) -> String {
+    chars.clone().take(2).collect()
+}
+
+impl Qasm {
+    fn peek(&self) -> Option<&str> {
+        self.tokens.get(self.next).map(|(token, _)| token.as_str())
+    }
+
+    fn line(&self) -> usize {
+        // the line of the token being looked at, or the last one at the end of the file.
+        let index = self.next.min(self.tokens.len().saturating_sub(1));
+        self.tokens.get(index).map_or(1, |&(_, line)| line)
+    }
+
+    fn advance(&mut self) -> Result<String, &'static str> {
+        let (token, _) = self
+            .tokens
+            .get(self.next)
+            .ok_or("Unexpected end of file.")?;
+        self.next += 1;
+        Ok(token.clone())
+    }
+
+    fn eat(&mut self, token: &str) -> bool {
+        let found = self.peek() == Some(token);
+        if found {
+            self.next += 1;
+        }
+        found
+    }
+
+    fn expect(&mut self, token: &str, error: &'static str) -> Result<(), &'static str> {
+        if self.eat(token) {
+            Ok(())
+        } else {
+            Err(error)
+        }
+    }
+
+    fn name(&mut self) -> Result<String, &'static str> {
+        let name = self.advance()?;
+        if !name.starts_with(|c: char| c.is_alphabetic() || c == '_') {
+            return Err("Expected a name.");
+        }
+        Ok(name)
+    }
+
+    fn statement(&mut self) -> Result<Vec<Instruction>, &'static str> {
+        let keyword = self.advance()?;
+        match keyword.as_str() {
+            "OPENQASM" => {
+                self.advance()?;
+                self.expect(";", "Expected a ; after the version.")?;
+                Ok(vec![])
+            }
+            "include" => {
+                if !self.advance()?.starts_with('"') {
+                    return Err("include needs a file name in quotes.");
+                }
+                self.expect(";", "Expected a ; after the include.")?;
+                Ok(vec![])
+            }
+            "qubit" | "bit" => {
+                let size = self.size()?.unwrap_or(1);
+                let name = self.name()?;
+                self.declare(keyword == "qubit", name, size)?;
+                self.expect(";", "Expected a ; after the declaration.")?;
+                Ok(vec![])
+            }
+            "qreg" | "creg" => {
+                let name = self.name()?;
+                let size = self.size()?.ok_or("qreg and creg need a size.")?;
+                self.declare(keyword == "qreg", name, size)?;
+                self.expect(";", "Expected a ; after the declaration.")?;
+                Ok(vec![])
+            }
+            "const" => {
+                self.name()?;
+                self.size()?;
+                let name = self.name()?;
+                self.expect("=", "Constants need a value.")?;
+                let value = self.expression()?;
+                self.expect(";", "Expected a ; after the constant.")?;
+                self.constants.push((name, value));
+                Ok(vec![])
+            }
+            "measure" => {
+                let qubits = self.qubit_operand()?;
+                let bits = if self.eat("->") {
+                    Some(self.bit_operand()?)
+                } else {
+                    None
+                };
+                self.expect(";", "Expected a ; after the measurement.")?;
+                measurements(&qubits, bits.as_deref())
+            }
+            "reset" => {
+                let qubits = self.qubit_operand()?;
+                self.expect(";", "Expected a ; after the reset.")?;
+                Ok(qubits.into_iter().map(Instruction::Reset).collect())
+            }
+            "barrier" => {
+                while !self.eat(";") {
+                    self.advance()?;
+                }
+                Ok(vec![Instruction::Tick])
+            }
+            "if" => self.if_statement(),
+            "for" => self.for_loop(),
+            "{" => {
+                let mut instructions = vec![];
+                while !self.eat("}") {
+                    instructions.extend(self.statement()?);
+                }
+                Ok(instructions)
+            }
+            _ if self.bits.iter().any(|register| register.name == keyword) => {
+                // c[0] = measure q[0];
+                self.next -= 1;
+                let bits = self.bit_operand()?;
+                self.expect("=", "Only measurements can be assigned to bits.")?;
+                self.expect("measure", "Only measurements can be assigned to bits.")?;
+                let qubits = self.qubit_operand()?;
+                self.expect(";", "Expected a ; after the measurement.")?;
+                measurements(&qubits, Some(&bits))
+            }
+            _ => {
+                let mut operands = vec![self.qubit_operand()?];
+                while self.eat(",") {
+                    operands.push(self.qubit_operand()?);
+                }
+                self.expect(";", "Expected a ; after the gate.")?;
+                Ok(gates(&keyword, &operands)?
+                    .into_iter()
+                    .map(Instruction::Gate)
+                    .collect())
+            }
+        }
+    }
+
+    fn if_statement(&mut self) -> Result<Vec<Instruction>, &'static str> {
+        // if (c[0]), if (!c[0]), if (c[0] == 1) or if (c[0] == false), each with an optional
+        // else.
+        self.expect("(", "if needs a condition in parentheses.")?;
+        let negated = self.eat("!");
+        let bit = match self.bit_operand()?[..] {
+            [bit] => bit,
+            _ => return Err("if can only test a single bit."),
+        };
+        let mut when = !negated;
+        if let Some(comparison @ ("==" | "!=")) = self.peek() {
+            let equal = comparison == "==";
+            self.next += 1;
+            let value = match self.advance()?.as_str() {
+                "1" | "true" => true,
+                "0" | "false" => false,
+                _ => return Err("A bit can only be compared with 0 or 1."),
+            };
+            when = when == (value == equal);
+        }
+        self.expect(")", "Expected a ) after the condition.")?;
+        let mut instructions = conditioned(bit, when, self.statement()?)?;
+        if self.eat("else") {
+            instructions.extend(conditioned(bit, !when, self.statement()?)?);
+        }
+        Ok(instructions)
+    }
+
+    fn for_loop(&mut self) -> Result<Vec<Instruction>, &'static str> {
+        // for int i in [a:b], [a:step:b] or {a, b, ...}. The type is optional.
+        if self.tokens.get(self.next + 1).map(|(t, _)| t.as_str()) != Some("in") {
+            self.name()?;
+            self.size()?;
+        }
+        let variable = self.name()?;
+        self.expect("in", "for needs an `in`.")?;
+        let values = if self.eat("{") {
+            let mut values = vec![self.expression()?];
+            while self.eat(",") {
+                values.push(self.expression()?);
+            }
+            self.expect("}", "Expected a } after the loop's values.")?;
+            values
+        } else {
+            self.expect(
+                "[",
+                "for loops need a range like [0:3] or a set like {0, 2}.",
+            )?;
+            let mut bounds = vec![self.expression()?];
+            while self.eat(":") {
+                bounds.push(self.expression()?);
+            }
+            self.expect("]", "Expected a ] after the range.")?;
+            let (start, step, end) = match bounds[..] {
+                [start, end] => (start, 1, end),
+                [start, step, end] if step != 0 => (start, step, end),
+                _ => return Err("for loops need a range like [0:3] or [0:2:6]."),
+            };
+            // counted before any are listed, so a huge range fails fast. Past the last value
+            // the next one can overflow, which ends the loop too.
+            let count = (end as i128 - start as i128) / step as i128 + 1;
+            if count > (MAX_LOOP_ITERATIONS - self.iterations) as i128 {
+                return Err(TOO_MANY_ITERATIONS);
+            }
+            let mut values = vec![];
+            let mut value = Some(start);
+            while let Some(current) =
+                value.filter(|&v| (step > 0 && v <= end) || (step < 0 && v >= end))
+            {
+                values.push(current);
+                value = current.checked_add(step);
+            }
+            values
+        };
+
+        // the body is read once per iteration. An empty loop still reads it, to find where
+        // it ends, but throws away what it says.
+        let body = self.next;
+        let mut instructions = vec![];
+        for &value in values.iter().chain(values.is_empty().then_some(&0)) {
+            self.iterations += 1;
+            if self.iterations > MAX_LOOP_ITERATIONS {
+                return Err(TOO_MANY_ITERATIONS);
+            }
+            self.next = body;
+            self.constants.push((variable.clone(), value));
+            let statement = self.statement();
+            self.constants.pop();
+            instructions.extend(statement?);
+            if instructions.len() > MAX_UNROLLED_INSTRUCTIONS {
+                return Err(TOO_MANY_INSTRUCTIONS);
+            }
+        }
+        if values.is_empty() {
+            instructions.clear();
+        }
+        Ok(instructions)
+    }
+
+    fn declare(&mut self, quantum: bool, name: String, size: i64) -> Result<(), &'static str> {
+        let size = u32::try_from(size).map_err(|_| "Register size can't be negative.")?;
+        if quantum && self.physical {
+            return Err(MIXED_QUBITS);
+        }
+        let taken = |registers: &[Register]| registers.iter().any(|r| r.name == name);
+        if taken(&self.qubits) || taken(&self.bits) {
+            return Err("There's already a register with that name.");
+        }
+        let registers = if quantum {
+            &mut self.qubits
+        } else {
+            &mut self.bits
+        };
+        // registers are laid out one after another, and every qubit or bit in them could end up
+        // in a whole-register operand, so together they're held to the unrolling cap.
+        let offset = registers.last().map_or(0, |r| r.offset + r.size);
+        let end = offset.checked_add(size).ok_or(TOO_MANY_REGISTER_BITS)?;
+        if end as usize > MAX_UNROLLED_INSTRUCTIONS {
+            return Err(TOO_MANY_REGISTER_BITS);
+        }
+        registers.push(Register { name, offset, size });
+        Ok(())
+    }
+
+    fn size(&mut self) -> Result<Option<i64>, &'static str> {
+        // an optional [n] after a type or register name.
+        if !self.eat("[") {
+            return Ok(None);
+        }
+        let size = self.expression()?;
+        self.expect("]", "Expected a ] after the size.")?;
+        Ok(Some(size))
+    }
+
+    fn operand(&mut self, quantum: bool) -> Result<Vec<u32>, &'static str> {
+        // q[i] is one qubit or bit, and a bare register name is all of them, in order.
+        let name = self.name()?;
+        let registers = if quantum { &self.qubits } else { &self.bits };
+        let register = registers
+            .iter()
+            .find(|register| register.name == name)
+            .ok_or(if quantum {
+                "Unknown qubit register."
+            } else {
+                "Unknown bit register."
+            })?;
+        let (offset, size) = (register.offset, register.size);
+        let end = offset.checked_add(size).ok_or(TOO_MANY_REGISTER_BITS)?;
+        match self.size()? {
+            None if size as usize > MAX_UNROLLED_INSTRUCTIONS => Err(TOO_MANY_INSTRUCTIONS),
+            None => Ok((offset..end).collect()),
+            Some(index) if (0..size as i64).contains(&index) => offset
+                .checked_add(index as u32)
+                .map(|bit| vec![bit])
+                .ok_or(TOO_MANY_REGISTER_BITS),
+            Some(_) => Err("Index is out of the register's range."),
+        }
+    }
+
+    fn qubit_operand(&mut self) -> Result<Vec<Qubit>, &'static str> {
+        // $k is physical qubit k, outside any register.
+        if let Some(physical) = self.peek().and_then(|token| token.strip_prefix('$')) {
+            let qubit: u32 = physical.parse().map_err(|_| "Expected a qubit like $0.")?;
+            if !self.qubits.is_empty() {
+                return Err(MIXED_QUBITS);
+            }
+            if qubit as usize >= MAX_UNROLLED_INSTRUCTIONS {
+                return Err(TOO_MANY_REGISTER_BITS);
+            }
+            self.physical = true;
+            self.next += 1;
+            return Ok(vec![Qubit(qubit)]);
+        }
+        Ok(self.operand(true)?.into_iter().map(Qubit).collect())
+    }
+
+    fn bit_operand(&mut self) -> Result<Vec<u32>, &'static str> {
+        self.operand(false)
+    }
+
+    fn expression(&mut self) -> Result<i64, &'static str> {
+        // integer arithmetic on constants and loop variables: + - * / % and parentheses.
+        let mut value = self.term()?;
+        loop {
+            if self.eat("+") {
+                value = value.checked_add(self.term()?).ok_or("Overflow.")?;
+            } else if self.eat("-") {
+                value = value.checked_sub(self.term()?).ok_or("Overflow.")?;
+            } else {
+                return Ok(value);
+            }
+        }
+    }
+
+    fn term(&mut self) -> Result<i64, &'static str> {
+        let mut value = self.factor()?;
+        loop {
+            if self.eat("*") {
+                value = value.checked_mul(self.factor()?).ok_or("Overflow.")?;
+            } else if self.eat("/") {
+                value = value
+                    .checked_div(self.factor()?)
+                    .ok_or("Division by zero.")?;
+            } else if self.eat("%") {
+                value = value
+                    .checked_rem(self.factor()?)
+                    .ok_or("Division by zero.")?;
+            } else {
+                return Ok(value);
+            }
+        }
+    }
+
+    fn factor(&mut self) -> Result<i64, &'static str> {
+        if self.eat("-") {
+            return self.factor()?.checked_neg().ok_or("Overflow.");
+        }
+        if self.eat("(") {
+            let value = self.expression()?;
+            self.expect(")", "Expected a ).")?;
+            return Ok(value);
+        }
+        let token = self.advance()?;
+        if let Ok(value) = token.parse() {
+            return Ok(value);
+        }
+        self.constants
+            .iter()
+            .rev()
+            .find(|(name, _)| *name == token)
+            .map(|&(_, value)| value)
+            .ok_or("Expected an integer, or a constant or loop variable.")
+    }
+}
+
+fn measurements(qubits: &[Qubit], bits: Option<&[u32]>) -> Result<Vec<Instruction>, &'static str> {
+    let Some(bits) = bits else {
+        return Ok(qubits
+            .iter()
+            .map(|&qubit| Instruction::Measure(qubit))
+            .collect());
+    };
+    if bits.len() != qubits.len() {
+        return Err("Measurement needs as many bits as qubits.");
+    }
+    Ok(qubits
+        .iter()
+        .zip(bits)
+        .map(|(&qubit, &bit)| Instruction::MeasureToBit { qubit, bit })
+        .collect())
+}
+
+fn gates(name: &str, operands: &[Vec<Qubit>]) -> Result<Vec<Gate>, &'static str> {
+    // a gate on whole registers applies to each qubit (or pair) in turn, like QASM's broadcast.
+    let width = operands.iter().map(Vec::len).max().unwrap_or(0);
+    if operands.iter().any(|o| o.len() != 1 && o.len() != width) {
+        return Err("Registers a gate is broadcast over need to be the same size.");
+    }
+    let mut gates = vec![];
+    for k in 0..width {
+        let qubits: Vec<Qubit> = operands.iter().map(|o| o[k % o.len()]).collect();
+        match (name, &qubits[..]) {
+            ("h", &[q]) => gates.push(Gate::H(q)),
+            ("s", &[q]) => gates.push(Gate::S(q)),
+            ("sdg" | "si", &[q]) => gates.extend(Gate::S(q).inverse()),
+            ("sx" | "v", &[q]) => gates.extend([Gate::H(q), Gate::S(q), Gate::H(q)]),
+            ("sxdg" | "vi", &[q]) => {
+                gates.push(Gate::H(q));
+                gates.extend(Gate::S(q).inverse());
+                gates.push(Gate::H(q));
+            }
+            ("x", &[q]) => gates.push(Gate::X(q)),
+            ("y", &[q]) => gates.push(Gate::Y(q)),
+            ("z", &[q]) => gates.push(Gate::Z(q)),
+            ("id", &[_]) => {}
+            (_, &[a, b]) if a == b => return Err("Two-qubit gates need two different qubits."),
+            ("cx" | "CX" | "cnot", &[a, b]) => gates.push(Gate::Cx(a, b)),
+            ("cz", &[a, b]) => gates.push(Gate::Cz(a, b)),
+            ("swap", &[a, b]) => gates.push(Gate::Swap(a, b)),
+            ("cy", &[a, b]) => {
+                gates.extend(Gate::S(b).inverse());
+                gates.push(Gate::Cx(a, b));
+                gates.push(Gate::S(b));
+            }
+            ("h" | "s" | "sdg" | "si" | "sx" | "v" | "sxdg" | "vi" | "x" | "y" | "z" | "id", _)
+            | ("cx" | "CX" | "cnot" | "cz" | "swap" | "cy", _) => {
+                return Err("Gate has the wrong number of qubits.");
+            }
+            _ => return Err("Unknown gate."),
+        }
+    }
+    Ok(gates)
+}
+
+fn conditioned(
+    bit: u32,
+    when: bool,
+    instructions: Vec<Instruction>,
+) -> Result<Vec<Instruction>, &'static str> {
+    // the branch as gates conditioned on the bit. A branch taken when the bit is 0 runs
+    // unconditionally and is then undone when the bit is 1.
+    let gates: Vec<Gate> = instructions
+        .into_iter()
+        .map(|instruction| match instruction {
+            Instruction::Gate(gate) => Ok(gate),
+            _ => Err("Only gates can go inside an if."),
+        })
+        .collect::<Result<_, _>>()?;
+    let on_bit = |gate| Instruction::ConditionalOnBit { bit, gate };
+    if when {
+        return Ok(gates.into_iter().map(on_bit).collect());
+    }
+    let undo = gates.iter().rev().flat_map(Gate::inverse).map(on_bit);
+    Ok(gates
+        .iter()
+        .copied()
+        .map(Instruction::Gate)
+        .chain(undo)
+        .collect())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_teleportation() {
+        let circuit = parse(
+            r#"OPENQASM 3.0;
+            include "stdgates.inc";
+            qubit[3] q;
+            bit[2] c;
+            /* a bell pair between q[1] and q[2],
+               then q[0] teleported over it */
+            h q[1];
+            cx q[1], q[2];
+            cx q[0], q[1];
+            h q[0];
+            c[0] = measure q[0];
+            measure q[1] -> c[1];
+            if (c[1]) x q[2];
+            if (c[0] == 1) {
+                z q[2];
+            }"#,
+        )
+        .unwrap();
+        assert_eq!(
+            &circuit.instructions()[4..],
+            &[
+                Instruction::MeasureToBit {
+                    qubit: Qubit(0),
+                    bit: 0
+                },
+                Instruction::MeasureToBit {
+                    qubit: Qubit(1),
+                    bit: 1
+                },
+                Instruction::ConditionalOnBit {
+                    bit: 1,
+                    gate: Gate::X(Qubit(2))
+                },
+                Instruction::ConditionalOnBit {
+                    bit: 0,
+                    gate: Gate::Z(Qubit(2))
+                },
+            ]
+        );
+        assert_eq!(circuit.num_qubits(), 3);
+        assert_eq!(circuit.classical_bit("c", 1), Some(1));
+    }
+
+    #[test]
+    fn test_loops_unroll() {
+        let circuit = parse(
+            "qreg a[1];
+            qubit[4] q;
+            const int n = 4;
+            h q[0];
+            for int i in [0:n - 2] {
+                cx q[i], q[i + 1];
+            }
+            for i in {3, 1} { x q[i]; }
+            for uint i in [2:-1:3] x q[i];  // empty
+            barrier q;
+            reset q;",
+        )
+        .unwrap();
+        let q = |k: u32| Qubit(k + 1);
+        let mut expected = vec![Instruction::Gate(Gate::H(q(0)))];
+        for k in 0..3 {
+            expected.push(Instruction::Gate(Gate::Cx(q(k), q(k + 1))));
+        }
+        expected.push(Instruction::Gate(Gate::X(q(3))));
+        expected.push(Instruction::Gate(Gate::X(q(1))));
+        expected.push(Instruction::Tick);
+        expected.extend((0..4).map(|k| Instruction::Reset(q(k))));
+        assert_eq!(circuit.instructions(), &expected[..]);
+    }
+
+    #[test]
+    fn test_branches_on_zero() {
+        // taken on 0: the gates, then their inverses if the bit is 1.
+        let circuit = parse(
+            "qubit[2] q;
+            bit c;
+            c = measure q[0];
+            if (!c) s q[1]; else { cx q[0], q[1]; }",
+        )
+        .unwrap();
+        let s = Gate::S(Qubit(1));
+        let mut expected = vec![
+            Instruction::MeasureToBit {
+                qubit: Qubit(0),
+                bit: 0,
+            },
+            Instruction::Gate(s),
+        ];
+        expected.extend([s; 3].map(|gate| Instruction::ConditionalOnBit { bit: 0, gate }));
+        expected.push(Instruction::ConditionalOnBit {
+            bit: 0,
+            gate: Gate::Cx(Qubit(0), Qubit(1)),
+        });
+        assert_eq!(circuit.instructions(), &expected[..]);
+    }
+
+    #[test]
+    fn test_qasm_errors_report_line() {
+        assert_eq!(
+            parse("qubit[2] q;\n\nt q[0];").unwrap_err(),
+            ParseError {
+                line: 3,
+                reason: "Unknown gate."
+            }
+        );
+        assert!(parse("qubit q; h q[1];").is_err());
+        assert!(parse("qubit[2] q; bit[2] c; if (c == 1) x q[0];").is_err());
+        assert!(parse("qubit q; bit c; if (c) { c = measure q; }").is_err());
+        assert!(parse("qubit[2] q; cx q[0], q[0];").is_err());
+        assert!(parse("qubit q; for i in [0:0:3] x q;").is_err());
+        assert!(parse("qubit q; /* never closed").is_err());
+    }
+
+    #[test]
+    fn test_unrolling_is_capped() {
+        let reason = |text: &str| parse(text).unwrap_err().reason;
+        // a range reaching the end of i64 stops there instead of overflowing.
+        let end = i64::MAX;
+        let circuit = parse(&format!("qubit q; for i in [{}:{end}] x q;", end - 2)).unwrap();
+        assert_eq!(circuit.instructions().len(), 3);
+        let circuit = parse(&format!(
+            "qubit q; for i in [{}:-4:-{end} - 1] x q;",
+            i64::MIN + 5
+        ))
+        .unwrap();
+        assert_eq!(circuit.instructions().len(), 2);
+
+        assert_eq!(
+            reason(&format!("qubit q; for i in [0:{end}] x q;")),
+            TOO_MANY_ITERATIONS
+        );
+        // nested loops count every pass through the inner body.
+        assert_eq!(
+            reason("qubit q; for i in [0:1023] for j in [0:1024] x q;"),
+            TOO_MANY_ITERATIONS
+        );
+        assert!(parse("qubit q; for i in [0:1023] for j in [0:1022] x q;").is_ok());
+        // few iterations, each of them wide.
+        assert_eq!(
+            reason("qubit[1048576] q; for i in [0:16] x q;"),
+            TOO_MANY_INSTRUCTIONS
+        );
+    }
+
+    #[test]
+    fn test_registers_are_checked() {
+        let reason = |text: &str| parse(text).unwrap_err().reason;
+        // sizes that overflow the layout, or could unroll past the cap in one operand.
+        assert_eq!(
+            reason("qubit[4294967295] q; qubit[2] r;"),
+            TOO_MANY_REGISTER_BITS
+        );
+        assert_eq!(
+            reason("qubit q; bit[4294967295] a; bit[2] b; b[1] = measure q;"),
+            TOO_MANY_REGISTER_BITS
+        );
+        assert_eq!(reason("qubit[16777217] q; h q;"), TOO_MANY_REGISTER_BITS);
+        assert_eq!(reason("h $16777216;"), TOO_MANY_REGISTER_BITS);
+
+        // physical qubits would share numbers with declared ones.
+        assert_eq!(reason("qubit[1] q; h $0; h q[0];"), MIXED_QUBITS);
+        assert_eq!(reason("h $0; qubit[1] q;"), MIXED_QUBITS);
+        let circuit = parse("bit[2] c; h $3; c[1] = measure $3;").unwrap();
+        assert_eq!(circuit.num_qubits(), 4);
+        assert_eq!(
+            circuit.instructions()[1],
+            Instruction::MeasureToBit {
+                qubit: Qubit(3),
+                bit: 1
+            }
+        );
+    }
+}
@@ -0,0 +1,36 @@
+use std::fmt;
+
+// a qubit index. Kept as u32 to put some semblance of an upper bound on the number of qubits,
+// and wrapped so indices don't get mixed up with counts, lookbacks and classical bits.
+// Registers are indexed with index(), which is the one place the u32 -> usize conversion
+// lives.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Default)]
+pub struct Qubit(pub u32);
+
+impl Qubit {
+    pub fn index(self) -> usize {
+        self.0 as usize
+    }
+
+    pub fn mask(self) -> usize {
+        // this qubit's bit in a computational basis state index, qubit 0 being the lowest.
+        1 << self.index()
+    }
+
+    pub fn range(num_qubits: usize) -> impl DoubleEndedIterator<Item = Qubit> + Clone {
+        // every qubit of an n-qubit register, in order.
+        (0..num_qubits as u32).map(Qubit)
+    }
+}
+
+impl From<u32> for Qubit {
+    fn from(index: u32) -> Qubit {
+        Qubit(index)
+    }
+}
+
+impl fmt::Display for Qubit {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
@@ -0,0 +1,145 @@
+use crate::circuit::Circuit;
+use crate::clifford::{self, CliffordOperator};
+use crate::gates::Gate;
+use crate::noise::NoiseModel;
+use crate::qubit::Qubit;
+use crate::stabilizer_simulator::StabilizerSimulator;
+use rand::{Rng, SeedableRng};
+
+// single-qubit randomized benchmarking. A sequence is m uniformly random single-qubit
+// Cliffords followed by the one Clifford that undoes all of them, so a noiseless run always
+// returns to |0>. Under noise the survival probability decays roughly like A * f^m + B,
+// and the decay rate f gives the average error per Clifford. Interleaved RB puts a fixed
+// gate after every random Clifford, and comparing its decay to the standard one isolates
+// that gate's error.
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct RbPoint {
+    pub sequence_length: usize,
+    pub survival_probability: f64,
+}
+
+pub struct RandomizedBenchmarking {
+    group: Vec<(CliffordOperator, Vec<Gate>)>,
+    interleaved: Option<Vec<Gate>>,
+}
+
+impl RandomizedBenchmarking {
+    pub fn standard() -> RandomizedBenchmarking {
+        RandomizedBenchmarking {
+            group: clifford::single_qubit_cliffords(),
+            interleaved: None,
+        }
+    }
+
+    pub fn interleaved(gates: &[Gate]) -> Result<RandomizedBenchmarking, &'static str> {
+        // the interleaved "gate" can be any single-qubit Clifford, given as a gate sequence on qubit 0.
+        if gates.iter().any(|gate| gate.qubits() != vec![Qubit(0)]) {
+            return Err("Interleaved RB needs a single-qubit Clifford acting on qubit 0.");
+        }
+        Ok(RandomizedBenchmarking {
+            group: clifford::single_qubit_cliffords(),
+            interleaved: Some(gates.to_vec()),
+        })
+    }
+
+    pub fn sequence(&self, length: usize, rng: &mut impl Rng) -> Circuit {
+        // `length` random Cliffords (each followed by the interleaved gate, if any), then the
+        // inverting Clifford, then a measurement. Noiseless, it always measures 0.
+        let mut circuit = Circuit::new(1);
+        let mut net = CliffordOperator::identity(1);
+        for _ in 0..length {
+            let (_, word) = &self.group[rng.gen_range(0..self.group.len())];
+            for step in [Some(word), self.interleaved.as_ref()]
+                .into_iter()
+                .flatten()
+            {
+                for gate in step {
+                    circuit.gate(*gate);
+                    net.then(gate);
+                }
+            }
+        }
+        let (_, inverse) = self
+            .group
+            .iter()
+            .find(|(_, word)| {
+                let mut undone = net.clone();
+                for gate in word {
+                    undone.then(gate);
+                }
+                undone.is_identity()
+            })
+            .expect("every Clifford has an inverse in the group");
+        for gate in inverse {
+            circuit.gate(*gate);
+        }
+        circuit.measure(Qubit(0));
+        circuit
+    }
+
+    pub fn run(
+        &self,
+        sequence_lengths: &[usize],
+        sequences_per_length: usize,
+        shots_per_sequence: usize,
+        noise: &NoiseModel,
+        seed: u64,
+    ) -> Result<Vec<RbPoint>, &'static str> {
+        // survival probability (fraction of shots measuring 0) for each sequence length,
+        // averaged over fresh random sequences.
+        let mut rng = rand::rngs::StdRng::seed_from_u64(seed);
+        let mut points = Vec::new();
+        for &sequence_length in sequence_lengths {
+            let mut survived = 0;
+            for _ in 0..sequences_per_length {
+                let circuit = self.sequence(sequence_length, &mut rng);
+                for _ in 0..shots_per_sequence {
+                    let mut simulator = StabilizerSimulator::new(1, rng.gen());
+                    if !simulator.run_noisy(&circuit, noise)?[0] {
+                        survived += 1;
+                    }
+                }
+            }
+            let total = (sequences_per_length * shots_per_sequence).max(1);
+            points.push(RbPoint {
+                sequence_length,
+                survival_probability: survived as f64 / total as f64,
+            });
+        }
+        Ok(points)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_single_qubit_clifford_group_has_24_elements() {
+        assert_eq!(clifford::single_qubit_cliffords().len(), 24);
+    }
+
+    #[test]
+    fn test_noiseless_sequences_always_survive() {
+        let noiseless = NoiseModel::noiseless();
+        for rb in [
+            RandomizedBenchmarking::standard(),
+            RandomizedBenchmarking::interleaved(&[Gate::H(Qubit(0)), Gate::S(Qubit(0))]).unwrap(),
+        ] {
+            for point in rb.run(&[1, 5, 20], 5, 3, &noiseless, 0).unwrap() {
+                assert_eq!(point.survival_probability, 1.0);
+            }
+        }
+    }
+
+    #[test]
+    fn test_noise_makes_survival_decay() {
+        let noise = NoiseModel::uniform_depolarizing(0.02);
+        let points = RandomizedBenchmarking::standard()
+            .run(&[1, 100], 10, 20, &noise, 1)
+            .unwrap();
+        assert!(points[0].survival_probability > points[1].survival_probability);
+        assert!(points[1].survival_probability < 0.9);
+    }
+}
@@ -0,0 +1,169 @@
+use std::collections::BTreeMap;
+
+// aggregates sampled shots (each a bitstring of measurement outcomes) into counts, and the
+// usual statistics on top of them. Everything comes back as plain vectors and maps, ready
+// to hand to whatever does the plotting.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Histogram {
+    num_bits: usize,
+    num_shots: usize,
+    counts: BTreeMap<Vec<bool>, usize>,
+}
+
+pub fn bitstring(bits: &[bool]) -> String {
+    // bit 0 first, the same order as the measurement record.
+    bits.iter()
+        .map(|&bit| if bit { '1' } else { '0' })
+        .collect()
+}
+
+impl Histogram {
+    pub fn from_shots(shots: &[Vec<bool>]) -> Result<Histogram, &'static str> {
+        let num_bits = shots.first().map_or(0, |shot| shot.len());
+        let mut counts = BTreeMap::new();
+        for shot in shots {
+            if shot.len() != num_bits {
+                return Err("Every shot must have the same number of bits.");
+            }
+            *counts.entry(shot.clone()).or_insert(0) += 1;
+        }
+        Ok(Histogram {
+            num_bits,
+            num_shots: shots.len(),
+            counts,
+        })
+    }
+
+    pub fn num_bits(&self) -> usize {
+        self.num_bits
+    }
+
+    pub fn num_shots(&self) -> usize {
+        self.num_shots
+    }
+
+    pub fn counts(&self) -> &BTreeMap<Vec<bool>, usize> {
+        // only outcomes that actually came up, in lexicographic order.
+        &self.counts
+    }
+
+    pub fn count(&self, outcome: &[bool]) -> usize {
+        self.counts.get(outcome).copied().unwrap_or(0)
+    }
+
+    pub fn frequencies(&self) -> BTreeMap<String, f64> {
+        // keyed by bitstring, for plotting.
+        self.counts
+            .iter()
+            .map(|(outcome, &count)| (bitstring(outcome), count as f64 / self.num_shots as f64))
+            .collect()
+    }
+
+    pub fn marginal(&self, bits: &[usize]) -> Result<Histogram, &'static str> {
+        // the histogram of just these bits, in the given order.
+        if bits.iter().any(|&bit| bit >= self.num_bits) {
+            return Err("Marginal asks for a bit past the end of the shots.");
+        }
+        let mut counts = BTreeMap::new();
+        for (outcome, &count) in &self.counts {
+            let kept: Vec<bool> = bits.iter().map(|&bit| outcome[bit]).collect();
+            *counts.entry(kept).or_insert(0) += count;
+        }
+        Ok(Histogram {
+            num_bits: bits.len(),
+            num_shots: self.num_shots,
+            counts,
+        })
+    }
+
+    pub fn bit_means(&self) -> Vec<f64> {
+        // how often each bit was 1.
+        let mut ones = vec![0; self.num_bits];
+        for (outcome, &count) in &self.counts {
+            for (ones, &bit) in ones.iter_mut().zip(outcome) {
+                if bit {
+                    *ones += count;
+                }
+            }
+        }
+        ones.into_iter()
+            .map(|ones| ones as f64 / self.num_shots.max(1) as f64)
+            .collect()
+    }
+
+    pub fn correlator(&self, bits: &[usize]) -> Result<f64, &'static str> {
+        // the average of the product of (-1)^bit over the given bits, i.e. the expectation of
+        // the Z-type observable those measurements read out. Lies in [-1, 1].
+        if bits.iter().any(|&bit| bit >= self.num_bits) {
+            return Err("Correlator asks for a bit past the end of the shots.");
+        }
+        if self.num_shots == 0 {
+            return Err("Can't compute statistics without any shots.");
+        }
+        let mut total = 0i64;
+        for (outcome, &count) in &self.counts {
+            let parity = bits
+                .iter()
+                .fold(false, |parity, &bit| parity ^ outcome[bit]);
+            total += if parity {
+                -(count as i64)
+            } else {
+                count as i64
+            };
+        }
+        Ok(total as f64 / self.num_shots as f64)
+    }
+
+    pub fn covariance_matrix(&self) -> Vec<Vec<f64>> {
+        // cov(b_i, b_j) = E[b_i b_j] - E[b_i] E[b_j], with bits read as 0/1.
+        let means = self.bit_means();
+        let mut both = vec![vec![0usize; self.num_bits]; self.num_bits];
+        for (outcome, &count) in &self.counts {
+            for i in (0..self.num_bits).filter(|&i| outcome[i]) {
+                for j in (0..self.num_bits).filter(|&j| outcome[j]) {
+                    both[i][j] += count;
+                }
+            }
+        }
+        let num_shots = self.num_shots.max(1) as f64;
+        (0..self.num_bits)
+            .map(|i| {
+                (0..self.num_bits)
+                    .map(|j| both[i][j] as f64 / num_shots - means[i] * means[j])
+                    .collect()
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_counts_and_marginals() {
+        let shots = vec![
+            vec![false, false, true],
+            vec![true, true, true],
+            vec![false, false, true],
+            vec![true, true, false],
+        ];
+        let histogram = Histogram::from_shots(&shots).unwrap();
+        assert_eq!(histogram.count(&[false, false, true]), 2);
+        assert_eq!(histogram.frequencies()["111"], 0.25);
+        let marginal = histogram.marginal(&[2]).unwrap();
+        assert_eq!(marginal.count(&[true]), 3);
+        assert_eq!(histogram.bit_means(), vec![0.5, 0.5, 0.75]);
+        assert!(histogram.marginal(&[3]).is_err());
+        assert!(Histogram::from_shots(&[vec![true], vec![]]).is_err());
+    }
+
+    #[test]
+    fn test_correlations() {
+        let shots = vec![vec![false, false], vec![true, true]];
+        let histogram = Histogram::from_shots(&shots).unwrap();
+        assert_eq!(histogram.correlator(&[0, 1]).unwrap(), 1.0);
+        assert_eq!(histogram.correlator(&[0]).unwrap(), 0.0);
+        assert_eq!(histogram.covariance_matrix(), vec![vec![0.25; 2]; 2]);
+    }
+}
@@ -0,0 +1,198 @@
+use crate::circuit::Instruction;
+use crate::export::RunMetadata;
+use crate::sampler::SamplerStats;
+use std::io::{self, Write};
+
+// a JSON Lines event log of a sampling run, for experiment trackers (MLflow, W&B and the
+// like) to ingest. Every line is one object with an "event" field:
+//
+//   run_started          the master seed, shot count and circuit hash
+//   instruction_applied  one per instruction per shot, with measurement outcomes (opt in,
+//                        since it's a line per instruction)
+//   shot_completed       the shot's index, its own seed and its measurements
+//   shot_decoded         the detection events and whether the decoder got it wrong
+//   batch_stats          throughput and simulator counts every batch_size shots
+//   run_finished         shots, and logical errors when a decoder ran
+//
+// Hashes are hex strings, like in export, since JSON numbers can't hold every u64. Seeds
+// are written as strings for the same reason.
+
+pub struct RunLog<W: Write> {
+    writer: W,
+    instructions: bool,
+    batch_size: u64,
+}
+
+fn bits(values: &[bool]) -> String {
+    let bits: Vec<&str> = values
+        .iter()
+        .map(|&bit| if bit { "1" } else { "0" })
+        .collect();
+    bits.join(",")
+}
+
+fn escape(text: &str) -> String {
+    text.replace('\\', "\\\\").replace('"', "\\\"")
+}
+
+impl<W: Write> RunLog<W> {
+    pub fn new(writer: W) -> RunLog<W> {
+        RunLog {
+            writer,
+            instructions: false,
+            batch_size: 1000,
+        }
+    }
+
+    pub fn with_instructions(mut self) -> RunLog<W> {
+        self.instructions = true;
+        self
+    }
+
+    pub fn with_batch_size(mut self, batch_size: u64) -> RunLog<W> {
+        self.batch_size = batch_size.max(1);
+        self
+    }
+
+    pub fn logs_instructions(&self) -> bool {
+        self.instructions
+    }
+
+    pub fn batch_size(&self) -> u64 {
+        self.batch_size
+    }
+
+    pub fn into_inner(self) -> W {
+        self.writer
+    }
+
+    pub fn run_started(&mut self, metadata: &RunMetadata) -> io::Result<()> {
+        writeln!(
+            self.writer,
+            "{{\"event\":\"run_started\",\"seed\":\"{}\",\"shots\":{},\
+             \"circuit_hash\":\"{:016x}\"}}",
+            metadata.seed, metadata.shots, metadata.circuit_hash
+        )
+    }
+
+    pub fn instruction_applied(
+        &mut self,
+        shot: u64,
+        index: usize,
+        instruction: &Instruction,
+        outcome: Option<bool>,
+    ) -> io::Result<()> {
+        // the outcome is the recorded one, readout errors included.
+        let outcome = match outcome {
+            Some(outcome) => format!(",\"outcome\":{}", outcome as u8),
+            None => String::new(),
+        };
+        writeln!(
+            self.writer,
+            "{{\"event\":\"instruction_applied\",\"shot\":{shot},\"index\":{index},\
+             \"instruction\":\"{}\"{outcome}}}",
+            escape(&instruction.to_string())
+        )
+    }
+
+    pub fn shot_completed(
+        &mut self,
+        shot: u64,
+        seed: u64,
+        measurements: &[bool],
+    ) -> io::Result<()> {
+        writeln!(
+            self.writer,
+            "{{\"event\":\"shot_completed\",\"shot\":{shot},\"seed\":\"{seed}\",\
+             \"measurements\":[{}]}}",
+            bits(measurements)
+        )
+    }
+
+    pub fn shot_decoded(
+        &mut self,
+        shot: u64,
+        events: &[bool],
+        logical_error: bool,
+    ) -> io::Result<()> {
+        // the detectors that fired, by index, rather than a bit per detector.
+        let fired: Vec<String> = events
+            .iter()
+            .enumerate()
+            .filter(|(_, &fired)| fired)
+            .map(|(detector, _)| detector.to_string())
+            .collect();
+        writeln!(
+            self.writer,
+            "{{\"event\":\"shot_decoded\",\"shot\":{shot},\"detection_events\":[{}],\
+             \"logical_error\":{logical_error}}}",
+            fired.join(",")
+        )
+    }
+
+    pub fn batch_stats(&mut self, first_shot: u64, stats: &SamplerStats) -> io::Result<()> {
+        writeln!(
+            self.writer,
+            "{{\"event\":\"batch_stats\",\"first_shot\":{first_shot},\"shots\":{},\
+             \"measurements\":{},\"elapsed_seconds\":{},\"shots_per_second\":{},\
+             \"gates\":{},\"random_measurements\":{},\"rng_draws\":{}}}",
+            stats.shots,
+            stats.measurements,
+            stats.elapsed.as_secs_f64(),
+            stats.shots_per_second(),
+            stats.simulator.num_gates(),
+            stats.simulator.random_measurements,
+            stats.simulator.rng_draws
+        )
+    }
+
+    pub fn run_finished(&mut self, shots: usize, logical_errors: Option<usize>) -> io::Result<()> {
+        let errors = match logical_errors {
+            Some(errors) => format!(",\"logical_errors\":{errors}"),
+            None => String::new(),
+        };
+        writeln!(
+            self.writer,
+            "{{\"event\":\"run_finished\",\"shots\":{shots}{errors}}}"
+        )?;
+        self.writer.flush()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::gates::Gate;
+    use crate::qubit::Qubit;
+
+    #[test]
+    fn test_event_lines() {
+        let mut log = RunLog::new(Vec::new()).with_batch_size(0);
+        assert_eq!(log.batch_size(), 1);
+        let metadata = RunMetadata {
+            seed: u64::MAX,
+            shots: 2,
+            circuit_hash: 0xabc,
+        };
+        log.run_started(&metadata).unwrap();
+        log.instruction_applied(0, 0, &Instruction::Gate(Gate::H(Qubit(0))), None)
+            .unwrap();
+        log.instruction_applied(0, 1, &Instruction::Measure(Qubit(0)), Some(true))
+            .unwrap();
+        log.shot_completed(0, 5, &[true, false]).unwrap();
+        log.shot_decoded(0, &[false, true, true], false).unwrap();
+        log.run_finished(2, Some(0)).unwrap();
+        assert_eq!(
+            String::from_utf8(log.into_inner()).unwrap(),
+            "{\"event\":\"run_started\",\"seed\":\"18446744073709551615\",\"shots\":2,\
+             \"circuit_hash\":\"0000000000000abc\"}\n\
+             {\"event\":\"instruction_applied\",\"shot\":0,\"index\":0,\"instruction\":\"H 0\"}\n\
+             {\"event\":\"instruction_applied\",\"shot\":0,\"index\":1,\"instruction\":\"M 0\",\
+             \"outcome\":1}\n\
+             {\"event\":\"shot_completed\",\"shot\":0,\"seed\":\"5\",\"measurements\":[1,0]}\n\
+             {\"event\":\"shot_decoded\",\"shot\":0,\"detection_events\":[1,2],\
+             \"logical_error\":false}\n\
+             {\"event\":\"run_finished\",\"shots\":2,\"logical_errors\":0}\n"
+        );
+    }
+}
@@ -0,0 +1,396 @@
+use crate::circuit::{Circuit, Instruction};
+use crate::export::{ExportFormat, RunMetadata, ShotExporter};
+use crate::noise::NoiseModel;
+use crate::run_log::RunLog;
+use crate::shot_format::{self, ShotFormat};
+use crate::stabilizer_simulator::{StabilizerSimulator, TableauStorage};
+use crate::stats::ExecutionStats;
+use std::io::{self, Write};
+use std::time::{Duration, Instant};
+
+pub fn shot_seed(master_seed: u64, shot: u64) -> u64 {
+    // the seed shot number `shot` runs with: output shot + 1 of a SplitMix64 stream started at
+    // master_seed. Every shot's seed only depends on (master seed, shot index), so results
+    // come out the same however the shots get split up between runs or threads, and the
+    // scheme is spelled out here rather than borrowed from rand, whose StdRng is allowed to
+    // change between versions. (The simulators still draw their own randomness from StdRng,
+    // seeded with this.)
+    let mut z = master_seed.wrapping_add(shot.wrapping_add(1).wrapping_mul(0x9e37_79b9_7f4a_7c15));
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    z ^ (z >> 31)
+}
+
+// what a sampler got through while its stats were enabled: shots, wall time spent in them,
+// and every shot's simulator stats added up.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SamplerStats {
+    pub shots: u64,
+    pub measurements: u64,
+    pub elapsed: Duration,
+    pub simulator: ExecutionStats,
+}
+
+impl SamplerStats {
+    pub fn shots_per_second(&self) -> f64 {
+        self.shots as f64 / self.elapsed.as_secs_f64().max(f64::MIN_POSITIVE)
+    }
+
+    pub fn measurements_per_second(&self) -> f64 {
+        self.measurements as f64 / self.elapsed.as_secs_f64().max(f64::MIN_POSITIVE)
+    }
+}
+
+// runs a circuit many times, each shot on a fresh simulator, and collects the measurement
+// record of every shot.
+pub struct Sampler<'a> {
+    circuit: &'a Circuit,
+    noise: NoiseModel,
+    seed: u64,
+    next_shot: u64,
+    stats: Option<SamplerStats>,
+    // the last shot's tableau, for the next shot to build its own in.
+    storage: TableauStorage,
+}
+
+impl<'a> Sampler<'a> {
+    pub fn new(circuit: &'a Circuit, seed: u64) -> Sampler<'a> {
+        Sampler {
+            circuit,
+            noise: NoiseModel::noiseless(),
+            seed,
+            next_shot: 0,
+            stats: None,
+            storage: TableauStorage::new(),
+        }
+    }
+
+    pub fn starting_at(mut self, shot: u64) -> Sampler<'a> {
+        // skips ahead, so the next shot is shot number `shot` of the run. Lets several samplers
+        // share one seed and each produce its own slice of the shots.
+        self.next_shot = shot;
+        self
+    }
+
+    pub fn with_noise(mut self, noise: NoiseModel) -> Sampler<'a> {
+        self.noise = noise;
+        self
+    }
+
+    pub fn with_storage(mut self, storage: TableauStorage) -> Sampler<'a> {
+        // starts from storage the caller already has, e.g. one from another sampler.
+        self.storage = storage;
+        self
+    }
+
+    pub fn into_storage(self) -> TableauStorage {
+        self.storage
+    }
+
+    pub fn enable_stats(&mut self) {
+        // from now on, count and time shots, with each shot's simulator collecting stats too.
+        // Timing every gate costs something, so the throughput is a little below what a
+        // sampler without stats gets.
+        self.stats.get_or_insert_with(SamplerStats::default);
+    }
+
+    pub fn disable_stats(&mut self) {
+        self.stats = None;
+    }
+
+    pub fn stats(&self) -> Option<&SamplerStats> {
+        self.stats.as_ref()
+    }
+
+    pub fn take_stats(&mut self) -> SamplerStats {
+        self.stats.as_mut().map(std::mem::take).unwrap_or_default()
+    }
+
+    pub fn sample_shot(&mut self) -> Result<Vec<bool>, &'static str> {
+        self.sample_swept_shot(&[])
+    }
+
+    pub fn sample_swept_shot(&mut self, sweep_bits: &[bool]) -> Result<Vec<bool>, &'static str> {
+        // one shot with the given sweep configuration. Missing sweep bits are 0.
+        let seed = shot_seed(self.seed, self.next_shot);
+        self.next_shot += 1;
+        let storage = std::mem::take(&mut self.storage);
+        let mut simulator =
+            StabilizerSimulator::from_storage(self.circuit.num_qubits(), seed, storage);
+        simulator.set_sweep_bits(sweep_bits);
+        let start = Instant::now();
+        if self.stats.is_some() {
+            simulator.enable_stats();
+        }
+        let shot = simulator.run_noisy(self.circuit, &self.noise)?;
+        if let Some(stats) = self.stats.as_mut() {
+            stats.elapsed += start.elapsed();
+            stats.shots += 1;
+            stats.measurements += shot.len() as u64;
+            stats.simulator.merge(&simulator.take_stats());
+        }
+        self.storage = simulator.into_storage();
+        Ok(shot)
+    }
+
+    pub fn sample_shot_logged(&mut self, log: &mut RunLog<impl Write>) -> io::Result<Vec<bool>> {
+        // sample_shot, with the shot (and its instructions, if the log wants them) written to
+        // the log. Stats get turned on, and every log.batch_size() shots they're written out
+        // and started over; finish_log writes whatever's left of the last batch.
+        self.enable_stats();
+        let index = self.next_shot;
+        let seed = shot_seed(self.seed, index);
+        let shot = self.sample_shot().map_err(io::Error::other)?;
+        if log.logs_instructions() {
+            let mut outcomes = shot.iter();
+            for (k, instruction) in self.circuit.instructions().iter().enumerate() {
+                let outcome = match instruction {
+                    Instruction::Measure(_) | Instruction::MeasureToBit { .. } => {
+                        outcomes.next().copied()
+                    }
+                    _ => None,
+                };
+                log.instruction_applied(index, k, instruction, outcome)?;
+            }
+        }
+        log.shot_completed(index, seed, &shot)?;
+        if self
+            .stats()
+            .is_some_and(|stats| stats.shots >= log.batch_size())
+        {
+            self.finish_log(log)?;
+        }
+        Ok(shot)
+    }
+
+    pub fn finish_log(&mut self, log: &mut RunLog<impl Write>) -> io::Result<()> {
+        // writes the stats of the shots since the last batch_stats line, if there were any.
+        let stats = self.take_stats();
+        if stats.shots > 0 {
+            log.batch_stats(self.next_shot - stats.shots, &stats)?;
+        }
+        Ok(())
+    }
+
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(
+            level = "info",
+            skip_all,
+            fields(
+                shots,
+                seed = self.seed,
+                first_shot = self.next_shot,
+            )
+        )
+    )]
+    pub fn sample(&mut self, shots: usize) -> Result<Vec<Vec<bool>>, &'static str> {
+        (0..shots).map(|_| self.sample_shot()).collect()
+    }
+
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(
+            level = "info",
+            skip_all,
+            fields(
+                shots = sweeps.len(),
+                seed = self.seed,
+                first_shot = self.next_shot,
+            )
+        )
+    )]
+    pub fn sample_swept(&mut self, sweeps: &[Vec<bool>]) -> Result<Vec<Vec<bool>>, &'static str> {
+        // one shot per sweep configuration, so a single batch can mix experiment variants
+        // (different initial states, say) that share the rest of the circuit.
+        sweeps
+            .iter()
+            .map(|sweep_bits| self.sample_swept_shot(sweep_bits))
+            .collect()
+    }
+
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(
+            level = "info",
+            skip_all,
+            fields(
+                shots,
+                seed = self.seed,
+                first_shot = self.next_shot,
+                ?format,
+            )
+        )
+    )]
+    pub fn write(
+        &mut self,
+        shots: usize,
+        format: ShotFormat,
+        writer: &mut impl Write,
+    ) -> io::Result<()> {
+        // streams shots out one at a time, so huge shot counts don't have to fit in memory.
+        for _ in 0..shots {
+            let shot = self.sample_shot().map_err(io::Error::other)?;
+            shot_format::write_shot(writer, format, &shot)?;
+        }
+        Ok(())
+    }
+
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(
+            level = "info",
+            skip_all,
+            fields(
+                shots,
+                seed = self.seed,
+                first_shot = self.next_shot,
+                ?format,
+            )
+        )
+    )]
+    pub fn export(
+        &mut self,
+        shots: usize,
+        format: ExportFormat,
+        writer: &mut impl Write,
+    ) -> io::Result<()> {
+        // like write, but as CSV or JSON Lines rows tagged with the seed and circuit.
+        let metadata = RunMetadata::new(self.circuit, self.seed, shots);
+        let mut exporter = ShotExporter::new(writer, format, metadata);
+        for _ in 0..shots {
+            let shot = self.sample_shot().map_err(io::Error::other)?;
+            exporter.write_shot(&shot)?;
+        }
+        Ok(())
+    }
+
+    pub fn sample_logged(
+        &mut self,
+        shots: usize,
+        log: &mut RunLog<impl Write>,
+    ) -> io::Result<Vec<Vec<bool>>> {
+        // sample, with a whole run's worth of events in the log: run_started, the shots,
+        // their batch stats and run_finished.
+        log.run_started(&RunMetadata::new(self.circuit, self.seed, shots))?;
+        let sampled = (0..shots)
+            .map(|_| self.sample_shot_logged(log))
+            .collect::<io::Result<Vec<_>>>()?;
+        self.finish_log(log)?;
+        log.run_finished(shots, None)?;
+        Ok(sampled)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::gates::Gate;
+    use crate::qubit::Qubit;
+
+    #[test]
+    fn test_bell_pair_shots_agree() {
+        let mut circuit = Circuit::bell_pair(Qubit(0), Qubit(1));
+        circuit.measure(Qubit(0)).measure(Qubit(1));
+        let shots = Sampler::new(&circuit, 5).sample(50).unwrap();
+        assert!(shots.iter().all(|shot| shot[0] == shot[1]));
+        assert!(shots.iter().any(|shot| shot[0]) && shots.iter().any(|shot| !shot[0]));
+
+        let mut sampler = Sampler::new(&circuit, 5);
+        sampler.enable_stats();
+        assert_eq!(sampler.sample(50).unwrap(), shots);
+        let stats = sampler.take_stats();
+        assert_eq!((stats.shots, stats.measurements), (50, 100));
+        assert_eq!(stats.simulator.random_measurements, 50);
+        assert_eq!(stats.simulator.rng_draws, 50);
+        assert!(stats.shots_per_second() > 0.0);
+        assert_eq!(sampler.stats(), Some(&SamplerStats::default()));
+    }
+
+    #[test]
+    fn test_write_matches_sample() {
+        let mut circuit = Circuit::ghz(&[Qubit(0), Qubit(1), Qubit(2)]);
+        circuit
+            .measure(Qubit(0))
+            .measure(Qubit(1))
+            .measure(Qubit(2));
+        let shots = Sampler::new(&circuit, 9).sample(20).unwrap();
+        let mut written = Vec::new();
+        Sampler::new(&circuit, 9)
+            .write(20, ShotFormat::Hits, &mut written)
+            .unwrap();
+        assert_eq!(
+            shot_format::read_shots(&written[..], ShotFormat::Hits, 3).unwrap(),
+            shots
+        );
+    }
+
+    #[test]
+    fn test_logged_run() {
+        let mut circuit = Circuit::bell_pair(Qubit(0), Qubit(1));
+        circuit.measure(Qubit(0)).measure(Qubit(1));
+        let shots = Sampler::new(&circuit, 3).sample(5).unwrap();
+        let mut log = RunLog::new(Vec::new())
+            .with_instructions()
+            .with_batch_size(2);
+        let mut sampler = Sampler::new(&circuit, 3);
+        assert_eq!(sampler.sample_logged(5, &mut log).unwrap(), shots);
+        let text = String::from_utf8(log.into_inner()).unwrap();
+        let lines: Vec<&str> = text.lines().collect();
+        let count = |event: &str| {
+            let tag = format!("\"event\":\"{event}\"");
+            lines.iter().filter(|line| line.contains(&tag)).count()
+        };
+        assert_eq!(count("run_started"), 1);
+        // H, CX and two measurements per shot.
+        assert_eq!(count("instruction_applied"), 20);
+        assert_eq!(count("shot_completed"), 5);
+        // shots 0-1, 2-3 and 4.
+        assert_eq!(count("batch_stats"), 3);
+        assert!(lines[lines.len() - 2].contains("\"first_shot\":4,\"shots\":1,"));
+        assert_eq!(
+            lines.last(),
+            Some(&"{\"event\":\"run_finished\",\"shots\":5}")
+        );
+        let seed = format!("\"seed\":\"{}\"", shot_seed(3, 1));
+        assert!(lines
+            .iter()
+            .any(|line| line.contains("\"shot\":1,") && line.contains(&seed)));
+    }
+
+    #[test]
+    fn test_sweep_bits_pick_the_variant() {
+        // prepares |1> on the shots whose sweep bit is set, and |0> on the rest.
+        let mut circuit = Circuit::new(1);
+        circuit
+            .sweep_conditional(0, Gate::X(Qubit(0)))
+            .measure(Qubit(0));
+        let sweeps = vec![vec![true], vec![false], vec![], vec![true, false]];
+        assert_eq!(
+            Sampler::new(&circuit, 1).sample_swept(&sweeps).unwrap(),
+            vec![vec![true], vec![false], vec![false], vec![true]]
+        );
+        assert_eq!(circuit.num_sweep_bits(), 1);
+    }
+
+    #[test]
+    fn test_shot_seeds_are_stable() {
+        // the first two outputs of SplitMix64 seeded with 0. If these change, old results
+        // stop being reproducible.
+        assert_eq!(shot_seed(0, 0), 0xe220_a839_7b1d_cdaf);
+        assert_eq!(shot_seed(0, 1), 0x6e78_9e6a_a1b9_65f4);
+
+        let mut circuit = Circuit::ghz(&[Qubit(0), Qubit(1), Qubit(2), Qubit(3)]);
+        circuit
+            .measure(Qubit(0))
+            .measure(Qubit(1))
+            .measure(Qubit(2))
+            .measure(Qubit(3));
+        let shots = Sampler::new(&circuit, 4).sample(30).unwrap();
+        let tail = Sampler::new(&circuit, 4)
+            .starting_at(18)
+            .sample(12)
+            .unwrap();
+        assert_eq!(tail, shots[18..]);
+    }
+}
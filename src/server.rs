@@ -0,0 +1,606 @@
+use crate::circuit::Circuit;
+use crate::dem::DetectorErrorModel;
+use crate::noise::NoiseModel;
+use crate::parser;
+use crate::sampler::Sampler;
+use serde_json::{json, Value};
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream};
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::{mpsc, Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+
+// a small REST server, so the simulator can sit behind a web frontend or take jobs from a
+// remote experiment runner. It speaks just enough HTTP/1.1 for that, one request per
+// connection and no keep-alive, so nothing beyond serde_json needs pulling in:
+//
+//     GET  /health        "ok"
+//     POST /circuits      a circuit in the text format (see parser) as the body. It's kept
+//                         for later requests, and the reply is {"id": ..., "qubits": ...,
+//                         "measurements": ..., "detectors": ..., "observables": ...}
+//     POST /sample        {"circuit": <text> or "circuit_id": <id>, "shots": 100, "seed": 0,
+//                         "noise": {...}}, answered with a stream of JSON lines, one per shot
+//                         as it's sampled: {"shot": 0, "measurements": "0110", "detectors":
+//                         "01"}, sent with chunked encoding so clients can start on the first
+//                         shots while the rest are still running
+//     POST /dem           {"circuit" or "circuit_id", "noise": {...}}, answered with the
+//                         detector error model in stim's text format
+//
+// Noise is {"depolarizing": p, "measurement": p, "idle": p}, each optional, with
+// depolarizing setting the measurement flip rate too unless measurement is given. Errors
+// come back as {"error": "..."} with a 4xx status (or 507 once the circuit store is full).
+//
+// Anyone who can reach the port can send it work, so ServerLimits caps the size of that work,
+// how many connections are served at once, and how long a connection may sit idle.
+
+// requests bigger than this are turned away, since bodies are read into memory.
+const MAX_BODY: usize = 16 << 20;
+// the request line and each header line, and how many headers there can be.
+const MAX_HEADER_LINE: usize = 8 << 10;
+const MAX_HEADERS: usize = 100;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ServerLimits {
+    // circuits on more qubits than this are refused, since the tableau is quadratic in them.
+    pub max_qubits: usize,
+    pub max_shots: u64,
+    // how many circuits POST /circuits keeps before refusing more.
+    pub max_circuits: usize,
+    // the most any one request may cost, counted as instructions times qubits (what a
+    // tableau pass over the circuit is proportional to) summed over its shots. /dem counts
+    // as a single shot.
+    pub max_work: u64,
+    // how many connections are served at once, each by a worker thread. Others wait their
+    // turn in the listen backlog. Must be nonzero.
+    pub max_connections: usize,
+    // how long a read or write on a connection may block before it's dropped. Must be
+    // nonzero.
+    pub timeout: Duration,
+}
+
+impl Default for ServerLimits {
+    fn default() -> ServerLimits {
+        ServerLimits {
+            max_qubits: 10_000,
+            max_shots: 1_000_000,
+            max_circuits: 1_000,
+            max_work: 100_000_000_000,
+            max_connections: 16,
+            timeout: Duration::from_secs(30),
+        }
+    }
+}
+
+impl ServerLimits {
+    pub fn validate(&self) -> Result<(), &'static str> {
+        // a zero timeout can't be set on a socket, so every connection would be dropped.
+        if self.timeout.is_zero() {
+            return Err("The connection timeout must be nonzero.");
+        }
+        if self.max_connections == 0 {
+            return Err("The server needs to serve at least one connection at a time.");
+        }
+        Ok(())
+    }
+}
+
+pub struct Server {
+    listener: TcpListener,
+    circuits: Arc<Mutex<Vec<Circuit>>>,
+    limits: Arc<ServerLimits>,
+}
+
+struct Request {
+    method: String,
+    path: String,
+    body: String,
+}
+
+struct Failure {
+    status: &'static str,
+    message: String,
+}
+
+fn bad_request(message: impl Into<String>) -> Failure {
+    Failure {
+        status: "400 Bad Request",
+        message: message.into(),
+    }
+}
+
+fn read_line(stream: &mut BufReader<TcpStream>) -> Result<String, Failure> {
+    // one line of the request head, refusing ones longer than MAX_HEADER_LINE rather than
+    // buffering whatever the client sends.
+    let mut line = String::new();
+    stream
+        .by_ref()
+        .take(MAX_HEADER_LINE as u64)
+        .read_line(&mut line)
+        .map_err(|_| bad_request("couldn't read the request"))?;
+    if line.len() == MAX_HEADER_LINE && !line.ends_with('\n') {
+        return Err(headers_too_large());
+    }
+    Ok(line)
+}
+
+fn headers_too_large() -> Failure {
+    Failure {
+        status: "431 Request Header Fields Too Large",
+        message: "request head too large".to_string(),
+    }
+}
+
+fn read_request(stream: &mut BufReader<TcpStream>) -> Result<Request, Failure> {
+    let line = read_line(stream)?;
+    let mut parts = line.split_whitespace();
+    let (Some(method), Some(path)) = (parts.next(), parts.next()) else {
+        return Err(bad_request("malformed request line"));
+    };
+    let (method, path) = (method.to_string(), path.to_string());
+    let mut content_length = 0;
+    for num_headers in 0.. {
+        let header = read_line(stream)?;
+        let header = header.trim_end();
+        if header.is_empty() {
+            break;
+        }
+        if num_headers == MAX_HEADERS {
+            return Err(headers_too_large());
+        }
+        if let Some((name, value)) = header.split_once(':') {
+            if name.eq_ignore_ascii_case("content-length") {
+                content_length = value
+                    .trim()
+                    .parse()
+                    .map_err(|_| bad_request("bad Content-Length"))?;
+            }
+        }
+    }
+    if content_length > MAX_BODY {
+        return Err(Failure {
+            status: "413 Payload Too Large",
+            message: "request body too large".to_string(),
+        });
+    }
+    let mut body = vec![0; content_length];
+    stream
+        .read_exact(&mut body)
+        .map_err(|_| bad_request("request body ended early"))?;
+    let body = String::from_utf8(body).map_err(|_| bad_request("body isn't UTF-8"))?;
+    Ok(Request { method, path, body })
+}
+
+fn respond(stream: &mut TcpStream, status: &str, content_type: &str, body: &str) -> io::Result<()> {
+    write!(
+        stream,
+        "HTTP/1.1 {status}\r\nContent-Type: {content_type}\r\nContent-Length: {}\r\n\
+         Connection: close\r\n\r\n{body}",
+        body.len()
+    )
+}
+
+fn noise_model(request: &Value) -> Result<NoiseModel, Failure> {
+    let mut noise = NoiseModel::noiseless();
+    let Some(spec) = request.get("noise") else {
+        return Ok(noise);
+    };
+    let rate = |name: &str| -> Result<Option<f64>, Failure> {
+        match spec.get(name) {
+            None => Ok(None),
+            Some(p) => match p.as_f64() {
+                Some(p) if (0.0..=1.0).contains(&p) => Ok(Some(p)),
+                _ => Err(bad_request(format!(
+                    "noise.{name} should be between 0 and 1"
+                ))),
+            },
+        }
+    };
+    if let Some(p) = rate("depolarizing")? {
+        noise = NoiseModel::uniform_depolarizing(p);
+    }
+    if let Some(p) = rate("measurement")? {
+        noise.measurement_flip = p;
+    }
+    if let Some(p) = rate("idle")? {
+        noise.idle_depolarization = p;
+    }
+    Ok(noise)
+}
+
+fn bits(values: &[bool]) -> String {
+    values
+        .iter()
+        .map(|&bit| if bit { '1' } else { '0' })
+        .collect()
+}
+
+fn write_chunk(stream: &mut TcpStream, data: &str) -> io::Result<()> {
+    write!(stream, "{:x}\r\n{data}\r\n", data.len())
+}
+
+impl Server {
+    pub fn bind(address: &str) -> io::Result<Server> {
+        Ok(Server {
+            listener: TcpListener::bind(address)?,
+            circuits: Arc::new(Mutex::new(Vec::new())),
+            limits: Arc::new(ServerLimits::default()),
+        })
+    }
+
+    pub fn with_limits(mut self, limits: ServerLimits) -> Result<Server, &'static str> {
+        limits.validate()?;
+        self.limits = Arc::new(limits);
+        Ok(self)
+    }
+
+    pub fn local_addr(&self) -> io::Result<SocketAddr> {
+        self.listener.local_addr()
+    }
+
+    pub fn run(&self) -> io::Result<()> {
+        // serves forever. Connections go to a fixed pool of max_connections workers over a
+        // channel with no room in it, so once every worker is busy the accept loop waits,
+        // and new clients queue in the listen backlog rather than getting a thread each.
+        // A failed accept (say, out of file descriptors) only costs that connection, so the
+        // loop backs off for a moment rather than giving up. It's reported as a tracing
+        // event when that feature is on.
+        let (sender, receiver) = mpsc::sync_channel::<TcpStream>(0);
+        let receiver = Arc::new(Mutex::new(receiver));
+        for _ in 0..self.limits.max_connections {
+            let receiver = Arc::clone(&receiver);
+            let circuits = Arc::clone(&self.circuits);
+            let limits = Arc::clone(&self.limits);
+            thread::spawn(move || loop {
+                let stream = receiver.lock().expect("no worker panicked").recv();
+                let Ok(stream) = stream else {
+                    return;
+                };
+                // a client that hangs up early (or stalls past the timeout) is its own
+                // problem, and a panic only loses its own connection, not the worker.
+                let _ =
+                    panic::catch_unwind(AssertUnwindSafe(|| handle(stream, &circuits, &limits)));
+            });
+        }
+        for stream in self.listener.incoming() {
+            match stream {
+                Ok(stream) => {
+                    if sender.send(stream).is_err() {
+                        return Err(io::Error::other("every worker thread has stopped"));
+                    }
+                }
+                Err(_error) => {
+                    #[cfg(feature = "tracing")]
+                    tracing::warn!(error = %_error, "accepting a connection failed");
+                    thread::sleep(Duration::from_millis(100));
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+fn handle(
+    stream: TcpStream,
+    circuits: &Mutex<Vec<Circuit>>,
+    limits: &ServerLimits,
+) -> io::Result<()> {
+    stream.set_read_timeout(Some(limits.timeout))?;
+    stream.set_write_timeout(Some(limits.timeout))?;
+    let mut reader = BufReader::new(stream.try_clone()?);
+    let mut stream = stream;
+    let result = read_request(&mut reader).and_then(|request| route(&request, circuits, limits));
+    match result {
+        Ok(Reply::Json(value)) => respond(&mut stream, "200 OK", "application/json", &value),
+        Ok(Reply::Text(text)) => respond(&mut stream, "200 OK", "text/plain", &text),
+        Ok(Reply::Shots(job)) => stream_shots(&mut stream, &job),
+        Err(failure) => respond(
+            &mut stream,
+            failure.status,
+            "application/json",
+            &json!({ "error": failure.message }).to_string(),
+        ),
+    }
+}
+
+struct SampleJob {
+    circuit: Circuit,
+    noise: NoiseModel,
+    shots: u64,
+    seed: u64,
+}
+
+enum Reply {
+    Json(String),
+    Text(String),
+    Shots(Box<SampleJob>),
+}
+
+fn route(
+    request: &Request,
+    circuits: &Mutex<Vec<Circuit>>,
+    limits: &ServerLimits,
+) -> Result<Reply, Failure> {
+    match (request.method.as_str(), request.path.as_str()) {
+        ("GET", "/health") => Ok(Reply::Text("ok".to_string())),
+        ("POST", "/circuits") => {
+            let circuit = parse_circuit(&request.body, limits)?;
+            let mut summary = json!({
+                "qubits": circuit.num_qubits(),
+                "measurements": circuit.num_measurements(),
+                "detectors": circuit.detectors().len(),
+                "observables": circuit.observables().len(),
+            });
+            let mut circuits = circuits.lock().expect("no request panicked");
+            if circuits.len() >= limits.max_circuits {
+                return Err(Failure {
+                    status: "507 Insufficient Storage",
+                    message: format!("the server already holds {} circuits", circuits.len()),
+                });
+            }
+            circuits.push(circuit);
+            summary["id"] = json!(circuits.len() - 1);
+            Ok(Reply::Json(summary.to_string()))
+        }
+        ("POST", "/sample") => {
+            let body = json_body(request)?;
+            let shots = body.get("shots").map_or(Some(1), Value::as_u64);
+            let seed = body.get("seed").map_or(Some(0), Value::as_u64);
+            let (Some(shots), Some(seed)) = (shots, seed) else {
+                return Err(bad_request("shots and seed should be whole numbers"));
+            };
+            if shots > limits.max_shots {
+                return Err(bad_request(format!(
+                    "at most {} shots per request",
+                    limits.max_shots
+                )));
+            }
+            let circuit = circuit_of(&body, circuits, limits)?;
+            check_work(&circuit, shots, limits)?;
+            Ok(Reply::Shots(Box::new(SampleJob {
+                circuit,
+                noise: noise_model(&body)?,
+                shots,
+                seed,
+            })))
+        }
+        ("POST", "/dem") => {
+            let body = json_body(request)?;
+            let circuit = circuit_of(&body, circuits, limits)?;
+            check_work(&circuit, 1, limits)?;
+            let model = DetectorErrorModel::from_circuit(&circuit, &noise_model(&body)?)
+                .map_err(bad_request)?;
+            Ok(Reply::Text(model.to_string()))
+        }
+        (_, "/health" | "/circuits" | "/sample" | "/dem") => Err(Failure {
+            status: "405 Method Not Allowed",
+            message: format!("{} isn't supported on {}", request.method, request.path),
+        }),
+        _ => Err(Failure {
+            status: "404 Not Found",
+            message: format!("nothing at {}", request.path),
+        }),
+    }
+}
+
+fn json_body(request: &Request) -> Result<Value, Failure> {
+    serde_json::from_str(&request.body).map_err(|_| bad_request("body isn't valid JSON"))
+}
+
+fn parse_circuit(text: &str, limits: &ServerLimits) -> Result<Circuit, Failure> {
+    // parsing only records instructions, so the qubit count is checked before anything
+    // allocates per qubit.
+    let circuit = parser::parse(text).map_err(|error| bad_request(error.to_string()))?;
+    if circuit.num_qubits() > limits.max_qubits {
+        return Err(bad_request(format!(
+            "circuit uses {} qubits, more than the {} allowed",
+            circuit.num_qubits(),
+            limits.max_qubits
+        )));
+    }
+    Ok(circuit)
+}
+
+fn check_work(circuit: &Circuit, shots: u64, limits: &ServerLimits) -> Result<(), Failure> {
+    let work = (circuit.instructions().len() as u64)
+        .saturating_mul(circuit.num_qubits().max(1) as u64)
+        .saturating_mul(shots);
+    if work > limits.max_work {
+        return Err(bad_request(format!(
+            "the request would take {work} units of work, more than the {} allowed",
+            limits.max_work
+        )));
+    }
+    Ok(())
+}
+
+fn circuit_of(
+    body: &Value,
+    circuits: &Mutex<Vec<Circuit>>,
+    limits: &ServerLimits,
+) -> Result<Circuit, Failure> {
+    // the request's circuit, given inline or by the id /circuits handed out.
+    if let Some(text) = body.get("circuit").and_then(Value::as_str) {
+        return parse_circuit(text, limits);
+    }
+    let id = body
+        .get("circuit_id")
+        .and_then(Value::as_u64)
+        .ok_or_else(|| bad_request("expected a circuit or a circuit_id"))?;
+    let circuits = circuits.lock().expect("no request panicked");
+    circuits.get(id as usize).cloned().ok_or(Failure {
+        status: "404 Not Found",
+        message: format!("no circuit with id {id}"),
+    })
+}
+
+fn stream_shots(stream: &mut TcpStream, job: &SampleJob) -> io::Result<()> {
+    // shots go out as they're sampled. A failure partway through can't change the status any
+    // more, so it's reported as a last {"error": ...} line.
+    write!(
+        stream,
+        "HTTP/1.1 200 OK\r\nContent-Type: application/x-ndjson\r\n\
+         Transfer-Encoding: chunked\r\nConnection: close\r\n\r\n"
+    )?;
+    let mut sampler = Sampler::new(&job.circuit, job.seed).with_noise(job.noise.clone());
+    for shot in 0..job.shots {
+        let line = match sampler.sample_shot() {
+            Ok(measurements) => json!({
+                "shot": shot,
+                "measurements": bits(&measurements),
+                "detectors": bits(&job.circuit.detector_values(&measurements)),
+            }),
+            Err(error) => {
+                write_chunk(stream, &format!("{}\n", json!({ "error": error })))?;
+                break;
+            }
+        };
+        write_chunk(stream, &format!("{line}\n"))?;
+    }
+    write!(stream, "0\r\n\r\n")?;
+    stream.flush()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn request(address: SocketAddr, method: &str, path: &str, body: &str) -> String {
+        let mut stream = TcpStream::connect(address).unwrap();
+        write!(
+            stream,
+            "{method} {path} HTTP/1.1\r\nHost: test\r\nContent-Length: {}\r\n\r\n{body}",
+            body.len()
+        )
+        .unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).unwrap();
+        response
+    }
+
+    #[test]
+    fn test_server() {
+        let server = Server::bind("127.0.0.1:0").unwrap();
+        let address = server.local_addr().unwrap();
+        thread::spawn(move || server.run());
+
+        assert!(request(address, "GET", "/health", "").ends_with("\r\n\r\nok"));
+        let created = request(
+            address,
+            "POST",
+            "/circuits",
+            "H 0\nCX 0 1\nM 0 1\nDETECTOR rec[-1] rec[-2]",
+        );
+        assert!(created.starts_with("HTTP/1.1 200 OK"));
+        assert!(created.contains("\"id\":0"));
+        assert!(created.contains("\"detectors\":1"));
+
+        let sampled = request(
+            address,
+            "POST",
+            "/sample",
+            r#"{"circuit_id": 0, "shots": 3, "seed": 5}"#,
+        );
+        assert!(sampled.contains("Transfer-Encoding: chunked"));
+        let shots: Vec<Value> = sampled
+            .lines()
+            .filter(|line| line.starts_with('{'))
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+        assert_eq!(shots.len(), 3);
+        for shot in &shots {
+            let measurements = shot["measurements"].as_str().unwrap();
+            assert!(measurements == "00" || measurements == "11");
+            assert_eq!(shot["detectors"], "0");
+        }
+        assert!(sampled.ends_with("0\r\n\r\n"));
+
+        let dem = request(
+            address,
+            "POST",
+            "/dem",
+            r#"{"circuit": "M 0\nDETECTOR rec[-1]", "noise": {"measurement": 0.125}}"#,
+        );
+        assert!(dem.ends_with("error(0.125) D0\n"));
+        assert!(
+            request(address, "POST", "/sample", r#"{"circuit_id": 7}"#).starts_with("HTTP/1.1 404")
+        );
+        assert!(request(address, "POST", "/dem", "not json").starts_with("HTTP/1.1 400"));
+        assert!(request(address, "GET", "/dem", "").starts_with("HTTP/1.1 405"));
+    }
+
+    #[test]
+    fn test_limits() {
+        let limits = ServerLimits {
+            max_qubits: 4,
+            max_shots: 10,
+            max_circuits: 1,
+            max_work: 120,
+            max_connections: 1,
+            timeout: Duration::from_millis(200),
+        };
+        let server = Server::bind("127.0.0.1:0")
+            .unwrap()
+            .with_limits(limits.clone())
+            .unwrap();
+        let address = server.local_addr().unwrap();
+        thread::spawn(move || server.run());
+
+        let too_wide = request(address, "POST", "/circuits", "H 4000000000");
+        assert!(too_wide.starts_with("HTTP/1.1 400"));
+        assert!(too_wide.contains("more than the 4 allowed"));
+        let dem = r#"{"circuit": "H 4000000000"}"#;
+        assert!(request(address, "POST", "/dem", dem).starts_with("HTTP/1.1 400"));
+        assert!(request(address, "POST", "/circuits", "H 3").starts_with("HTTP/1.1 200"));
+        assert!(request(address, "POST", "/circuits", "H 0").starts_with("HTTP/1.1 507"));
+        let sample = |shots: u64| {
+            let body = format!(r#"{{"circuit_id": 0, "shots": {shots}}}"#);
+            request(address, "POST", "/sample", &body)
+        };
+        assert!(sample(10).starts_with("HTTP/1.1 200"));
+        assert!(sample(11).starts_with("HTTP/1.1 400"));
+        // 14 instructions on 4 qubits is 56 units a shot.
+        let busy = r#"{"circuit": "H 0 1 2 3\nCX 0 1 2 3\nM 0 1 2 3 0 1 2 3", "shots": 3}"#;
+        let refused = request(address, "POST", "/sample", busy);
+        assert!(refused.starts_with("HTTP/1.1 400"));
+        assert!(refused.contains("more than the 120 allowed"));
+        let busy = r#"{"circuit": "H 0 1 2 3\nCX 0 1 2 3\nM 0 1 2 3 0 1 2 3", "shots": 2}"#;
+        assert!(request(address, "POST", "/sample", busy).starts_with("HTTP/1.1 200"));
+
+        // exactly as much as the server will read of a line, so none is left unread when it
+        // hangs up.
+        let long_header = format!("X-Padding: {}", "a".repeat(MAX_HEADER_LINE - 11));
+        let mut stream = TcpStream::connect(address).unwrap();
+        write!(stream, "GET /health HTTP/1.1\r\n{long_header}").unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).unwrap();
+        assert!(response.starts_with("HTTP/1.1 431"));
+
+        // a client that never finishes its request is dropped once the timeout passes, and
+        // until then it holds the only worker, so the next client waits its turn.
+        let mut stalled = TcpStream::connect(address).unwrap();
+        write!(stalled, "GET /health HTTP/1.1\r\n").unwrap();
+        let start = std::time::Instant::now();
+        thread::sleep(Duration::from_millis(50));
+        assert!(request(address, "GET", "/health", "").ends_with("\r\n\r\nok"));
+        assert!(start.elapsed() >= Duration::from_millis(150));
+        let mut response = String::new();
+        stalled.read_to_string(&mut response).unwrap();
+        assert!(response.starts_with("HTTP/1.1 400"));
+
+        for broken in [
+            ServerLimits {
+                timeout: Duration::ZERO,
+                ..limits.clone()
+            },
+            ServerLimits {
+                max_connections: 0,
+                ..limits
+            },
+        ] {
+            assert!(broken.validate().is_err());
+            let server = Server::bind("127.0.0.1:0").unwrap();
+            assert!(server.with_limits(broken).is_err());
+        }
+    }
+}
@@ -0,0 +1,161 @@
+use crate::circuit::Circuit;
+use crate::mbqc::MeasurementBasis;
+use crate::pauli::PauliString;
+use crate::qubit::Qubit;
+use crate::stabilizer_simulator::StabilizerSimulator;
+use rand::{Rng, SeedableRng};
+
+// classical shadows (Huang, Kueng & Preskill) with random pauli measurements: prepare the
+// state, measure every qubit in a uniformly random X, Y or Z basis, and keep the bases and
+// outcomes as a snapshot. Inverting the measurement channel gives an unbiased estimator for
+// any pauli observable P: a snapshot whose bases agree with P wherever P isn't the identity
+// contributes 3^weight(P) times the product of its outcomes' eigenvalues there, and every
+// other snapshot contributes 0.
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Snapshot {
+    pub bases: Vec<MeasurementBasis>,
+    pub outcomes: Vec<bool>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ClassicalShadow {
+    num_qubits: usize,
+    snapshots: Vec<Snapshot>,
+}
+
+impl ClassicalShadow {
+    pub fn collect(
+        preparation: &Circuit,
+        num_snapshots: usize,
+        seed: u64,
+    ) -> Result<ClassicalShadow, &'static str> {
+        // the preparation is rerun from scratch for every snapshot, so it may contain
+        // measurements of its own.
+        let num_qubits = preparation.num_qubits();
+        let mut rng = rand::rngs::StdRng::seed_from_u64(seed);
+        let mut snapshots = Vec::with_capacity(num_snapshots);
+        for _ in 0..num_snapshots {
+            let mut simulator = StabilizerSimulator::new(num_qubits, rng.gen());
+            simulator.run(preparation)?;
+            let mut bases = Vec::with_capacity(num_qubits);
+            let mut outcomes = Vec::with_capacity(num_qubits);
+            for qubit in Qubit::range(num_qubits) {
+                let basis = match rng.gen_range(0..3) {
+                    0 => MeasurementBasis::X,
+                    1 => MeasurementBasis::Y,
+                    _ => MeasurementBasis::Z,
+                };
+                simulator.apply_gates(basis.rotation_to_z(qubit));
+                bases.push(basis);
+                outcomes.push(simulator.measure(qubit)?);
+            }
+            snapshots.push(Snapshot { bases, outcomes });
+        }
+        Ok(ClassicalShadow {
+            num_qubits,
+            snapshots,
+        })
+    }
+
+    pub fn snapshots(&self) -> &[Snapshot] {
+        &self.snapshots
+    }
+
+    fn snapshot_estimate(&self, snapshot: &Snapshot, observable: &PauliString) -> f64 {
+        let mut estimate = if observable.phase_is_negated {
+            -1.0
+        } else {
+            1.0
+        };
+        for (qubit, (&x, &z)) in observable.x_bits.iter().zip(&observable.z_bits).enumerate() {
+            let needed = match (x, z) {
+                (false, false) => continue,
+                (true, false) => MeasurementBasis::X,
+                (true, true) => MeasurementBasis::Y,
+                (false, true) => MeasurementBasis::Z,
+            };
+            if snapshot.bases[qubit] != needed {
+                return 0.0;
+            }
+            estimate *= if snapshot.outcomes[qubit] { -3.0 } else { 3.0 };
+        }
+        estimate
+    }
+
+    pub fn estimate(&self, observable: &PauliString) -> Result<f64, &'static str> {
+        // the mean over snapshots of the single-snapshot estimator.
+        if observable.num_qubits() != self.num_qubits {
+            return Err("Observable must act on as many qubits as the shadow.");
+        }
+        if self.snapshots.is_empty() {
+            return Err("Can't estimate anything from an empty shadow.");
+        }
+        let total: f64 = self
+            .snapshots
+            .iter()
+            .map(|snapshot| self.snapshot_estimate(snapshot, observable))
+            .sum();
+        Ok(total / self.snapshots.len() as f64)
+    }
+
+    pub fn median_of_means_estimate(
+        &self,
+        observable: &PauliString,
+        num_batches: usize,
+    ) -> Result<f64, &'static str> {
+        // split the snapshots into batches, average each, and take the median of those
+        // averages. More robust to the estimator's occasional huge values than the plain mean.
+        self.estimate(observable)?;
+        if num_batches == 0 || num_batches > self.snapshots.len() {
+            return Err("Need between 1 and num_snapshots batches.");
+        }
+        let batch_size = self.snapshots.len() / num_batches;
+        let mut means: Vec<f64> = self
+            .snapshots
+            .chunks_exact(batch_size)
+            .take(num_batches)
+            .map(|batch| {
+                batch
+                    .iter()
+                    .map(|snapshot| self.snapshot_estimate(snapshot, observable))
+                    .sum::<f64>()
+                    / batch_size as f64
+            })
+            .collect();
+        means.sort_by(|a, b| a.total_cmp(b));
+        Ok(means[means.len() / 2])
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_bell_state_shadow() {
+        let shadow =
+            ClassicalShadow::collect(&Circuit::bell_pair(Qubit(0), Qubit(1)), 3000, 0).unwrap();
+        for (observable, expected) in [("XX", 1.0), ("YY", -1.0), ("ZZ", 1.0), ("ZI", 0.0)] {
+            let estimate = shadow.estimate(&observable.parse().unwrap()).unwrap();
+            assert!(
+                (estimate - expected).abs() < 0.25,
+                "{observable}: {estimate}"
+            );
+            let robust = shadow
+                .median_of_means_estimate(&observable.parse().unwrap(), 10)
+                .unwrap();
+            assert!((robust - expected).abs() < 0.3, "{observable}: {robust}");
+        }
+        let mut negated: PauliString = "ZZ".parse().unwrap();
+        negated.phase_is_negated = true;
+        assert!(shadow.estimate(&negated).unwrap() < -0.75);
+    }
+
+    #[test]
+    fn test_estimate_rejects_wrong_size() {
+        let shadow = ClassicalShadow::collect(&Circuit::new(2), 10, 0).unwrap();
+        assert!(shadow.estimate(&"Z".parse().unwrap()).is_err());
+        assert_eq!(shadow.snapshots().len(), 10);
+    }
+}
@@ -0,0 +1,166 @@
+use std::io::{self, BufRead, Read, Write};
+use std::str::FromStr;
+
+// on-disk formats for sampled shots, named and laid out like stim's so existing decoding
+// scripts can read them:
+//
+//   01    one line per shot, a '0' or '1' per bit.
+//   b8    each shot packed into bytes, bit k in byte k/8 at position k%8 (little endian),
+//         padded with zeros up to a whole byte. Binary, no separators.
+//   hits  one line per shot, the comma separated indices of the bits that are 1.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ShotFormat {
+    Ascii01,
+    B8,
+    Hits,
+}
+
+impl FromStr for ShotFormat {
+    type Err = &'static str;
+
+    fn from_str(name: &str) -> Result<ShotFormat, &'static str> {
+        match name {
+            "01" => Ok(ShotFormat::Ascii01),
+            "b8" => Ok(ShotFormat::B8),
+            "hits" => Ok(ShotFormat::Hits),
+            _ => Err("Unknown shot format, expected one of 01, b8 or hits."),
+        }
+    }
+}
+
+pub fn write_shot(writer: &mut impl Write, format: ShotFormat, shot: &[bool]) -> io::Result<()> {
+    match format {
+        ShotFormat::Ascii01 => {
+            let line: String = shot
+                .iter()
+                .map(|&bit| if bit { '1' } else { '0' })
+                .collect();
+            writeln!(writer, "{line}")
+        }
+        ShotFormat::B8 => {
+            let mut bytes = vec![0u8; shot.len().div_ceil(8)];
+            for (k, &bit) in shot.iter().enumerate() {
+                bytes[k / 8] |= (bit as u8) << (k % 8);
+            }
+            writer.write_all(&bytes)
+        }
+        ShotFormat::Hits => {
+            let hits: Vec<String> = shot
+                .iter()
+                .enumerate()
+                .filter(|(_, &bit)| bit)
+                .map(|(k, _)| k.to_string())
+                .collect();
+            writeln!(writer, "{}", hits.join(","))
+        }
+    }
+}
+
+pub fn write_shots(
+    writer: &mut impl Write,
+    format: ShotFormat,
+    shots: &[Vec<bool>],
+) -> io::Result<()> {
+    for shot in shots {
+        write_shot(writer, format, shot)?;
+    }
+    Ok(())
+}
+
+pub fn read_shots(
+    reader: impl Read,
+    format: ShotFormat,
+    bits_per_shot: usize,
+) -> io::Result<Vec<Vec<bool>>> {
+    // b8 and hits don't record how long a shot is, so the caller has to say.
+    let invalid = |message: &'static str| io::Error::new(io::ErrorKind::InvalidData, message);
+    let mut shots = Vec::new();
+    match format {
+        ShotFormat::B8 => {
+            let mut bytes = Vec::new();
+            io::BufReader::new(reader).read_to_end(&mut bytes)?;
+            let bytes_per_shot = bits_per_shot.div_ceil(8);
+            if bytes_per_shot == 0 {
+                return Err(invalid("b8 shots need at least one bit."));
+            }
+            if bytes.len() % bytes_per_shot != 0 {
+                return Err(invalid("b8 data ends partway through a shot."));
+            }
+            for chunk in bytes.chunks(bytes_per_shot) {
+                shots.push(
+                    (0..bits_per_shot)
+                        .map(|k| chunk[k / 8] & (1 << (k % 8)) != 0)
+                        .collect(),
+                );
+            }
+        }
+        ShotFormat::Ascii01 | ShotFormat::Hits => {
+            for line in io::BufReader::new(reader).lines() {
+                let line = line?;
+                let mut shot = vec![false; bits_per_shot];
+                if format == ShotFormat::Ascii01 {
+                    if line.len() != bits_per_shot {
+                        return Err(invalid("01 line has the wrong number of bits."));
+                    }
+                    for (bit, c) in shot.iter_mut().zip(line.chars()) {
+                        *bit = match c {
+                            '0' => false,
+                            '1' => true,
+                            _ => return Err(invalid("01 lines may only contain 0 and 1.")),
+                        };
+                    }
+                } else {
+                    for hit in line.split(',').filter(|hit| !hit.is_empty()) {
+                        let k: usize = hit
+                            .trim()
+                            .parse()
+                            .map_err(|_| invalid("hits lines must be comma separated indices."))?;
+                        if k >= bits_per_shot {
+                            return Err(invalid("hit index is past the end of the shot."));
+                        }
+                        shot[k] = true;
+                    }
+                }
+                shots.push(shot);
+            }
+        }
+    }
+    Ok(shots)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_formats_round_trip() {
+        let shots = vec![
+            vec![
+                true, false, false, true, false, false, false, false, false, true,
+            ],
+            vec![false; 10],
+        ];
+        for format in [ShotFormat::Ascii01, ShotFormat::B8, ShotFormat::Hits] {
+            let mut written = Vec::new();
+            write_shots(&mut written, format, &shots).unwrap();
+            assert_eq!(read_shots(&written[..], format, 10).unwrap(), shots);
+        }
+    }
+
+    #[test]
+    fn test_format_layouts() {
+        let shot = [
+            true, false, false, true, false, false, false, false, false, true,
+        ];
+        let written = |format| {
+            let mut written = Vec::new();
+            write_shot(&mut written, format, &shot).unwrap();
+            written
+        };
+        assert_eq!(written(ShotFormat::Ascii01), b"1001000001\n");
+        assert_eq!(written(ShotFormat::B8), vec![0b0000_1001, 0b0000_0010]);
+        assert_eq!(written(ShotFormat::Hits), b"0,3,9\n");
+        assert_eq!("b8".parse(), Ok(ShotFormat::B8));
+        assert!("ptb64".parse::<ShotFormat>().is_err());
+    }
+}
@@ -0,0 +1,190 @@
+// the word-level kernels under the packed bit matrices: xor one run of words into another,
+// and count the ones in a run of words. There's a plain scalar version of each, and with the
+// simd feature on x86_64, AVX2 and AVX-512 versions too. Which one runs is decided once, from
+// what the CPU running the binary supports, not what it was built on, so one build can ship
+// to machines with and without the wide registers.
+//
+// The callers are the packed rows: the stabilizer tableau's rowsums and commutation checks
+// (see StabilizerSimulator and PauliView), and the gf2 matrices (row reduction, kernels, the
+// code constructions built on them). Gates only touch a bit or two of each tableau row, so
+// they work on the words in place rather than going through here.
+
+use std::sync::OnceLock;
+
+// how many bits (qubits, columns) the packed rows fit in a word.
+pub const WORD_BITS: usize = 64;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Kernel {
+    Scalar,
+    Avx2,
+    // AVX-512F for the xor, and the VPOPCNTDQ extension for counting.
+    Avx512,
+}
+
+impl Kernel {
+    pub fn detect() -> Kernel {
+        // the widest kernel this CPU can run, worked out on the first call.
+        static DETECTED: OnceLock<Kernel> = OnceLock::new();
+        *DETECTED.get_or_init(|| {
+            [Kernel::Avx512, Kernel::Avx2]
+                .into_iter()
+                .find(|kernel| kernel.is_available())
+                .unwrap_or(Kernel::Scalar)
+        })
+    }
+
+    pub fn is_available(self) -> bool {
+        match self {
+            Kernel::Scalar => true,
+            #[cfg(all(feature = "simd", target_arch = "x86_64"))]
+            Kernel::Avx2 => is_x86_feature_detected!("avx2"),
+            #[cfg(all(feature = "simd", target_arch = "x86_64"))]
+            Kernel::Avx512 => {
+                is_x86_feature_detected!("avx512f") && is_x86_feature_detected!("avx512vpopcntdq")
+            }
+            #[allow(unreachable_patterns)]
+            _ => false,
+        }
+    }
+
+    pub fn xor_into(self, target: &mut [u64], source: &[u64]) {
+        // target ^= source, word by word. A kernel the CPU can't run falls back to scalar.
+        assert_eq!(target.len(), source.len(), "xor of different lengths");
+        match self {
+            // SAFETY: is_available checked the CPU has the instructions.
+            #[cfg(all(feature = "simd", target_arch = "x86_64"))]
+            Kernel::Avx2 if self.is_available() => unsafe { x86::xor_avx2(target, source) },
+            #[cfg(all(feature = "simd", target_arch = "x86_64"))]
+            Kernel::Avx512 if self.is_available() => unsafe { x86::xor_avx512(target, source) },
+            _ => xor_scalar(target, source),
+        }
+    }
+
+    pub fn popcount(self, words: &[u64]) -> u64 {
+        match self {
+            // SAFETY: as above.
+            #[cfg(all(feature = "simd", target_arch = "x86_64"))]
+            Kernel::Avx2 if self.is_available() => unsafe { x86::popcount_avx2(words) },
+            #[cfg(all(feature = "simd", target_arch = "x86_64"))]
+            Kernel::Avx512 if self.is_available() => unsafe { x86::popcount_avx512(words) },
+            _ => popcount_scalar(words),
+        }
+    }
+}
+
+pub fn xor_into(target: &mut [u64], source: &[u64]) {
+    Kernel::detect().xor_into(target, source);
+}
+
+pub fn popcount(words: &[u64]) -> u64 {
+    Kernel::detect().popcount(words)
+}
+
+fn xor_scalar(target: &mut [u64], source: &[u64]) {
+    for (word, source) in target.iter_mut().zip(source) {
+        *word ^= source;
+    }
+}
+
+fn popcount_scalar(words: &[u64]) -> u64 {
+    words.iter().map(|word| word.count_ones() as u64).sum()
+}
+
+#[cfg(all(feature = "simd", target_arch = "x86_64"))]
+mod x86 {
+    // each kernel does as many whole registers as fit and hands the tail to the scalar one.
+    // Loads and stores are unaligned, since rows start wherever the matrix puts them.
+    use std::arch::x86_64::*;
+
+    #[target_feature(enable = "avx2")]
+    pub unsafe fn xor_avx2(target: &mut [u64], source: &[u64]) {
+        let whole = target.len() / 4 * 4;
+        for k in (0..whole).step_by(4) {
+            let t = target.as_mut_ptr().add(k) as *mut __m256i;
+            let s = source.as_ptr().add(k) as *const __m256i;
+            _mm256_storeu_si256(
+                t,
+                _mm256_xor_si256(_mm256_loadu_si256(t), _mm256_loadu_si256(s)),
+            );
+        }
+        super::xor_scalar(&mut target[whole..], &source[whole..]);
+    }
+
+    #[target_feature(enable = "avx512f")]
+    pub unsafe fn xor_avx512(target: &mut [u64], source: &[u64]) {
+        let whole = target.len() / 8 * 8;
+        for k in (0..whole).step_by(8) {
+            let t = target.as_mut_ptr().add(k) as *mut __m512i;
+            let s = source.as_ptr().add(k) as *const __m512i;
+            _mm512_storeu_si512(
+                t,
+                _mm512_xor_si512(_mm512_loadu_si512(t), _mm512_loadu_si512(s)),
+            );
+        }
+        super::xor_scalar(&mut target[whole..], &source[whole..]);
+    }
+
+    #[target_feature(enable = "avx2")]
+    pub unsafe fn popcount_avx2(words: &[u64]) -> u64 {
+        // AVX2 has no popcount, so count nibbles with a 16 entry lookup table (one shuffle
+        // per half byte), then sum the bytes of each 64 bit lane with sad against zero.
+        let lookup = _mm256_setr_epi8(
+            0, 1, 1, 2, 1, 2, 2, 3, 1, 2, 2, 3, 2, 3, 3, 4, 0, 1, 1, 2, 1, 2, 2, 3, 1, 2, 2, 3, 2,
+            3, 3, 4,
+        );
+        let low_nibbles = _mm256_set1_epi8(0x0f);
+        let mut totals = _mm256_setzero_si256();
+        let whole = words.len() / 4 * 4;
+        for k in (0..whole).step_by(4) {
+            let v = _mm256_loadu_si256(words.as_ptr().add(k) as *const __m256i);
+            let low = _mm256_and_si256(v, low_nibbles);
+            let high = _mm256_and_si256(_mm256_srli_epi16(v, 4), low_nibbles);
+            let counts = _mm256_add_epi8(
+                _mm256_shuffle_epi8(lookup, low),
+                _mm256_shuffle_epi8(lookup, high),
+            );
+            totals = _mm256_add_epi64(totals, _mm256_sad_epu8(counts, _mm256_setzero_si256()));
+        }
+        let mut lanes = [0u64; 4];
+        _mm256_storeu_si256(lanes.as_mut_ptr() as *mut __m256i, totals);
+        lanes.iter().sum::<u64>() + super::popcount_scalar(&words[whole..])
+    }
+
+    #[target_feature(enable = "avx512f,avx512vpopcntdq")]
+    pub unsafe fn popcount_avx512(words: &[u64]) -> u64 {
+        let mut totals = _mm512_setzero_si512();
+        let whole = words.len() / 8 * 8;
+        for k in (0..whole).step_by(8) {
+            let v = _mm512_loadu_si512(words.as_ptr().add(k) as *const __m512i);
+            totals = _mm512_add_epi64(totals, _mm512_popcnt_epi64(v));
+        }
+        _mm512_reduce_add_epi64(totals) as u64 + super::popcount_scalar(&words[whole..])
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use rand::{Rng, SeedableRng};
+
+    #[test]
+    fn test_kernels_agree_with_scalar() {
+        // lengths either side of a whole number of registers, so the tails get covered.
+        let mut rng = rand::rngs::StdRng::seed_from_u64(2);
+        let kernels = [Kernel::Scalar, Kernel::Avx2, Kernel::Avx512];
+        assert!(Kernel::detect().is_available());
+        for length in [0, 1, 3, 4, 7, 8, 9, 17, 64] {
+            let a: Vec<u64> = (0..length).map(|_| rng.gen()).collect();
+            let b: Vec<u64> = (0..length).map(|_| rng.gen()).collect();
+            let mut expected = a.clone();
+            xor_scalar(&mut expected, &b);
+            for kernel in kernels {
+                let mut xored = a.clone();
+                kernel.xor_into(&mut xored, &b);
+                assert_eq!(xored, expected, "{kernel:?}");
+                assert_eq!(kernel.popcount(&a), popcount_scalar(&a), "{kernel:?}");
+            }
+        }
+    }
+}
@@ -0,0 +1,100 @@
+use crate::circuit::{Circuit, Instruction};
+use crate::gates::Gate;
+use crate::qubit::Qubit;
+
+// what every backend has to provide to run circuits. The tableau (StabilizerSimulator) is
+// the default; others trade generality for speed on particular queries.
+pub trait Simulator {
+    fn num_qubits(&self) -> usize;
+    fn apply_gate(&mut self, gate: &Gate);
+    // measures in the Z basis and appends the outcome to the measurement record.
+    fn measure(&mut self, qubit: Qubit) -> Result<bool, &'static str>;
+    // every measurement outcome so far, oldest first.
+    fn measurement_record(&self) -> &[bool];
+    // the classical register bits measurements have been written to, by flat bit index.
+    // Bits nobody has written yet read as 0, like a freshly declared QASM creg.
+    fn classical_bits(&self) -> &[bool];
+    fn set_classical_bit(&mut self, bit: u32, value: bool);
+    // this shot's sweep bits. Bits past the end read as 0, like stim's missing sweep data.
+    fn sweep_bits(&self) -> &[bool];
+    // puts the qubit back in |0>, without touching the measurement record.
+    fn reset(&mut self, qubit: Qubit) -> Result<(), &'static str>;
+
+    fn apply_gates<I: IntoIterator<Item = Gate>>(&mut self, gates: I)
+    where
+        Self: Sized,
+    {
+        // streams gates in one after another. Backends that can batch or fuse gates override it.
+        for gate in gates {
+            self.apply_gate(&gate);
+        }
+    }
+
+    fn apply_inverse(&mut self, gate: &Gate) {
+        for inverse in gate.inverse() {
+            self.apply_gate(&inverse);
+        }
+    }
+
+    fn classical_bit(&self, bit: u32) -> bool {
+        self.classical_bits()
+            .get(bit as usize)
+            .copied()
+            .unwrap_or(false)
+    }
+
+    fn apply_instruction(&mut self, instruction: &Instruction) -> Result<(), &'static str> {
+        match instruction {
+            Instruction::Gate(gate) => self.apply_gate(gate),
+            Instruction::Measure(qubit) => {
+                self.measure(*qubit)?;
+            }
+            Instruction::Reset(qubit) | Instruction::PrepX(qubit) | Instruction::PrepY(qubit) => {
+                self.reset(*qubit)?;
+                for gate in instruction.preparation_gates() {
+                    self.apply_gate(&gate);
+                }
+            }
+            Instruction::Conditional { lookback, gate } => {
+                let record = self.measurement_record();
+                if *lookback == 0 || *lookback as usize > record.len() {
+                    return Err(
+                        "Measurement record lookback reaches before the first measurement.",
+                    );
+                }
+                if record[record.len() - *lookback as usize] {
+                    self.apply_gate(gate);
+                }
+            }
+            Instruction::MeasureToBit { qubit, bit } => {
+                let outcome = self.measure(*qubit)?;
+                self.set_classical_bit(*bit, outcome);
+            }
+            Instruction::ConditionalOnBit { bit, gate } => {
+                if self.classical_bit(*bit) {
+                    self.apply_gate(gate);
+                }
+            }
+            Instruction::SweepConditional { bit, gate } => {
+                if self.sweep_bits().get(*bit as usize) == Some(&true) {
+                    self.apply_gate(gate);
+                }
+            }
+            Instruction::Tick => {}
+        }
+        Ok(())
+    }
+
+    fn run(&mut self, circuit: &Circuit) -> Result<Vec<bool>, &'static str> {
+        // applies the circuit's instructions in order, and returns the outcomes of its
+        // measurements in the order they happened.
+        if circuit.num_qubits() > self.num_qubits() {
+            return Err("Circuit acts on more qubits than the simulator has.");
+        }
+        let record_start = self.measurement_record().len();
+        for instruction in circuit.instructions() {
+            self.apply_instruction(instruction)?;
+        }
+        Ok(self.measurement_record()[record_start..].to_vec())
+    }
+}
@@ -1,45 +1,288 @@
+use crate::circuit::{Circuit, Instruction};
 use crate::gates::Gate;
+use crate::gf2::BitMatrix;
+use crate::leakage::{LeakageModel, LeakyShot};
+use crate::noise::{self, NoiseModel};
+use crate::parser::{self, ParseError};
+use crate::pauli::{PauliString, PauliView};
+use crate::qubit::Qubit;
+use crate::simd::{self, WORD_BITS};
+use crate::simulator::Simulator;
+use crate::stats::{CountingRng, ExecutionStats};
+use crate::trace;
 use rand::Rng;
+use std::collections::HashMap;
+use std::io::{self, BufRead};
 use std::mem;
+use std::time::Instant;
 
-// TODO: const N is a choice. It makes things
-// easy, but it means
-// you can't determine the simulator size
-// dynamically. This is something to fix
-// later -- we should probably back storage
-// by vectors.
-#[derive(Debug, Clone)]
-struct TableauGeneratorRow<const N: usize> {
+// storage is backed by vectors so the register can be
+// sized when the simulator is constructed, and grown or
+// shrunk while it runs. The pauli is packed 64 qubits to a
+// word, x words first and then z words, so a rowsum is one
+// run of word xors (see simd).
+#[derive(Debug, Clone, Default)]
+struct TableauGeneratorRow {
     phase_is_negated: bool,
-    x_bits: [bool; N],
-    z_bits: [bool; N],
+    num_qubits: usize,
+    words: Vec<u64>,
+}
+
+impl From<&TableauGeneratorRow> for PauliString {
+    fn from(row: &TableauGeneratorRow) -> PauliString {
+        row.view().to_pauli_string()
+    }
+}
+
+impl TableauGeneratorRow {
+    fn view(&self) -> PauliView<'_> {
+        PauliView::packed(self.phase_is_negated, self.num_qubits, &self.words)
+    }
+
+    fn identity(num_qubits: usize) -> TableauGeneratorRow {
+        let mut row = TableauGeneratorRow::default();
+        row.set_identity(num_qubits);
+        row
+    }
+
+    fn set_identity(&mut self, num_qubits: usize) {
+        // like identity, but in place, so a row that's already the right size doesn't
+        // allocate.
+        self.phase_is_negated = false;
+        self.num_qubits = num_qubits;
+        self.words.clear();
+        self.words.resize(2 * num_qubits.div_ceil(WORD_BITS), 0);
+    }
+
+    fn from_pauli(pauli: &PauliString) -> TableauGeneratorRow {
+        let mut row = TableauGeneratorRow::identity(pauli.num_qubits());
+        row.phase_is_negated = pauli.phase_is_negated;
+        for j in 0..pauli.num_qubits() {
+            row.set_x(j, pauli.x_bits[j]);
+            row.set_z(j, pauli.z_bits[j]);
+        }
+        row
+    }
+
+    // where qubit j's x and z bits live: (word, mask) for x, and the z word is half the row
+    // further on.
+    fn position(&self, qubit: usize) -> (usize, u64) {
+        debug_assert!(qubit < self.num_qubits);
+        (qubit / WORD_BITS, 1 << (qubit % WORD_BITS))
+    }
+
+    fn x(&self, qubit: usize) -> bool {
+        let (word, mask) = self.position(qubit);
+        self.words[word] & mask != 0
+    }
+
+    fn z(&self, qubit: usize) -> bool {
+        let (word, mask) = self.position(qubit);
+        self.words[self.words.len() / 2 + word] & mask != 0
+    }
+
+    fn xor_x(&mut self, qubit: usize, bit: bool) {
+        let (word, mask) = self.position(qubit);
+        if bit {
+            self.words[word] ^= mask;
+        }
+    }
+
+    fn xor_z(&mut self, qubit: usize, bit: bool) {
+        let (word, mask) = self.position(qubit);
+        if bit {
+            let half = self.words.len() / 2;
+            self.words[half + word] ^= mask;
+        }
+    }
+
+    fn set_x(&mut self, qubit: usize, bit: bool) {
+        self.xor_x(qubit, self.x(qubit) ^ bit);
+    }
+
+    fn set_z(&mut self, qubit: usize, bit: bool) {
+        self.xor_z(qubit, self.z(qubit) ^ bit);
+    }
+
+    fn remap_qubits(&mut self, num_qubits: usize, source: impl Fn(usize) -> Option<usize>) {
+        // resizes the row to num_qubits, with qubit j taking the pauli old qubit source(j) had
+        // (or I for None). Growing, shrinking and relabelling the register all move bits
+        // between words, so they rebuild the row.
+        let old = mem::take(self);
+        self.set_identity(num_qubits);
+        self.phase_is_negated = old.phase_is_negated;
+        for j in 0..num_qubits {
+            if let Some(q) = source(j) {
+                self.set_x(j, old.x(q));
+                self.set_z(j, old.z(q));
+            }
+        }
+        // keep the old row's allocation if it's big enough.
+        if old.words.capacity() >= self.words.len() {
+            let mut words = old.words;
+            words.clear();
+            words.extend_from_slice(&self.words);
+            self.words = words;
+        }
+    }
+
+    fn commutes_with(&self, other: &TableauGeneratorRow) -> bool {
+        self.view().commutes_with(&other.view())
+    }
+}
+
+// what project_onto did: the eigenvalue the state ended up in, as a measurement outcome
+// (false for +1, true for -1), and whether that was forced because the state already was in
+// one of the eigenspaces.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Projection {
+    pub outcome: bool,
+    pub deterministic: bool,
+}
+
+// how the stabilizer generators split up across a cut of the register into a subsystem A
+// and the rest, B: independent generators acting only inside A, only inside B, and the
+// remaining ones that straddle the cut.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SubsystemRank {
+    pub num_qubits: usize,
+    pub inside: usize,
+    pub outside: usize,
+    pub crossing: usize,
+}
+
+impl SubsystemRank {
+    pub fn entanglement_entropy(&self) -> usize {
+        // the von neumann entropy of A's reduced state, in bits. That state is 2^-|A| times
+        // the sum of the inside stabilizers, so its spectrum is flat over 2^(|A| - inside)
+        // eigenvalues.
+        self.num_qubits - self.inside
+    }
+
+    pub fn purity(&self) -> f64 {
+        // tr(rho_A^2): 1 for a product across the cut, 2^-|A| for a maximally mixed A.
+        0.5f64.powi(self.entanglement_entropy() as i32)
+    }
 }
 
 // humble beginnings: slow stabilizer
 // simulator that tracks stabilizers and
 // destabilizers for n qubits, and supports
 // h, s, and cnot.
-pub struct StabilizerSimulator<const N: usize> {
-    stabilizers: [TableauGeneratorRow<N>; N],
-    destabilizers: [TableauGeneratorRow<N>; N],
-    rand: rand::rngs::StdRng,
+#[derive(Debug, Clone)]
+pub struct StabilizerSimulator {
+    stabilizers: Vec<TableauGeneratorRow>,
+    destabilizers: Vec<TableauGeneratorRow>,
+    rand: CountingRng<rand::rngs::StdRng>,
+    measurement_record: Vec<bool>,
+    classical_bits: Vec<bool>,
+    sweep_bits: Vec<bool>,
+    trace: Option<Vec<String>>,
+    stats: Option<ExecutionStats>,
+    journal: Option<Vec<Gate>>,
+    fuse_gates: bool,
+    // whether every update is followed by verify_invariants (see enable_invariant_checks).
+    check_invariants: bool,
+    // per qubit, the outcome a Z measurement is sure to give, when we know it. Measuring a
+    // qubit leaves it in a Z eigenstate, and gates on other qubits can't change that, so
+    // measuring it again before anything touches it can skip the tableau entirely. Gates
+    // that keep Z on the qubit a Z (up to sign) keep the entry, and the rest clear it.
+    known_outcomes: Vec<Option<bool>>,
+    // a spare row for measurements to work in, kept around so sampling shot after shot
+    // doesn't allocate a row per measurement.
+    scratch_row: TableauGeneratorRow,
 }
 
-impl<const N: usize> StabilizerSimulator<N> {
-    pub fn new(seed: u64) -> StabilizerSimulator<N> {
-        let mut initial_stabilizers: [TableauGeneratorRow<N>; N] = unsafe { mem::zeroed() };
-        let mut initial_destabilizers: [TableauGeneratorRow<N>; N] = unsafe { mem::zeroed() };
-        for i in 0..N {
-            initial_stabilizers[i] = TableauGeneratorRow {
-                phase_is_negated: false,
-                x_bits: [false; N],
-                z_bits: [false; N],
-            };
-            initial_destabilizers[i] = TableauGeneratorRow {
+// a run of single-qubit gates on one qubit, folded into one clifford: what it does to each
+// pauli on that qubit, indexed by x + 2 z, as (x, z, whether the sign flips). Acting on a
+// generator only needs the generator's pauli on that qubit, so applying it is one lookup per
+// row however many gates went into it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct FusedGate {
+    qubit: Qubit,
+    images: [(bool, bool, bool); 4],
+}
+
+impl FusedGate {
+    fn new(qubit: Qubit, gates: &[Gate]) -> FusedGate {
+        let images = [(false, false), (true, false), (false, true), (true, true)].map(|(x, z)| {
+            let mut pauli = PauliString {
                 phase_is_negated: false,
-                x_bits: [false; N],
-                z_bits: [false; N],
+                x_bits: vec![x],
+                z_bits: vec![z],
             };
+            for gate in gates {
+                pauli.conjugate_by(&gate.remap(|_| Qubit(0)));
+            }
+            (pauli.x_bits[0], pauli.z_bits[0], pauli.phase_is_negated)
+        });
+        FusedGate { qubit, images }
+    }
+}
+
+// the heap storage behind a tableau, kept so it can outlive the simulator using it. Programs
+// that make many short-lived simulators (a sampler, a server handling a request per circuit)
+// can hand each one the storage the last one gave back with into_storage, instead of
+// allocating every row afresh and leaving the old ones to fragment the heap. Rows are
+// reused whatever register size they were last used for; they grow if they have to.
+//
+// This is reuse, not placement: the rows are ordinary Vecs, so they come from the global
+// allocator. Custom allocators for collections (allocator_api) are still unstable, so an
+// embedding program that wants its tableaus in an arena or a particular heap sets that up
+// with #[global_allocator], and uses this to keep the number of allocations down.
+#[derive(Debug, Clone, Default)]
+pub struct TableauStorage {
+    rows: Vec<TableauGeneratorRow>,
+    tables: Vec<Vec<TableauGeneratorRow>>,
+}
+
+impl TableauStorage {
+    pub fn new() -> TableauStorage {
+        TableauStorage::default()
+    }
+
+    pub fn with_capacity(num_qubits: usize) -> TableauStorage {
+        // enough for one simulator on num_qubits qubits without allocating, up front.
+        TableauStorage {
+            rows: vec![TableauGeneratorRow::identity(num_qubits); 2 * num_qubits + 1],
+            tables: (0..2).map(|_| Vec::with_capacity(num_qubits)).collect(),
+        }
+    }
+
+    pub fn num_rows(&self) -> usize {
+        self.rows.len()
+    }
+
+    fn table(&mut self, num_qubits: usize) -> Vec<TableauGeneratorRow> {
+        let mut table = self.tables.pop().unwrap_or_default();
+        table.clear();
+        table.reserve(num_qubits);
+        table
+    }
+
+    fn row(&mut self, num_qubits: usize) -> TableauGeneratorRow {
+        let mut row = self.rows.pop().unwrap_or_default();
+        row.set_identity(num_qubits);
+        row
+    }
+}
+
+impl StabilizerSimulator {
+    pub fn new(num_qubits: usize, seed: u64) -> StabilizerSimulator {
+        StabilizerSimulator::from_storage(num_qubits, seed, TableauStorage::new())
+    }
+
+    pub fn from_storage(
+        num_qubits: usize,
+        seed: u64,
+        mut storage: TableauStorage,
+    ) -> StabilizerSimulator {
+        // like new, but building the tableau out of storage's rows.
+        let mut initial_stabilizers = storage.table(num_qubits);
+        let mut initial_destabilizers = storage.table(num_qubits);
+        for _ in 0..num_qubits {
+            initial_stabilizers.push(storage.row(num_qubits));
+            initial_destabilizers.push(storage.row(num_qubits));
         }
 
         // initialize the stabilizers and destabilziers of the
@@ -49,23 +292,304 @@ impl<const N: usize> StabilizerSimulator<N> {
         // XI*...*I, respectively. We just need N of each generator with
         // a single Z or X acting on each qubit. From there, all stabilizer
         // pauli strings can be generated by the product of these generators.
-        for i in 0..N {
-            initial_stabilizers[i].z_bits[i] = true;
-            initial_destabilizers[i].x_bits[i] = true;
+        for i in 0..num_qubits {
+            initial_stabilizers[i].set_z(i, true);
+            initial_destabilizers[i].set_x(i, true);
         }
 
         StabilizerSimulator {
             stabilizers: initial_stabilizers,
             destabilizers: initial_destabilizers,
-            rand: rand::SeedableRng::seed_from_u64(seed),
+            rand: CountingRng::new(rand::SeedableRng::seed_from_u64(seed)),
+            measurement_record: Vec::new(),
+            classical_bits: Vec::new(),
+            sweep_bits: Vec::new(),
+            trace: None,
+            stats: None,
+            journal: None,
+            fuse_gates: true,
+            check_invariants: false,
+            known_outcomes: vec![Some(false); num_qubits],
+            scratch_row: storage.row(num_qubits),
+        }
+    }
+
+    pub fn into_storage(self) -> TableauStorage {
+        // gives the tableau's storage back, for the next simulator to reuse.
+        let mut storage = TableauStorage::default();
+        for mut table in [self.stabilizers, self.destabilizers] {
+            storage.rows.append(&mut table);
+            storage.tables.push(table);
         }
+        storage.rows.push(self.scratch_row);
+        storage
+    }
+
+    pub fn seeded(num_qubits: usize) -> StabilizerSimulator {
+        StabilizerSimulator::new(num_qubits, 0)
+    }
+
+    pub fn num_qubits(&self) -> usize {
+        self.stabilizers.len()
     }
 
-    pub fn seeded() -> StabilizerSimulator<N> {
-        StabilizerSimulator::new(0)
+    pub fn reset_all(&mut self) {
+        // back to |0...0> with an empty measurement record and cleared classical bits, reusing the tableau's storage so
+        // one simulator can run shot after shot. The rng carries on where it was, so later
+        // shots still get fresh randomness.
+        self.reset_tableau();
+        self.measurement_record.clear();
+        self.classical_bits.clear();
     }
 
     pub fn apply_gate(&mut self, gate: &Gate) {
+        let start = self.stats.is_some().then(Instant::now);
+        let generators_before = self
+            .trace
+            .is_some()
+            .then(|| (self.stabilizers(), self.destabilizers()));
+        self.update_generators(gate);
+        if let (Some(stats), Some(start)) = (self.stats.as_mut(), start) {
+            stats.record_gate(gate, 2 * self.stabilizers.len(), start.elapsed());
+        }
+        self.assert_invariants();
+        if let Some((stabilizers_before, destabilizers_before)) = generators_before {
+            let explanation = trace::explain_gate(
+                gate,
+                &stabilizers_before,
+                &self.stabilizers(),
+                &destabilizers_before,
+                &self.destabilizers(),
+            );
+            if let Some(log) = self.trace.as_mut() {
+                log.push(explanation);
+            }
+        }
+        if let Some(journal) = self.journal.as_mut() {
+            journal.push(*gate);
+        }
+    }
+
+    pub fn apply_gates<I: IntoIterator<Item = Gate>>(&mut self, gates: I) {
+        // runs of single-qubit gates in the batch are fused: gates on different qubits
+        // commute, so a run splits into one fused clifford per qubit, and they all go in one
+        // pass over the tableau instead of a pass per gate. Tracing, stats and the journal
+        // all want to see gates one at a time, so any of them turns fusion off, as does
+        // disable_fusion.
+        let fuse = self.fuse_gates
+            && self.trace.is_none()
+            && self.stats.is_none()
+            && self.journal.is_none();
+        if !fuse {
+            for gate in gates {
+                self.apply_gate(&gate);
+            }
+            return;
+        }
+        let gates: Vec<Gate> = gates.into_iter().collect();
+        let mut index = 0;
+        while index < gates.len() {
+            let run_length = gates[index..]
+                .iter()
+                .take_while(|gate| gate.qubits().len() == 1)
+                .count();
+            if run_length < 2 {
+                self.apply_gate(&gates[index]);
+                index += 1;
+                continue;
+            }
+            let mut by_qubit: Vec<(Qubit, Vec<Gate>)> = Vec::new();
+            for gate in &gates[index..index + run_length] {
+                let qubit = gate.qubits()[0];
+                match by_qubit.iter_mut().find(|(other, _)| *other == qubit) {
+                    Some((_, gates)) => gates.push(*gate),
+                    None => by_qubit.push((qubit, vec![*gate])),
+                }
+            }
+            let fused: Vec<FusedGate> = by_qubit
+                .iter()
+                .map(|(qubit, gates)| FusedGate::new(*qubit, gates))
+                .collect();
+            self.apply_fused(&fused);
+            index += run_length;
+        }
+    }
+
+    pub fn apply_inverse(&mut self, gate: &Gate) {
+        self.apply_gates(gate.inverse());
+    }
+
+    pub fn set_computational_basis_state(&mut self, bits: &[bool]) -> Result<(), &'static str> {
+        // jumps straight to |bits>, where bits[q] is qubit q's value. The measurement record is
+        // left alone. |1> is stabilized by -Z, so it's the all-zero tableau with some signs flipped.
+        if bits.len() != self.num_qubits() {
+            return Err("Basis state must have one bit per qubit.");
+        }
+        self.reset_tableau();
+        for (stabilizer, &bit) in self.stabilizers.iter_mut().zip(bits) {
+            stabilizer.phase_is_negated = bit;
+        }
+        for (known, &bit) in self.known_outcomes.iter_mut().zip(bits) {
+            *known = Some(bit);
+        }
+        Ok(())
+    }
+
+    fn reset_tableau(&mut self) {
+        for generator in self
+            .stabilizers
+            .iter_mut()
+            .chain(self.destabilizers.iter_mut())
+        {
+            generator.phase_is_negated = false;
+            generator.words.fill(0);
+        }
+        for i in 0..self.num_qubits() {
+            self.stabilizers[i].set_z(i, true);
+            self.destabilizers[i].set_x(i, true);
+        }
+        self.known_outcomes.fill(Some(false));
+        self.clear_journal();
+    }
+
+    pub fn enable_trace(&mut self) {
+        // from now on, every gate records a human readable explanation of what it did to
+        // each generator. Meant for learning the formalism, not for speed.
+        self.trace.get_or_insert_with(Vec::new);
+    }
+
+    pub fn disable_trace(&mut self) {
+        self.trace = None;
+    }
+
+    pub fn trace_log(&self) -> &[String] {
+        self.trace.as_deref().unwrap_or(&[])
+    }
+
+    pub fn take_trace_log(&mut self) -> Vec<String> {
+        self.trace.as_mut().map(std::mem::take).unwrap_or_default()
+    }
+
+    pub fn enable_stats(&mut self) {
+        // from now on, count gates, measurements, rowsums, rows and rng draws, and time gates
+        // and measurements.
+        // Turning it on again doesn't reset the counts; take_stats does.
+        self.count_rng_draws();
+        self.stats.get_or_insert_with(ExecutionStats::default);
+    }
+
+    pub fn disable_stats(&mut self) {
+        self.stats = None;
+    }
+
+    pub fn stats(&self) -> Option<&ExecutionStats> {
+        self.stats.as_ref()
+    }
+
+    pub fn take_stats(&mut self) -> ExecutionStats {
+        self.stats.as_mut().map(mem::take).unwrap_or_default()
+    }
+
+    fn count_rowsums(&mut self, rowsums: u64) {
+        let num_qubits = self.num_qubits();
+        if let Some(stats) = self.stats.as_mut() {
+            stats.record_rowsums(rowsums, num_qubits);
+        }
+    }
+
+    fn count_rng_draws(&mut self) {
+        // moves the rng's draw count into the stats. Called at the end of anything that can
+        // draw, so the stats are up to date whenever control is back with the caller.
+        let draws = mem::take(&mut self.rand.draws);
+        if let Some(stats) = self.stats.as_mut() {
+            stats.rng_draws += draws;
+        }
+    }
+
+    pub fn enable_invariant_checks(&mut self) {
+        // from now on, run verify_invariants after every gate, measurement, projection and
+        // reset, and panic as soon as one breaks the tableau. It's O(n^3) per update, so it's
+        // for tests and for chasing down a suspect gate rule, never for real workloads.
+        self.check_invariants = true;
+    }
+
+    pub fn disable_invariant_checks(&mut self) {
+        self.check_invariants = false;
+    }
+
+    fn assert_invariants(&self) {
+        if self.check_invariants {
+            if let Err(reason) = self.verify_invariants() {
+                panic!("tableau invariant broken: {reason}");
+            }
+        }
+    }
+
+    pub fn enable_journal(&mut self) {
+        // from now on, remember the gates applied since the last measurement so they can be
+        // undone. Measuring (which includes freeing a qubit) is irreversible, and relabelling
+        // qubits would change what the journalled gates act on, so both clear the journal.
+        self.journal.get_or_insert_with(Vec::new);
+    }
+
+    pub fn disable_journal(&mut self) {
+        self.journal = None;
+    }
+
+    pub fn undoable_gates(&self) -> usize {
+        self.journal.as_ref().map_or(0, Vec::len)
+    }
+
+    pub fn undo_last(&mut self) -> Result<Gate, &'static str> {
+        // undoes the most recent gate and returns it.
+        let Some(gate) = self.journal.as_mut().and_then(Vec::pop) else {
+            return Err("Nothing to undo since the last measurement (or journaling is off).");
+        };
+        // don't journal the undo itself.
+        let journal = self.journal.take();
+        self.apply_inverse(&gate);
+        self.journal = journal;
+        Ok(gate)
+    }
+
+    pub fn rewind(&mut self, num_gates: usize) -> Result<(), &'static str> {
+        // undoes the last num_gates gates, or nothing at all if there aren't that many.
+        if num_gates > self.undoable_gates() {
+            return Err("Can't rewind past the last measurement.");
+        }
+        for _ in 0..num_gates {
+            self.undo_last()?;
+        }
+        Ok(())
+    }
+
+    fn clear_journal(&mut self) {
+        if let Some(journal) = self.journal.as_mut() {
+            journal.clear();
+        }
+    }
+
+    fn update_known_outcomes(&mut self, gate: &Gate) {
+        let known = &mut self.known_outcomes;
+        match *gate {
+            Gate::H(qubit) => known[qubit.index()] = None,
+            Gate::S(_) | Gate::Z(_) | Gate::Cz(_, _) => {}
+            Gate::X(qubit) | Gate::Y(qubit) => {
+                if let Some(outcome) = known[qubit.index()].as_mut() {
+                    *outcome ^= true;
+                }
+            }
+            Gate::Cx(control, target) => {
+                known[target.index()] = known[control.index()]
+                    .zip(known[target.index()])
+                    .map(|(control, target)| control ^ target);
+            }
+            Gate::Swap(a, b) => known.swap(a.index(), b.index()),
+        }
+    }
+
+    fn update_generators(&mut self, gate: &Gate) {
+        self.update_known_outcomes(gate);
         match gate {
             // TODO: I wonder if I should move the dispatch to a trait
             // on the gates enum. This is probably only important in a world
@@ -79,12 +603,12 @@ impl<const N: usize> StabilizerSimulator<N> {
             // each i in 1..n, the ith destabilizer must anticommute with the ith stabilizer,
             // but commute with all other stabilizers. This is the tableau convention.
             Gate::H(qubit) => {
-                for i in 0..N {
+                for i in 0..self.num_qubits() {
                     for generator in
                         [&mut self.stabilizers[i], &mut self.destabilizers[i]].iter_mut()
                     {
-                        let generator_x_component = generator.x_bits[*qubit as usize];
-                        let generator_z_component = generator.z_bits[*qubit as usize];
+                        let generator_x_component = generator.x(qubit.index());
+                        let generator_z_component = generator.z(qubit.index());
                         //H swaps X and Z components of the stabilizer. Y == -iZX, which we turn into
                         // -iXZ == -Y. So we just need to flip the sign of the stabilizer if it has both
                         // X and Z components.
@@ -96,15 +620,13 @@ impl<const N: usize> StabilizerSimulator<N> {
                         // and with the same phase.
                         generator.phase_is_negated ^=
                             generator_x_component && generator_z_component;
-                        mem::swap(
-                            &mut generator.x_bits[*qubit as usize],
-                            &mut generator.z_bits[*qubit as usize],
-                        )
+                        generator.set_x(qubit.index(), generator_z_component);
+                        generator.set_z(qubit.index(), generator_x_component);
                     }
                 }
             }
             Gate::S(qubit) => {
-                for i in 0..N {
+                for i in 0..self.num_qubits() {
                     for generator in
                         [&mut self.stabilizers[i], &mut self.destabilizers[i]].iter_mut()
                     {
@@ -113,19 +635,38 @@ impl<const N: usize> StabilizerSimulator<N> {
                         // That means, if you are a Y stabilizer (you have both X and Z components),
                         // you will be mapped to an X stabilizer with an opposing phase. If you are an X
                         // stabilizer, you will be mapped to a Y stabilizer with the same phase.
-                        let generator_x_component = generator.x_bits[*qubit as usize];
-                        let generator_z_component = generator.z_bits[*qubit as usize];
+                        let generator_x_component = generator.x(qubit.index());
+                        let generator_z_component = generator.z(qubit.index());
                         // flip phase of Y stabilizers.
                         generator.phase_is_negated ^=
                             generator_x_component && generator_z_component;
 
                         // cycle through X and Y stabilizers.
-                        generator.z_bits[*qubit as usize] ^= generator_x_component;
+                        generator.xor_z(qubit.index(), generator_x_component);
+                    }
+                }
+            }
+            Gate::X(qubit) | Gate::Y(qubit) | Gate::Z(qubit) => {
+                for i in 0..self.num_qubits() {
+                    for generator in
+                        [&mut self.stabilizers[i], &mut self.destabilizers[i]].iter_mut()
+                    {
+                        // conjugating by a pauli never changes which paulis a generator is made of,
+                        // it only negates the generators that anticommute with it. X anticommutes
+                        // with Z components, Z with X components, and Y with anything that isn't
+                        // I or Y.
+                        let generator_x_component = generator.x(qubit.index());
+                        let generator_z_component = generator.z(qubit.index());
+                        generator.phase_is_negated ^= match gate {
+                            Gate::X(_) => generator_z_component,
+                            Gate::Z(_) => generator_x_component,
+                            _ => generator_x_component ^ generator_z_component,
+                        };
                     }
                 }
             }
             Gate::Cx(control, target) => {
-                for i in 0..N {
+                for i in 0..self.num_qubits() {
                     for generator in
                         [&mut self.stabilizers[i], &mut self.destabilizers[i]].iter_mut()
                     {
@@ -139,8 +680,8 @@ impl<const N: usize> StabilizerSimulator<N> {
                         // CNOT * I ⊗ X * CNOT = I ⊗ X
                         // CNOT * X ⊗ X * CNOT = X ⊗ I
                         // and for action on Y operators you can take the product of X and Z cases.
-                        generator.x_bits[*target as usize] ^= generator.x_bits[*control as usize];
-                        generator.z_bits[*control as usize] ^= generator.z_bits[*target as usize];
+                        generator.xor_x(target.index(), generator.x(control.index()));
+                        generator.xor_z(control.index(), generator.z(target.index()));
                         // we invert the phase if CNOT would negate a pauli operator in the heisenberg picture.
                         // that is to say, something like CNOT * (P1 ⊗ P2) * CNOT = -P1 ⊗ P2.
                         // This happens when the control qubit is stabilized by X, and the target qubit is stabilized by Z.
@@ -150,117 +691,562 @@ impl<const N: usize> StabilizerSimulator<N> {
                         // so either
                         // iY ⊗ iY = -(Y ⊗ Y).
                         // or -iY ⊗ -iY = -(Y ⊗ Y).
-                        let add_phase_flip = generator.x_bits[*control as usize]
-                            && generator.z_bits[*target as usize];
+                        let add_phase_flip =
+                            generator.x(control.index()) && generator.z(target.index());
                         // However, if you have an odd balance of X and Y components, the anticommutation rules described
                         // above cancel out. E.g. CNOT(Y ⊗  X)CNOT = Y ⊗ I
-                        let anticommutation_parity = generator.z_bits[*control as usize]
-                            ^ generator.x_bits[*target as usize]
-                            ^ true;
+                        let anticommutation_parity =
+                            generator.z(control.index()) ^ generator.x(target.index()) ^ true;
                         generator.phase_is_negated ^= add_phase_flip && anticommutation_parity;
                     }
                 }
             }
+            Gate::Cz(a, b) => {
+                for i in 0..self.num_qubits() {
+                    for generator in
+                        [&mut self.stabilizers[i], &mut self.destabilizers[i]].iter_mut()
+                    {
+                        // CZ is symmetric, and conjugates like so:
+                        // CZ * X ⊗ I * CZ = X ⊗ Z
+                        // CZ * I ⊗ X * CZ = Z ⊗ X
+                        // CZ * Z ⊗ I * CZ = Z ⊗ I
+                        // so an X component on either qubit drags a Z onto the other one.
+                        // Like CNOT, the phase flips when that reordering produces a -1, which
+                        // happens for X ⊗ X-like terms whose Z components disagree, e.g.
+                        // CZ * (Y ⊗ X) * CZ = -(X ⊗ Y).
+                        let (x_a, z_a) = (generator.x(a.index()), generator.z(a.index()));
+                        let (x_b, z_b) = (generator.x(b.index()), generator.z(b.index()));
+                        generator.phase_is_negated ^= x_a && x_b && (z_a ^ z_b);
+                        generator.xor_z(a.index(), x_b);
+                        generator.xor_z(b.index(), x_a);
+                    }
+                }
+            }
+            Gate::Swap(a, b) => {
+                for i in 0..self.num_qubits() {
+                    for generator in
+                        [&mut self.stabilizers[i], &mut self.destabilizers[i]].iter_mut()
+                    {
+                        // SWAP just exchanges which qubit each pauli factor acts on, so
+                        // it never changes a generator's phase.
+                        let (x_a, z_a) = (generator.x(a.index()), generator.z(a.index()));
+                        let (x_b, z_b) = (generator.x(b.index()), generator.z(b.index()));
+                        generator.set_x(a.index(), x_b);
+                        generator.set_z(a.index(), z_b);
+                        generator.set_x(b.index(), x_a);
+                        generator.set_z(b.index(), z_a);
+                    }
+                }
+            }
+        }
+    }
+
+    pub fn run(&mut self, circuit: &Circuit) -> Result<Vec<bool>, &'static str> {
+        // applies the circuit's instructions in order, and returns the outcomes of its
+        // measurements in the order they happened. Consecutive gates are handed to
+        // apply_gates together, so runs of single-qubit gates get fused.
+        if circuit.num_qubits() > self.num_qubits() {
+            return Err("Circuit acts on more qubits than the simulator has.");
+        }
+        let record_start = self.measurement_record.len();
+        let mut pending = Vec::new();
+        for instruction in circuit.instructions() {
+            if let Instruction::Gate(gate) = instruction {
+                pending.push(*gate);
+                continue;
+            }
+            self.apply_gates(pending.drain(..));
+            self.apply_instruction(instruction)?;
+        }
+        self.apply_gates(pending);
+        Ok(self.measurement_record[record_start..].to_vec())
+    }
+
+    fn apply_fused(&mut self, fused: &[FusedGate]) {
+        for gate in fused {
+            let known = &mut self.known_outcomes[gate.qubit.index()];
+            // images[2] is where Z goes.
+            *known = match gate.images[2] {
+                (false, true, negate) => known.map(|outcome| outcome ^ negate),
+                _ => None,
+            };
+        }
+        for generator in self
+            .stabilizers
+            .iter_mut()
+            .chain(self.destabilizers.iter_mut())
+        {
+            for gate in fused {
+                let q = gate.qubit.index();
+                let pauli = generator.x(q) as usize + 2 * generator.z(q) as usize;
+                let (x, z, negate) = gate.images[pauli];
+                generator.set_x(q, x);
+                generator.set_z(q, z);
+                generator.phase_is_negated ^= negate;
+            }
+        }
+        self.assert_invariants();
+    }
+
+    pub fn enable_fusion(&mut self) {
+        self.fuse_gates = true;
+    }
+
+    pub fn disable_fusion(&mut self) {
+        // apply_gates, and so run and run_noisy, apply every gate on its own, the way
+        // apply_gate does.
+        self.fuse_gates = false;
+    }
+
+    pub fn prepare_bell_pair(&mut self, a: Qubit, b: Qubit) {
+        // entangles qubits a and b into (|00> + |11>)/sqrt(2), if they start out in |0>.
+        self.prepare_ghz(&[a, b]);
+    }
+
+    pub fn prepare_ghz(&mut self, qubits: &[Qubit]) {
+        self.apply_gates(
+            Circuit::ghz(qubits).instructions().iter().filter_map(
+                |instruction| match instruction {
+                    Instruction::Gate(gate) => Some(*gate),
+                    _ => None,
+                },
+            ),
+        );
+    }
+
+    pub fn apply_instruction(&mut self, instruction: &Instruction) -> Result<(), &'static str> {
+        match instruction {
+            Instruction::Gate(gate) => self.apply_gate(gate),
+            Instruction::Measure(qubit) => {
+                self.measure(*qubit)?;
+            }
+            Instruction::Reset(qubit) | Instruction::PrepX(qubit) | Instruction::PrepY(qubit) => {
+                self.reset(*qubit)?;
+                self.apply_gates(instruction.preparation_gates());
+            }
+            Instruction::Conditional { lookback, gate } => {
+                if self.lookup_measurement(*lookback)? {
+                    self.apply_gate(gate);
+                }
+            }
+            Instruction::MeasureToBit { qubit, bit } => {
+                let outcome = self.measure(*qubit)?;
+                self.set_classical_bit(*bit, outcome);
+            }
+            Instruction::ConditionalOnBit { bit, gate } => {
+                if self.classical_bit(*bit) {
+                    self.apply_gate(gate);
+                }
+            }
+            Instruction::SweepConditional { bit, gate } => {
+                if self.sweep_bit(*bit) {
+                    self.apply_gate(gate);
+                }
+            }
+            Instruction::Tick => {}
+        }
+        Ok(())
+    }
+
+    pub fn run_noisy(
+        &mut self,
+        circuit: &Circuit,
+        noise: &NoiseModel,
+    ) -> Result<Vec<bool>, &'static str> {
+        // like run, but with the noise model's errors sampled from the simulator's rng.
+        // Flipped measurement results are what ends up in the record, so conditional
+        // gates see the noisy values, just like classical control on hardware would.
+        //
+        // Gates and the errors sampled around them are held back until the next instruction
+        // that isn't a gate, then applied as one batch, so they fuse the way run's do.
+        // Sampling an error doesn't depend on the tableau, so the rng is drawn in the same
+        // order either way.
+        if circuit.num_qubits() > self.num_qubits() {
+            return Err("Circuit acts on more qubits than the simulator has.");
+        }
+        let idle = noise::idle_qubits(circuit);
+        let record_start = self.measurement_record.len();
+        let mut pending = Vec::new();
+        for (index, instruction) in circuit.instructions().iter().enumerate() {
+            if let Some(qubits) = idle.get(&index) {
+                pending.extend(noise.idle_errors(qubits, &mut self.rand));
+            }
+            if let Instruction::Gate(gate) = instruction {
+                pending.push(*gate);
+            } else {
+                self.apply_gates(pending.drain(..));
+                self.apply_instruction(instruction)?;
+            }
+            if let Instruction::Measure(qubit) | Instruction::MeasureToBit { qubit, .. } =
+                *instruction
+            {
+                if noise.flips_measurement(qubit, &mut self.rand) {
+                    self.flip_last_measurement(instruction);
+                }
+            }
+            pending.extend(noise.errors_after(instruction, &mut self.rand));
+        }
+        self.apply_gates(pending);
+        self.count_rng_draws();
+        Ok(self.measurement_record[record_start..].to_vec())
+    }
+
+    pub(crate) fn flip_last_measurement(&mut self, instruction: &Instruction) {
+        // a readout error on the measurement instruction just applied, in the record and in
+        // the classical bit it was written to, if any.
+        if let Some(outcome) = self.measurement_record.last_mut() {
+            *outcome ^= true;
+        }
+        if let Instruction::MeasureToBit { bit, .. } = *instruction {
+            self.set_classical_bit(bit, !self.classical_bit(bit));
+        }
+    }
+
+    pub fn run_leaky(
+        &mut self,
+        circuit: &Circuit,
+        noise: &NoiseModel,
+        leakage: &LeakageModel,
+    ) -> Result<LeakyShot, &'static str> {
+        // like run_noisy, with leakage on top (see the leakage module). The tableau keeps a
+        // leaked qubit's last state, but nothing touches it until it comes back.
+        if circuit.num_qubits() > self.num_qubits() {
+            return Err("Circuit acts on more qubits than the simulator has.");
+        }
+        leakage.validate()?;
+        let mut leaked = vec![false; self.num_qubits()];
+        let mut heralds = Vec::new();
+        let idle = noise::idle_qubits(circuit);
+        let record_start = self.measurement_record.len();
+        for (index, instruction) in circuit.instructions().iter().enumerate() {
+            if let Some(qubits) = idle.get(&index) {
+                for error in noise.idle_errors(qubits, &mut self.rand) {
+                    self.apply_gate(&error);
+                }
+            }
+            let fired = match *instruction {
+                Instruction::Gate(gate) => Some(gate),
+                Instruction::Conditional { lookback, gate } => {
+                    self.lookup_measurement(lookback)?.then_some(gate)
+                }
+                Instruction::ConditionalOnBit { bit, gate } => {
+                    self.classical_bit(bit).then_some(gate)
+                }
+                Instruction::SweepConditional { bit, gate } => self.sweep_bit(bit).then_some(gate),
+                _ => None,
+            };
+            match *instruction {
+                _ if fired.is_some() => {
+                    let qubits = instruction.qubits();
+                    if qubits.iter().any(|qubit| leaked[qubit.index()]) {
+                        for &qubit in &qubits {
+                            if leaked[qubit.index()] {
+                                if leakage.seeps(&mut self.rand) {
+                                    leaked[qubit.index()] = false;
+                                    self.reset(qubit)?;
+                                    if self.rand.gen() {
+                                        self.apply_gate(&Gate::X(qubit));
+                                    }
+                                }
+                            } else if let Some(error) =
+                                leakage.leaked_gate_error(qubit, &mut self.rand)
+                            {
+                                self.apply_gate(&error);
+                            }
+                        }
+                        continue;
+                    }
+                    self.apply_instruction(instruction)?;
+                    for qubit in qubits {
+                        leaked[qubit.index()] = leakage.leaks(&mut self.rand);
+                    }
+                }
+                Instruction::Measure(qubit) | Instruction::MeasureToBit { qubit, .. } => {
+                    heralds.push(leaked[qubit.index()]);
+                    let mut outcome = if leaked[qubit.index()] {
+                        leakage.leaked_outcome(&mut self.rand)
+                    } else {
+                        self.measure(qubit)?
+                    };
+                    outcome ^= noise.flips_measurement(qubit, &mut self.rand);
+                    if leaked[qubit.index()] {
+                        self.measurement_record.push(outcome);
+                    } else if let Some(recorded) = self.measurement_record.last_mut() {
+                        *recorded = outcome;
+                    }
+                    if let Instruction::MeasureToBit { bit, .. } = *instruction {
+                        self.set_classical_bit(bit, outcome);
+                    }
+                }
+                Instruction::Reset(qubit)
+                | Instruction::PrepX(qubit)
+                | Instruction::PrepY(qubit) => {
+                    leaked[qubit.index()] = false;
+                    self.apply_instruction(instruction)?;
+                }
+                _ => self.apply_instruction(instruction)?,
+            }
+            for error in noise.errors_after(instruction, &mut self.rand) {
+                self.apply_gate(&error);
+            }
+        }
+        self.count_rng_draws();
+        Ok(LeakyShot {
+            measurements: self.measurement_record[record_start..].to_vec(),
+            heralds: leakage.heralded.then_some(heralds),
+        })
+    }
+
+    pub fn run_stream(&mut self, reader: impl io::Read) -> Result<Vec<bool>, ParseError> {
+        // parses and runs text instructions (see the parser module) a line at a time, so the
+        // program never has to be in memory all at once. Returns the measurement outcomes, and
+        // stops at the first line that can't be parsed or run.
+        let record_start = self.measurement_record.len();
+        for (index, line) in io::BufReader::new(reader).lines().enumerate() {
+            let error = |reason| ParseError {
+                line: index + 1,
+                reason,
+            };
+            let line = line.map_err(|_| error("Couldn't read from the instruction stream."))?;
+            for instruction in parser::parse_line(&line).map_err(error)? {
+                if instruction
+                    .qubits()
+                    .iter()
+                    .any(|&qubit| qubit.index() >= self.num_qubits())
+                {
+                    return Err(error("Instruction acts on a qubit outside the simulator."));
+                }
+                self.apply_instruction(&instruction).map_err(error)?;
+            }
+        }
+        Ok(self.measurement_record[record_start..].to_vec())
+    }
+
+    pub fn lookup_measurement(&self, lookback: u32) -> Result<bool, &'static str> {
+        // lookback 1 is the most recent measurement, 2 the one before it, and so on.
+        let record_length = self.measurement_record.len();
+        if lookback == 0 || lookback as usize > record_length {
+            return Err("Measurement record lookback reaches before the first measurement.");
+        }
+        Ok(self.measurement_record[record_length - lookback as usize])
+    }
+
+    pub fn measurement_record(&self) -> &[bool] {
+        // every measurement outcome so far, oldest first.
+        &self.measurement_record
+    }
+
+    pub fn classical_bits(&self) -> &[bool] {
+        // the classical register bits, by flat index (see Circuit::add_classical_register).
+        // Bits past the end haven't been written, and read as 0.
+        &self.classical_bits
+    }
+
+    pub fn classical_bit(&self, bit: u32) -> bool {
+        self.classical_bits
+            .get(bit as usize)
+            .copied()
+            .unwrap_or(false)
+    }
+
+    pub fn set_classical_bit(&mut self, bit: u32, value: bool) {
+        let bit = bit as usize;
+        if bit >= self.classical_bits.len() {
+            self.classical_bits.resize(bit + 1, false);
+        }
+        self.classical_bits[bit] = value;
+    }
+
+    pub fn set_sweep_bits(&mut self, sweep_bits: &[bool]) {
+        // the configuration SweepConditional instructions read. It stays put across
+        // reset_all, so set it again before each shot that needs a different one.
+        self.sweep_bits = sweep_bits.to_vec();
+    }
+
+    pub fn sweep_bits(&self) -> &[bool] {
+        &self.sweep_bits
+    }
+
+    pub fn sweep_bit(&self, bit: u32) -> bool {
+        self.sweep_bits.get(bit as usize).copied().unwrap_or(false)
+    }
+
+    pub fn stabilizer_row(&self, i: usize) -> Option<PauliView<'_>> {
+        // generator i of the stabilizer group, borrowed straight from the tableau. None past
+        // the last qubit.
+        self.stabilizers.get(i).map(TableauGeneratorRow::view)
+    }
+
+    pub fn destabilizer_row(&self, i: usize) -> Option<PauliView<'_>> {
+        // the destabilizer paired with stabilizer_row(i): it anticommutes with that generator
+        // and commutes with every other one.
+        self.destabilizers.get(i).map(TableauGeneratorRow::view)
+    }
+
+    pub fn stabilizer_rows(&self) -> impl ExactSizeIterator<Item = PauliView<'_>> {
+        self.stabilizers.iter().map(TableauGeneratorRow::view)
+    }
+
+    pub fn destabilizer_rows(&self) -> impl ExactSizeIterator<Item = PauliView<'_>> {
+        self.destabilizers.iter().map(TableauGeneratorRow::view)
+    }
+
+    pub fn stabilizers(&self) -> Vec<PauliString> {
+        self.stabilizers.iter().map(PauliString::from).collect()
+    }
+
+    pub fn destabilizers(&self) -> Vec<PauliString> {
+        self.destabilizers.iter().map(PauliString::from).collect()
+    }
+
+    pub fn canonical_stabilizers(&self) -> Result<Vec<PauliString>, &'static str> {
+        // the stabilizer generators are far from unique -- any gate sequence leaves you with
+        // some generating set of the group, and different circuits preparing the same state
+        // usually leave you with different ones. Reducing the generators to row echelon form
+        // (pivoting on the X bits of each qubit first, then the Z bits) gives a generating set
+        // that only depends on the group, and so on the state. Two simulators are in the same
+        // state exactly when their canonical stabilizers are equal.
+        let mut rows = self.stabilizers.clone();
+        let num_qubits = self.num_qubits();
+        let mut rank = 0;
+        for column in 0..2 * num_qubits {
+            let bit = |row: &TableauGeneratorRow| {
+                if column < num_qubits {
+                    row.x(column)
+                } else {
+                    row.z(column - num_qubits)
+                }
+            };
+            let Some(pivot) = (rank..rows.len()).find(|&i| bit(&rows[i])) else {
+                continue;
+            };
+            rows.swap(rank, pivot);
+            let pivot_row = rows[rank].clone();
+            for (i, row) in rows.iter_mut().enumerate() {
+                if i != rank && bit(row) {
+                    Self::rowsum(row, &pivot_row)?;
+                }
+            }
+            rank += 1;
+        }
+        Ok(rows.iter().map(PauliString::from).collect())
+    }
+
+    pub fn subsystem_rank(&self, subsystem: &[Qubit]) -> Result<SubsystemRank, &'static str> {
+        // generators acting only inside A are the combinations that are the identity on B,
+        // so there are n - rank(generators restricted to B) independent ones, and vice versa.
+        let num_qubits = self.num_qubits();
+        let mut in_subsystem = vec![false; num_qubits];
+        for qubit in subsystem {
+            *in_subsystem
+                .get_mut(qubit.index())
+                .ok_or("Qubit is outside the register.")? = true;
         }
+        let restricted_rank = |keep: bool| {
+            let columns: Vec<usize> = (0..num_qubits)
+                .filter(|&j| in_subsystem[j] == keep)
+                .collect();
+            let mut matrix = BitMatrix::new(self.stabilizers.len(), 2 * columns.len());
+            for (i, row) in self.stabilizers.iter().enumerate() {
+                for (k, &j) in columns.iter().enumerate() {
+                    matrix.set(i, k, row.x(j));
+                    matrix.set(i, columns.len() + k, row.z(j));
+                }
+            }
+            matrix.rank()
+        };
+        let inside = num_qubits - restricted_rank(false);
+        let outside = num_qubits - restricted_rank(true);
+        Ok(SubsystemRank {
+            num_qubits: in_subsystem.iter().filter(|&&bit| bit).count(),
+            inside,
+            outside,
+            crossing: num_qubits - inside - outside,
+        })
     }
 
-    fn is_deterministic(&self, qubit: u32) -> bool {
+    pub(crate) fn is_deterministic(&self, qubit: Qubit) -> bool {
         // are there no stabilizer rows with an X component at the qubit?
         // if so, we're chillin -- we are already in the Z measurement basis because
         // we are either stabilized by Z or -Z, and so either |0> or |1>.
-        self.find_x_stabilizer_index(qubit).is_none()
+        self.known_outcomes[qubit.index()].is_some()
+            || self.find_x_stabilizer_index(qubit).is_none()
     }
 
-    fn pauli_imaginary_phase_exponent(x1: bool, z1: bool, x2: bool, z2: bool) -> i32 {
-        // return the sign to which i is raised when the pauli matrices represented by x1*z1 and x2*z2 are multiplied.
-        // e.g. X*X = I. X*Z = iY. Z*Z = I. Z*X = -iY. etc.
-        // I've used scott aaronson's math here, and it checks out.
-        match (x1, z1) {
-            (false, false) => 0,
-            (true, true) => z2 as i32 - x2 as i32,
-            (true, false) => (z2 as i32) * (2 * x2 as i32 - 1),
-            (false, true) => (1 - 2 * z2 as i32) * x2 as i32,
-        }
+    fn rowsum(
+        row_h: &mut TableauGeneratorRow,
+        row_i: &TableauGeneratorRow,
+    ) -> Result<(), &'static str> {
+        // row_h <- row_i * row_h. See PauliString::rowsum.
+        row_h.phase_is_negated = row_i.view().product_sign(&row_h.view())?;
+        simd::xor_into(&mut row_h.words, &row_i.words);
+        Ok(())
     }
 
-    fn rowsum(
-        row_h: &mut TableauGeneratorRow<N>,
-        row_i: &TableauGeneratorRow<N>,
+    fn rowsum_within(
+        rows: &mut [TableauGeneratorRow],
+        h: usize,
+        i: usize,
     ) -> Result<(), &'static str> {
-        let mut exponent_sum: i32 = 0;
-        for j in 0..N {
-            exponent_sum += Self::pauli_imaginary_phase_exponent(
-                row_i.x_bits[j],
-                row_i.z_bits[j],
-                row_h.x_bits[j],
-                row_h.z_bits[j],
-            );
-        }
-        let pauli_operator_phase =
-            2 * (row_h.phase_is_negated as i32) + 2 * (row_i.phase_is_negated as i32);
-        let pauli_operator_phase = (pauli_operator_phase + exponent_sum) % 4;
-        if pauli_operator_phase == 0 {
-            row_h.phase_is_negated = false;
-        } else if pauli_operator_phase == 2 {
-            row_h.phase_is_negated = true;
+        // rowsum of two rows of the same half of the tableau. Splitting the slice between them
+        // hands out both at once, so the source row doesn't need copying first.
+        assert_ne!(h, i, "rowsum of a row with itself");
+        let (row_h, row_i) = if h < i {
+            let (low, high) = rows.split_at_mut(i);
+            (&mut low[h], &high[0])
         } else {
-            // TODO -- maybe use anyhow results and dynamic strings.
-            return Err("Non-stabilizer rowsum");
-        }
-        for j in 0..N {
-            row_h.x_bits[j] ^= row_i.x_bits[j];
-            row_h.z_bits[j] ^= row_i.z_bits[j];
-        }
-        Ok(())
+            let (low, high) = rows.split_at_mut(h);
+            (&mut high[0], &low[i])
+        };
+        Self::rowsum(row_h, row_i)
     }
 
-    fn find_x_stabilizer_index(&self, qubit: u32) -> Option<usize> {
-        self.stabilizers
-            .iter()
-            .position(|row| row.x_bits[qubit as usize])
+    fn find_x_stabilizer_index(&self, qubit: Qubit) -> Option<usize> {
+        self.stabilizers.iter().position(|row| row.x(qubit.index()))
     }
 
     fn extract_stabilizer_p_after_flipping_preparing_other_stabilizers_to_expect_collapsed_state(
         &mut self,
-        qubit: u32,
+        qubit: Qubit,
         p: usize,
     ) -> Result<(), &'static str> {
-        // helper method for nondeterministic_measurement
-        let p_stabilizer = self.stabilizers[p].clone();
-        for i in 0..N {
+        // helper method for nondeterministic_measurement. Row p itself is never a target, so
+        // it can be summed in straight from the tableau.
+        let mut rowsums = 0;
+        for i in 0..self.num_qubits() {
             if i == p {
                 continue;
             }
-            if self.stabilizers[i].x_bits[qubit as usize] {
-                Self::rowsum(&mut self.stabilizers[i], &p_stabilizer)?;
+            if self.stabilizers[i].x(qubit.index()) {
+                Self::rowsum_within(&mut self.stabilizers, i, p)?;
+                rowsums += 1;
             }
-            if self.destabilizers[i].x_bits[qubit as usize] {
-                Self::rowsum(&mut self.destabilizers[p], &p_stabilizer)?;
+            if self.destabilizers[i].x(qubit.index()) {
+                Self::rowsum(&mut self.destabilizers[i], &self.stabilizers[p])?;
+                rowsums += 1;
             }
         }
+        self.count_rowsums(rowsums);
         Ok(())
     }
 
     fn collapse_p_stabilizer_and_return_measurement_outcome(
         &mut self,
         p: usize,
-        qubit: u32,
+        qubit: Qubit,
     ) -> Result<bool, &'static str> {
-        // helper method for nondeterministic_measurement
-        let old_p_stabilizer = mem::replace(
-            &mut self.stabilizers[p],
-            TableauGeneratorRow {
-                phase_is_negated: self.rand.gen_bool(0.5),
-                x_bits: [false; N],
-                z_bits: [false; N],
-            },
-        );
-        self.stabilizers[p].z_bits[qubit as usize] = true;
-        self.destabilizers[p] = old_p_stabilizer;
-        Ok(self.stabilizers[p].phase_is_negated)
+        // helper method for nondeterministic_measurement. The old stabilizer moves over to
+        // the destabilizers, and the old destabilizer's storage is reused for the new one.
+        let num_qubits = self.num_qubits();
+        mem::swap(&mut self.stabilizers[p], &mut self.destabilizers[p]);
+        let stabilizer = &mut self.stabilizers[p];
+        stabilizer.set_identity(num_qubits);
+        stabilizer.phase_is_negated = self.rand.gen_bool(0.5);
+        stabilizer.set_z(qubit.index(), true);
+        Ok(stabilizer.phase_is_negated)
     }
 
-    fn nondeterministic_measurement(&mut self, qubit: u32) -> Result<bool, &'static str> {
+    fn nondeterministic_measurement(&mut self, qubit: Qubit) -> Result<bool, &'static str> {
         // 1. find index p amoung stabilizers such that stabilizers[p][x_bits][qubit] = 1
         //
         // 1. add all rows (i, p)  for all i over stabilizers[i] and destabilizers[i] such
@@ -289,12 +1275,10 @@ impl<const N: usize> StabilizerSimulator<N> {
         self.collapse_p_stabilizer_and_return_measurement_outcome(p, qubit)
     }
 
-    fn determine_deterministic_measurement(&mut self, qubit: u32) -> Result<bool, &'static str> {
-        let mut scratch_row = TableauGeneratorRow {
-            phase_is_negated: false,
-            x_bits: [false; N],
-            z_bits: [false; N],
-        };
+    fn determine_deterministic_measurement(&mut self, qubit: Qubit) -> Result<bool, &'static str> {
+        let mut scratch_row = mem::take(&mut self.scratch_row);
+        scratch_row.set_identity(self.num_qubits());
+        let mut rowsums = 0;
         // try and determine if Z or -Z on the qubit is a stabilizer of the state.
         // You need to sum up a subset of stabilizer generators that produces +-Z[qubit] with
         // identity on all other qubits. The choice of which stabilizers to include in this
@@ -312,19 +1296,426 @@ impl<const N: usize> StabilizerSimulator<N> {
             .iter_mut()
             .zip(self.stabilizers.iter_mut())
         {
-            if destabilizer_row.x_bits[qubit as usize] {
+            if destabilizer_row.x(qubit.index()) {
+                Self::rowsum(&mut scratch_row, stabilizer_row)?;
+                rowsums += 1;
+            }
+        }
+        self.count_rowsums(rowsums);
+        let outcome = scratch_row.phase_is_negated;
+        self.scratch_row = scratch_row;
+        Ok(outcome)
+    }
+
+    pub(crate) fn collapse(&mut self, qubit: Qubit) -> Result<bool, &'static str> {
+        // measures the qubit without recording the outcome.
+        let start = self.stats.is_some().then(Instant::now);
+        let known = self.known_outcomes[qubit.index()];
+        let deterministic = self.is_deterministic(qubit);
+        let outcome = match known {
+            Some(outcome) => outcome,
+            None if deterministic => self.determine_deterministic_measurement(qubit)?,
+            None => self.nondeterministic_measurement(qubit)?,
+        };
+        self.known_outcomes[qubit.index()] = Some(outcome);
+        if let (Some(stats), Some(start)) = (self.stats.as_mut(), start) {
+            stats.record_measurement(deterministic, start.elapsed());
+        }
+        self.count_rng_draws();
+        Ok(outcome)
+    }
+
+    pub fn measure(&mut self, qubit: Qubit) -> Result<bool, &'static str> {
+        let outcome = self.collapse(qubit)?;
+        self.measurement_record.push(outcome);
+        self.clear_journal();
+        self.assert_invariants();
+        Ok(outcome)
+    }
+
+    pub fn project_onto(
+        &mut self,
+        observable: &PauliString,
+        outcome: Option<bool>,
+    ) -> Result<Projection, &'static str> {
+        // measures an arbitrary pauli observable, without recording the outcome. It's the same
+        // update as a Z measurement, with "has an X component on the qubit" generalized to
+        // "anticommutes with the observable". Passing an outcome projects onto that eigenspace
+        // instead of a random one, i.e. postselects -- which fails when the state is entirely
+        // in the other eigenspace.
+        if observable.num_qubits() != self.num_qubits() {
+            return Err("Observable acts on a different number of qubits than the simulator.");
+        }
+        let start = self.stats.is_some().then(Instant::now);
+        let anticommutes =
+            |row: &TableauGeneratorRow| !row.view().commutes_with(&observable.view());
+        let mut rowsums = 0;
+        let projection = if let Some(p) = self.stabilizers.iter().position(anticommutes) {
+            // make every other row commute with the observable, then swap it in for the pth
+            // stabilizer, which becomes its destabilizer.
+            for i in 0..self.num_qubits() {
+                if i == p {
+                    continue;
+                }
+                if anticommutes(&self.stabilizers[i]) {
+                    Self::rowsum_within(&mut self.stabilizers, i, p)?;
+                    rowsums += 1;
+                }
+                if anticommutes(&self.destabilizers[i]) {
+                    Self::rowsum(&mut self.destabilizers[i], &self.stabilizers[p])?;
+                    rowsums += 1;
+                }
+            }
+            let outcome = outcome.unwrap_or_else(|| self.rand.gen_bool(0.5));
+            let mut projected_row = TableauGeneratorRow::from_pauli(observable);
+            projected_row.phase_is_negated ^= outcome;
+            self.destabilizers[p] = mem::replace(&mut self.stabilizers[p], projected_row);
+            // a qubit only stays in a Z eigenstate if Z commutes with the observable.
+            for (known, &x) in self.known_outcomes.iter_mut().zip(&observable.x_bits) {
+                if x {
+                    *known = None;
+                }
+            }
+            Projection {
+                outcome,
+                deterministic: false,
+            }
+        } else {
+            let (group_sign, group_rowsums) = self.stabilizer_group_sign(observable)?;
+            rowsums += group_rowsums;
+            let forced = group_sign ^ observable.phase_is_negated;
+            if outcome.is_some_and(|outcome| outcome != forced) {
+                return Err("The state has no overlap with the requested eigenspace.");
+            }
+            Projection {
+                outcome: forced,
+                deterministic: true,
+            }
+        };
+        self.count_rowsums(rowsums);
+        if let (Some(stats), Some(start)) = (self.stats.as_mut(), start) {
+            stats.record_measurement(projection.deterministic, start.elapsed());
+        }
+        self.count_rng_draws();
+        self.clear_journal();
+        self.assert_invariants();
+        Ok(projection)
+    }
+
+    fn stabilizer_group_sign(&self, observable: &PauliString) -> Result<(bool, u64), &'static str> {
+        // for an observable commuting with every stabilizer, so that it's in the stabilizer
+        // group up to sign: whether the group holds the negated (unsigned) observable, and how
+        // many rowsums that took. The sign comes from multiplying the generators whose
+        // destabilizers anticommute with it, like determine_deterministic_measurement.
+        let mut scratch_row = TableauGeneratorRow::identity(self.num_qubits());
+        let mut rowsums = 0;
+        for (destabilizer_row, stabilizer_row) in self.destabilizers.iter().zip(&self.stabilizers) {
+            if !destabilizer_row.view().commutes_with(&observable.view()) {
                 Self::rowsum(&mut scratch_row, stabilizer_row)?;
+                rowsums += 1;
             }
         }
-        Ok(scratch_row.phase_is_negated)
+        Ok((scratch_row.phase_is_negated, rowsums))
     }
 
-    pub fn measure(&mut self, qubit: u32) -> Result<bool, &'static str> {
-        if self.is_deterministic(qubit) {
-            self.determine_deterministic_measurement(qubit)
+    pub fn pauli_expectation(&self, observable: &PauliString) -> Result<i32, &'static str> {
+        // <P> for a signed pauli string P. In a stabilizer state that's 0 unless P is in the
+        // stabilizer group up to sign, and then it's the sign.
+        if observable.num_qubits() != self.num_qubits() {
+            return Err("Observable acts on a different number of qubits than the simulator.");
+        }
+        if self
+            .stabilizers
+            .iter()
+            .any(|row| !row.view().commutes_with(&observable.view()))
+        {
+            return Ok(0);
+        }
+        let (group_sign, _) = self.stabilizer_group_sign(observable)?;
+        Ok(if group_sign ^ observable.phase_is_negated {
+            -1
         } else {
-            self.nondeterministic_measurement(qubit)
+            1
+        })
+    }
+
+    fn two_point_correlator(
+        &self,
+        i: Qubit,
+        j: Qubit,
+        x: bool,
+        z: bool,
+    ) -> Result<i32, &'static str> {
+        let num_qubits = self.num_qubits();
+        if i.index() >= num_qubits || j.index() >= num_qubits {
+            return Err("Qubit is outside the register.");
+        }
+        // a pauli squares to the identity, so P_i P_i is I.
+        let mut observable = PauliString::identity(num_qubits);
+        if i != j {
+            for qubit in [i, j] {
+                observable.x_bits[qubit.index()] = x;
+                observable.z_bits[qubit.index()] = z;
+            }
+        }
+        self.pauli_expectation(&observable)
+    }
+
+    pub fn correlator_xx(&self, i: Qubit, j: Qubit) -> Result<i32, &'static str> {
+        self.two_point_correlator(i, j, true, false)
+    }
+
+    pub fn correlator_yy(&self, i: Qubit, j: Qubit) -> Result<i32, &'static str> {
+        self.two_point_correlator(i, j, true, true)
+    }
+
+    pub fn correlator_zz(&self, i: Qubit, j: Qubit) -> Result<i32, &'static str> {
+        // <Z_i Z_j>, read off the stabilizer group rather than estimated from samples. It's
+        // +1 or -1 when the two qubits are perfectly (anti)correlated, and 0 otherwise.
+        self.two_point_correlator(i, j, false, true)
+    }
+
+    pub fn conditional_probability_of_one(
+        &self,
+        qubit: Qubit,
+        given: &[(Qubit, bool)],
+    ) -> Result<f64, &'static str> {
+        // P(qubit = 1 | the given qubits were measured with the given outcomes), worked out on a
+        // snapshot so this simulator's state, record and rng are left alone. Stabilizer states
+        // only ever give 0, 1/2 or 1. Conditioning on outcomes that can't happen is an error.
+        let num_qubits = self.num_qubits();
+        if qubit.index() >= num_qubits || given.iter().any(|(q, _)| q.index() >= num_qubits) {
+            return Err("Qubit is outside the register.");
+        }
+        let mut snapshot = StabilizerSimulator {
+            stabilizers: self.stabilizers.clone(),
+            destabilizers: self.destabilizers.clone(),
+            known_outcomes: self.known_outcomes.clone(),
+            ..StabilizerSimulator::seeded(0)
+        };
+        for &(observed, outcome) in given {
+            snapshot
+                .project_onto(&PauliString::z(num_qubits, observed), Some(outcome))
+                .map_err(|_| "The observed outcomes have probability zero.")?;
+        }
+        if !snapshot.is_deterministic(qubit) {
+            return Ok(0.5);
+        }
+        let one = snapshot.determine_deterministic_measurement(qubit)?;
+        Ok(if one { 1.0 } else { 0.0 })
+    }
+
+    pub fn reset(&mut self, qubit: Qubit) -> Result<(), &'static str> {
+        // collapse to |0> or |1>, then flip |1> back. Like a measurement, it can't be undone.
+        if self.collapse(qubit)? {
+            self.apply_gate(&Gate::X(qubit));
+        }
+        self.clear_journal();
+        self.assert_invariants();
+        Ok(())
+    }
+
+    pub fn verify_invariants(&self) -> Result<(), &'static str> {
+        // checks the tableau convention every update rule relies on: the stabilizers commute
+        // with each other, the destabilizers commute with each other, the ith destabilizer
+        // anticommutes with the ith stabilizer and only that one, and the stabilizers are
+        // independent. A gate rule with a bug will usually break one of these right away,
+        // long before it shows up as wrong measurement statistics.
+        // This is O(n^3), so it only runs automatically when enable_invariant_checks asks.
+        let num_qubits = self.num_qubits();
+        for i in 0..num_qubits {
+            for j in 0..num_qubits {
+                if i < j && !self.stabilizers[i].commutes_with(&self.stabilizers[j]) {
+                    return Err("Two stabilizer generators anticommute.");
+                }
+                if i < j && !self.destabilizers[i].commutes_with(&self.destabilizers[j]) {
+                    return Err("Two destabilizer generators anticommute.");
+                }
+                let commutes = self.destabilizers[i].commutes_with(&self.stabilizers[j]);
+                if i == j && commutes {
+                    return Err("A destabilizer commutes with its own stabilizer.");
+                }
+                if i != j && !commutes {
+                    return Err("A destabilizer anticommutes with another generator's stabilizer.");
+                }
+            }
+        }
+        if Self::rank(&self.stabilizers) != num_qubits {
+            return Err("Stabilizer generators aren't independent.");
+        }
+        Ok(())
+    }
+
+    fn rank(rows: &[TableauGeneratorRow]) -> usize {
+        // gaussian elimination over GF(2), treating each row as the bit vector (x bits | z bits).
+        let paulis: Vec<PauliString> = rows.iter().map(PauliString::from).collect();
+        let num_qubits = rows.first().map_or(0, |row| row.num_qubits);
+        BitMatrix::from_paulis(num_qubits, &paulis).map_or(0, |matrix| matrix.rank())
+    }
+
+    pub fn allocate_qubit(&mut self) -> Qubit {
+        // a fresh qubit starts out in |0>, unentangled from everything else. So every
+        // existing generator acts as identity on it, and we only need to add the Z stabilizer
+        // and X destabilizer pair for it, the same way new() does for the initial register.
+        let new_qubit = self.num_qubits();
+        for generator in self
+            .stabilizers
+            .iter_mut()
+            .chain(self.destabilizers.iter_mut())
+        {
+            generator.remap_qubits(new_qubit + 1, |j| (j < new_qubit).then_some(j));
+        }
+        let mut stabilizer = TableauGeneratorRow::identity(new_qubit + 1);
+        let mut destabilizer = TableauGeneratorRow::identity(new_qubit + 1);
+        stabilizer.set_z(new_qubit, true);
+        destabilizer.set_x(new_qubit, true);
+        self.stabilizers.push(stabilizer);
+        self.destabilizers.push(destabilizer);
+        self.known_outcomes.push(Some(false));
+        Qubit(new_qubit as u32)
+    }
+
+    pub fn free_qubit(&mut self, qubit: Qubit) -> Result<bool, &'static str> {
+        // we can only drop a qubit's column from the tableau once it's unentangled from the rest
+        // of the register, so we measure it first and hand the outcome back to the caller.
+        // Qubits with a higher index than the freed one shift down by one, like Vec::remove.
+        if qubit.index() >= self.num_qubits() {
+            return Err("Cannot free a qubit that isn't allocated.");
+        }
+        let outcome = self.measure(qubit)?;
+        let qubit = qubit.index();
+
+        // after the measurement, +-Z[qubit] is in the stabilizer group. It's the product of
+        // the stabilizers whose destabilizers anticommute with it (have an X component on the qubit).
+        // Fold all of those destabilizers into a single pivot p, and do the dual operation on the
+        // stabilizers so the tableau convention still holds. Afterwards stabilizers[p] is exactly +-Z[qubit].
+        let p = self
+            .destabilizers
+            .iter()
+            .position(|row| row.x(qubit))
+            .ok_or("No destabilizer row with X component at qubit -- the measured qubit should be stabilized by +-Z.")?;
+        let mut rowsums = 0;
+        for i in 0..self.num_qubits() {
+            if i == p || !self.destabilizers[i].x(qubit) {
+                continue;
+            }
+            Self::rowsum_within(&mut self.destabilizers, i, p)?;
+            Self::rowsum_within(&mut self.stabilizers, p, i)?;
+            rowsums += 2;
+        }
+
+        // now clear the qubit's column from every other generator by multiplying in +-Z[qubit].
+        // Nothing else has an X component there anymore, and since row p is about to be dropped we
+        // don't need to keep it consistent with the rows we touch.
+        for i in 0..self.num_qubits() {
+            if i == p {
+                continue;
+            }
+            if self.stabilizers[i].z(qubit) {
+                Self::rowsum_within(&mut self.stabilizers, i, p)?;
+                rowsums += 1;
+            }
+            if self.destabilizers[i].z(qubit) {
+                Self::rowsum(&mut self.destabilizers[i], &self.stabilizers[p])?;
+                rowsums += 1;
+            }
+        }
+        self.count_rowsums(rowsums);
+
+        self.stabilizers.remove(p);
+        self.destabilizers.remove(p);
+        for generator in self
+            .stabilizers
+            .iter_mut()
+            .chain(self.destabilizers.iter_mut())
+        {
+            let num_qubits = generator.num_qubits - 1;
+            generator.remap_qubits(num_qubits, |j| Some(if j < qubit { j } else { j + 1 }));
+        }
+        self.known_outcomes.remove(qubit);
+        Ok(outcome)
+    }
+
+    pub fn permute_qubits(&mut self, permutation: &[Qubit]) -> Result<(), &'static str> {
+        // permutation[q] is the index that qubit q ends up at. Relabeling qubits is
+        // just reordering the tableau columns -- every generator keeps its pauli
+        // on each qubit and its phase, so there is no need to route it through SWAP gates.
+        let num_qubits = self.num_qubits();
+        if permutation.len() != num_qubits {
+            return Err("Permutation must have one entry per qubit.");
+        }
+        let mut seen = vec![false; num_qubits];
+        for &target in permutation {
+            let target = target.index();
+            if target >= num_qubits || seen[target] {
+                return Err("Qubit permutation must map the register onto itself one-to-one.");
+            }
+            seen[target] = true;
+        }
+        let mut source = vec![0; num_qubits];
+        for (qubit, &target) in permutation.iter().enumerate() {
+            source[target.index()] = qubit;
+        }
+        for generator in self
+            .stabilizers
+            .iter_mut()
+            .chain(self.destabilizers.iter_mut())
+        {
+            generator.remap_qubits(num_qubits, |j| Some(source[j]));
         }
+        let mut known_outcomes = vec![None; num_qubits];
+        for (qubit, &target) in permutation.iter().enumerate() {
+            known_outcomes[target.index()] = self.known_outcomes[qubit];
+        }
+        self.known_outcomes = known_outcomes;
+        self.clear_journal();
+        Ok(())
+    }
+
+    pub fn relabel(&mut self, map: &HashMap<Qubit, Qubit>) -> Result<(), &'static str> {
+        // like permute_qubits, but only the qubits that move need to be mentioned.
+        let permutation: Vec<Qubit> = Qubit::range(self.num_qubits())
+            .map(|qubit| *map.get(&qubit).unwrap_or(&qubit))
+            .collect();
+        self.permute_qubits(&permutation)
+    }
+}
+
+impl Simulator for StabilizerSimulator {
+    fn num_qubits(&self) -> usize {
+        StabilizerSimulator::num_qubits(self)
+    }
+
+    fn apply_gate(&mut self, gate: &Gate) {
+        StabilizerSimulator::apply_gate(self, gate)
+    }
+
+    fn measure(&mut self, qubit: Qubit) -> Result<bool, &'static str> {
+        StabilizerSimulator::measure(self, qubit)
+    }
+
+    fn measurement_record(&self) -> &[bool] {
+        StabilizerSimulator::measurement_record(self)
+    }
+
+    fn classical_bits(&self) -> &[bool] {
+        StabilizerSimulator::classical_bits(self)
+    }
+
+    fn set_classical_bit(&mut self, bit: u32, value: bool) {
+        StabilizerSimulator::set_classical_bit(self, bit, value)
+    }
+
+    fn sweep_bits(&self) -> &[bool] {
+        StabilizerSimulator::sweep_bits(self)
+    }
+
+    fn reset(&mut self, qubit: Qubit) -> Result<(), &'static str> {
+        StabilizerSimulator::reset(self, qubit)
+    }
+
+    fn apply_gates<I: IntoIterator<Item = Gate>>(&mut self, gates: I) {
+        StabilizerSimulator::apply_gates(self, gates)
     }
 }
 
@@ -336,38 +1727,38 @@ mod test {
 
     #[test]
     fn test_i_measured_in_z_basis() {
-        let mut stabilizer: StabilizerSimulator<1> = StabilizerSimulator::seeded();
-        assert!(!stabilizer.measure(0).unwrap());
+        let mut stabilizer: StabilizerSimulator = StabilizerSimulator::seeded(1);
+        assert!(!stabilizer.measure(Qubit(0)).unwrap());
     }
 
     #[test]
     fn test_h_s_s_h_equals_x() {
-        let mut stabilizer: StabilizerSimulator<1> = StabilizerSimulator::seeded();
-        stabilizer.apply_gate(&Gate::H(0));
-        stabilizer.apply_gate(&Gate::S(0));
-        stabilizer.apply_gate(&Gate::S(0));
-        stabilizer.apply_gate(&Gate::H(0));
-        assert!(stabilizer.measure(0).unwrap());
+        let mut stabilizer: StabilizerSimulator = StabilizerSimulator::seeded(1);
+        stabilizer.apply_gate(&Gate::H(Qubit(0)));
+        stabilizer.apply_gate(&Gate::S(Qubit(0)));
+        stabilizer.apply_gate(&Gate::S(Qubit(0)));
+        stabilizer.apply_gate(&Gate::H(Qubit(0)));
+        assert!(stabilizer.measure(Qubit(0)).unwrap());
     }
 
     #[test]
     fn test_cnot_when_control_is_zero() {
-        let mut stabilizer: StabilizerSimulator<2> = StabilizerSimulator::seeded();
-        stabilizer.apply_gate(&Gate::Cx(0, 1));
-        assert!(!stabilizer.measure(0).unwrap());
-        assert!(!stabilizer.measure(1).unwrap());
+        let mut stabilizer: StabilizerSimulator = StabilizerSimulator::seeded(2);
+        stabilizer.apply_gate(&Gate::Cx(Qubit(0), Qubit(1)));
+        assert!(!stabilizer.measure(Qubit(0)).unwrap());
+        assert!(!stabilizer.measure(Qubit(1)).unwrap());
     }
 
     #[test]
     fn test_cnot_when_control_is_one() {
-        let mut stabilizer: StabilizerSimulator<2> = StabilizerSimulator::seeded();
-        stabilizer.apply_gate(&Gate::H(0));
-        stabilizer.apply_gate(&Gate::S(0));
-        stabilizer.apply_gate(&Gate::S(0));
-        stabilizer.apply_gate(&Gate::H(0));
-        stabilizer.apply_gate(&Gate::Cx(0, 1));
-        assert!(stabilizer.measure(0).unwrap());
-        assert!(stabilizer.measure(1).unwrap());
+        let mut stabilizer: StabilizerSimulator = StabilizerSimulator::seeded(2);
+        stabilizer.apply_gate(&Gate::H(Qubit(0)));
+        stabilizer.apply_gate(&Gate::S(Qubit(0)));
+        stabilizer.apply_gate(&Gate::S(Qubit(0)));
+        stabilizer.apply_gate(&Gate::H(Qubit(0)));
+        stabilizer.apply_gate(&Gate::Cx(Qubit(0), Qubit(1)));
+        assert!(stabilizer.measure(Qubit(0)).unwrap());
+        assert!(stabilizer.measure(Qubit(1)).unwrap());
     }
 
     #[test]
@@ -376,7 +1767,7 @@ mod test {
         // |+> |-> or the Y eigenstates. Our stabilizer simulator is seeded, so, once we have passed
         // with a given configuration, we should expect this test to pass deterministically.
 
-        let mut stabilizer: StabilizerSimulator<2> = StabilizerSimulator::seeded();
+        let mut stabilizer: StabilizerSimulator = StabilizerSimulator::seeded(2);
         let mut results = HashSet::new();
         // s_reps = 0, 1, 2, 3.
         // The amount of s gates to apply after hadamard.
@@ -388,7 +1779,7 @@ mod test {
             // We only have 1 - 0.5^10 chance of not getting either,
             // e.g. 99.9%+ chance of getting getting both.
             for _ in 0..10 {
-                stabilizer.apply_gate(&Gate::H(0));
+                stabilizer.apply_gate(&Gate::H(Qubit(0)));
                 for _ in 0..s_reps {
                     // the amount of additional S gates determines
                     // which X/Y eigenstate we are in.
@@ -396,9 +1787,9 @@ mod test {
                     // 1 -- Y stabilizer state
                     // 2 -- |->
                     // 3 -- -Y stabilizer state
-                    stabilizer.apply_gate(&Gate::S(0));
+                    stabilizer.apply_gate(&Gate::S(Qubit(0)));
                 }
-                let result = stabilizer.measure(0).unwrap();
+                let result = stabilizer.measure(Qubit(0)).unwrap();
                 results.insert(result);
             }
             assert!(results.len() == 2);
@@ -407,4 +1798,531 @@ mod test {
             results.clear();
         }
     }
+
+    #[test]
+    fn test_allocate_qubit_starts_in_zero_and_entangles() {
+        let mut stabilizer: StabilizerSimulator = StabilizerSimulator::seeded(1);
+        stabilizer.apply_gate(&Gate::H(Qubit(0)));
+        let ancilla = stabilizer.allocate_qubit();
+        assert_eq!(ancilla, Qubit(1));
+        assert_eq!(stabilizer.num_qubits(), 2);
+        stabilizer.apply_gate(&Gate::Cx(Qubit(0), ancilla));
+        let result = stabilizer.measure(Qubit(0)).unwrap();
+        assert_eq!(stabilizer.measure(ancilla).unwrap(), result);
+    }
+
+    #[test]
+    fn test_rows_span_several_words() {
+        // a GHZ state wider than a word, grown and shrunk across the word boundary.
+        let mut simulator = StabilizerSimulator::new(64, 3);
+        let qubits: Vec<Qubit> = (0..130).map(Qubit).collect();
+        for _ in 64..130 {
+            simulator.allocate_qubit();
+        }
+        simulator.prepare_ghz(&qubits);
+        assert_eq!(simulator.verify_invariants(), Ok(()));
+        assert_eq!(simulator.correlator_zz(Qubit(0), Qubit(129)), Ok(1));
+        let outcome = simulator.free_qubit(Qubit(64)).unwrap();
+        assert_eq!(simulator.num_qubits(), 129);
+        assert_eq!(simulator.verify_invariants(), Ok(()));
+        for q in 0..129 {
+            assert_eq!(simulator.measure(Qubit(q)), Ok(outcome));
+        }
+    }
+
+    #[test]
+    fn test_free_qubit_keeps_remaining_state() {
+        for seed in 0..10 {
+            let mut stabilizer: StabilizerSimulator = StabilizerSimulator::new(3, seed);
+            stabilizer.enable_invariant_checks();
+            // |1> on qubit 2, and a bell pair between qubits 0 and 1.
+            stabilizer.apply_gate(&Gate::H(Qubit(2)));
+            stabilizer.apply_gate(&Gate::S(Qubit(2)));
+            stabilizer.apply_gate(&Gate::S(Qubit(2)));
+            stabilizer.apply_gate(&Gate::H(Qubit(2)));
+            stabilizer.apply_gate(&Gate::H(Qubit(0)));
+            stabilizer.apply_gate(&Gate::Cx(Qubit(0), Qubit(1)));
+            let freed_outcome = stabilizer.free_qubit(Qubit(0)).unwrap();
+            assert_eq!(stabilizer.num_qubits(), 2);
+            // former qubits 1 and 2 shift down.
+            assert_eq!(stabilizer.measure(Qubit(0)).unwrap(), freed_outcome);
+            assert!(stabilizer.measure(Qubit(1)).unwrap());
+        }
+    }
+
+    #[test]
+    fn test_permute_qubits_moves_state() {
+        let mut stabilizer: StabilizerSimulator = StabilizerSimulator::seeded(3);
+        // |100>
+        stabilizer.apply_gate(&Gate::H(Qubit(0)));
+        stabilizer.apply_gate(&Gate::S(Qubit(0)));
+        stabilizer.apply_gate(&Gate::S(Qubit(0)));
+        stabilizer.apply_gate(&Gate::H(Qubit(0)));
+        stabilizer
+            .permute_qubits(&[Qubit(2), Qubit(0), Qubit(1)])
+            .unwrap();
+        assert!(!stabilizer.measure(Qubit(0)).unwrap());
+        assert!(!stabilizer.measure(Qubit(1)).unwrap());
+        assert!(stabilizer.measure(Qubit(2)).unwrap());
+        assert!(stabilizer
+            .permute_qubits(&[Qubit(0), Qubit(0), Qubit(1)])
+            .is_err());
+    }
+
+    #[test]
+    fn test_relabel_only_moves_mapped_qubits() {
+        let mut stabilizer: StabilizerSimulator = StabilizerSimulator::seeded(3);
+        stabilizer.apply_gate(&Gate::H(Qubit(1)));
+        stabilizer.apply_gate(&Gate::S(Qubit(1)));
+        stabilizer.apply_gate(&Gate::S(Qubit(1)));
+        stabilizer.apply_gate(&Gate::H(Qubit(1)));
+        stabilizer
+            .relabel(&HashMap::from([(Qubit(1), Qubit(2)), (Qubit(2), Qubit(1))]))
+            .unwrap();
+        assert!(!stabilizer.measure(Qubit(0)).unwrap());
+        assert!(!stabilizer.measure(Qubit(1)).unwrap());
+        assert!(stabilizer.measure(Qubit(2)).unwrap());
+    }
+
+    #[test]
+    fn test_trace_explains_generator_changes() {
+        let mut stabilizer: StabilizerSimulator = StabilizerSimulator::seeded(2);
+        stabilizer.apply_gate(&Gate::H(Qubit(0)));
+        assert!(stabilizer.trace_log().is_empty());
+        stabilizer.enable_trace();
+        stabilizer.apply_gate(&Gate::S(Qubit(0)));
+        stabilizer.apply_gate(&Gate::S(Qubit(0)));
+        let log = stabilizer.take_trace_log();
+        assert_eq!(log.len(), 2);
+        assert!(log[0].contains("stabilizer 0: +XI -> +YI"));
+        assert!(log[1].contains("stabilizer 0: +YI -> -XI (sign flipped)"));
+        assert!(stabilizer.trace_log().is_empty());
+    }
+
+    #[test]
+    fn test_verify_invariants_catches_broken_tableau() {
+        let mut stabilizer: StabilizerSimulator = StabilizerSimulator::seeded(2);
+        stabilizer.apply_gate(&Gate::H(Qubit(0)));
+        stabilizer.apply_gate(&Gate::Cx(Qubit(0), Qubit(1)));
+        assert_eq!(stabilizer.verify_invariants(), Ok(()));
+        // a made up "gate" that turns the Z on qubit 0 of the second stabilizer into an X.
+        stabilizer.stabilizers[1].set_x(0, true);
+        stabilizer.stabilizers[1].set_z(0, false);
+        assert!(stabilizer.verify_invariants().is_err());
+    }
+
+    #[test]
+    #[should_panic(expected = "tableau invariant broken")]
+    fn test_invariant_checks_stop_at_the_broken_update() {
+        let mut stabilizer: StabilizerSimulator = StabilizerSimulator::seeded(2);
+        stabilizer.apply_gate(&Gate::H(Qubit(0)));
+        // unchecked, the broken tableau goes unnoticed.
+        stabilizer.stabilizers[1].set_x(0, true);
+        stabilizer.apply_gate(&Gate::S(Qubit(1)));
+        stabilizer.enable_invariant_checks();
+        stabilizer.apply_gate(&Gate::S(Qubit(1)));
+    }
+
+    #[test]
+    fn test_prepare_bell_pair() {
+        let mut stabilizer: StabilizerSimulator = StabilizerSimulator::seeded(2);
+        stabilizer.prepare_bell_pair(Qubit(1), Qubit(0));
+        let stabilizers: Vec<String> = stabilizer
+            .stabilizers()
+            .iter()
+            .map(|generator| generator.to_string())
+            .collect();
+        assert_eq!(stabilizers, vec!["+ZZ", "+XX"]);
+    }
+
+    #[test]
+    fn test_canonical_stabilizers_identify_the_state() {
+        // two different ways of preparing the same bell pair.
+        let mut first: StabilizerSimulator = StabilizerSimulator::seeded(2);
+        first.prepare_bell_pair(Qubit(0), Qubit(1));
+        let mut second: StabilizerSimulator = StabilizerSimulator::seeded(2);
+        second.apply_gate(&Gate::H(Qubit(1)));
+        second.apply_gate(&Gate::Cx(Qubit(1), Qubit(0)));
+        assert_ne!(first.stabilizers(), second.stabilizers());
+        assert_eq!(
+            first.canonical_stabilizers().unwrap(),
+            second.canonical_stabilizers().unwrap()
+        );
+        second.apply_gate(&Gate::Z(Qubit(0)));
+        assert_ne!(
+            first.canonical_stabilizers().unwrap(),
+            second.canonical_stabilizers().unwrap()
+        );
+    }
+
+    #[test]
+    fn test_run_stream() {
+        let program = "H 0\nCX 0 1\nM 0 1\nCX rec[-1] 1\nM 1\n";
+        let mut simulator = StabilizerSimulator::seeded(2);
+        let outcomes = simulator.run_stream(program.as_bytes()).unwrap();
+        assert_eq!(outcomes.len(), 3);
+        assert_eq!(outcomes[0], outcomes[1]);
+        assert!(!outcomes[2]);
+        let error = simulator.run_stream("H 0\nH 2\n".as_bytes()).unwrap_err();
+        assert_eq!(error.line, 2);
+    }
+
+    #[test]
+    fn test_undo_and_rewind() {
+        let mut simulator = StabilizerSimulator::seeded(2);
+        simulator.apply_gate(&Gate::H(Qubit(0)));
+        let start = simulator.stabilizers();
+        simulator.enable_journal();
+        simulator.apply_gate(&Gate::S(Qubit(0)));
+        simulator.apply_gate(&Gate::Cx(Qubit(0), Qubit(1)));
+        simulator.apply_gate(&Gate::H(Qubit(1)));
+        assert_eq!(simulator.undo_last(), Ok(Gate::H(Qubit(1))));
+        assert_eq!(simulator.undoable_gates(), 2);
+        assert!(simulator.rewind(3).is_err());
+        simulator.rewind(2).unwrap();
+        assert_eq!(simulator.stabilizers(), start);
+        assert!(simulator.undo_last().is_err());
+
+        simulator.apply_gate(&Gate::H(Qubit(1)));
+        simulator.measure(Qubit(1)).unwrap();
+        assert_eq!(simulator.undoable_gates(), 0);
+    }
+
+    #[test]
+    fn test_reset_all() {
+        let mut simulator = StabilizerSimulator::seeded(3);
+        simulator
+            .run(&Circuit::ghz(&[Qubit(0), Qubit(1), Qubit(2)]))
+            .unwrap();
+        simulator.measure(Qubit(2)).unwrap();
+        simulator.reset_all();
+        assert!(simulator.measurement_record().is_empty());
+        let fresh = StabilizerSimulator::seeded(3);
+        assert_eq!(simulator.stabilizers(), fresh.stabilizers());
+        assert_eq!(simulator.destabilizers(), fresh.destabilizers());
+    }
+
+    #[test]
+    fn test_set_computational_basis_state() {
+        let mut simulator = StabilizerSimulator::seeded(4);
+        simulator.apply_gate(&Gate::H(Qubit(0)));
+        simulator
+            .set_computational_basis_state(&[false, true, true, false])
+            .unwrap();
+        let outcomes: Vec<bool> = Qubit::range(4)
+            .map(|q| simulator.measure(q).unwrap())
+            .collect();
+        assert_eq!(outcomes, vec![false, true, true, false]);
+        assert!(simulator.set_computational_basis_state(&[true]).is_err());
+    }
+
+    #[test]
+    fn test_preparations_ignore_the_old_state() {
+        // entangled going in, so every reset has to collapse something first.
+        let mut circuit = Circuit::ghz(&[Qubit(0), Qubit(1), Qubit(2)]);
+        circuit.reset(Qubit(0)).prep_x(Qubit(1)).prep_y(Qubit(2));
+        // rotate |+> and |+i> back to |0> before measuring.
+        circuit
+            .gate(Gate::H(Qubit(1)))
+            .gate(Gate::S(Qubit(2)))
+            .gate(Gate::S(Qubit(2)))
+            .gate(Gate::S(Qubit(2)))
+            .gate(Gate::H(Qubit(2)));
+        circuit
+            .measure(Qubit(0))
+            .measure(Qubit(1))
+            .measure(Qubit(2));
+        for seed in 0..10 {
+            let mut simulator = StabilizerSimulator::new(3, seed);
+            assert_eq!(simulator.run(&circuit), Ok(vec![false; 3]));
+            assert_eq!(simulator.measurement_record().len(), 3);
+        }
+    }
+
+    #[test]
+    fn test_stats_count_gates_and_measurements() {
+        let mut simulator = StabilizerSimulator::seeded(2);
+        simulator.apply_gate(&Gate::X(Qubit(0)));
+        assert_eq!(simulator.stats(), None);
+        simulator.enable_stats();
+        simulator.prepare_bell_pair(Qubit(0), Qubit(1));
+        simulator.measure(Qubit(0)).unwrap();
+        simulator.measure(Qubit(1)).unwrap();
+        let stats = simulator.take_stats();
+        assert_eq!(stats.gates["H"], 1);
+        assert_eq!(stats.gates["CX"], 1);
+        assert_eq!(
+            stats.num_gates(),
+            2,
+            "gates before enable_stats aren't counted"
+        );
+        // the first measurement is random, and fixes the second.
+        assert_eq!(stats.random_measurements, 1);
+        assert_eq!(stats.deterministic_measurements, 1);
+        assert!(stats.rowsums > 0);
+        // two gates on all four rows, plus a row per rowsum, each xoring 2 x 2 bits.
+        assert_eq!(stats.rows_touched, 8 + stats.rowsums);
+        assert_eq!(stats.bits_xored, 4 * stats.rowsums);
+        assert_eq!(stats.rng_draws, 1, "one coin flip for the random outcome");
+        assert_eq!(simulator.stats(), Some(&ExecutionStats::default()));
+    }
+
+    #[test]
+    fn test_apply_gates_streams_in_order() {
+        let mut streamed = StabilizerSimulator::seeded(3);
+        streamed.apply_gates([
+            Gate::H(Qubit(0)),
+            Gate::Cx(Qubit(0), Qubit(1)),
+            Gate::S(Qubit(1)),
+        ]);
+        let mut one_by_one = StabilizerSimulator::seeded(3);
+        one_by_one.apply_gate(&Gate::H(Qubit(0)));
+        one_by_one.apply_gate(&Gate::Cx(Qubit(0), Qubit(1)));
+        one_by_one.apply_gate(&Gate::S(Qubit(1)));
+        assert_eq!(streamed.stabilizers(), one_by_one.stabilizers());
+        assert_eq!(streamed.destabilizers(), one_by_one.destabilizers());
+    }
+
+    #[test]
+    fn test_rows_view_the_tableau() {
+        let mut stabilizer = StabilizerSimulator::seeded(2);
+        stabilizer.prepare_bell_pair(Qubit(1), Qubit(0));
+        assert_eq!(stabilizer.num_qubits(), 2);
+        assert_eq!(stabilizer.stabilizer_row(1).unwrap().to_string(), "+XX");
+        assert!(stabilizer.stabilizer_row(2).is_none());
+        let rows: Vec<PauliString> = stabilizer
+            .stabilizer_rows()
+            .map(|row| row.to_pauli_string())
+            .collect();
+        assert_eq!(rows, stabilizer.stabilizers());
+        assert_eq!(
+            stabilizer.destabilizer_row(0).unwrap(),
+            stabilizer.destabilizers()[0].view()
+        );
+        assert_eq!(stabilizer.destabilizer_rows().len(), 2);
+    }
+
+    #[test]
+    fn test_project_onto_pauli_observables() {
+        let mut stabilizer = StabilizerSimulator::seeded(2);
+        stabilizer.prepare_bell_pair(Qubit(0), Qubit(1));
+        let deterministic = |outcome| Projection {
+            outcome,
+            deterministic: true,
+        };
+        assert_eq!(
+            stabilizer.project_onto(&"-YY".parse().unwrap(), None),
+            Ok(deterministic(false))
+        );
+        assert!(stabilizer
+            .project_onto(&"+ZZ".parse().unwrap(), Some(true))
+            .is_err());
+        assert!(stabilizer
+            .project_onto(&"+Z".parse().unwrap(), None)
+            .is_err());
+
+        // postselecting Z0 = 1 leaves |11>, without touching the measurement record.
+        let projection = stabilizer.project_onto(&"+ZI".parse().unwrap(), Some(true));
+        assert_eq!(
+            projection,
+            Ok(Projection {
+                outcome: true,
+                deterministic: false
+            })
+        );
+        assert_eq!(
+            stabilizer.project_onto(&"+IZ".parse().unwrap(), None),
+            Ok(deterministic(true))
+        );
+        assert!(stabilizer.measurement_record().is_empty());
+
+        // and a Y eigenstate out of |1>.
+        stabilizer
+            .project_onto(&"+IY".parse().unwrap(), Some(false))
+            .unwrap();
+        assert_eq!(
+            stabilizer.project_onto(&"-IY".parse().unwrap(), None),
+            Ok(deterministic(true))
+        );
+    }
+
+    #[test]
+    fn test_conditional_probability_of_one() {
+        let mut stabilizer = StabilizerSimulator::seeded(3);
+        stabilizer.prepare_bell_pair(Qubit(0), Qubit(1));
+        stabilizer.apply_gate(&Gate::H(Qubit(2)));
+        let probability = |qubit, given: &[(Qubit, bool)]| {
+            stabilizer.conditional_probability_of_one(Qubit(qubit), given)
+        };
+        assert_eq!(probability(1, &[]), Ok(0.5));
+        assert_eq!(probability(1, &[(Qubit(0), true)]), Ok(1.0));
+        assert_eq!(probability(1, &[(Qubit(0), false)]), Ok(0.0));
+        assert_eq!(probability(2, &[(Qubit(0), true)]), Ok(0.5));
+        assert!(probability(1, &[(Qubit(0), true), (Qubit(1), false)]).is_err());
+        assert!(probability(3, &[]).is_err());
+        // the snapshot didn't collapse anything.
+        assert!(stabilizer.find_x_stabilizer_index(Qubit(0)).is_some());
+    }
+
+    #[test]
+    fn test_two_point_correlators() {
+        let mut stabilizer = StabilizerSimulator::seeded(4);
+        stabilizer.prepare_ghz(&[Qubit(0), Qubit(1), Qubit(2)]);
+        stabilizer.apply_gate(&Gate::X(Qubit(2)));
+        assert_eq!(stabilizer.correlator_zz(Qubit(0), Qubit(1)), Ok(1));
+        assert_eq!(stabilizer.correlator_zz(Qubit(1), Qubit(2)), Ok(-1));
+        assert_eq!(stabilizer.correlator_zz(Qubit(0), Qubit(3)), Ok(0));
+        assert_eq!(stabilizer.correlator_xx(Qubit(0), Qubit(1)), Ok(0));
+        assert_eq!(stabilizer.correlator_yy(Qubit(2), Qubit(2)), Ok(1));
+        assert!(stabilizer.correlator_zz(Qubit(0), Qubit(4)).is_err());
+
+        let mut bell = StabilizerSimulator::seeded(2);
+        bell.prepare_bell_pair(Qubit(0), Qubit(1));
+        assert_eq!(bell.correlator_xx(Qubit(0), Qubit(1)), Ok(1));
+        assert_eq!(bell.correlator_yy(Qubit(0), Qubit(1)), Ok(-1));
+    }
+
+    #[test]
+    fn test_subsystem_rank() {
+        // a bell pair on 0 and 1 next to a lone |0> on 2.
+        let mut stabilizer = StabilizerSimulator::seeded(3);
+        stabilizer.prepare_bell_pair(Qubit(0), Qubit(1));
+        let rank = stabilizer.subsystem_rank(&[Qubit(0)]).unwrap();
+        assert_eq!(
+            rank,
+            SubsystemRank {
+                num_qubits: 1,
+                inside: 0,
+                outside: 1,
+                crossing: 2
+            }
+        );
+        assert_eq!(rank.entanglement_entropy(), 1);
+        assert_eq!(rank.purity(), 0.5);
+        let rank = stabilizer.subsystem_rank(&[Qubit(0), Qubit(1)]).unwrap();
+        assert_eq!((rank.inside, rank.outside, rank.crossing), (2, 1, 0));
+        assert_eq!(rank.purity(), 1.0);
+        assert_eq!(stabilizer.subsystem_rank(&[]).unwrap().inside, 0);
+        assert!(stabilizer.subsystem_rank(&[Qubit(3)]).is_err());
+    }
+
+    #[test]
+    fn test_fused_runs_match_gate_by_gate() {
+        use rand::{Rng, SeedableRng};
+        let mut rng = rand::rngs::StdRng::seed_from_u64(11);
+        for seed in 0..20 {
+            let mut circuit = Circuit::new(4);
+            for _ in 0..60 {
+                let a = Qubit(rng.gen_range(0..4));
+                let b = Qubit((a.0 + rng.gen_range(1..4)) % 4);
+                match rng.gen_range(0..8) {
+                    0 => circuit.gate(Gate::Cx(a, b)),
+                    1 => circuit.measure(a),
+                    2 => circuit.gate(Gate::H(a)),
+                    3 => circuit.gate(Gate::S(a)),
+                    _ => circuit.gate([Gate::X(a), Gate::Y(a), Gate::Z(a)][rng.gen_range(0..3)]),
+                };
+            }
+            let mut fused = StabilizerSimulator::new(4, seed);
+            let mut unfused = StabilizerSimulator::new(4, seed);
+            unfused.disable_fusion();
+            fused.enable_invariant_checks();
+            unfused.enable_invariant_checks();
+            assert_eq!(fused.run(&circuit), unfused.run(&circuit));
+            assert_eq!(fused.stabilizers(), unfused.stabilizers());
+            assert_eq!(fused.destabilizers(), unfused.destabilizers());
+
+            // noisy runs fuse too, with the errors drawn in the same order.
+            let noise = NoiseModel::uniform_depolarizing(0.1).with_idle_depolarization(0.1);
+            assert_eq!(
+                fused.run_noisy(&circuit, &noise),
+                unfused.run_noisy(&circuit, &noise)
+            );
+            assert_eq!(fused.stabilizers(), unfused.stabilizers());
+            assert_eq!(fused.destabilizers(), unfused.destabilizers());
+        }
+    }
+
+    #[test]
+    fn test_known_outcomes_match_the_tableau() {
+        use rand::{Rng, SeedableRng};
+        let mut rng = rand::rngs::StdRng::seed_from_u64(5);
+        let mut simulator = StabilizerSimulator::new(4, 5);
+        simulator.enable_invariant_checks();
+        for _ in 0..400 {
+            let a = Qubit(rng.gen_range(0..4));
+            let b = Qubit((a.0 + rng.gen_range(1..4)) % 4);
+            let gate = match rng.gen_range(0..9) {
+                0 => Gate::H(a),
+                1 => Gate::S(a),
+                2 => Gate::X(a),
+                3 => Gate::Y(a),
+                4 => Gate::Z(a),
+                5 => Gate::Cx(a, b),
+                6 => Gate::Cz(a, b),
+                7 => Gate::Swap(a, b),
+                _ => {
+                    simulator.measure(a).unwrap();
+                    continue;
+                }
+            };
+            simulator.apply_gate(&gate);
+            for qubit in Qubit::range(4) {
+                if let Some(outcome) = simulator.known_outcomes[qubit.index()] {
+                    assert!(simulator.find_x_stabilizer_index(qubit).is_none());
+                    let mut check = simulator.clone();
+                    assert_eq!(
+                        check.determine_deterministic_measurement(qubit),
+                        Ok(outcome)
+                    );
+                }
+            }
+        }
+
+        // measuring again without touching the qubit doesn't go near the tableau.
+        let mut simulator = StabilizerSimulator::seeded(3);
+        simulator.prepare_ghz(&[Qubit(0), Qubit(1), Qubit(2)]);
+        let first = simulator.measure(Qubit(0)).unwrap();
+        simulator.enable_stats();
+        for _ in 0..5 {
+            assert_eq!(simulator.measure(Qubit(0)), Ok(first));
+        }
+        simulator.apply_gate(&Gate::X(Qubit(0)));
+        assert_eq!(simulator.measure(Qubit(0)), Ok(!first));
+        assert_eq!(simulator.take_stats().rowsums, 0);
+        assert_eq!(simulator.measure(Qubit(2)), Ok(first));
+    }
+
+    #[test]
+    fn test_measurements_reuse_the_scratch_row() {
+        let mut simulator = StabilizerSimulator::seeded(3);
+        let scratch = simulator.scratch_row.words.as_ptr();
+        for _ in 0..10 {
+            simulator.prepare_ghz(&[Qubit(0), Qubit(1), Qubit(2)]);
+            let first = simulator.measure(Qubit(0)).unwrap();
+            assert_eq!(simulator.measure(Qubit(2)), Ok(first));
+            simulator.reset_all();
+        }
+        assert_eq!(simulator.scratch_row.words.as_ptr(), scratch);
+    }
+
+    #[test]
+    fn test_simulators_reuse_tableau_storage() {
+        let mut storage = TableauStorage::with_capacity(3);
+        assert_eq!(storage.num_rows(), 7);
+        for num_qubits in [3, 2, 4] {
+            let mut simulator = StabilizerSimulator::from_storage(num_qubits, 1, storage);
+            assert_eq!(
+                simulator.stabilizers(),
+                StabilizerSimulator::seeded(num_qubits).stabilizers()
+            );
+            simulator.prepare_bell_pair(Qubit(0), Qubit(1));
+            let first = simulator.measure(Qubit(0)).unwrap();
+            assert_eq!(simulator.measure(Qubit(1)), Ok(first));
+            let row = simulator.stabilizers[0].words.as_ptr();
+            storage = simulator.into_storage();
+            assert_eq!(storage.num_rows(), 2 * num_qubits + 1);
+            assert!(storage.rows.iter().any(|r| r.words.as_ptr() == row));
+        }
+    }
 }
@@ -0,0 +1,131 @@
+use crate::gates::Gate;
+use rand::RngCore;
+use std::collections::BTreeMap;
+use std::fmt;
+use std::time::Duration;
+
+// what a simulator spent its time on, for performance work. Collected only while enabled
+// (see StabilizerSimulator::enable_stats), since timing every gate isn't free.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ExecutionStats {
+    // gates applied, keyed by name (H, CX, ...).
+    pub gates: BTreeMap<&'static str, u64>,
+    // resets collapse the qubit too, so they're counted here as measurements.
+    pub deterministic_measurements: u64,
+    pub random_measurements: u64,
+    // row multiplications, the O(n) step measurements are made of.
+    pub rowsums: u64,
+    // generator rows written, by gates (every row, once per gate) and rowsums (one each).
+    pub rows_touched: u64,
+    // tableau bits flipped by rowsums, x and z bits both. The tableau keeps a bool per bit,
+    // so this is the xor work the packed kernels in simd would do 64 at a time.
+    pub bits_xored: u64,
+    // draws from the simulator's rng, for random outcomes and sampled noise alike.
+    pub rng_draws: u64,
+    pub gate_time: Duration,
+    pub measurement_time: Duration,
+}
+
+impl ExecutionStats {
+    pub fn num_gates(&self) -> u64 {
+        self.gates.values().sum()
+    }
+
+    pub fn num_measurements(&self) -> u64 {
+        self.deterministic_measurements + self.random_measurements
+    }
+
+    pub(crate) fn record_gate(&mut self, gate: &Gate, num_rows: usize, time: Duration) {
+        *self.gates.entry(gate.name()).or_insert(0) += 1;
+        self.rows_touched += num_rows as u64;
+        self.gate_time += time;
+    }
+
+    pub(crate) fn record_rowsums(&mut self, rowsums: u64, num_qubits: usize) {
+        self.rowsums += rowsums;
+        self.rows_touched += rowsums;
+        self.bits_xored += rowsums * 2 * num_qubits as u64;
+    }
+
+    pub fn merge(&mut self, other: &ExecutionStats) {
+        // adds other's counts and times to these.
+        for (name, count) in &other.gates {
+            *self.gates.entry(name).or_insert(0) += count;
+        }
+        self.deterministic_measurements += other.deterministic_measurements;
+        self.random_measurements += other.random_measurements;
+        self.rowsums += other.rowsums;
+        self.rows_touched += other.rows_touched;
+        self.bits_xored += other.bits_xored;
+        self.rng_draws += other.rng_draws;
+        self.gate_time += other.gate_time;
+        self.measurement_time += other.measurement_time;
+    }
+
+    pub(crate) fn record_measurement(&mut self, deterministic: bool, time: Duration) {
+        if deterministic {
+            self.deterministic_measurements += 1;
+        } else {
+            self.random_measurements += 1;
+        }
+        self.measurement_time += time;
+    }
+}
+
+impl fmt::Display for ExecutionStats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "gates: {} in {:?}", self.num_gates(), self.gate_time)?;
+        for (name, count) in &self.gates {
+            writeln!(f, "  {name}: {count}")?;
+        }
+        writeln!(
+            f,
+            "measurements: {} deterministic, {} random in {:?}",
+            self.deterministic_measurements, self.random_measurements, self.measurement_time
+        )?;
+        writeln!(f, "rowsums: {}", self.rowsums)?;
+        writeln!(
+            f,
+            "rows touched: {}, bits xored: {}",
+            self.rows_touched, self.bits_xored
+        )?;
+        write!(f, "rng draws: {}", self.rng_draws)
+    }
+}
+
+// an rng that counts how often it's drawn from, each next_u32, next_u64 or fill_bytes
+// counting once. Counting is always on, since it's one add; the simulator moves the count
+// into its stats when those are enabled, and drops it otherwise.
+#[derive(Debug, Clone)]
+pub(crate) struct CountingRng<R> {
+    rng: R,
+    pub(crate) draws: u64,
+}
+
+impl<R> CountingRng<R> {
+    pub(crate) fn new(rng: R) -> CountingRng<R> {
+        CountingRng { rng, draws: 0 }
+    }
+}
+
+impl<R: RngCore> RngCore for CountingRng<R> {
+    fn next_u32(&mut self) -> u32 {
+        self.draws += 1;
+        self.rng.next_u32()
+    }
+
+    fn next_u64(&mut self) -> u64 {
+        self.draws += 1;
+        self.rng.next_u64()
+    }
+
+    fn fill_bytes(&mut self, dest: &mut [u8]) {
+        self.draws += 1;
+        self.rng.fill_bytes(dest);
+    }
+
+    fn try_fill_bytes(&mut self, dest: &mut [u8]) -> Result<(), rand::Error> {
+        self.draws += 1;
+        self.rng.try_fill_bytes(dest)
+    }
+}
@@ -0,0 +1,219 @@
+use crate::circuit::Circuit;
+use crate::diagram::escape;
+
+// one shot's detection events laid out on the detectors' coordinates, round by round, to look
+// at what a decoder is being given. Detector coordinates are read the way stim's are: the
+// last one is the round (time), and the ones before it place the detector in space, x then y.
+// A detector with a single coordinate sits at that x in round 0, and one without any is
+// placed at its index.
+//
+// As SVG, each round is a panel of the detector grid, with the detectors that fired in red.
+// As plotly JSON, it's a 3D scatter with the round going up, which plotly.io.from_json (or
+// Plotly.newPlot in a web page) can show and rotate.
+
+const UNIT: f64 = 24.0;
+const PAD: f64 = 20.0;
+const PANELS_PER_ROW: usize = 8;
+const FIRED: &str = "#d62728";
+
+struct Placed {
+    x: f64,
+    y: f64,
+    round: f64,
+}
+
+fn place(circuit: &Circuit, events: &[bool]) -> Result<Vec<Placed>, &'static str> {
+    if events.len() != circuit.detectors().len() {
+        return Err("Expected one detection event per detector.");
+    }
+    Ok(circuit
+        .detectors()
+        .iter()
+        .enumerate()
+        .map(|(k, detector)| match detector.coords[..] {
+            [] => Placed {
+                x: k as f64,
+                y: 0.0,
+                round: 0.0,
+            },
+            [x] => Placed {
+                x,
+                y: 0.0,
+                round: 0.0,
+            },
+            [x, round] => Placed { x, y: 0.0, round },
+            [x, y, .., round] => Placed { x, y, round },
+        })
+        .collect())
+}
+
+fn number(value: f64) -> String {
+    // JSON has no NaN or infinity.
+    if value.is_finite() {
+        value.to_string()
+    } else {
+        "null".to_string()
+    }
+}
+
+pub fn detection_timeline_svg(circuit: &Circuit, events: &[bool]) -> Result<String, &'static str> {
+    let placed = place(circuit, events)?;
+    let mut rounds: Vec<f64> = placed.iter().map(|p| p.round).collect();
+    rounds.sort_by(f64::total_cmp);
+    rounds.dedup();
+    let range = |values: &mut dyn Iterator<Item = f64>| {
+        values.fold((f64::INFINITY, f64::NEG_INFINITY), |(low, high), v| {
+            (low.min(v), high.max(v))
+        })
+    };
+    let (x_min, x_max) = range(&mut placed.iter().map(|p| p.x));
+    let (y_min, y_max) = range(&mut placed.iter().map(|p| p.y));
+    let panel_width = (x_max - x_min).max(0.0) * UNIT + 2.0 * PAD;
+    let panel_height = (y_max - y_min).max(0.0) * UNIT + 2.0 * PAD + 16.0;
+    let columns = rounds.len().clamp(1, PANELS_PER_ROW);
+    let width = columns as f64 * panel_width;
+    let height = rounds.len().div_ceil(PANELS_PER_ROW).max(1) as f64 * panel_height;
+
+    let mut svg = format!(
+        "<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"{width}\" height=\"{height}\" \
+         viewBox=\"0 0 {width} {height}\" font-family=\"monospace\" font-size=\"12\">\n\
+         <rect width=\"100%\" height=\"100%\" fill=\"white\"/>\n"
+    );
+    let corner = |panel: usize| {
+        (
+            (panel % PANELS_PER_ROW) as f64 * panel_width,
+            (panel / PANELS_PER_ROW) as f64 * panel_height,
+        )
+    };
+    for (panel, round) in rounds.iter().enumerate() {
+        let (left, top) = corner(panel);
+        let fired = placed
+            .iter()
+            .zip(events)
+            .filter(|(p, &event)| p.round == *round && event)
+            .count();
+        svg.push_str(&format!(
+            "<rect x=\"{}\" y=\"{}\" width=\"{}\" height=\"{}\" fill=\"none\" \
+             stroke=\"#cccccc\"/>\n<text x=\"{}\" y=\"{}\">round {round} ({fired} fired)</text>\n",
+            left + 2.0,
+            top + 2.0,
+            panel_width - 4.0,
+            panel_height - 4.0,
+            left + 8.0,
+            top + 16.0
+        ));
+    }
+    for (k, (p, &event)) in placed.iter().zip(events).enumerate() {
+        let panel = rounds.iter().position(|&round| round == p.round).unwrap();
+        let (left, top) = corner(panel);
+        let x = left + PAD + (p.x - x_min) * UNIT;
+        let y = top + 16.0 + PAD + (p.y - y_min) * UNIT;
+        let (r, fill) = if event {
+            (7.0, FIRED)
+        } else {
+            (4.0, "#dddddd")
+        };
+        let coords: Vec<String> = circuit.detectors()[k]
+            .coords
+            .iter()
+            .map(f64::to_string)
+            .collect();
+        svg.push_str(&format!(
+            "<circle cx=\"{x}\" cy=\"{y}\" r=\"{r}\" fill=\"{fill}\"><title>{}</title></circle>\n",
+            escape(&format!("D{k} ({})", coords.join(", ")))
+        ));
+    }
+    svg.push_str("</svg>\n");
+    Ok(svg)
+}
+
+pub fn detection_timeline_plotly(
+    circuit: &Circuit,
+    events: &[bool],
+) -> Result<String, &'static str> {
+    let placed = place(circuit, events)?;
+    let trace = |name: &str, color: &str, size: u32, fired: bool| {
+        let chosen: Vec<(usize, &Placed)> = placed
+            .iter()
+            .enumerate()
+            .filter(|&(k, _)| events[k] == fired)
+            .collect();
+        let list = |f: &dyn Fn(&(usize, &Placed)) -> String| {
+            chosen.iter().map(f).collect::<Vec<_>>().join(", ")
+        };
+        format!(
+            "{{\"type\": \"scatter3d\", \"mode\": \"markers\", \"name\": \"{name}\", \
+             \"x\": [{}], \"y\": [{}], \"z\": [{}], \"text\": [{}], \
+             \"marker\": {{\"size\": {size}, \"color\": \"{color}\"}}}}",
+            list(&|(_, p)| number(p.x)),
+            list(&|(_, p)| number(p.y)),
+            list(&|(_, p)| number(p.round)),
+            list(&|(k, _)| format!("\"D{k}\"")),
+        )
+    };
+    Ok(format!(
+        "{{\"data\": [{}, {}], \"layout\": {{\"scene\": {{\
+         \"xaxis\": {{\"title\": {{\"text\": \"x\"}}}}, \
+         \"yaxis\": {{\"title\": {{\"text\": \"y\"}}}}, \
+         \"zaxis\": {{\"title\": {{\"text\": \"round\"}}}}}}}}}}",
+        trace("detectors", "#dddddd", 3, false),
+        trace("detection events", FIRED, 6, true),
+    ))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::decoding::detection_events;
+    use crate::qubit::Qubit;
+
+    fn two_rounds() -> Circuit {
+        // three detectors a round, on a line, for two rounds.
+        let mut circuit = Circuit::new(3);
+        for round in 0..2 {
+            for q in 0..3 {
+                circuit.measure(Qubit(q));
+            }
+            for q in 0..3 {
+                let x = q as f64;
+                circuit
+                    .add_detector(&[3 - q], &[x, 1.0, round as f64])
+                    .unwrap();
+            }
+        }
+        circuit
+    }
+
+    #[test]
+    fn test_detection_timeline_svg() {
+        let circuit = two_rounds();
+        let shots = vec![
+            vec![false; 6],
+            vec![false, false, false, false, true, false],
+        ];
+        let events = detection_events(&circuit, &shots, 3).unwrap();
+        assert_eq!(events[0], vec![false; 6]);
+        assert_eq!(events[1], vec![false, false, false, false, true, false]);
+
+        let svg = detection_timeline_svg(&circuit, &events[1]).unwrap();
+        assert!(svg.contains(">round 0 (0 fired)</text>"));
+        assert!(svg.contains(">round 1 (1 fired)</text>"));
+        assert_eq!(svg.matches("<circle").count(), 6);
+        assert_eq!(svg.matches(FIRED).count(), 1);
+        assert!(svg.contains("<title>D4 (1, 1, 1)</title>"));
+        assert!(detection_timeline_svg(&circuit, &[true]).is_err());
+    }
+
+    #[test]
+    fn test_detection_timeline_plotly() {
+        let circuit = two_rounds();
+        let mut events = vec![false; 6];
+        events[2] = true;
+        let json = detection_timeline_plotly(&circuit, &events).unwrap();
+        assert!(json.starts_with("{\"data\": [{\"type\": \"scatter3d\""));
+        assert!(json.contains(
+            "\"name\": \"detection events\", \"x\": [2], \"y\": [1], \"z\": [0], \"text\": [\"D2\"]"
+        ));
+        assert_eq!(json.matches('{').count(), json.matches('}').count());
+    }
+}
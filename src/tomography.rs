@@ -0,0 +1,201 @@
+use crate::circuit::Circuit;
+use crate::mbqc::MeasurementBasis;
+use crate::noise::NoiseModel;
+use crate::pauli::PauliString;
+use crate::qubit::Qubit;
+use crate::sampler::{self, Sampler};
+use rand::{Rng, SeedableRng};
+use std::collections::HashMap;
+
+// pauli-basis state tomography. Each measurement setting picks an X, Y or Z basis per qubit;
+// its circuit prepares the state, rotates every qubit into its basis and measures them all.
+// One setting's shots estimate the expectation of every pauli that agrees with it wherever
+// it isn't the identity (a setting of XZ covers XZ, XI, IZ and II), and estimates from all
+// the settings that cover a pauli are pooled.
+//
+// All 3^n settings cover every pauli, which only makes sense for a handful of qubits, so a
+// random subset of settings can be used instead.
+
+pub fn all_settings(num_qubits: usize) -> Vec<Vec<MeasurementBasis>> {
+    let mut settings = vec![vec![]];
+    for _ in 0..num_qubits {
+        settings = settings
+            .into_iter()
+            .flat_map(|setting: Vec<MeasurementBasis>| {
+                [
+                    MeasurementBasis::X,
+                    MeasurementBasis::Y,
+                    MeasurementBasis::Z,
+                ]
+                .map(|basis| {
+                    let mut setting = setting.clone();
+                    setting.push(basis);
+                    setting
+                })
+            })
+            .collect();
+    }
+    settings
+}
+
+pub fn random_settings(
+    num_qubits: usize,
+    num_settings: usize,
+    seed: u64,
+) -> Vec<Vec<MeasurementBasis>> {
+    let mut rng = rand::rngs::StdRng::seed_from_u64(seed);
+    (0..num_settings)
+        .map(|_| {
+            (0..num_qubits)
+                .map(|_| match rng.gen_range(0..3) {
+                    0 => MeasurementBasis::X,
+                    1 => MeasurementBasis::Y,
+                    _ => MeasurementBasis::Z,
+                })
+                .collect()
+        })
+        .collect()
+}
+
+pub fn tomography_circuit(
+    preparation: &Circuit,
+    setting: &[MeasurementBasis],
+) -> Result<Circuit, &'static str> {
+    // the preparation, then a measurement of every qubit in the setting's basis. The last
+    // num_qubits measurements of the record are the ones that count.
+    if setting.len() != preparation.num_qubits() {
+        return Err("Measurement setting needs one basis per qubit.");
+    }
+    let mut circuit = preparation.clone();
+    for (qubit, basis) in Qubit::range(setting.len()).zip(setting) {
+        for gate in basis.rotation_to_z(qubit) {
+            circuit.gate(gate);
+        }
+    }
+    for qubit in Qubit::range(setting.len()) {
+        circuit.measure(qubit);
+    }
+    Ok(circuit)
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Tomography {
+    num_qubits: usize,
+    // per pauli (sign left positive), the sum of its eigenvalue over every shot that
+    // measured it, and the number of those shots.
+    totals: HashMap<PauliString, (f64, usize)>,
+}
+
+fn pauli_for(setting: &[MeasurementBasis], support: usize) -> PauliString {
+    // the pauli the setting measures on the qubits in the support bitmask.
+    let mut pauli = PauliString::identity(setting.len());
+    for (qubit, basis) in setting.iter().enumerate() {
+        if support >> qubit & 1 == 1 {
+            let (x, z) = match basis {
+                MeasurementBasis::X => (true, false),
+                MeasurementBasis::Y => (true, true),
+                MeasurementBasis::Z => (false, true),
+            };
+            pauli.x_bits[qubit] = x;
+            pauli.z_bits[qubit] = z;
+        }
+    }
+    pauli
+}
+
+impl Tomography {
+    pub fn run(
+        preparation: &Circuit,
+        settings: &[Vec<MeasurementBasis>],
+        shots_per_setting: usize,
+        noise: &NoiseModel,
+        seed: u64,
+    ) -> Result<Tomography, &'static str> {
+        let num_qubits = preparation.num_qubits();
+        if num_qubits > 16 {
+            return Err("Tomography covers 2^n paulis per setting, so it's capped at 16 qubits.");
+        }
+        let mut totals = HashMap::new();
+        for (index, setting) in settings.iter().enumerate() {
+            let circuit = tomography_circuit(preparation, setting)?;
+            let shots = Sampler::new(&circuit, sampler::shot_seed(seed, index as u64))
+                .with_noise(noise.clone())
+                .sample(shots_per_setting)?;
+            for support in 1..1usize << num_qubits {
+                let mut sum = 0.0;
+                for shot in &shots {
+                    let outcomes = &shot[shot.len() - num_qubits..];
+                    let parity = (0..num_qubits)
+                        .filter(|&qubit| support >> qubit & 1 == 1 && outcomes[qubit])
+                        .count();
+                    sum += if parity % 2 == 0 { 1.0 } else { -1.0 };
+                }
+                let total = totals
+                    .entry(pauli_for(setting, support))
+                    .or_insert((0.0, 0));
+                total.0 += sum;
+                total.1 += shots.len();
+            }
+        }
+        Ok(Tomography { num_qubits, totals })
+    }
+
+    pub fn num_qubits(&self) -> usize {
+        self.num_qubits
+    }
+
+    pub fn estimate(&self, pauli: &PauliString) -> Result<f64, &'static str> {
+        // the estimated expectation of a pauli, if any setting measured it.
+        if pauli.num_qubits() != self.num_qubits {
+            return Err("Pauli must act on as many qubits as the tomography.");
+        }
+        let sign = if pauli.phase_is_negated { -1.0 } else { 1.0 };
+        if pauli.x_bits.iter().chain(&pauli.z_bits).all(|&bit| !bit) {
+            return Ok(sign);
+        }
+        let mut unsigned = pauli.clone();
+        unsigned.phase_is_negated = false;
+        match self.totals.get(&unsigned) {
+            Some(&(sum, shots)) if shots > 0 => Ok(sign * sum / shots as f64),
+            _ => Err("No measurement setting covers that pauli."),
+        }
+    }
+
+    pub fn estimates(&self) -> impl Iterator<Item = (&PauliString, f64)> {
+        // every measured pauli with its estimate, in no particular order.
+        self.totals
+            .iter()
+            .filter(|(_, &(_, shots))| shots > 0)
+            .map(|(pauli, &(sum, shots))| (pauli, sum / shots as f64))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_bell_state_tomography() {
+        let bell = Circuit::bell_pair(Qubit(0), Qubit(1));
+        assert_eq!(all_settings(2).len(), 9);
+        let tomography =
+            Tomography::run(&bell, &all_settings(2), 200, &NoiseModel::noiseless(), 3).unwrap();
+        assert_eq!(tomography.estimate(&"XX".parse().unwrap()), Ok(1.0));
+        assert_eq!(tomography.estimate(&"YY".parse().unwrap()), Ok(-1.0));
+        assert_eq!(tomography.estimate(&"ZZ".parse().unwrap()), Ok(1.0));
+        assert_eq!(tomography.estimate(&"II".parse().unwrap()), Ok(1.0));
+        // single qubit paulis average to 0 over 600 shots each.
+        assert!(tomography.estimate(&"XI".parse().unwrap()).unwrap().abs() < 0.2);
+        assert!(tomography.estimate(&"IZ".parse().unwrap()).unwrap().abs() < 0.2);
+        assert_eq!(tomography.estimates().count(), 15);
+
+        // a single ZZ setting covers ZZ, ZI and IZ, and nothing else.
+        let settings = vec![vec![MeasurementBasis::Z; 2]];
+        let tomography =
+            Tomography::run(&bell, &settings, 10, &NoiseModel::noiseless(), 3).unwrap();
+        assert_eq!(tomography.estimates().count(), 3);
+        assert!(tomography.estimate(&"XX".parse().unwrap()).is_err());
+        assert_eq!(random_settings(3, 5, 1).len(), 5);
+        assert!(tomography_circuit(&bell, &settings[0][..1]).is_err());
+    }
+}
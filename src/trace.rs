@@ -0,0 +1,79 @@
+use crate::gates::Gate;
+use crate::pauli::PauliString;
+
+// plain-language versions of the reasoning in StabilizerSimulator::apply_gate,
+// used by the simulator's trace mode.
+fn describe_rule(gate: &Gate) -> String {
+    match *gate {
+        Gate::H(qubit) => format!(
+            "H on qubit {qubit} swaps the X and Z components there. X <-> Z keep their sign, \
+             but Y picks up a -1, since Y == -iZX turns into -iXZ == -Y."
+        ),
+        Gate::S(qubit) => format!(
+            "S on qubit {qubit} rotates X -> Y -> -X -> -Y around the Z axis. X components \
+             gain a Z component, and Y generators become X with the opposite sign. Z is untouched."
+        ),
+        Gate::X(qubit) | Gate::Y(qubit) | Gate::Z(qubit) => format!(
+            "{} on qubit {qubit} leaves every generator's paulis alone, and only negates \
+             the generators that anticommute with it there.",
+            gate.name()
+        ),
+        Gate::Cx(control, target) => format!(
+            "CX from qubit {control} to qubit {target} copies X components from the control \
+             onto the target, and Z components from the target back onto the control. \
+             The sign flips when that reorders an X on the control past a Z on the target \
+             into something like -(Y ⊗ Y)."
+        ),
+        Gate::Cz(a, b) => format!(
+            "CZ between qubits {a} and {b} drags a Z onto each qubit for every X component on \
+             the other. The sign flips for X ⊗ X-like terms whose Z components disagree."
+        ),
+        Gate::Swap(a, b) => {
+            format!("SWAP exchanges the pauli factors on qubits {a} and {b}. Signs never change.")
+        }
+    }
+}
+
+fn describe_changes(
+    kind: &str,
+    before: &[PauliString],
+    after: &[PauliString],
+    explanation: &mut String,
+) {
+    for (i, (before, after)) in before.iter().zip(after).enumerate() {
+        if before == after {
+            continue;
+        }
+        explanation.push_str(&format!("\n  {kind} {i}: {before} -> {after}"));
+        if before.phase_is_negated != after.phase_is_negated {
+            explanation.push_str(" (sign flipped)");
+        }
+    }
+}
+
+pub(crate) fn explain_gate(
+    gate: &Gate,
+    stabilizers_before: &[PauliString],
+    stabilizers_after: &[PauliString],
+    destabilizers_before: &[PauliString],
+    destabilizers_after: &[PauliString],
+) -> String {
+    let mut explanation = format!("{gate}: {}", describe_rule(gate));
+    let explanation_len = explanation.len();
+    describe_changes(
+        "stabilizer",
+        stabilizers_before,
+        stabilizers_after,
+        &mut explanation,
+    );
+    describe_changes(
+        "destabilizer",
+        destabilizers_before,
+        destabilizers_after,
+        &mut explanation,
+    );
+    if explanation.len() == explanation_len {
+        explanation.push_str("\n  no generator acts on these qubits, so nothing changed.");
+    }
+    explanation
+}
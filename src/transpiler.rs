@@ -0,0 +1,521 @@
+use crate::circuit::{Circuit, Instruction};
+use crate::coupling_map::CouplingMap;
+use crate::gates::Gate;
+use crate::pauli::PauliString;
+use crate::qubit::Qubit;
+use std::collections::HashSet;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct RoutedCircuit {
+    pub circuit: Circuit,
+    // routing overhead. Each SWAP costs three CX on hardware that doesn't have one natively.
+    pub swaps_inserted: usize,
+    // final_layout[logical] is the physical qubit the logical qubit ended up on.
+    pub final_layout: Vec<Qubit>,
+}
+
+#[cfg_attr(
+    feature = "tracing",
+    tracing::instrument(
+        level = "debug",
+        skip_all,
+        fields(
+            num_qubits = circuit.num_qubits(),
+            instructions = circuit.instructions().len(),
+        )
+    )
+)]
+pub fn route(circuit: &Circuit, coupling_map: &CouplingMap) -> Result<RoutedCircuit, &'static str> {
+    // greedy router: logical qubit q starts out on physical qubit q. Whenever a
+    // two-qubit gate acts on qubits that aren't coupled, walk one of them along the
+    // shortest path towards the other with SWAPs until they are neighbors.
+    // Measurements keep their position in the instruction stream, so the routed
+    // circuit produces the same measurement record as the original.
+    if circuit.num_qubits() > coupling_map.num_qubits() {
+        return Err("Circuit needs more qubits than the device has.");
+    }
+    let num_physical = coupling_map.num_qubits();
+    let mut physical_of: Vec<Qubit> = Qubit::range(num_physical).collect();
+    let mut logical_of: Vec<Qubit> = Qubit::range(num_physical).collect();
+    let mut routed = Circuit::new(num_physical);
+    let mut swaps_inserted = 0;
+
+    for instruction in circuit.instructions() {
+        if let [a, b] = instruction.qubits()[..] {
+            let path = coupling_map
+                .shortest_path(physical_of[a.index()], physical_of[b.index()])
+                .ok_or("Two-qubit gate acts on qubits that aren't connected on the device.")?;
+            for step in path.windows(2).take(path.len().saturating_sub(2)) {
+                let (from, to) = (step[0], step[1]);
+                routed.gate(Gate::Swap(from, to));
+                swaps_inserted += 1;
+                let (logical_from, logical_to) = (logical_of[from.index()], logical_of[to.index()]);
+                logical_of.swap(from.index(), to.index());
+                physical_of.swap(logical_from.index(), logical_to.index());
+            }
+        }
+        routed.push(instruction.remap(|q| physical_of[q.index()]));
+    }
+
+    physical_of.truncate(circuit.num_qubits());
+    Ok(RoutedCircuit {
+        circuit: routed,
+        swaps_inserted,
+        final_layout: physical_of,
+    })
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GateSet {
+    // {CZ, H, S}
+    CzHS,
+    // {CX, H, S}, i.e. CX plus generators of the single-qubit Cliffords.
+    CxHS,
+}
+
+impl GateSet {
+    pub fn contains(&self, gate: &Gate) -> bool {
+        match gate {
+            Gate::H(_) | Gate::S(_) => true,
+            Gate::X(_) | Gate::Y(_) | Gate::Z(_) => false,
+            Gate::Cx(_, _) => *self == GateSet::CxHS,
+            Gate::Cz(_, _) => *self == GateSet::CzHS,
+            Gate::Swap(_, _) => false,
+        }
+    }
+}
+
+fn rebase_gate(gate: Gate, target: GateSet, rebased: &mut Circuit) {
+    if target.contains(&gate) {
+        rebased.gate(gate);
+        return;
+    }
+    // every gate outside the target set is rewritten in terms of gates that are one step
+    // closer to it, and those get rebased in turn.
+    match gate {
+        Gate::Cx(control, target_qubit) => {
+            // conjugating the target of a CZ by H turns its Z into an X.
+            rebase_gate(Gate::H(target_qubit), target, rebased);
+            rebase_gate(Gate::Cz(control, target_qubit), target, rebased);
+            rebase_gate(Gate::H(target_qubit), target, rebased);
+        }
+        Gate::Cz(a, b) => {
+            rebase_gate(Gate::H(b), target, rebased);
+            rebase_gate(Gate::Cx(a, b), target, rebased);
+            rebase_gate(Gate::H(b), target, rebased);
+        }
+        Gate::Swap(a, b) => {
+            rebase_gate(Gate::Cx(a, b), target, rebased);
+            rebase_gate(Gate::Cx(b, a), target, rebased);
+            rebase_gate(Gate::Cx(a, b), target, rebased);
+        }
+        Gate::Z(qubit) => {
+            rebase_gate(Gate::S(qubit), target, rebased);
+            rebase_gate(Gate::S(qubit), target, rebased);
+        }
+        Gate::X(qubit) => {
+            rebase_gate(Gate::H(qubit), target, rebased);
+            rebase_gate(Gate::Z(qubit), target, rebased);
+            rebase_gate(Gate::H(qubit), target, rebased);
+        }
+        Gate::Y(qubit) => {
+            // Y == iXZ, and the global phase doesn't matter.
+            rebase_gate(Gate::Z(qubit), target, rebased);
+            rebase_gate(Gate::X(qubit), target, rebased);
+        }
+        Gate::H(_) | Gate::S(_) => unreachable!("H and S are in every gate set"),
+    }
+}
+
+#[cfg_attr(
+    feature = "tracing",
+    tracing::instrument(
+        level = "debug",
+        skip_all,
+        fields(
+            num_qubits = circuit.num_qubits(),
+            instructions = circuit.instructions().len(),
+        )
+    )
+)]
+pub fn rebase(circuit: &Circuit, target: GateSet) -> Circuit {
+    let mut rebased = circuit.empty_like();
+    for instruction in circuit.instructions() {
+        match instruction {
+            Instruction::Gate(gate) => rebase_gate(*gate, target, &mut rebased),
+            Instruction::Conditional { gate, .. }
+            | Instruction::ConditionalOnBit { gate, .. }
+            | Instruction::SweepConditional { gate, .. } => {
+                // the rewrite doesn't add measurements, so the control (lookback, register bit
+                // or sweep bit) still points at the same thing for every rewritten gate.
+                let mut rewritten = Circuit::default();
+                rebase_gate(*gate, target, &mut rewritten);
+                for rewritten_instruction in rewritten.instructions() {
+                    if let Instruction::Gate(gate) = rewritten_instruction {
+                        rebased.push(instruction.with_gate(*gate));
+                    }
+                }
+            }
+            other => rebased.push(*other),
+        }
+    }
+    rebased
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct PrunedCircuit {
+    pub circuit: Circuit,
+    // qubits[new] is the original qubit that new qubit stands for.
+    pub qubits: Vec<Qubit>,
+    pub instructions_removed: usize,
+}
+
+pub fn eliminate_dead_gates(circuit: &Circuit) -> Result<PrunedCircuit, &'static str> {
+    // drops whatever can't affect a measurement, then the qubits left with nothing to do.
+    //
+    // Walking backwards, a qubit is live if something later on it can reach a measurement.
+    // Measurements are always kept (detectors, observables and lookbacks index into the
+    // record) and make their qubit live. Gates, controlled or not, are kept when they touch a
+    // live qubit, and then all their qubits are live. A kept reset makes its qubit dead before
+    // it, since nothing earlier on that qubit alone can show through. Nothing can signal
+    // through a dead qubit, so dropping what happens on one doesn't change any outcome's
+    // distribution, even when it was entangled with live ones.
+    let mut live = vec![false; circuit.num_qubits()];
+    let mut keep = vec![true; circuit.instructions().len()];
+    for (index, instruction) in circuit.instructions().iter().enumerate().rev() {
+        let qubits = instruction.qubits();
+        match instruction {
+            Instruction::Measure(qubit) | Instruction::MeasureToBit { qubit, .. } => {
+                live[qubit.index()] = true;
+            }
+            Instruction::Reset(qubit) | Instruction::PrepX(qubit) | Instruction::PrepY(qubit) => {
+                keep[index] = live[qubit.index()];
+                live[qubit.index()] = false;
+            }
+            Instruction::Tick => {}
+            _ => {
+                keep[index] = qubits.iter().any(|qubit| live[qubit.index()]);
+                if keep[index] {
+                    for qubit in qubits {
+                        live[qubit.index()] = true;
+                    }
+                }
+            }
+        }
+    }
+
+    let mut pruned = circuit.empty_like();
+    let mut used = vec![false; circuit.num_qubits()];
+    for (instruction, &keep) in circuit.instructions().iter().zip(&keep) {
+        if keep {
+            for qubit in instruction.qubits() {
+                used[qubit.index()] = true;
+            }
+            pruned.push(*instruction);
+        }
+    }
+    let qubits: Vec<Qubit> = Qubit::range(circuit.num_qubits())
+        .filter(|qubit| used[qubit.index()])
+        .collect();
+    let mut map = vec![None; circuit.num_qubits()];
+    for (new, qubit) in qubits.iter().enumerate() {
+        map[qubit.index()] = Some(Qubit(new as u32));
+    }
+    Ok(PrunedCircuit {
+        circuit: pruned.remap_qubits(&map)?,
+        qubits,
+        instructions_removed: keep.iter().filter(|&&keep| !keep).count(),
+    })
+}
+
+// a circuit with its pauli gates commuted to the end: running `body`, flipping the outcomes
+// of `flipped_measurements`, then applying `final_paulis` does what the original circuit did.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PauliNormalForm {
+    pub body: Circuit,
+    pub final_paulis: Vec<Gate>,
+    // indices into the measurement record.
+    pub flipped_measurements: Vec<usize>,
+}
+
+struct FrameSweep {
+    frame: PauliString,
+    body: Circuit,
+}
+
+impl FrameSweep {
+    fn gate(&mut self, gate: Gate) {
+        // pauli gates join the frame instead of the body, and cliffords conjugate it on their
+        // way into the body.
+        match gate {
+            Gate::X(qubit) | Gate::Y(qubit) | Gate::Z(qubit) => {
+                let q = qubit.index();
+                self.frame.x_bits[q] ^= !matches!(gate, Gate::Z(_));
+                self.frame.z_bits[q] ^= !matches!(gate, Gate::X(_));
+            }
+            _ => {
+                self.frame.conjugate_by(&gate);
+                self.body.gate(gate);
+            }
+        }
+    }
+
+    fn controlled(&mut self, instruction: Instruction, gate: Gate) {
+        // a controlled pauli stays in the body as it is, since whether it happens isn't known
+        // until the circuit runs. Any other controlled gate would conjugate the frame one way
+        // or the other depending on its control, so the frame on its qubits is emitted as
+        // gates just before it.
+        if !matches!(gate, Gate::X(_) | Gate::Y(_) | Gate::Z(_)) {
+            let mut flushed = PauliString::identity(self.frame.num_qubits());
+            for qubit in gate.qubits() {
+                let q = qubit.index();
+                flushed.x_bits[q] = std::mem::take(&mut self.frame.x_bits[q]);
+                flushed.z_bits[q] = std::mem::take(&mut self.frame.z_bits[q]);
+            }
+            for pauli in flushed.to_gates() {
+                self.body.gate(pauli);
+            }
+        }
+        self.body.push(instruction.with_gate(gate));
+    }
+}
+
+pub fn pauli_normal_form(circuit: &Circuit) -> PauliNormalForm {
+    // sweeps a pauli frame through the circuit (see FrameSweep). At a measurement, an X or Y
+    // in the frame on that qubit flips the outcome and stays (it's still there on the
+    // collapsed state), and a Z drops out, as it's only a phase on a basis state. Resets and
+    // preparations clear the frame on their qubit.
+    //
+    // The body's record has the flipped outcomes unflipped, so a gate controlled by one of
+    // them has to fire when its control reads 0 instead: that's the gate itself, then its
+    // inverse controlled as before. Paulis left in the body are the controlled ones and the
+    // frame emitted before other controlled gates.
+    let mut sweep = FrameSweep {
+        frame: PauliString::identity(circuit.num_qubits()),
+        body: circuit.empty_like(),
+    };
+    let mut flipped = vec![];
+    let mut flipped_bits: HashSet<u32> = HashSet::new();
+    for instruction in circuit.instructions() {
+        match *instruction {
+            Instruction::Gate(gate) => sweep.gate(gate),
+            Instruction::Measure(qubit) | Instruction::MeasureToBit { qubit, .. } => {
+                let flip = sweep.frame.x_bits[qubit.index()];
+                sweep.frame.z_bits[qubit.index()] = false;
+                flipped.push(flip);
+                if let Instruction::MeasureToBit { bit, .. } = *instruction {
+                    if flip {
+                        flipped_bits.insert(bit);
+                    } else {
+                        flipped_bits.remove(&bit);
+                    }
+                }
+                sweep.body.push(*instruction);
+            }
+            Instruction::Reset(qubit) | Instruction::PrepX(qubit) | Instruction::PrepY(qubit) => {
+                sweep.frame.x_bits[qubit.index()] = false;
+                sweep.frame.z_bits[qubit.index()] = false;
+                sweep.body.push(*instruction);
+            }
+            Instruction::Conditional { gate, .. }
+            | Instruction::ConditionalOnBit { gate, .. }
+            | Instruction::SweepConditional { gate, .. } => {
+                let inverted = match *instruction {
+                    Instruction::Conditional { lookback, .. } => flipped
+                        .len()
+                        .checked_sub(lookback as usize)
+                        .is_some_and(|index| flipped[index]),
+                    Instruction::ConditionalOnBit { bit, .. } => flipped_bits.contains(&bit),
+                    _ => false,
+                };
+                if inverted {
+                    sweep.gate(gate);
+                    for inverse in gate.inverse() {
+                        sweep.controlled(*instruction, inverse);
+                    }
+                } else {
+                    sweep.controlled(*instruction, gate);
+                }
+            }
+            Instruction::Tick => sweep.body.push(*instruction),
+        }
+    }
+    PauliNormalForm {
+        body: sweep.body,
+        final_paulis: sweep.frame.to_gates(),
+        flipped_measurements: (0..flipped.len()).filter(|&i| flipped[i]).collect(),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::stabilizer_simulator::StabilizerSimulator;
+
+    fn x(circuit: &mut Circuit, qubit: Qubit) {
+        circuit
+            .gate(Gate::H(qubit))
+            .gate(Gate::S(qubit))
+            .gate(Gate::S(qubit))
+            .gate(Gate::H(qubit));
+    }
+
+    #[test]
+    fn test_route_inserts_swaps_for_distant_qubits() {
+        let mut circuit = Circuit::new(4);
+        x(&mut circuit, Qubit(0));
+        circuit
+            .gate(Gate::Cx(Qubit(0), Qubit(3)))
+            .gate(Gate::Cx(Qubit(1), Qubit(2)));
+        for qubit in Qubit::range(4) {
+            circuit.measure(qubit);
+        }
+        let routed = route(&circuit, &CouplingMap::line(4)).unwrap();
+        assert_eq!(routed.swaps_inserted, 2);
+        for instruction in routed.circuit.instructions() {
+            if let Instruction::Gate(gate) = instruction {
+                if let [a, b] = gate.qubits()[..] {
+                    assert!(CouplingMap::line(4).are_adjacent(a, b));
+                }
+            }
+        }
+
+        let expected = StabilizerSimulator::seeded(4).run(&circuit).unwrap();
+        let actual = StabilizerSimulator::seeded(4).run(&routed.circuit).unwrap();
+        assert_eq!(expected, vec![true, false, false, true]);
+        assert_eq!(actual, expected);
+    }
+
+    #[test]
+    fn test_route_fails_on_disconnected_device() {
+        let mut circuit = Circuit::new(2);
+        circuit.gate(Gate::Cx(Qubit(0), Qubit(1)));
+        let device = CouplingMap::new(2, &[]).unwrap();
+        assert!(route(&circuit, &device).is_err());
+    }
+
+    #[test]
+    fn test_rebase_only_uses_target_gates() {
+        let mut circuit = Circuit::new(3);
+        x(&mut circuit, Qubit(0));
+        circuit
+            .gate(Gate::Cx(Qubit(0), Qubit(1)))
+            .gate(Gate::Swap(Qubit(1), Qubit(2)))
+            .gate(Gate::H(Qubit(0)))
+            .gate(Gate::Cz(Qubit(0), Qubit(2)))
+            .gate(Gate::H(Qubit(0)));
+        for qubit in Qubit::range(3) {
+            circuit.measure(qubit);
+        }
+        let expected = StabilizerSimulator::seeded(3).run(&circuit).unwrap();
+        assert_eq!(expected, vec![false, false, true]);
+        for target in [GateSet::CzHS, GateSet::CxHS] {
+            let rebased = rebase(&circuit, target);
+            for instruction in rebased.instructions() {
+                if let Instruction::Gate(gate) = instruction {
+                    assert!(target.contains(gate));
+                }
+            }
+            assert_eq!(
+                StabilizerSimulator::seeded(3).run(&rebased).unwrap(),
+                expected
+            );
+        }
+    }
+
+    #[test]
+    fn test_pauli_normal_form() {
+        // qubit 0 is measured after an X (a flip), qubits 1 and 2 carry paulis through a CX
+        // to the end, and qubit 3's X is wiped out by a reset.
+        let (a, b, c, d) = (Qubit(0), Qubit(1), Qubit(2), Qubit(3));
+        let mut circuit = Circuit::new(4);
+        circuit
+            .gate(Gate::X(a))
+            .gate(Gate::H(b))
+            .gate(Gate::Z(b))
+            .gate(Gate::Cx(b, c))
+            .gate(Gate::Y(c))
+            .measure(a)
+            .gate(Gate::X(d))
+            .reset(d)
+            .gate(Gate::Z(a))
+            .measure(a)
+            .conditional(1, Gate::H(b));
+        let form = pauli_normal_form(&circuit);
+        assert_eq!(form.flipped_measurements, vec![0, 1]);
+        // Z on the control of a CX stays Z, and then Y joins in on the target. The
+        // conditional H reads a flipped outcome, so it becomes an H, which turns qubit 1's Z
+        // into an X, then a controlled H, which has that X emitted before it.
+        assert_eq!(form.final_paulis, vec![Gate::X(a), Gate::Y(c)]);
+        let paulis: Vec<&Instruction> = form
+            .body
+            .instructions()
+            .iter()
+            .filter(|instruction| {
+                matches!(
+                    instruction,
+                    Instruction::Gate(Gate::X(_) | Gate::Y(_) | Gate::Z(_))
+                )
+            })
+            .collect();
+        assert_eq!(paulis, vec![&Instruction::Gate(Gate::X(b))]);
+
+        // both measurements are deterministic, so the flips show up shot by shot, and the
+        // final paulis put the state back.
+        let mut original = StabilizerSimulator::seeded(4);
+        let expected = original.run(&circuit).unwrap();
+        let mut simulator = StabilizerSimulator::seeded(4);
+        let mut record = simulator.run(&form.body).unwrap();
+        for &index in &form.flipped_measurements {
+            record[index] ^= true;
+        }
+        assert_eq!(record, expected);
+        for gate in &form.final_paulis {
+            simulator.apply_gate(gate);
+        }
+        assert_eq!(
+            simulator.canonical_stabilizers().unwrap(),
+            original.canonical_stabilizers().unwrap()
+        );
+    }
+
+    #[test]
+    fn test_eliminate_dead_gates() {
+        // qubits 2 and 3 are never measured, so their CX goes, and then so do they. The last
+        // H on qubit 0 comes too late to matter, and qubit 1's H can't show through the
+        // reset after it.
+        let q: Vec<Qubit> = Qubit::range(5).collect();
+        let mut circuit = Circuit::new(5);
+        circuit
+            .gate(Gate::H(q[0]))
+            .gate(Gate::H(q[1]))
+            .reset(q[1])
+            .gate(Gate::Cx(q[0], q[1]))
+            .gate(Gate::S(q[4]))
+            .measure(q[1])
+            .gate(Gate::Cx(q[2], q[3]))
+            .tick()
+            .gate(Gate::H(q[0]))
+            .measure(q[4]);
+        circuit.set_qubit_coords(q[4], &[4.0]);
+        circuit.add_detector(&[1, 2], &[]).unwrap();
+        let pruned = eliminate_dead_gates(&circuit).unwrap();
+        assert_eq!(pruned.qubits, vec![q[0], q[1], q[4]]);
+        assert_eq!(pruned.instructions_removed, 3);
+        assert_eq!(
+            pruned.circuit.instructions(),
+            [
+                Instruction::Gate(Gate::H(q[0])),
+                Instruction::Reset(q[1]),
+                Instruction::Gate(Gate::Cx(q[0], q[1])),
+                Instruction::Gate(Gate::S(q[2])),
+                Instruction::Measure(q[1]),
+                Instruction::Tick,
+                Instruction::Measure(q[2]),
+            ]
+        );
+        assert_eq!(pruned.circuit.num_qubits(), 3);
+        assert_eq!(pruned.circuit.qubit_coords(q[2]), Some(&[4.0][..]));
+        assert_eq!(pruned.circuit.detectors(), circuit.detectors());
+    }
+}
@@ -0,0 +1,127 @@
+use crate::circuit::Circuit;
+use crate::debugger::Debugger;
+use crate::pauli::PauliString;
+use crossterm::event::{self, Event, KeyCode, KeyEventKind};
+use crossterm::style::Stylize;
+use crossterm::{cursor, execute, queue, style, terminal};
+use std::io::{self, Write};
+
+// a live view of the tableau while stepping through a circuit. Each generator is
+// drawn as a row of its X bits, then its Z bits, then its sign, so you can watch the
+// bits move around as gates are applied. Aimed at students, so it's deliberately plain.
+
+fn render_generators(label: &str, generators: &[PauliString], lines: &mut Vec<String>) {
+    for (i, generator) in generators.iter().enumerate() {
+        let bits = |bits: &[bool]| {
+            bits.iter()
+                .map(|bit| if *bit { "1" } else { "." })
+                .collect::<Vec<_>>()
+                .join(" ")
+        };
+        lines.push(format!(
+            "{label} {i:>3} | {} | {} | {}",
+            bits(&generator.x_bits),
+            bits(&generator.z_bits),
+            if generator.phase_is_negated { "-" } else { "+" },
+        ));
+    }
+}
+
+pub fn render(debugger: &Debugger) -> Vec<String> {
+    let mut lines = Vec::new();
+    let next = match debugger.next_instruction() {
+        Some(instruction) => instruction.to_string(),
+        None => "done".to_string(),
+    };
+    lines.push(format!(
+        "instruction {}, next: {}",
+        debugger.position(),
+        next
+    ));
+    let num_qubits = debugger.simulator().num_qubits();
+    let header_width = 2 * num_qubits.max(1) - 1;
+    lines.push(format!(
+        "             | {:<header_width$} | {:<header_width$} | sign",
+        "x", "z"
+    ));
+    render_generators("destab", &debugger.destabilizers(), &mut lines);
+    render_generators("stab  ", &debugger.stabilizers(), &mut lines);
+    let record: String = debugger
+        .measurement_record()
+        .iter()
+        .map(|outcome| if *outcome { '1' } else { '0' })
+        .collect();
+    lines.push(format!("measurement record: {}", record));
+    lines
+}
+
+fn draw(stdout: &mut impl Write, debugger: &Debugger) -> io::Result<()> {
+    queue!(
+        stdout,
+        terminal::Clear(terminal::ClearType::All),
+        cursor::MoveTo(0, 0)
+    )?;
+    queue!(
+        stdout,
+        style::PrintStyledContent("YASS -- [n/space] step  [r] run to end  [q] quit".bold()),
+        cursor::MoveToNextLine(2)
+    )?;
+    for line in render(debugger) {
+        queue!(stdout, style::Print(line), cursor::MoveToNextLine(1))?;
+    }
+    stdout.flush()
+}
+
+pub fn run(circuit: &Circuit, seed: u64) -> io::Result<()> {
+    // takes over the terminal until the user quits.
+    let mut debugger = Debugger::new(circuit, seed);
+    let mut stdout = io::stdout();
+    terminal::enable_raw_mode()?;
+    execute!(stdout, terminal::EnterAlternateScreen, cursor::Hide)?;
+
+    let result = (|| -> io::Result<()> {
+        loop {
+            draw(&mut stdout, &debugger)?;
+            let Event::Key(key) = event::read()? else {
+                continue;
+            };
+            if key.kind != KeyEventKind::Press {
+                continue;
+            }
+            let stepped = match key.code {
+                KeyCode::Char('q') | KeyCode::Esc => return Ok(()),
+                KeyCode::Char('n') | KeyCode::Char(' ') | KeyCode::Right => {
+                    debugger.step().map(|_| ())
+                }
+                KeyCode::Char('r') => debugger.run_to_end(),
+                _ => Ok(()),
+            };
+            stepped.map_err(io::Error::other)?;
+        }
+    })();
+
+    execute!(stdout, cursor::Show, terminal::LeaveAlternateScreen)?;
+    terminal::disable_raw_mode()?;
+    result
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::gates::Gate;
+    use crate::qubit::Qubit;
+
+    #[test]
+    fn test_render_shows_bits_and_signs() {
+        let mut circuit = Circuit::new(2);
+        circuit
+            .gate(Gate::H(Qubit(0)))
+            .gate(Gate::Cx(Qubit(0), Qubit(1)));
+        let mut debugger = Debugger::new(&circuit, 0);
+        debugger.run_to_end().unwrap();
+        let lines = render(&debugger);
+        assert_eq!(lines[0], "instruction 2, next: done");
+        assert_eq!(lines[4], "stab     0 | 1 1 | . . | +");
+        assert_eq!(lines[5], "stab     1 | . . | 1 1 | +");
+    }
+}
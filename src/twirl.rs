@@ -0,0 +1,145 @@
+use crate::complex::Complex;
+use crate::noise::PauliChannel;
+
+// the pauli twirling approximation: averaging a channel over conjugation by random paulis
+// leaves a pauli channel, which the stabilizer simulator can sample. For a channel with
+// Kraus operators K_k on d = 2^n dimensions, pauli P comes out with probability
+//
+//     p_P = sum_k |Tr(P K_k)|^2 / d^2,
+//
+// which is also the diagonal of the channel's process (chi) matrix in the pauli basis.
+//
+// Matrices are lists of rows, and bit q of a basis index is the value of qubit q, like the
+// dense simulator. Paulis are numbered like noise::pauli_error.
+
+const TOLERANCE: f64 = 1e-9;
+
+fn pauli_trace(pauli: usize, num_qubits: usize, matrix: &[Vec<Complex>]) -> Complex {
+    // Tr(P M). P sends |j> to a phase times |j ^ flips>, so only those entries of M count.
+    let mut trace = Complex::ZERO;
+    for (column, entries) in matrix.iter().enumerate() {
+        let mut row = column;
+        let mut phase = Complex::ONE;
+        for qubit in 0..num_qubits {
+            let bit = (column >> qubit) & 1 == 1;
+            match (pauli >> (2 * qubit)) & 3 {
+                1 => row ^= 1 << qubit,
+                2 if bit => phase = phase.scale(-1.0),
+                3 => {
+                    // Y|0> = i|1>, Y|1> = -i|0>.
+                    row ^= 1 << qubit;
+                    phase = phase * Complex::I.scale(if bit { -1.0 } else { 1.0 });
+                }
+                _ => {}
+            }
+        }
+        // P[row][column] M[column][row]
+        trace = trace + phase * entries[row];
+    }
+    trace
+}
+
+fn num_qubits_of(matrix: &[Vec<Complex>]) -> Result<usize, &'static str> {
+    match matrix.len() {
+        2 if matrix.iter().all(|row| row.len() == 2) => Ok(1),
+        4 if matrix.iter().all(|row| row.len() == 4) => Ok(2),
+        _ => Err("Expected a 2x2 or 4x4 matrix."),
+    }
+}
+
+pub fn twirl_kraus(kraus: &[Vec<Vec<Complex>>]) -> Result<PauliChannel, &'static str> {
+    let num_qubits = num_qubits_of(kraus.first().ok_or("Expected Kraus operators.")?)?;
+    let dimension = (1 << num_qubits) as f64;
+    let mut probabilities = vec![0.0; 1 << (2 * num_qubits)];
+    for operator in kraus {
+        if num_qubits_of(operator)? != num_qubits {
+            return Err("Kraus operators must all act on the same number of qubits.");
+        }
+        for (pauli, probability) in probabilities.iter_mut().enumerate() {
+            *probability +=
+                pauli_trace(pauli, num_qubits, operator).norm_squared() / (dimension * dimension);
+        }
+    }
+    // the probabilities sum to Tr(sum_k K_k^dagger K_k) / d, which is 1 exactly when the
+    // channel preserves the trace.
+    if (probabilities.iter().sum::<f64>() - 1.0).abs() > TOLERANCE {
+        return Err("Kraus operators aren't trace preserving.");
+    }
+    PauliChannel::new(num_qubits, probabilities)
+}
+
+pub fn twirl_process_matrix(chi: &[Vec<Complex>]) -> Result<PauliChannel, &'static str> {
+    // chi is the process matrix in the pauli basis, rows and columns in pauli number order,
+    // so 4x4 for one qubit and 16x16 for two.
+    let num_qubits = match chi.len() {
+        4 => 1,
+        16 => 2,
+        _ => return Err("Expected a 4x4 or 16x16 process matrix."),
+    };
+    if chi.iter().any(|row| row.len() != chi.len()) {
+        return Err("Expected a 4x4 or 16x16 process matrix.");
+    }
+    let probabilities: Vec<f64> = chi.iter().enumerate().map(|(i, row)| row[i].re).collect();
+    if (probabilities.iter().sum::<f64>() - 1.0).abs() > TOLERANCE {
+        return Err("Process matrix isn't trace preserving.");
+    }
+    PauliChannel::new(num_qubits, probabilities)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn real(rows: &[&[f64]]) -> Vec<Vec<Complex>> {
+        rows.iter()
+            .map(|row| row.iter().map(|&re| Complex { re, im: 0.0 }).collect())
+            .collect()
+    }
+
+    #[test]
+    fn test_amplitude_damping_twirl() {
+        // the textbook result: p_X = p_Y = gamma / 4, p_Z = (1 - gamma / 2 - sqrt(1 - gamma)) / 2.
+        let gamma: f64 = 0.3;
+        let kraus = [
+            real(&[&[1.0, 0.0], &[0.0, (1.0 - gamma).sqrt()]]),
+            real(&[&[0.0, gamma.sqrt()], &[0.0, 0.0]]),
+        ];
+        let channel = twirl_kraus(&kraus).unwrap();
+        let p_z = (1.0 - gamma / 2.0 - (1.0 - gamma).sqrt()) / 2.0;
+        let expected = [1.0 - gamma / 2.0 - p_z, gamma / 4.0, p_z, gamma / 4.0];
+        for (p, q) in channel.probabilities().iter().zip(expected) {
+            assert!((p - q).abs() < 1e-12);
+        }
+        assert!(twirl_kraus(&kraus[..1]).is_err());
+    }
+
+    #[test]
+    fn test_two_qubit_twirl() {
+        // a stray CX: a unitary channel that's a clifford, not a pauli, spreads its weight
+        // over the paulis in its expansion. CX = (II + ZI + IX - ZX) / 2 with qubit 0 the
+        // control, so 4 paulis at 1/4 each.
+        let cx = real(&[
+            &[1.0, 0.0, 0.0, 0.0],
+            &[0.0, 0.0, 0.0, 1.0],
+            &[0.0, 0.0, 1.0, 0.0],
+            &[0.0, 1.0, 0.0, 0.0],
+        ]);
+        let channel = twirl_kraus(&[cx]).unwrap();
+        // ZI is 2, IX is 4, ZX is 2 + 4.
+        for (pauli, &p) in channel.probabilities().iter().enumerate() {
+            let expected = if [0, 2, 4, 6].contains(&pauli) {
+                0.25
+            } else {
+                0.0
+            };
+            assert!((p - expected).abs() < 1e-12);
+        }
+
+        let mut chi = vec![vec![Complex::ZERO; 4]; 4];
+        chi[0][0] = Complex::ONE.scale(0.9);
+        chi[3][3] = Complex::ONE.scale(0.1);
+        chi[0][3] = Complex::ONE.scale(0.3);
+        let channel = twirl_process_matrix(&chi).unwrap();
+        assert_eq!(channel.probabilities(), &[0.9, 0.0, 0.0, 0.1]);
+    }
+}
@@ -0,0 +1,610 @@
+use crate::circuit::{Circuit, Instruction};
+use crate::clifford::CliffordOperator;
+use crate::gates::Gate;
+use crate::pauli::PauliString;
+use crate::qubit::Qubit;
+use crate::stabilizer_simulator::StabilizerSimulator;
+
+// clifford circuits as ZX diagrams, for handing to ZX-calculus tools (PyZX, quizx) to simplify.
+// Every gate is a spider or two: S and Z are Z spiders with phase pi/2 and pi, X is an X
+// spider with phase pi, CX is a Z spider on the control joined to an X spider on the target,
+// and CZ joins two Z spiders with a hadamard edge. H doesn't get a vertex of its own: it turns
+// the next edge along its wire into a hadamard edge (and a second H turns it back). SWAP just
+// crosses the wires over. Global phases are dropped, as usual.
+//
+// Phases are all multiples of pi/2, so they're kept as a count of quarter turns, 0 to 3.
+//
+// Coming back, any clifford diagram with as many inputs as outputs that's a unitary turns into
+// a circuit, not just ones laid out like circuits, so a diagram simplified out of all
+// recognition still comes back (see to_circuit).
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum VertexKind {
+    Boundary,
+    Z,
+    X,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EdgeKind {
+    Simple,
+    Hadamard,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Vertex {
+    pub kind: VertexKind,
+    // in units of pi/2.
+    pub phase: u8,
+    // where to draw it: row runs left to right with time, qubit top to bottom.
+    pub row: f64,
+    pub qubit: f64,
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ZxGraph {
+    pub vertices: Vec<Vertex>,
+    pub edges: Vec<(usize, usize, EdgeKind)>,
+    // the boundary vertices, by qubit.
+    pub inputs: Vec<usize>,
+    pub outputs: Vec<usize>,
+}
+
+impl ZxGraph {
+    pub fn from_circuit(circuit: &Circuit) -> Result<ZxGraph, &'static str> {
+        let n = circuit.num_qubits();
+        let mut graph = ZxGraph::default();
+        for q in 0..n {
+            let input = graph.add_vertex(VertexKind::Boundary, 0, 0.0, q as f64);
+            graph.inputs.push(input);
+        }
+        let mut wires = Wires {
+            last: graph.inputs.clone(),
+            hadamard: vec![false; n],
+            row: vec![0; n],
+        };
+        for instruction in circuit.instructions() {
+            let gate = match instruction {
+                Instruction::Gate(gate) => *gate,
+                Instruction::Tick => continue,
+                _ => return Err("Only gates can be drawn as a ZX diagram."),
+            };
+            match gate {
+                Gate::H(a) => wires.hadamard[a.index()] ^= true,
+                Gate::S(a) => {
+                    wires.spider(&mut graph, a.index(), VertexKind::Z, 1);
+                }
+                Gate::Z(a) => {
+                    wires.spider(&mut graph, a.index(), VertexKind::Z, 2);
+                }
+                Gate::X(a) => {
+                    wires.spider(&mut graph, a.index(), VertexKind::X, 2);
+                }
+                Gate::Y(a) => {
+                    // Y is XZ up to a phase, so Z first, then X.
+                    wires.spider(&mut graph, a.index(), VertexKind::Z, 2);
+                    wires.spider(&mut graph, a.index(), VertexKind::X, 2);
+                }
+                Gate::Cx(a, b) | Gate::Cz(a, b) => {
+                    let (a, b) = (a.index(), b.index());
+                    let row = wires.row[a].max(wires.row[b]);
+                    wires.row[a] = row;
+                    wires.row[b] = row;
+                    let (target_kind, edge) = match gate {
+                        Gate::Cx(..) => (VertexKind::X, EdgeKind::Simple),
+                        _ => (VertexKind::Z, EdgeKind::Hadamard),
+                    };
+                    let control = wires.spider(&mut graph, a, VertexKind::Z, 0);
+                    let target = wires.spider(&mut graph, b, target_kind, 0);
+                    graph.edges.push((control, target, edge));
+                }
+                Gate::Swap(a, b) => {
+                    let (a, b) = (a.index(), b.index());
+                    wires.last.swap(a, b);
+                    wires.hadamard.swap(a, b);
+                    let row = wires.row[a].max(wires.row[b]);
+                    wires.row[a] = row;
+                    wires.row[b] = row;
+                }
+            }
+        }
+        let end = wires.row.iter().max().map_or(1, |row| row + 1);
+        for q in 0..n {
+            let output = graph.add_vertex(VertexKind::Boundary, 0, end as f64, q as f64);
+            graph.connect(wires.last[q], output, wires.hadamard[q]);
+            graph.outputs.push(output);
+        }
+        Ok(graph)
+    }
+
+    pub fn to_circuit(&self) -> Result<Circuit, &'static str> {
+        // evaluates the diagram on a stabilizer simulator, then synthesizes the clifford it
+        // turned out to be. Every leg of every spider gets a qubit, spiders are prepared as
+        // GHZ-like states on their legs (|0..0> + i^phase |1..1>, with hadamards all round for
+        // X spiders), and each boundary is a bell pair between its leg and an outside qubit.
+        // Edges are contracted by postselecting their two legs onto a bell pair, after an H on
+        // one side for hadamard edges. What's left on the outside qubits is the Choi state of
+        // the diagram's map, whose stabilizers X_i (x) U X_i U^dagger and Z_i (x) U Z_i U^dagger
+        // give the clifford's images.
+        let n = self.inputs.len();
+        if self.outputs.len() != n {
+            return Err("ZX diagram needs as many outputs as inputs to be a circuit.");
+        }
+        let outside = |v: usize| {
+            self.inputs
+                .iter()
+                .position(|&input| input == v)
+                .or_else(|| self.outputs.iter().position(|&o| o == v).map(|q| n + q))
+        };
+        let mut legs: Vec<Vec<usize>> = vec![vec![]; self.vertices.len()];
+        let mut halves = vec![];
+        for (e, &(a, b, _)) in self.edges.iter().enumerate() {
+            let (leg_a, leg_b) = (2 * n + 2 * e, 2 * n + 2 * e + 1);
+            legs[a].push(leg_a);
+            legs[b].push(leg_b);
+            halves.push((leg_a, leg_b));
+        }
+        let num_qubits = 2 * n + 2 * self.edges.len();
+        let mut simulator = StabilizerSimulator::seeded(num_qubits);
+        let qubit = |q: usize| Qubit(q as u32);
+        for (v, vertex) in self.vertices.iter().enumerate() {
+            match (vertex.kind, &legs[v][..]) {
+                (VertexKind::Boundary, &[leg]) => {
+                    let outside =
+                        outside(v).ok_or("Boundary is neither an input nor an output.")?;
+                    simulator.prepare_bell_pair(qubit(outside), qubit(leg));
+                }
+                (VertexKind::Boundary, _) => return Err("Boundary needs exactly one edge."),
+                (_, []) => {}
+                (kind, &[first, ref rest @ ..]) => {
+                    simulator.apply_gate(&Gate::H(qubit(first)));
+                    for &leg in rest {
+                        simulator.apply_gate(&Gate::Cx(qubit(first), qubit(leg)));
+                    }
+                    for _ in 0..vertex.phase {
+                        simulator.apply_gate(&Gate::S(qubit(first)));
+                    }
+                    if kind == VertexKind::X {
+                        for &leg in &legs[v] {
+                            simulator.apply_gate(&Gate::H(qubit(leg)));
+                        }
+                    }
+                }
+            }
+        }
+        for (&(a, b), &(_, _, kind)) in halves.iter().zip(&self.edges) {
+            if kind == EdgeKind::Hadamard {
+                simulator.apply_gate(&Gate::H(qubit(a)));
+            }
+            for x in [true, false] {
+                let mut bell = PauliString::identity(num_qubits);
+                for leg in [a, b] {
+                    bell.x_bits[leg] = x;
+                    bell.z_bits[leg] = !x;
+                }
+                simulator
+                    .project_onto(&bell, Some(false))
+                    .map_err(|_| "ZX diagram is zero, so it isn't a circuit.")?;
+            }
+        }
+
+        // stabilizers that act only on the outside qubits, then reduced so each input has
+        // one with just X on it and one with just Z.
+        let mut rows = simulator.stabilizers();
+        let inside: Vec<(usize, bool)> = (2 * n..num_qubits)
+            .flat_map(|q| [(q, true), (q, false)])
+            .collect();
+        let pivots = reduce(&mut rows, &inside)?;
+        let mut rows: Vec<PauliString> = rows
+            .into_iter()
+            .enumerate()
+            .filter(|(i, _)| !pivots.contains(&Some(*i)))
+            .map(|(_, row)| row)
+            .collect();
+        let input_columns: Vec<(usize, bool)> =
+            (0..n).flat_map(|q| [(q, true), (q, false)]).collect();
+        let pivots = reduce(&mut rows, &input_columns)?;
+        if pivots.iter().any(Option::is_none) {
+            return Err("ZX diagram isn't a unitary, so it isn't a circuit.");
+        }
+        let image = |row: &PauliString| PauliString {
+            phase_is_negated: row.phase_is_negated,
+            x_bits: row.x_bits[n..2 * n].to_vec(),
+            z_bits: row.z_bits[n..2 * n].to_vec(),
+        };
+        let (mut x_images, mut z_images) = (vec![], vec![]);
+        for pair in pivots.chunks(2) {
+            x_images.push(image(&rows[pair[0].unwrap()]));
+            z_images.push(image(&rows[pair[1].unwrap()]));
+        }
+        Ok(CliffordOperator::from_images(x_images, z_images)?.to_circuit())
+    }
+
+    #[cfg(feature = "zx")]
+    pub fn from_pyzx_json(text: &str) -> Result<ZxGraph, &'static str> {
+        // reads what to_pyzx_json writes, and PyZX's own output: hadamard edges either as
+        // hadamard nodes between two plain edges or as edges with "type": "hadamard", phases
+        // as PyZX spells them ("\\pi/2", "3\\pi/2", "1/2", or a number, all in units of pi).
+        // Boundaries have to say which input or output they are.
+        use serde_json::{Map, Value};
+        use std::collections::HashMap;
+
+        let json: Value = serde_json::from_str(text).map_err(|_| "ZX graph isn't valid JSON.")?;
+        let empty = Map::new();
+        let section = |key: &str| match json.get(key) {
+            None => Ok(&empty),
+            Some(value) => value
+                .as_object()
+                .ok_or("Expected an object of vertices or edges."),
+        };
+        let position = |vertex: &Value| {
+            let coord = vertex
+                .pointer("/annotation/coord")
+                .and_then(Value::as_array);
+            let at = |k: usize| coord.and_then(|c| c.get(k)).and_then(Value::as_f64);
+            (at(0).unwrap_or(0.0), -at(1).unwrap_or(0.0))
+        };
+
+        let mut graph = ZxGraph::default();
+        let mut names: HashMap<&str, usize> = HashMap::new();
+        let (mut inputs, mut outputs) = (vec![], vec![]);
+        for (name, vertex) in section("wire_vertices")? {
+            let (row, qubit) = position(vertex);
+            let v = graph.add_vertex(VertexKind::Boundary, 0, row, qubit);
+            names.insert(name, v);
+            let side = |key: &str| vertex.pointer(&format!("/annotation/{key}"));
+            let (list, index) = match (side("input"), side("output")) {
+                (Some(index), _) if index != &Value::Bool(false) => (&mut inputs, index),
+                (_, Some(index)) if index != &Value::Bool(false) => (&mut outputs, index),
+                _ => return Err("Boundary doesn't say whether it's an input or an output."),
+            };
+            // an index, or just `true`, in which case they go in order of qubit.
+            let order = index.as_f64().unwrap_or(qubit);
+            list.push((order, v));
+        }
+        let mut hadamards: HashMap<&str, Vec<usize>> = HashMap::new();
+        for (name, vertex) in section("node_vertices")? {
+            let data = vertex.get("data");
+            let kind = match data.and_then(|d| d.get("type")).and_then(Value::as_str) {
+                None | Some("Z") => VertexKind::Z,
+                Some("X") => VertexKind::X,
+                Some("hadamard") => {
+                    hadamards.insert(name, vec![]);
+                    continue;
+                }
+                Some(_) => return Err("ZX graph has a vertex that isn't a Z or X spider."),
+            };
+            let phase = parse_phase(data.and_then(|d| d.get("value")))?;
+            let (row, qubit) = position(vertex);
+            names.insert(name, graph.add_vertex(kind, phase, row, qubit));
+        }
+        for edge in section("undir_edges")?.values() {
+            let end = |key: &str| {
+                edge.get(key)
+                    .and_then(Value::as_str)
+                    .ok_or("Edge is missing its src or tgt.")
+            };
+            let (src, tgt) = (end("src")?, end("tgt")?);
+            let lookup = |name: &str| names.get(name).copied().ok_or("Edge ends at no vertex.");
+            match (hadamards.contains_key(src), hadamards.contains_key(tgt)) {
+                (false, false) => {
+                    let hadamard = edge.get("type").and_then(Value::as_str) == Some("hadamard");
+                    graph.connect(lookup(src)?, lookup(tgt)?, hadamard);
+                }
+                (true, false) => hadamards.get_mut(src).unwrap().push(lookup(tgt)?),
+                (false, true) => hadamards.get_mut(tgt).unwrap().push(lookup(src)?),
+                (true, true) => return Err("Hadamard nodes next to each other aren't supported."),
+            }
+        }
+        let mut hadamards: Vec<(&str, Vec<usize>)> = hadamards.into_iter().collect();
+        hadamards.sort();
+        for (_, ends) in hadamards {
+            let [a, b] = ends[..] else {
+                return Err("Hadamard node needs exactly two edges.");
+            };
+            graph.connect(a, b, true);
+        }
+        for (list, boundaries) in [
+            (&mut inputs, &mut graph.inputs),
+            (&mut outputs, &mut graph.outputs),
+        ] {
+            list.sort_by(|a, b| a.0.total_cmp(&b.0));
+            boundaries.extend(list.iter().map(|&(_, v)| v));
+        }
+        Ok(graph)
+    }
+
+    fn add_vertex(&mut self, kind: VertexKind, phase: u8, row: f64, qubit: f64) -> usize {
+        self.vertices.push(Vertex {
+            kind,
+            phase: phase % 4,
+            row,
+            qubit,
+        });
+        self.vertices.len() - 1
+    }
+
+    fn connect(&mut self, a: usize, b: usize, hadamard: bool) {
+        let kind = if hadamard {
+            EdgeKind::Hadamard
+        } else {
+            EdgeKind::Simple
+        };
+        self.edges.push((a, b, kind));
+    }
+
+    pub fn to_pyzx_json(&self) -> String {
+        // PyZX's graph JSON (what Graph.to_json writes and pyzx.Graph.from_json reads, and
+        // quizx reads too): boundaries under wire_vertices, spiders under node_vertices,
+        // and edges under undir_edges. Hadamard edges are written the way PyZX writes them,
+        // as a "hadamard" node with is_edge set, halfway along a pair of plain edges.
+        // Coordinates are [row, -qubit].
+        let name = |v: usize| match self.vertices[v].kind {
+            VertexKind::Boundary => format!("b{v}"),
+            _ => format!("v{v}"),
+        };
+        let coord = |row: f64, qubit: f64| format!("[{row}, {}]", 0.0 - qubit);
+        let mut wires = vec![];
+        let mut nodes = vec![];
+        for (v, vertex) in self.vertices.iter().enumerate() {
+            let position = coord(vertex.row, vertex.qubit);
+            match vertex.kind {
+                VertexKind::Boundary => {
+                    let side = match self.inputs.iter().position(|&input| input == v) {
+                        Some(q) => format!("\"input\": {q}"),
+                        None => {
+                            let q = self.outputs.iter().position(|&o| o == v).unwrap_or(0);
+                            format!("\"output\": {q}")
+                        }
+                    };
+                    wires.push(format!(
+                        "\"{}\": {{\"annotation\": {{\"boundary\": true, \"coord\": {position}, \
+                         {side}}}}}",
+                        name(v)
+                    ));
+                }
+                VertexKind::Z | VertexKind::X => {
+                    let kind = if vertex.kind == VertexKind::Z {
+                        "Z"
+                    } else {
+                        "X"
+                    };
+                    let value = match vertex.phase {
+                        1 => ", \"value\": \"\\\\pi/2\"",
+                        2 => ", \"value\": \"\\\\pi\"",
+                        3 => ", \"value\": \"3\\\\pi/2\"",
+                        _ => "",
+                    };
+                    nodes.push(format!(
+                        "\"{}\": {{\"annotation\": {{\"coord\": {position}}}, \
+                         \"data\": {{\"type\": \"{kind}\"{value}}}}}",
+                        name(v)
+                    ));
+                }
+            }
+        }
+        let mut edges = vec![];
+        let mut edge = |src: String, tgt: String| {
+            edges.push(format!(
+                "\"e{}\": {{\"src\": \"{src}\", \"tgt\": \"{tgt}\"}}",
+                edges.len()
+            ));
+        };
+        for (h, &(a, b, kind)) in self.edges.iter().enumerate() {
+            match kind {
+                EdgeKind::Simple => edge(name(a), name(b)),
+                EdgeKind::Hadamard => {
+                    let (va, vb) = (&self.vertices[a], &self.vertices[b]);
+                    let middle = format!("h{h}");
+                    nodes.push(format!(
+                        "\"{middle}\": {{\"annotation\": {{\"coord\": {}}}, \
+                         \"data\": {{\"type\": \"hadamard\", \"is_edge\": \"true\"}}}}",
+                        coord((va.row + vb.row) / 2.0, (va.qubit + vb.qubit) / 2.0)
+                    ));
+                    edge(name(a), middle.clone());
+                    edge(name(b), middle);
+                }
+            }
+        }
+        format!(
+            "{{\"wire_vertices\": {{{}}}, \"node_vertices\": {{{}}}, \"undir_edges\": {{{}}}}}",
+            wires.join(", "),
+            nodes.join(", "),
+            edges.join(", ")
+        )
+    }
+}
+
+#[cfg(feature = "zx")]
+fn parse_phase(value: Option<&serde_json::Value>) -> Result<u8, &'static str> {
+    // a phase in units of pi, as quarter turns. Anything that isn't a multiple of pi/2 isn't
+    // clifford.
+    let not_clifford = "ZX graph has a phase that isn't a multiple of pi/2.";
+    let (numerator, denominator) = match value {
+        None => (0, 1),
+        Some(serde_json::Value::Number(number)) => {
+            let halves = number.as_f64().ok_or(not_clifford)? * 2.0;
+            if halves.fract() != 0.0 {
+                return Err(not_clifford);
+            }
+            (halves as i64, 2)
+        }
+        Some(serde_json::Value::String(text)) => {
+            let text: String = text.chars().filter(|c| !c.is_whitespace()).collect();
+            if text.is_empty() {
+                (0, 1)
+            } else {
+                let text = text.replace("\\pi", "").replace('π', "").replace("pi", "");
+                let (numerator, denominator) = text.split_once('/').unwrap_or((&text, "1"));
+                let numerator = match numerator {
+                    "" => 1,
+                    "-" => -1,
+                    numerator => numerator.parse().map_err(|_| "ZX graph has a bad phase.")?,
+                };
+                let denominator: i64 = denominator
+                    .parse()
+                    .map_err(|_| "ZX graph has a bad phase.")?;
+                (numerator, denominator)
+            }
+        }
+        Some(_) => return Err("ZX graph has a bad phase."),
+    };
+    if denominator == 0 || (2 * numerator) % denominator != 0 {
+        return Err(not_clifford);
+    }
+    Ok((2 * numerator / denominator).rem_euclid(4) as u8)
+}
+
+fn reduce(
+    rows: &mut [PauliString],
+    columns: &[(usize, bool)],
+) -> Result<Vec<Option<usize>>, &'static str> {
+    // gaussian elimination on the given (qubit, x or z) columns, with rowsum so signs follow
+    // along. Returns the pivot row of each column, if it has one, and leaves that row the only
+    // one with a 1 there.
+    let mut pivots = vec![];
+    let mut used = vec![false; rows.len()];
+    let bit =
+        |row: &PauliString, (q, x): (usize, bool)| if x { row.x_bits[q] } else { row.z_bits[q] };
+    for &column in columns {
+        let Some(p) = (0..rows.len()).find(|&i| !used[i] && bit(&rows[i], column)) else {
+            pivots.push(None);
+            continue;
+        };
+        used[p] = true;
+        let pivot = rows[p].clone();
+        for (i, row) in rows.iter_mut().enumerate() {
+            if i != p && bit(row, column) {
+                row.rowsum(&pivot.view())?;
+            }
+        }
+        pivots.push(Some(p));
+    }
+    Ok(pivots)
+}
+
+// where each qubit's wire has got to while a circuit is laid out: the vertex it ends on,
+// whether the next edge along it is a hadamard edge, and the row it's reached.
+struct Wires {
+    last: Vec<usize>,
+    hadamard: Vec<bool>,
+    row: Vec<usize>,
+}
+
+impl Wires {
+    fn spider(&mut self, graph: &mut ZxGraph, q: usize, kind: VertexKind, phase: u8) -> usize {
+        self.row[q] += 1;
+        let v = graph.add_vertex(kind, phase, self.row[q] as f64, q as f64);
+        graph.connect(self.last[q], v, self.hadamard[q]);
+        self.last[q] = v;
+        self.hadamard[q] = false;
+        v
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use rand::SeedableRng;
+
+    fn random_circuit(num_qubits: usize, seed: u64) -> Circuit {
+        let mut rng = rand::rngs::StdRng::seed_from_u64(seed);
+        crate::clifford::random_clifford(num_qubits, &mut rng).to_circuit()
+    }
+
+    #[test]
+    fn test_zx_round_trip() {
+        for seed in 0..4 {
+            let circuit = random_circuit(3, seed);
+            let graph = ZxGraph::from_circuit(&circuit).unwrap();
+            let back = graph.to_circuit().unwrap();
+            assert_eq!(
+                CliffordOperator::from_gates(3, &gates(&back)).unwrap(),
+                CliffordOperator::from_gates(3, &gates(&circuit)).unwrap(),
+                "seed {seed}"
+            );
+        }
+        // a lone Z spider on a wire with both ends as inputs isn't a unitary.
+        let mut cap = ZxGraph::from_circuit(&Circuit::new(1)).unwrap();
+        cap.inputs.push(cap.outputs.pop().unwrap());
+        assert!(cap.to_circuit().is_err());
+        // and |+> capped off with <-| is zero.
+        let mut zero = ZxGraph::default();
+        let plus = zero.add_vertex(VertexKind::Z, 0, 0.0, 0.0);
+        let minus = zero.add_vertex(VertexKind::Z, 2, 1.0, 0.0);
+        zero.connect(plus, minus, false);
+        assert!(zero.to_circuit().is_err());
+    }
+
+    #[cfg(feature = "zx")]
+    #[test]
+    fn test_pyzx_json_round_trip() {
+        let circuit = random_circuit(3, 7);
+        let json = ZxGraph::from_circuit(&circuit).unwrap().to_pyzx_json();
+        let graph = ZxGraph::from_pyzx_json(&json).unwrap();
+        assert_eq!(
+            CliffordOperator::from_gates(3, &gates(&graph.to_circuit().unwrap())).unwrap(),
+            CliffordOperator::from_gates(3, &gates(&circuit)).unwrap()
+        );
+
+        // PyZX's other spellings: a hadamard edge by type, and phases without a backslash.
+        let json = r#"{
+            "wire_vertices": {
+                "b0": {"annotation": {"boundary": true, "coord": [0, 0], "input": 0}},
+                "b1": {"annotation": {"boundary": true, "coord": [2, 0], "output": 0}}
+            },
+            "node_vertices": {
+                "v0": {"annotation": {"coord": [1, 0]}, "data": {"type": "Z", "value": "1/2"}}
+            },
+            "undir_edges": {
+                "e0": {"src": "b0", "tgt": "v0", "type": "hadamard"},
+                "e1": {"src": "v0", "tgt": "b1"}
+            }
+        }"#;
+        let circuit = ZxGraph::from_pyzx_json(json).unwrap().to_circuit().unwrap();
+        let (h, s) = (Gate::H(Qubit(0)), Gate::S(Qubit(0)));
+        assert_eq!(
+            CliffordOperator::from_gates(1, &gates(&circuit)).unwrap(),
+            CliffordOperator::from_gates(1, &[h, s]).unwrap()
+        );
+        assert!(ZxGraph::from_pyzx_json(&json.replace("1/2", "1/4")).is_err());
+        assert!(ZxGraph::from_pyzx_json("{").is_err());
+    }
+
+    fn gates(circuit: &Circuit) -> Vec<Gate> {
+        circuit
+            .instructions()
+            .iter()
+            .filter_map(|instruction| match instruction {
+                Instruction::Gate(gate) => Some(*gate),
+                _ => None,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_bell_pair_as_zx() {
+        let (a, b) = (Qubit(0), Qubit(1));
+        let mut circuit = Circuit::bell_pair(a, b);
+        circuit.gate(Gate::S(b));
+        let graph = ZxGraph::from_circuit(&circuit).unwrap();
+        // two inputs, the CX's two spiders, the S, and two outputs.
+        assert_eq!(graph.vertices.len(), 7);
+        let kinds: Vec<VertexKind> = graph.vertices.iter().map(|v| v.kind).collect();
+        assert_eq!(kinds[2..5], [VertexKind::Z, VertexKind::X, VertexKind::Z]);
+        assert_eq!(graph.vertices[4].phase, 1);
+        // the H is the edge from qubit 0's input into the CX.
+        assert_eq!(graph.edges[0], (0, 2, EdgeKind::Hadamard));
+        assert_eq!(graph.edges.len(), 6);
+
+        let json = graph.to_pyzx_json();
+        assert!(json.starts_with("{\"wire_vertices\": {\"b0\": {\"annotation\": {\"boundary\": true, \"coord\": [0, 0], \"input\": 0}}"));
+        assert!(json.contains("\"v4\": {\"annotation\": {\"coord\": [2, -1]}, \"data\": {\"type\": \"Z\", \"value\": \"\\\\pi/2\"}}"));
+        assert!(json.contains("\"type\": \"hadamard\", \"is_edge\": \"true\""));
+        assert_eq!(json.matches("\"src\"").count(), 7);
+
+        let mut measured = circuit.clone();
+        measured.measure(a);
+        assert!(ZxGraph::from_circuit(&measured).is_err());
+    }
+}
@@ -0,0 +1,68 @@
+use yass::circuit::Circuit;
+use yass::dense::verify_against_dense;
+use yass::gates::Gate;
+use yass::qubit::Qubit;
+use yass::stabilizer_simulator::StabilizerSimulator;
+
+// the six single-qubit stabilizer states, as (preparation from |0>, and its inverse).
+fn single_qubit_states(qubit: Qubit) -> Vec<(Vec<Gate>, Vec<Gate>)> {
+    let h = Gate::H(qubit);
+    let s = Gate::S(qubit);
+    let x = Gate::X(qubit);
+    vec![
+        (vec![], vec![]),
+        (vec![x], vec![x]),
+        (vec![h], vec![h]),
+        (vec![x, h], vec![h, x]),
+        (vec![h, s], vec![s, s, s, h]),
+        (vec![h, s, s, s], vec![s, h]),
+    ]
+}
+
+#[test]
+fn test_teleportation_moves_every_single_qubit_stabilizer_state() {
+    let sources = single_qubit_states(Qubit(0));
+    let targets = single_qubit_states(Qubit(2));
+    for ((preparation, _), (_, unpreparation)) in sources.iter().zip(&targets) {
+        for seed in 0..20 {
+            let mut circuit = Circuit::new(3);
+            for gate in preparation {
+                circuit.gate(*gate);
+            }
+            for instruction in Circuit::teleportation(Qubit(0), Qubit(1), Qubit(2)).instructions() {
+                circuit.push(*instruction);
+            }
+            // undo the preparation on the target, which should bring it back to |0>.
+            for gate in unpreparation {
+                circuit.gate(*gate);
+            }
+            circuit.measure(Qubit(2));
+
+            let record = StabilizerSimulator::new(3, seed).run(&circuit).unwrap();
+            assert_eq!(record.len(), 3);
+            assert!(
+                !record[2],
+                "teleported state came out wrong for seed {seed}"
+            );
+            assert_eq!(verify_against_dense(&circuit, seed), Ok(()));
+        }
+    }
+}
+
+#[test]
+fn test_corrections_depend_on_the_measurement_record() {
+    // without the conditional corrections, the target is only right when both
+    // bell measurement outcomes are 0.
+    let mut saw_needed_correction = false;
+    for seed in 0..20 {
+        let mut simulator = StabilizerSimulator::new(3, seed);
+        simulator.apply_gate(&Gate::X(Qubit(0)));
+        simulator
+            .run(&Circuit::teleportation(Qubit(0), Qubit(1), Qubit(2)))
+            .unwrap();
+        let record = simulator.measurement_record().to_vec();
+        saw_needed_correction |= record[0] || record[1];
+        assert!(simulator.measure(Qubit(2)).unwrap());
+    }
+    assert!(saw_needed_correction);
+}